@@ -6,6 +6,10 @@ pub enum ArgumentParseError {
     UnknownArgument(String),
     ExtraPositional(String),
     InvalidDebug(u32),
+    InvalidTarget(String),
+    InvalidEmit(String),
+    InvalidOptimization(String),
+    InvalidFormat(String),
     MissingValue(&'static str),
     Usage,
     Options,
@@ -17,6 +21,10 @@ impl Diagnostic for ArgumentParseError {
             Self::UnknownArgument(arg) => format!("unknown argument -{arg}"),
             Self::ExtraPositional(arg) => format!("unknown argument {arg}"),
             Self::InvalidDebug(num) => format!("invalid option for debug '{num}'"),
+            Self::InvalidTarget(name) => format!("unknown target '{name}'"),
+            Self::InvalidEmit(name) => format!("unknown emit kind '{name}'"),
+            Self::InvalidOptimization(level) => format!("invalid optimization level '-{level}'"),
+            Self::InvalidFormat(name) => format!("unknown output format '{name}'"),
             Self::MissingValue(arg) => format!("missing value for -{arg}"),
             Self::Usage => "usage".to_string(),
             Self::Options => "options".to_string(),
@@ -40,7 +48,12 @@ impl Diagnostic for ArgumentParseError {
 
     fn others(&self) -> Option<&dyn Report> {
         match self {
-            Self::MissingValue(_) | Self::InvalidDebug(_) => Some(&Self::Usage),
+            Self::MissingValue(_)
+            | Self::InvalidDebug(_)
+            | Self::InvalidTarget(_)
+            | Self::InvalidEmit(_)
+            | Self::InvalidOptimization(_)
+            | Self::InvalidFormat(_) => Some(&Self::Usage),
             Self::Usage => Some(&Self::Options),
             _ => None,
         }
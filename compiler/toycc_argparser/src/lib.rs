@@ -6,7 +6,8 @@ use itertools::Itertools;
 use std::env::args;
 
 const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
-const USAGE: &str = r"toycc [options] input_file";
+const USAGE: &str = r"toycc [options] input_file | toycc [options] ast.json | toycc [-run] file.tcb
+       toycc fmt [--check] file...";
 const OPTIONS: &str = r#"
     -help               display a usage message
     -output <file>      specifies target file name
@@ -16,12 +17,53 @@ const OPTIONS: &str = r#"
                             0 - all messages
                             1 - scanner messages only
                             2 - parser messages only
-                            3 - code generation messages only
+                            3 - code generation and optimization
+                                messages only
     -abstract           dump the abstract syntax tree
+    -abstract=<format>  dump it as a tree (default), as JSON with the
+                        location of every node, or as S-expressions:
+                        tree, json, sexpr
     -symbol             dump the symbol table(s)
+    -symbol=<format>    dump them as tables (default), or every scope
+                        with its symbols, their slots, declarations and
+                        uses as JSON: table, json
     -code               dump the generated program
+    -emit   <kind>      print an intermediate form and stop: ir, ssa,
+                        or write Graphviz files instead: dot-ast for
+                        the syntax tree, dot-cfg for the control flow
+                        graph of every function
+    -O<level>           optimization level: 0 (default) runs no passes,
+                        1 folds and propagates constants, removes dead
+                        code, turns self tail calls into jumps and runs
+                        the peephole optimizer, 2 also inlines small
+                        functions and, for the jvm and bytecode
+                        targets and -emit, hoists loop-invariant code
+                        and reduces multiplications of induction
+                        variables
+    -inline-threshold <n>
+                        inline functions of up to n nodes at -O2
+                        (default 16)
+    -print-passes       list the optimization passes that ran
+    -Wno-unused         do not warn about unused variables, parameters
+                        and functions
+    -Wshadow            warn about declarations that hide a variable or
+                        function of an enclosing scope
+    -Wno-error=uninitialized
+                        report reads of variables that may be
+                        unassigned as warnings, and start every local
+                        variable of the jvm target at zero
+    -target <name>      select the code generator: jvm (default), llvm, c,
+                        x86_64, wasm, riscv32, bytecode
+    -binary             also write a binary .wasm module for the
+                        wasm target
+    -run                execute the program with the interpreter, or
+                        with the bytecode VM for the bytecode target
     -verbose            display all information
-    -version            display the program version"#;
+    -version            display the program version
+
+    fmt [--check] file...
+                        rewrite the files in the canonical style, or
+                        list those that are not in it and fail"#;
 
 #[derive(Debug, Eq, PartialEq, Default)]
 pub struct Arguments {
@@ -31,11 +73,142 @@ pub struct Arguments {
     pub class: Option<String>,
     pub output: Option<String>,
     pub dump_ast: bool,
+    pub ast_format: AstFormat,
     pub dump_sym: bool,
+    pub symbol_format: SymbolFormat,
     pub dump_cgn: bool,
+    pub run: bool,
+    pub target: Target,
+    pub binary: bool,
+    pub emit: Option<Emit>,
+    pub optimize: u32,
+    pub inline_threshold: Option<u32>,
+    pub print_passes: bool,
+    pub no_unused_warnings: bool,
+    pub no_uninitialized_errors: bool,
+    pub shadow_warnings: bool,
     pub version: bool,
     pub verbose: bool,
     pub file_name: Option<String>,
+    /// Set by `toycc fmt`, which takes no other options
+    pub format: Option<FormatArguments>,
+}
+
+/// Arguments of `toycc fmt`
+#[derive(Debug, Eq, PartialEq, Default)]
+pub struct FormatArguments {
+    pub check: bool,
+    pub files: Vec<String>,
+}
+
+impl FormatArguments {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, ArgumentParseError> {
+        let mut format = FormatArguments::default();
+        for arg in args {
+            match arg.as_str() {
+                "--check" => format.check = true,
+                _ if arg.starts_with('-') => {
+                    return Err(ArgumentParseError::UnknownArgument(arg[1..].to_string()))
+                }
+                _ => format.files.push(arg),
+            }
+        }
+        Ok(format)
+    }
+}
+
+/// Code generator selected with `-target`
+#[derive(Debug, Eq, PartialEq, Default, Clone, Copy)]
+pub enum Target {
+    #[default]
+    Jvm,
+    Llvm,
+    C,
+    X86_64,
+    Wasm,
+    Riscv32,
+    Bytecode,
+}
+
+impl TryFrom<&str> for Target {
+    type Error = ArgumentParseError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "jvm" => Ok(Target::Jvm),
+            "llvm" => Ok(Target::Llvm),
+            "c" => Ok(Target::C),
+            "x86_64" => Ok(Target::X86_64),
+            "wasm" => Ok(Target::Wasm),
+            "riscv32" => Ok(Target::Riscv32),
+            "bytecode" => Ok(Target::Bytecode),
+            _ => Err(ArgumentParseError::InvalidTarget(value.to_string())),
+        }
+    }
+}
+
+/// How `-abstract` prints the abstract syntax tree
+#[derive(Debug, Eq, PartialEq, Default, Clone, Copy)]
+pub enum AstFormat {
+    #[default]
+    Tree,
+    Json,
+    Sexpr,
+}
+
+impl TryFrom<&str> for AstFormat {
+    type Error = ArgumentParseError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "tree" => Ok(AstFormat::Tree),
+            "json" => Ok(AstFormat::Json),
+            "sexpr" => Ok(AstFormat::Sexpr),
+            _ => Err(ArgumentParseError::InvalidFormat(value.to_string())),
+        }
+    }
+}
+
+/// How `-symbol` prints the symbol tables
+#[derive(Debug, Eq, PartialEq, Default, Clone, Copy)]
+pub enum SymbolFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+impl TryFrom<&str> for SymbolFormat {
+    type Error = ArgumentParseError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "table" => Ok(SymbolFormat::Table),
+            "json" => Ok(SymbolFormat::Json),
+            _ => Err(ArgumentParseError::InvalidFormat(value.to_string())),
+        }
+    }
+}
+
+/// Intermediate form printed with `-emit` instead of generating code
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Emit {
+    Ir,
+    Ssa,
+    /// The parsed syntax tree, written to `<output>.ast.dot`
+    DotAst,
+    /// The IR control flow graph of each function, written to
+    /// `<output>.<function>.dot`
+    DotCfg,
+}
+
+impl TryFrom<&str> for Emit {
+    type Error = ArgumentParseError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "ir" => Ok(Emit::Ir),
+            "ssa" => Ok(Emit::Ssa),
+            "dot-ast" => Ok(Emit::DotAst),
+            "dot-cfg" => Ok(Emit::DotCfg),
+            _ => Err(ArgumentParseError::InvalidEmit(value.to_string())),
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -46,8 +219,20 @@ enum Argument {
     Verbose,
     Positional(String),
     DumpAST,
+    DumpASTFormat(AstFormat),
     DumpSYM,
+    DumpSYMFormat(SymbolFormat),
     DumpCGN,
+    Run,
+    Target,
+    Binary,
+    Emit,
+    Optimize(u32),
+    InlineThreshold,
+    PrintPasses,
+    NoUnusedWarnings,
+    NoUninitializedErrors,
+    ShadowWarnings,
     Version,
     Class,
     Output,
@@ -91,7 +276,15 @@ impl Arguments {
     }
 
     pub fn parse() -> Result<Self, ArgumentParseError> {
-        let input = args().skip(1).join(" ");
+        let mut input = args().skip(1).peekable();
+        if input.peek().is_some_and(|command| command == "fmt") {
+            let format = FormatArguments::parse(input.skip(1))?;
+            return Ok(Self {
+                format: Some(format),
+                ..Default::default()
+            });
+        }
+        let input = input.join(" ");
         let mut args = Arguments::default();
         let mut tokens = scan_tokens(&input)?.into_iter();
         while let Some(token) = tokens.next() {
@@ -115,9 +308,42 @@ impl Arguments {
                     None => args.file_name = Some(s.clone()),
                 },
                 Token::Argument(Argument::DumpAST) => args.dump_ast = true,
+                Token::Argument(Argument::DumpASTFormat(format)) => {
+                    args.dump_ast = true;
+                    args.ast_format = format
+                }
                 Token::Argument(Argument::DumpSYM) => args.dump_sym = true,
+                Token::Argument(Argument::DumpSYMFormat(format)) => {
+                    args.dump_sym = true;
+                    args.symbol_format = format
+                }
                 Token::Argument(Argument::DumpCGN) => args.dump_cgn = true,
+                Token::Argument(Argument::Run) => args.run = true,
                 Token::Argument(Argument::Version) => args.version = true,
+                Token::Argument(Argument::Binary) => args.binary = true,
+                Token::Argument(Argument::Target) => match tokens.next() {
+                    Some(Token::Argument(Argument::Positional(s))) => {
+                        args.target = s.as_str().try_into()?
+                    }
+                    _ => return Err(ArgumentParseError::MissingValue("target")),
+                },
+                Token::Argument(Argument::Emit) => match tokens.next() {
+                    Some(Token::Argument(Argument::Positional(s))) => {
+                        args.emit = Some(s.as_str().try_into()?)
+                    }
+                    _ => return Err(ArgumentParseError::MissingValue("emit")),
+                },
+                Token::Argument(Argument::Optimize(level)) => args.optimize = level,
+                Token::Argument(Argument::InlineThreshold) => match tokens.next() {
+                    Some(Token::Number(n)) => args.inline_threshold = Some(n),
+                    _ => return Err(ArgumentParseError::MissingValue("inline-threshold")),
+                },
+                Token::Argument(Argument::PrintPasses) => args.print_passes = true,
+                Token::Argument(Argument::NoUnusedWarnings) => args.no_unused_warnings = true,
+                Token::Argument(Argument::ShadowWarnings) => args.shadow_warnings = true,
+                Token::Argument(Argument::NoUninitializedErrors) => {
+                    args.no_uninitialized_errors = true
+                }
                 Token::Argument(Argument::Class) => match tokens.next() {
                     Some(Token::Argument(Argument::Positional(s))) => args.class = Some(s.clone()),
                     _ => return Err(ArgumentParseError::MissingValue("class")),
@@ -199,9 +425,28 @@ impl TryFrom<&str> for Argument {
             "abstract" => Ok(Argument::DumpAST),
             "symbol" => Ok(Argument::DumpSYM),
             "code" => Ok(Argument::DumpCGN),
+            "run" => Ok(Argument::Run),
+            "target" => Ok(Argument::Target),
+            "binary" => Ok(Argument::Binary),
+            "emit" => Ok(Argument::Emit),
             "version" => Ok(Argument::Version),
             "class" => Ok(Argument::Class),
             "output" => Ok(Argument::Output),
+            "print-passes" => Ok(Argument::PrintPasses),
+            "inline-threshold" => Ok(Argument::InlineThreshold),
+            "Wno-unused" => Ok(Argument::NoUnusedWarnings),
+            "Wno-error=uninitialized" => Ok(Argument::NoUninitializedErrors),
+            "Wshadow" => Ok(Argument::ShadowWarnings),
+            format if format.starts_with("abstract=") => Ok(Argument::DumpASTFormat(
+                format["abstract=".len()..].try_into()?,
+            )),
+            format if format.starts_with("symbol=") => Ok(Argument::DumpSYMFormat(
+                format["symbol=".len()..].try_into()?,
+            )),
+            level if level.starts_with('O') => match level[1..].parse() {
+                Ok(level @ 0..=2) => Ok(Argument::Optimize(level)),
+                _ => Err(ArgumentParseError::InvalidOptimization(value.to_string())),
+            },
             _ => Err(ArgumentParseError::UnknownArgument(value.to_string())),
         }
     }
@@ -209,7 +454,10 @@ impl TryFrom<&str> for Argument {
 
 #[cfg(test)]
 mod scanner_tests {
-    use super::{scan_tokens, Argument, Token};
+    use super::{
+        scan_tokens, Argument, ArgumentParseError, AstFormat, Emit, FormatArguments, SymbolFormat,
+        Token,
+    };
 
     #[test]
     fn test_help() {
@@ -249,6 +497,103 @@ mod scanner_tests {
         )
     }
 
+    #[test]
+    fn test_optimization_level() {
+        assert_eq!(
+            scan_tokens("-O2 a.tc -print-passes"),
+            Ok(vec![
+                Token::Argument(Argument::Optimize(2)),
+                Token::Argument(Argument::Positional("a.tc".to_string())),
+                Token::Argument(Argument::PrintPasses),
+                Token::Eos
+            ])
+        );
+        assert_eq!(
+            scan_tokens("-inline-threshold 8 a.tc"),
+            Ok(vec![
+                Token::Argument(Argument::InlineThreshold),
+                Token::Number(8),
+                Token::Argument(Argument::Positional("a.tc".to_string())),
+                Token::Eos
+            ])
+        );
+        assert_eq!(
+            scan_tokens("-Wno-unused a.tc"),
+            Ok(vec![
+                Token::Argument(Argument::NoUnusedWarnings),
+                Token::Argument(Argument::Positional("a.tc".to_string())),
+                Token::Eos
+            ])
+        );
+        assert_eq!(
+            scan_tokens("-Wno-error=uninitialized -Wshadow a.tc"),
+            Ok(vec![
+                Token::Argument(Argument::NoUninitializedErrors),
+                Token::Argument(Argument::ShadowWarnings),
+                Token::Argument(Argument::Positional("a.tc".to_string())),
+                Token::Eos
+            ])
+        );
+        assert_eq!(
+            scan_tokens("-O3 a.tc"),
+            Err(ArgumentParseError::InvalidOptimization("O3".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            scan_tokens("-symbol=json -abstract=sexpr a.tc"),
+            Ok(vec![
+                Token::Argument(Argument::DumpSYMFormat(SymbolFormat::Json)),
+                Token::Argument(Argument::DumpASTFormat(AstFormat::Sexpr)),
+                Token::Argument(Argument::Positional("a.tc".to_string())),
+                Token::Eos
+            ])
+        );
+        assert_eq!(
+            scan_tokens("-abstract=yaml"),
+            Err(ArgumentParseError::InvalidFormat("yaml".to_string()))
+        );
+        assert_eq!(
+            scan_tokens("-symbol=table"),
+            Ok(vec![
+                Token::Argument(Argument::DumpSYMFormat(SymbolFormat::Table)),
+                Token::Eos
+            ])
+        );
+        assert_eq!(
+            scan_tokens("-symbol=xml"),
+            Err(ArgumentParseError::InvalidFormat("xml".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_emit() {
+        assert_eq!(Emit::try_from("dot-ast"), Ok(Emit::DotAst));
+        assert_eq!(Emit::try_from("dot-cfg"), Ok(Emit::DotCfg));
+        assert_eq!(
+            Emit::try_from("dot"),
+            Err(ArgumentParseError::InvalidEmit("dot".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_format_arguments() {
+        let args = |s: &str| FormatArguments::parse(s.split(' ').map(str::to_string));
+        assert_eq!(
+            args("--check a.tc b.tc"),
+            Ok(FormatArguments {
+                check: true,
+                files: vec!["a.tc".to_string(), "b.tc".to_string()]
+            })
+        );
+        assert_eq!(
+            args("a.tc --diff"),
+            Err(ArgumentParseError::UnknownArgument("-diff".to_string()))
+        );
+    }
+
     #[test]
     fn test_positional2() {
         assert_eq!(
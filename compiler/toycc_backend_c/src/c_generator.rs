@@ -0,0 +1,752 @@
+use toycc_backend_jvm::symbol_table::{Function, Symbol, SymbolTable};
+use toycc_backend_jvm::{SemanticError, SemanticErrorKind};
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+
+const INDENT: &str = "    ";
+
+/// C99 keywords and the library names the generated code relies on; ToyC
+/// identifiers that collide with one of these or start with `toyc_` get a
+/// trailing underscore
+const RESERVED: [&str; 40] = [
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while", "_Bool", "printf", "scanf", "putchar",
+    "fflush", "stdout",
+];
+
+/// Arithmetic that wraps around like ToyC's instead of overflowing, which is
+/// undefined in C, and division that gives `-a` and 0 for a divisor of -1
+/// instead of trapping on `INT_MIN / -1`
+const HELPERS: [(&str, &str); 6] = [
+    (
+        "toyc_neg",
+        "int toyc_neg(int a)\n{\n    return (int)(0u - (unsigned)a);\n}",
+    ),
+    (
+        "toyc_add",
+        "int toyc_add(int a, int b)\n{\n    return (int)((unsigned)a + (unsigned)b);\n}",
+    ),
+    (
+        "toyc_sub",
+        "int toyc_sub(int a, int b)\n{\n    return (int)((unsigned)a - (unsigned)b);\n}",
+    ),
+    (
+        "toyc_mul",
+        "int toyc_mul(int a, int b)\n{\n    return (int)((unsigned)a * (unsigned)b);\n}",
+    ),
+    (
+        "toyc_div",
+        "int toyc_div(int a, int b)\n{\n    return b == -1 ? toyc_neg(a) : a / b;\n}",
+    ),
+    (
+        "toyc_rem",
+        "int toyc_rem(int a, int b)\n{\n    return b == -1 ? 0 : a % b;\n}",
+    ),
+];
+
+/// Pretty-prints a checked program as portable C99
+pub struct CGenerator<'a> {
+    source_name: &'a str,
+    symbol_table: Vec<SymbolTable<'a>>,
+    output: String,
+    depth: usize,
+    /// Statements that evaluate parts of the expression being generated in
+    /// ToyC's left-to-right order, printed before the line that uses them
+    pending: Vec<String>,
+    temp_count: usize,
+    helpers: Vec<&'static str>,
+}
+
+impl<'a> CGenerator<'a> {
+    pub fn new(source_name: &'a str) -> Self {
+        Self {
+            source_name,
+            symbol_table: vec![SymbolTable::default()],
+            output: String::new(),
+            depth: 0,
+            pending: vec![],
+            temp_count: 0,
+            helpers: vec![],
+        }
+    }
+
+    pub fn generate(&mut self, program: &'a Program) -> Result<String, Box<SemanticError>> {
+        self.output = format!(
+            "/* Generated by toycc from {} */\n#include <stdio.h>\n\n",
+            self.source_name
+        );
+
+        let mut globals = vec![];
+        let mut prototypes = vec![];
+        for definition in &program.definitions {
+            match definition {
+                Definition::VarDef(var_def) => {
+                    self.declare_variables(var_def)?;
+                    globals.push(format!("{};", variable_declaration(var_def, false)));
+                }
+                Definition::FuncDef(func_def) => {
+                    let arguments = parameters(func_def)
+                        .map(|(_, toyc_type)| descriptor(toyc_type).to_owned())
+                        .collect();
+                    let function = Function::new(
+                        func_def.identifier.clone(),
+                        arguments,
+                        vec![],
+                        func_def.toyc_type.clone(),
+                    );
+                    self.insert_symbol(&func_def.identifier, Symbol::Function(function))?;
+                    prototypes.push(format!("{};", signature(func_def)));
+                }
+            }
+        }
+
+        for line in globals.iter().chain(prototypes.iter()) {
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+
+        for definition in &program.definitions {
+            if let Definition::FuncDef(func_def) = definition {
+                self.output.push('\n');
+                self.generate_function(func_def)?;
+            }
+        }
+
+        self.output
+            .push_str("\nint main(void)\n{\n    return toyc_main();\n}\n");
+
+        let helpers = HELPERS
+            .iter()
+            .filter(|(name, _)| self.helpers.contains(name))
+            .map(|(_, helper)| format!("static {helper}\n\n"))
+            .collect::<String>();
+        let prelude = self.output.find("\n\n").unwrap() + 2;
+        self.output.insert_str(prelude, &helpers);
+        Ok(std::mem::take(&mut self.output))
+    }
+
+    fn generate_function(&mut self, func_def: &'a FuncDef) -> Result<(), Box<SemanticError>> {
+        self.temp_count = 0;
+        self.symbol_table.push(SymbolTable::default());
+        for var_def in &func_def.var_def {
+            self.declare_variables(var_def)?;
+        }
+        self.output.push_str(&format!("{}\n", signature(func_def)));
+        match &func_def.statement.kind {
+            StatementKind::BlockState(var_defs, statements) => {
+                self.generate_block(var_defs, statements)?
+            }
+            _ => self.generate_statement(&func_def.statement)?,
+        }
+        self.output.push('\n');
+        self.symbol_table.pop();
+        Ok(())
+    }
+
+    fn generate_block(
+        &mut self,
+        var_defs: &'a [VarDef],
+        statements: &'a [Statement],
+    ) -> Result<(), Box<SemanticError>> {
+        self.line("{");
+        self.depth += 1;
+        self.symbol_table.push(SymbolTable::default());
+        for var_def in var_defs {
+            self.declare_variables(var_def)?;
+            self.line(&format!("{};", variable_declaration(var_def, true)));
+        }
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.generate_statement(statement));
+        self.symbol_table.pop();
+        self.depth -= 1;
+        self.indent();
+        self.output.push('}');
+        result
+    }
+
+    fn generate_statement(&mut self, statement: &'a Statement) -> Result<(), Box<SemanticError>> {
+        match &statement.kind {
+            StatementKind::BlockState(var_defs, statements) => {
+                self.generate_block(var_defs, statements)?;
+                self.output.push('\n');
+            }
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
+                // evaluated parts of the condition go before the `if`
+                let condition = self.generate_expression(expr)?;
+                self.line(&format!("if ({})", strip_parens(&condition)));
+                self.generate_body(if_stmt)?;
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    self.line("else");
+                    self.generate_body(else_stmt)?;
+                }
+            }
+            StatementKind::WhileState(expr, body) if needs_sequencing(expr) => {
+                // the condition's statements run before every test
+                self.line("while (1)");
+                self.line("{");
+                self.depth += 1;
+                let condition = self.generate_expression(expr)?;
+                self.line(&format!("if (!{condition})"));
+                self.line(&format!("{INDENT}break;"));
+                if !matches!(body.kind, StatementKind::NullState) {
+                    self.generate_statement(body)?;
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            StatementKind::WhileState(expr, body) => {
+                let condition = self.generate_expression(expr)?;
+                self.line(&format!("while ({})", strip_parens(&condition)));
+                self.generate_body(body)?;
+            }
+            StatementKind::WriteState(expr, others) => self.generate_write(expr, others)?,
+            _ => {
+                let line = self.simple_statement(statement)?;
+                self.line(&line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the items of a `write` with as few `printf` calls as keep them
+    /// in ToyC's order: one starts before an item with side effects and after
+    /// it at the next one that is not a constant
+    fn generate_write(
+        &mut self,
+        expr: &'a Expression,
+        others: &'a Option<Vec<Expression>>,
+    ) -> Result<(), Box<SemanticError>> {
+        let mut format = String::new();
+        let mut arguments = vec![];
+        let mut sequenced = false;
+        for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+            if let ExpressionKind::StringLiteral(s) = &expr.kind {
+                format.push_str(&escape(&unescape(s)).replace('%', "%%"));
+                continue;
+            }
+            let effects = has_side_effects(expr);
+            if effects || (sequenced && !is_constant(expr)) {
+                self.printf(&mut format, &mut arguments);
+                sequenced = false;
+            }
+            sequenced |= effects;
+            format.push_str(match self.type_of(expr)? {
+                Type::Int => "%d",
+                Type::Char => "%c",
+            });
+            arguments.push(strip_parens(&self.generate_expression(expr)?).to_owned());
+        }
+        self.printf(&mut format, &mut arguments);
+        Ok(())
+    }
+
+    fn printf(&mut self, format: &mut String, arguments: &mut Vec<String>) {
+        match arguments.is_empty() {
+            true if format.is_empty() => return,
+            true => self.line(&format!("printf(\"{format}\");")),
+            false => self.line(&format!("printf(\"{format}\", {});", arguments.join(", "))),
+        }
+        format.clear();
+        arguments.clear();
+    }
+
+    /// Prints the body of an `if`/`while`, indenting it unless it is a block.
+    /// An empty body becomes `{ }` to keep `-Wempty-body` quiet, and one that
+    /// needs more than a statement gets braces.
+    fn generate_body(&mut self, statement: &'a Statement) -> Result<(), Box<SemanticError>> {
+        match &statement.kind {
+            StatementKind::BlockState(..) => self.generate_statement(statement),
+            StatementKind::NullState => {
+                self.line("{ }");
+                Ok(())
+            }
+            _ if spans_statements(statement) => {
+                self.line("{");
+                self.depth += 1;
+                let result = self.generate_statement(statement);
+                self.depth -= 1;
+                self.line("}");
+                result
+            }
+            _ => {
+                self.depth += 1;
+                let result = self.generate_statement(statement);
+                self.depth -= 1;
+                result
+            }
+        }
+    }
+
+    fn simple_statement(&mut self, statement: &'a Statement) -> Result<String, Box<SemanticError>> {
+        Ok(match &statement.kind {
+            StatementKind::Expression(expr) => {
+                format!("{};", strip_parens(&self.generate_expression(expr)?))
+            }
+            StatementKind::Break => "break;".to_owned(),
+            StatementKind::NullState => ";".to_owned(),
+            StatementKind::ReturnState(Some(expr)) => {
+                format!("return {};", strip_parens(&self.generate_expression(expr)?))
+            }
+            StatementKind::ReturnState(None) => "return 0;".to_owned(),
+            StatementKind::ReadState(name, others) => {
+                let mut format = vec![];
+                let mut arguments = vec![];
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    format.push(match self.variable_type(name)? {
+                        Type::Int => "%d",
+                        Type::Char => " %c",
+                    });
+                    arguments.push(format!("&{}", c_identifier(name)));
+                }
+                format!("scanf(\"{}\", {});", format.join(""), arguments.join(", "))
+            }
+            StatementKind::NewLineState => "putchar('\\n');".to_owned(),
+            StatementKind::BlockState(..)
+            | StatementKind::IfState(..)
+            | StatementKind::WhileState(..)
+            | StatementKind::WriteState(..) => unreachable!("generated by generate_statement"),
+        })
+    }
+
+    /// Renders an expression, parenthesizing every compound subexpression so
+    /// that the C precedence rules cannot change its meaning. Operands C
+    /// would evaluate in an unspecified order are first stored in temporaries
+    /// when one of them has side effects.
+    fn generate_expression(
+        &mut self,
+        expression: &'a Expression,
+    ) -> Result<String, Box<SemanticError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(num) => (*num as i32).to_string(),
+            ExpressionKind::Identifier(id) => {
+                self.variable_type(id)?;
+                c_identifier(id)
+            }
+            ExpressionKind::CharLiteral(Some(c)) => format!("'{}'", escape_char(*c, '\'')),
+            ExpressionKind::CharLiteral(None) => "'\\0'".to_owned(),
+            ExpressionKind::StringLiteral(s) => format!("\"{}\"", escape(&unescape(s))),
+            ExpressionKind::FuncCall(name, arguments) => {
+                self.function_type(name)?;
+                let arguments = self
+                    .generate_operands(arguments.iter())?
+                    .iter()
+                    .map(|a| strip_parens(a).to_owned())
+                    .collect::<Vec<_>>();
+                format!("{}({})", function_name(name), arguments.join(", "))
+            }
+            ExpressionKind::Expr(op @ (Operator::And | Operator::Or), lhs, rhs)
+                if needs_sequencing(rhs) =>
+            {
+                let lhs = self.generate_expression(lhs)?;
+                let temp = self.temp(&format!("{lhs} != 0"));
+                // the right operand's statements only run when it is evaluated
+                let outer = std::mem::take(&mut self.pending);
+                let rhs = self.generate_expression(rhs);
+                let inner = std::mem::replace(&mut self.pending, outer);
+                let rhs = rhs?;
+                self.pending.push(match op {
+                    Operator::And => format!("if ({temp})"),
+                    _ => format!("if (!{temp})"),
+                });
+                self.pending.push("{".to_owned());
+                self.pending.extend(
+                    inner
+                        .into_iter()
+                        .chain([format!("{temp} = {rhs} != 0;")])
+                        .map(|line| format!("{INDENT}{line}")),
+                );
+                self.pending.push("}".to_owned());
+                temp
+            }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                let lhs = self.generate_expression(lhs)?;
+                let rhs = self.generate_expression(rhs)?;
+                format!("({lhs} = {})", strip_parens(&rhs))
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                let operands = self.generate_operands([&**lhs, &**rhs].into_iter())?;
+                let (lhs, rhs) = (&operands[0], &operands[1]);
+                match op {
+                    Operator::Plus => self.helper("toyc_add", lhs, rhs),
+                    Operator::Minus => self.helper("toyc_sub", lhs, rhs),
+                    Operator::Multiply => self.helper("toyc_mul", lhs, rhs),
+                    Operator::Divide => self.helper("toyc_div", lhs, rhs),
+                    Operator::Modulo => self.helper("toyc_rem", lhs, rhs),
+                    _ => format!("({lhs} {op} {rhs})"),
+                }
+            }
+            ExpressionKind::Not(expr) => format!("!{}", self.generate_expression(expr)?),
+            ExpressionKind::Minus(expr) => match self.generate_expression(expr)? {
+                operand
+                    if matches!(expr.kind, ExpressionKind::Number(_))
+                        && !operand.starts_with('-') =>
+                {
+                    format!("-{operand}")
+                }
+                operand => {
+                    self.use_helper("toyc_neg");
+                    format!("toyc_neg({})", strip_parens(&operand))
+                }
+            },
+        })
+    }
+
+    /// Renders the operands of a call or a binary operator. If one of them
+    /// has side effects, each one that is not a constant is evaluated into a
+    /// temporary in turn, since C leaves their order unspecified.
+    fn generate_operands(
+        &mut self,
+        operands: impl Iterator<Item = &'a Expression> + Clone,
+    ) -> Result<Vec<String>, Box<SemanticError>> {
+        let sequence = operands.clone().any(has_side_effects)
+            && operands.clone().filter(|e| !is_constant(e)).count() > 1;
+        operands
+            .map(|operand| {
+                let value = self.generate_expression(operand)?;
+                Ok(match sequence && !is_constant(operand) {
+                    true => self.temp(strip_parens(&value)),
+                    false => value,
+                })
+            })
+            .collect()
+    }
+
+    /// Declares a temporary holding `value` among the pending statements
+    fn temp(&mut self, value: &str) -> String {
+        self.temp_count += 1;
+        let temp = format!("toyc_t{}", self.temp_count);
+        self.pending.push(format!("int {temp} = {value};"));
+        temp
+    }
+
+    fn helper(&mut self, name: &'static str, lhs: &str, rhs: &str) -> String {
+        self.use_helper(name);
+        format!("{name}({}, {})", strip_parens(lhs), strip_parens(rhs))
+    }
+
+    fn use_helper(&mut self, name: &'static str) {
+        if name == "toyc_div" {
+            self.use_helper("toyc_neg");
+        }
+        if !self.helpers.contains(&name) {
+            self.helpers.push(name);
+        }
+    }
+
+    /// Static type of an expression: a compound expression takes the type of
+    /// its left-most operand, as in the JVM backend
+    fn type_of(&mut self, expression: &'a Expression) -> Result<Type, Box<SemanticError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(_) | ExpressionKind::StringLiteral(_) => Type::Int,
+            ExpressionKind::CharLiteral(_) => Type::Char,
+            ExpressionKind::Identifier(id) => self.variable_type(id)?,
+            ExpressionKind::FuncCall(name, _) => self.function_type(name)?,
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs)?,
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr)?,
+        })
+    }
+
+    fn variable_type(&mut self, name: &str) -> Result<Type, Box<SemanticError>> {
+        match self.get_symbol(name) {
+            Some(Symbol::Variable(_, toyc_type, _)) => Ok(toyc_type.clone()),
+            Some(_) => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::ExpectedIdentifier,
+            ))),
+            None => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::UndeclaredIdentifier(name.to_owned()),
+            ))),
+        }
+    }
+
+    fn function_type(&mut self, name: &str) -> Result<Type, Box<SemanticError>> {
+        match self.get_symbol(name) {
+            Some(Symbol::Function(function)) => Ok(function.return_type.clone()),
+            Some(_) => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::ExpectedFunction,
+            ))),
+            None => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::UndeclaredFunction(name.to_owned()),
+            ))),
+        }
+    }
+
+    fn declare_variables(&mut self, var_def: &'a VarDef) -> Result<(), Box<SemanticError>> {
+        for id in &var_def.identifiers {
+            let variable = Symbol::Variable(id.clone(), var_def.toyc_type.clone(), 0);
+            self.insert_symbol(id, variable)?;
+        }
+        Ok(())
+    }
+
+    fn get_symbol(&mut self, name: &str) -> Option<&Symbol> {
+        self.symbol_table
+            .iter_mut()
+            .rev()
+            .find_map(|table| table.find(name))
+    }
+
+    fn insert_symbol(&mut self, name: &'a str, symbol: Symbol) -> Result<(), Box<SemanticError>> {
+        self.symbol_table
+            .iter_mut()
+            .next_back()
+            .unwrap()
+            .insert(name, symbol)
+            .map(|_| ())
+    }
+
+    fn indent(&mut self) {
+        self.output.push_str(&INDENT.repeat(self.depth));
+    }
+
+    /// Prints a line after the statements its expressions are waiting for
+    fn line(&mut self, line: &str) {
+        for pending in std::mem::take(&mut self.pending) {
+            self.indent();
+            self.output.push_str(&pending);
+            self.output.push('\n');
+        }
+        self.indent();
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+}
+
+fn parameters(func_def: &FuncDef) -> impl Iterator<Item = (&String, &Type)> {
+    func_def
+        .var_def
+        .iter()
+        .flat_map(|v| v.identifiers.iter().map(move |id| (id, &v.toyc_type)))
+}
+
+fn signature(func_def: &FuncDef) -> String {
+    let parameters = parameters(func_def)
+        .map(|(id, toyc_type)| format!("{} {}", c_type(toyc_type), c_identifier(id)))
+        .collect::<Vec<_>>();
+    format!(
+        "{} {}({})",
+        c_type(&func_def.toyc_type),
+        function_name(&func_def.identifier),
+        match parameters.is_empty() {
+            true => "void".to_owned(),
+            false => parameters.join(", "),
+        }
+    )
+}
+
+/// Declares the variables of a `VarDef`, zeroing them if `initialized` is set
+/// since C leaves locals indeterminate
+fn variable_declaration(var_def: &VarDef, initialized: bool) -> String {
+    let identifiers = var_def
+        .identifiers
+        .iter()
+        .map(|id| match initialized {
+            true => format!("{} = 0", c_identifier(id)),
+            false => c_identifier(id),
+        })
+        .collect::<Vec<_>>();
+    format!("{} {}", c_type(&var_def.toyc_type), identifiers.join(", "))
+}
+
+fn c_type(toyc_type: &Type) -> &'static str {
+    match toyc_type {
+        Type::Int => "int",
+        Type::Char => "char",
+    }
+}
+
+fn descriptor(toyc_type: &Type) -> &'static str {
+    match toyc_type {
+        Type::Int => "I",
+        Type::Char => "C",
+    }
+}
+
+fn function_name(name: &str) -> String {
+    match name {
+        "main" => "toyc_main".to_owned(),
+        _ => c_identifier(name),
+    }
+}
+
+fn c_identifier(name: &str) -> String {
+    match RESERVED.contains(&name) || name == "main" || name.starts_with("toyc_") {
+        true => format!("{name}_"),
+        false => name.to_owned(),
+    }
+}
+
+/// Whether evaluating an expression calls a function or assigns a variable
+fn has_side_effects(expression: &Expression) -> bool {
+    match &expression.kind {
+        ExpressionKind::FuncCall(..) | ExpressionKind::Expr(Operator::Assign, ..) => true,
+        ExpressionKind::Expr(_, lhs, rhs) => has_side_effects(lhs) || has_side_effects(rhs),
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => has_side_effects(expr),
+        _ => false,
+    }
+}
+
+fn is_constant(expression: &Expression) -> bool {
+    matches!(
+        expression.kind,
+        ExpressionKind::Number(_) | ExpressionKind::CharLiteral(_)
+    )
+}
+
+/// Whether rendering an expression stores some of it in temporaries first
+fn needs_sequencing(expression: &Expression) -> bool {
+    let operands = match &expression.kind {
+        ExpressionKind::FuncCall(_, arguments) => arguments.iter().collect(),
+        ExpressionKind::Expr(Operator::And | Operator::Or, lhs, rhs) => {
+            return needs_sequencing(lhs) || needs_sequencing(rhs)
+        }
+        ExpressionKind::Expr(Operator::Assign, _, rhs) => return needs_sequencing(rhs),
+        ExpressionKind::Expr(_, lhs, rhs) => vec![&**lhs, &**rhs],
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => return needs_sequencing(expr),
+        _ => return false,
+    };
+    (operands.iter().any(|e| has_side_effects(e))
+        && operands.iter().filter(|e| !is_constant(e)).count() > 1)
+        || operands.into_iter().any(needs_sequencing)
+}
+
+/// Whether a statement is printed as more than one C statement, so that it
+/// needs braces as the body of an `if` or `while`
+fn spans_statements(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Expression(expr)
+        | StatementKind::ReturnState(Some(expr))
+        | StatementKind::IfState(expr, ..) => needs_sequencing(expr),
+        StatementKind::WriteState(expr, others) => {
+            let items = std::iter::once(expr).chain(others.iter().flatten());
+            items.clone().any(needs_sequencing)
+                || (items.clone().count() > 1 && items.clone().any(has_side_effects))
+        }
+        _ => false,
+    }
+}
+
+/// Drops the parentheses around a whole expression where the surrounding C
+/// syntax already delimits it
+fn strip_parens(expression: &str) -> &str {
+    match expression
+        .strip_prefix('(')
+        .and_then(|e| e.strip_suffix(')'))
+    {
+        Some(inner) if balanced(inner) => inner,
+        _ => expression,
+    }
+}
+
+fn balanced(expression: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in expression.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '(' => depth += 1,
+                ')' if depth == 0 => return false,
+                ')' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    depth == 0
+}
+
+/// Expands the escape sequences accepted in ToyC string literals
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn escape(s: &str) -> String {
+    s.chars().map(|c| escape_char(c, '"')).collect()
+}
+
+fn escape_char(c: char, quote: char) -> String {
+    match c {
+        '\n' => "\\n".to_owned(),
+        '\t' => "\\t".to_owned(),
+        '\r' => "\\r".to_owned(),
+        '\\' => "\\\\".to_owned(),
+        c if c == quote => format!("\\{c}"),
+        ' '..='~' => c.to_string(),
+        c => format!("\\{:03o}", c as u32 & 0xff),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn generate(source: &str) -> Result<String, Box<SemanticError>> {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        CGenerator::new("test.tc").generate(&program)
+    }
+
+    #[test]
+    fn test_functions_and_main() {
+        let c = generate("int addTwo(int n){ return n + 2; } int main(){ char c; read(c); write(\"c = \", c, addTwo(1)); newline; return 0; }").unwrap();
+        assert!(c.contains("int addTwo(int n);\nint toyc_main(void);\n"));
+        assert!(c.contains("int addTwo(int n)\n{\n    return toyc_add(n, 2);\n}\n"));
+        assert!(c.contains("    char c = 0;\n"));
+        assert!(c.contains("    scanf(\" %c\", &c);\n"));
+        // addTwo could print, so it is called after the rest is written
+        assert!(c.contains("    printf(\"c = %c\", c);\n    printf(\"%d\", addTwo(1));\n"));
+        assert!(c.contains("    putchar('\\n');\n"));
+        assert!(c.ends_with("int main(void)\n{\n    return toyc_main();\n}\n"));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let c = generate("int main(){ int a; while (1) { read(a); if (a <= 2) break; else ; } if (!(a == 1) && a) write(\"100%\"); return 0; }").unwrap();
+        assert!(c.contains("    while (1)\n    {\n"));
+        assert!(c.contains("        if (a <= 2)\n            break;\n        else\n        { }\n"));
+        assert!(c.contains("    if (!(a == 1) && a)\n        printf(\"100%%\");\n"));
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        let c = generate("int float; int main(){ float = 1; return float; }").unwrap();
+        assert!(c.contains("int float_;\n"));
+        assert!(c.contains("    float_ = 1;\n    return float_;\n"));
+    }
+
+    #[test]
+    fn test_evaluation_order() {
+        let c = generate("int f(int n){ return n; } int main(){ int a; a = f(1) - f(2); write(a, f(3)); return f(a) && f(f(1) + a); }").unwrap();
+        assert!(c.starts_with("/* Generated by toycc from test.tc */\n#include <stdio.h>\n\nstatic int toyc_add(int a, int b)\n{\n    return (int)((unsigned)a + (unsigned)b);\n}\n\nstatic int toyc_sub("));
+        assert!(c.contains("    int toyc_t1 = f(1);\n    int toyc_t2 = f(2);\n    a = toyc_sub(toyc_t1, toyc_t2);\n"));
+        assert!(c.contains("    printf(\"%d\", a);\n    printf(\"%d\", f(3));\n"));
+        assert!(c.contains("    int toyc_t3 = f(a) != 0;\n    if (toyc_t3)\n    {\n        int toyc_t4 = f(1);\n        int toyc_t5 = a;\n        toyc_t3 = f(toyc_add(toyc_t4, toyc_t5)) != 0;\n    }\n    return toyc_t3;\n"));
+    }
+}
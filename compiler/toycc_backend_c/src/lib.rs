@@ -0,0 +1,3 @@
+pub mod c_generator;
+
+pub use c_generator::CGenerator;
@@ -0,0 +1,91 @@
+//! Compiles generated programs with `cc`, under the undefined behavior
+//! sanitizer where available, and compares their output with the
+//! tree-walking interpreter. The tests pass vacuously without `cc`.
+
+use std::path::Path;
+use std::process::Command;
+use toycc_backend_c::CGenerator;
+use toycc_test_support::run_program;
+
+/// Compiles `source` to `binary`, under the sanitizer when `cc` supports it
+fn compile(source: &Path, binary: &Path) {
+    let sanitizer = ["-fsanitize=undefined", "-fno-sanitize-recover"];
+    for flags in [&sanitizer[..], &[]] {
+        let output = Command::new("cc")
+            .args(["-std=c99", "-Wall", "-o"])
+            .arg(binary)
+            .arg(source)
+            .args(flags)
+            .output()
+            .unwrap();
+        match output.status.success() {
+            true => return,
+            false if flags.is_empty() => panic!("{}", String::from_utf8_lossy(&output.stderr)),
+            false => {}
+        }
+    }
+}
+
+fn run_both(name: &str, source: &str, input: &str) -> Option<String> {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("cc not found, skipping {name}");
+        return None;
+    }
+    let (expected, _) = toycc_test_support::run_both(source, input, |program| {
+        let c = CGenerator::new("test.tc")
+            .generate(program)
+            .expect("failed to generate");
+        let base = std::env::temp_dir().join(format!("toycc-{}-{name}", std::process::id()));
+        let c_path = base.with_extension("c");
+        std::fs::write(&c_path, &c).unwrap();
+        compile(&c_path, &base);
+        let (output, status) = run_program(&mut Command::new(&base), input);
+        std::fs::remove_file(&c_path).unwrap();
+        std::fs::remove_file(&base).unwrap();
+        (c, output, Some(status))
+    });
+    Some(expected)
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    run_both("demo", source, "7 1");
+}
+
+#[test]
+fn test_evaluation_order() {
+    let source = "int g;
+int bump(int by){ g = g + by; write(\"bump\", by, \" \"); return g; }
+int pair(int a, int b){ return a * 10 + b; }
+int main(){
+    int a;
+    write(pair(bump(1), bump(2)), \" \", (a = 5) + a, \"\\n\");
+    write(g, bump(3), g, \"\\n\");
+    if (g && pair(bump(1), bump(0))) write(\"yes\\n\");
+    while (pair(bump(1), g) < 800) write(\".\");
+    newline;
+    return g;
+}";
+    run_both("order", source, "");
+}
+
+#[test]
+fn test_overflow_and_division() {
+    let source = "int main(){
+    int a;
+    int m;
+    m = -1;
+    a = 2147483647;
+    write(a + 1, \" \", -a - 2, \" \", a * 2, \"\\n\");
+    a = -2147483647 - 1;
+    write(a / m, \" \", a % m, \" \", -a, \" \", 7 / m, \" \", -7 % 2, \"\\n\");
+    return 0;
+}";
+    if let Some(output) = run_both("overflow", source, "") {
+        assert_eq!(
+            output,
+            "-2147483648 2147483647 -2\n-2147483648 0 -2147483648 -7 -1\n"
+        );
+    }
+}
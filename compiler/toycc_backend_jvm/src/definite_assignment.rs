@@ -0,0 +1,375 @@
+use crate::error::{Note, UninitializedVariable};
+use std::collections::HashMap;
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, Operator, Program, Span, Statement, StatementKind,
+    VarDef,
+};
+
+/// Locations explaining how control reaches a read without assigning the
+/// variable, starting at its declaration
+type Path = Vec<(Span, String)>;
+
+/// Locals that may be unassigned at a point of a function, by declaration,
+/// with a path on which they are. `None` where control cannot reach.
+type State = Option<HashMap<usize, Path>>;
+
+/// Finds reads of local variables that are not assigned on every path
+/// leading to them, which the JVM verifier rejects. Paths through code a
+/// constant condition rules out are not taken, and only the first read of
+/// each variable is reported.
+pub fn find_uninitialized_reads(
+    program: &Program,
+    file_name: &str,
+    source: &str,
+) -> Vec<UninitializedVariable> {
+    let mut analysis = Analysis::default();
+    for definition in &program.definitions {
+        if let Definition::FuncDef(func_def) = definition {
+            analysis.scopes = vec![HashMap::new()];
+            let mut state = Some(HashMap::new());
+            for param in &func_def.var_def {
+                analysis.declare(param, &mut None);
+            }
+            analysis.statement(&func_def.statement, &mut state);
+        }
+    }
+    let line = |span: Span| source.lines().nth(span.line() - 1).map(str::to_owned);
+    analysis
+        .reads
+        .into_iter()
+        .map(|(name, span, path)| {
+            let definite = path.len() == 1;
+            let notes = path.into_iter().rev().fold(None, |next, (span, message)| {
+                let note = Note::new(message, span, line(span), file_name.to_owned(), next);
+                Some(Box::new(note))
+            });
+            let line = line(span);
+            UninitializedVariable::new(name, definite, span, line, file_name.to_owned(), notes)
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Analysis<'a> {
+    /// Declarations visible in each enclosing block, innermost last
+    scopes: Vec<HashMap<&'a str, usize>>,
+    declarations: usize,
+    /// States leaving each enclosing loop through a `break`
+    breaks: Vec<Vec<State>>,
+    reads: Vec<(String, Span, Path)>,
+}
+
+impl<'a> Analysis<'a> {
+    /// Brings the variables of a declaration into scope, unassigned in
+    /// `state`. Parameters are declared with no state, as they always hold
+    /// an argument.
+    fn declare(&mut self, var_def: &'a VarDef, state: &mut State) {
+        for id in &var_def.identifiers {
+            let declaration = self.declarations;
+            self.declarations += 1;
+            self.scopes.last_mut().unwrap().insert(id, declaration);
+            if let Some(state) = state {
+                let note = format!("'{id}' is declared here without a value");
+                state.insert(declaration, vec![(var_def.span, note)]);
+            }
+        }
+    }
+
+    /// Declaration a name refers to, or `None` for a global
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rev().find_map(|s| s.get(name)).copied()
+    }
+
+    fn assign(&self, name: &str, state: &mut State) {
+        if let (Some(declaration), Some(state)) = (self.lookup(name), state) {
+            state.remove(&declaration);
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Statement, state: &mut State) {
+        if state.is_none() {
+            return;
+        }
+        match &statement.kind {
+            StatementKind::Expression(expr) => self.expression(expr, state),
+            StatementKind::Break => {
+                let mut exit = state.take();
+                note(&mut exit, statement.span, "leaving the loop here");
+                if let Some(breaks) = self.breaks.last_mut() {
+                    breaks.push(exit);
+                }
+            }
+            StatementKind::BlockState(var_defs, statements) => {
+                self.scopes.push(HashMap::new());
+                for var_def in var_defs {
+                    self.declare(var_def, state);
+                }
+                for statement in statements {
+                    self.statement(statement, state);
+                }
+                let scope = self.scopes.pop().unwrap();
+                if let Some(state) = state {
+                    state.retain(|declaration, _| !scope.values().any(|d| d == declaration));
+                }
+            }
+            StatementKind::IfState(expr, then_stmt, else_stmt) => {
+                let (mut then_state, mut else_state) = self.condition(expr, state);
+                if expr.constant_value().is_none() {
+                    note(
+                        &mut then_state,
+                        statement.span,
+                        "assuming the condition is true",
+                    );
+                    note(
+                        &mut else_state,
+                        statement.span,
+                        "assuming the condition is false",
+                    );
+                }
+                self.statement(then_stmt, &mut then_state);
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    self.statement(else_stmt, &mut else_state);
+                }
+                *state = join(state, [then_state, else_state]);
+            }
+            StatementKind::WhileState(expr, body) => {
+                let (mut body_state, mut skipped) = self.condition(expr, state);
+                if expr.constant_value().is_none() {
+                    note(
+                        &mut skipped,
+                        statement.span,
+                        "assuming the loop body does not run",
+                    );
+                }
+                self.breaks.push(vec![]);
+                self.statement(body, &mut body_state);
+                let breaks = self.breaks.pop().unwrap();
+                // Assignments only ever shrink a state, so going around the
+                // loop again cannot add variables to the one it started with.
+                // A loop whose condition is a constant is only left by a break.
+                let looped = body_state.filter(|_| expr.constant_value().is_none());
+                let exits = [skipped, looped].into_iter().chain(breaks);
+                *state = join(state, exits);
+            }
+            StatementKind::ReturnState(expr) => {
+                if let Some(expr) = expr {
+                    self.expression(expr, state);
+                }
+                *state = None;
+            }
+            StatementKind::ReadState(name, others) => {
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    self.assign(name, state);
+                }
+            }
+            StatementKind::WriteState(expr, others) => {
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    self.expression(expr, state);
+                }
+            }
+            StatementKind::NullState | StatementKind::NewLineState => {}
+        }
+    }
+
+    fn expression(&mut self, expression: &'a Expression, state: &mut State) {
+        match &expression.kind {
+            ExpressionKind::Identifier(id) => {
+                let declaration = self.lookup(id);
+                if let (Some(declaration), Some(state)) = (declaration, state.as_mut()) {
+                    // Reported once, as the first read explains the others
+                    if let Some(path) = state.remove(&declaration) {
+                        self.reads.push((id.clone(), expression.span, path));
+                    }
+                }
+            }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                self.expression(rhs, state);
+                if let ExpressionKind::Identifier(id) = &lhs.kind {
+                    self.assign(id, state);
+                }
+            }
+            ExpressionKind::Expr(Operator::And | Operator::Or, ..) => {
+                let (when_true, when_false) = self.condition(expression, state);
+                *state = join(state, [when_true, when_false]);
+            }
+            ExpressionKind::Expr(_, lhs, rhs) => {
+                self.expression(lhs, state);
+                self.expression(rhs, state);
+            }
+            ExpressionKind::FuncCall(_, arguments) => {
+                for argument in arguments {
+                    self.expression(argument, state);
+                }
+            }
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.expression(expr, state),
+            ExpressionKind::Number(_)
+            | ExpressionKind::CharLiteral(_)
+            | ExpressionKind::StringLiteral(_) => {}
+        }
+    }
+
+    /// States after a condition when it is true and when it is false, as in
+    /// chapter 16 of the Java Language Specification: `a && b` is true when
+    /// `b` is, and false when `a` is or when `b` is after `a` was true. A
+    /// constant condition is never false, or never true.
+    fn condition(&mut self, expression: &'a Expression, state: &State) -> (State, State) {
+        match &expression.kind {
+            ExpressionKind::Expr(op @ Operator::And, lhs, rhs) => {
+                let (lhs_true, mut lhs_false) = self.condition(lhs, state);
+                let message = format!("assuming '{op}' does not evaluate its right operand");
+                note(&mut lhs_false, expression.span, &message);
+                let (rhs_true, rhs_false) = self.condition(rhs, &lhs_true);
+                (rhs_true, join(state, [lhs_false, rhs_false]))
+            }
+            ExpressionKind::Expr(op @ Operator::Or, lhs, rhs) => {
+                let (mut lhs_true, lhs_false) = self.condition(lhs, state);
+                let message = format!("assuming '{op}' does not evaluate its right operand");
+                note(&mut lhs_true, expression.span, &message);
+                let (rhs_true, rhs_false) = self.condition(rhs, &lhs_false);
+                (join(state, [lhs_true, rhs_true]), rhs_false)
+            }
+            ExpressionKind::Not(expr) => {
+                let (when_true, when_false) = self.condition(expr, state);
+                (when_false, when_true)
+            }
+            _ => {
+                let mut state = state.clone();
+                self.expression(expression, &mut state);
+                match expression.constant_value() {
+                    Some(0) => (None, state),
+                    Some(_) => (state, None),
+                    None => (state.clone(), state),
+                }
+            }
+        }
+    }
+}
+
+/// Extends the path of every variable still unassigned in `state`
+fn note(state: &mut State, span: Span, message: &str) {
+    for path in state.iter_mut().flat_map(|state| state.values_mut()) {
+        path.push((span, message.to_owned()));
+    }
+}
+
+/// State where the paths leaving `before` through `branches` meet. A
+/// variable unassigned on every branch keeps the path it had before them,
+/// as the choice of branch does not matter for it.
+fn join(before: &State, branches: impl IntoIterator<Item = State>) -> State {
+    let branches = branches.into_iter().flatten().collect::<Vec<_>>();
+    if branches.is_empty() {
+        return None;
+    }
+    let mut joined = HashMap::new();
+    for (declaration, path) in before.iter().flatten() {
+        let mut unassigned = branches.iter().filter_map(|b| b.get(declaration));
+        let Some(branch_path) = unassigned.next() else {
+            continue;
+        };
+        let path = match 1 + unassigned.count() == branches.len() {
+            true => path,
+            false => branch_path,
+        };
+        joined.insert(*declaration, path.clone());
+    }
+    Some(joined)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_report::Diagnostic;
+
+    type Location = (usize, usize);
+
+    fn analyze(source: &str) -> Vec<(String, Location, Vec<Location>)> {
+        let program = toycc_frontend::Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        find_uninitialized_reads(&program, "test.tc", source)
+            .iter()
+            .map(|error| {
+                let notes = error.notes().map(|note| note.span.location).collect();
+                (error.info(), error.span.location, notes)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_definite_assignment() {
+        assert_eq!(
+            analyze("int main(){ int a; write(a); write(a); return 0; }"),
+            vec![(
+                "'a' is used uninitialized".to_owned(),
+                (1, 26),
+                vec![(1, 17)]
+            )]
+        );
+        assert_eq!(
+            analyze(
+                "int main(int c){ int a; int b;
+  if (c) a = 1; else { a = 2; b = 3; }
+  write(a, b);
+  return 0;
+}"
+            ),
+            vec![(
+                "'b' may be used uninitialized".to_owned(),
+                (3, 12),
+                vec![(1, 29), (2, 3)]
+            )]
+        );
+        assert_eq!(
+            analyze(
+                "int main(){ int i; int s;
+  read(i);
+  while (i > 0) { s = i; i = i - 1; }
+  return s;
+}"
+            ),
+            vec![(
+                "'s' may be used uninitialized".to_owned(),
+                (4, 10),
+                vec![(1, 24), (3, 3)]
+            )]
+        );
+        // Assigned on every path: parameters, both branches, before a
+        // return, in a loop left only through a break, in the left operand
+        // of `&&`, and in both operands when it is true
+        assert!(analyze(
+            "int f(int n){ int a; int b; int c; int d; int e;
+  if (n) a = 1; else a = 2;
+  if (n) return 0; else b = a;
+  while (1) { c = b; break; }
+  if ((d = c) && (e = 1)) write(d, e);
+  if (0) write(e);
+  return n + c;
+}
+int main(){ return f(1); }"
+        )
+        .is_empty());
+        // The branches of a condition see what it assigns when it is true
+        // or false, and `!` swaps the two
+        assert!(analyze(
+            "int main(){ int b; int c; int d; int e;
+  if (((b = 1) > 0) && ((c = 2) > 0)) write(c);
+  read(d);
+  if (!((d > 0) || ((e = 1) > 0))) write(e);
+  while ((d > 0) && ((e = d) > 1)) d = e - 1;
+  return b;
+}"
+        )
+        .is_empty());
+        assert_eq!(
+            analyze(
+                "int main(){ int a; int b; read(a); if ((a > 0) || (b = 1)) write(b); return 0; }"
+            ),
+            vec![(
+                "'b' may be used uninitialized".to_owned(),
+                (1, 66),
+                vec![(1, 24), (1, 48), (1, 36)]
+            )]
+        );
+    }
+}
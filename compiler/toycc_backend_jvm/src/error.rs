@@ -1,4 +1,5 @@
-use toycc_report::{Diagnostic, ErrorKind, Report, ReportLevel};
+use toycc_frontend::ast::Span;
+use toycc_report::{Diagnostic, ErrorKind, NoteKind, Report, ReportLevel, WarningKind};
 
 #[derive(Report, Debug)]
 pub struct SemanticError {
@@ -22,6 +23,7 @@ pub enum SemanticErrorKind {
     ExpectedFunction,
     ExpectedIdentifier,
     MissingReturn,
+    BreakOutsideLoop,
 }
 
 impl Diagnostic for SemanticError {
@@ -41,6 +43,7 @@ impl Diagnostic for SemanticError {
             SemanticErrorKind::ExpectedFunction => "expected function declaration".to_owned(),
             SemanticErrorKind::ExpectedIdentifier => "expected identifier".to_owned(),
             SemanticErrorKind::MissingReturn => "missing return".to_owned(),
+            SemanticErrorKind::BreakOutsideLoop => "break statement not within loop".to_owned(),
         }
     }
 
@@ -56,3 +59,140 @@ impl Diagnostic for SemanticError {
         None
     }
 }
+
+/// A read of a local variable that is not assigned on every path reaching
+/// it. An error unless turned into a warning with
+/// [`UninitializedVariable::as_warning`].
+#[derive(Report, Debug)]
+pub struct UninitializedVariable {
+    pub name: String,
+    /// Whether the variable is unassigned on every path to the read
+    pub definite: bool,
+    pub span: Span,
+    line: Option<String>,
+    stream_name: String,
+    notes: Option<Box<Note>>,
+    error: bool,
+}
+
+impl UninitializedVariable {
+    pub fn new(
+        name: String,
+        definite: bool,
+        span: Span,
+        line: Option<String>,
+        stream_name: String,
+        notes: Option<Box<Note>>,
+    ) -> Self {
+        Self {
+            name,
+            definite,
+            span,
+            line,
+            stream_name,
+            notes,
+            error: true,
+        }
+    }
+
+    pub fn as_warning(mut self) -> Self {
+        self.error = false;
+        self
+    }
+
+    /// Steps of the path on which the variable is not assigned
+    pub fn notes(&self) -> impl Iterator<Item = &Note> {
+        std::iter::successors(self.notes.as_deref(), |note| note.next.as_deref())
+    }
+}
+
+impl Diagnostic for UninitializedVariable {
+    fn info(&self) -> String {
+        match self.definite {
+            true => format!("'{}' is used uninitialized", self.name),
+            false => format!("'{}' may be used uninitialized", self.name),
+        }
+    }
+
+    fn level(&self) -> ReportLevel {
+        let (file_name, pos, len, source) = (
+            self.stream_name.clone(),
+            self.span.location,
+            self.span.len,
+            self.line.clone(),
+        );
+        match self.error {
+            true => ReportLevel::Error(ErrorKind::ParsingError {
+                file_name,
+                pos,
+                len,
+                source,
+            }),
+            false => ReportLevel::Warning(WarningKind::ParsingWarning {
+                file_name,
+                pos,
+                len,
+                source,
+            }),
+        }
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        self.notes.as_deref().map(|note| note as &dyn Report)
+    }
+}
+
+/// A location explaining part of another diagnostic, followed by the next
+#[derive(Report, Debug)]
+pub struct Note {
+    message: String,
+    pub span: Span,
+    line: Option<String>,
+    stream_name: String,
+    next: Option<Box<Note>>,
+}
+
+impl Note {
+    pub fn new(
+        message: String,
+        span: Span,
+        line: Option<String>,
+        stream_name: String,
+        next: Option<Box<Note>>,
+    ) -> Self {
+        Self {
+            message,
+            span,
+            line,
+            stream_name,
+            next,
+        }
+    }
+}
+
+impl Diagnostic for Note {
+    fn info(&self) -> String {
+        self.message.clone()
+    }
+
+    fn level(&self) -> ReportLevel {
+        ReportLevel::Note(NoteKind::ParsingNote {
+            file_name: self.stream_name.clone(),
+            pos: self.span.location,
+            len: self.span.len,
+            source: self.line.clone(),
+        })
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        self.next.as_deref().map(|note| note as &dyn Report)
+    }
+}
@@ -1,3 +1,9 @@
+pub mod definite_assignment;
 mod error;
+pub mod peephole;
 pub mod semantic_analyzer;
-mod symbol_table;
+pub mod symbol_table;
+mod warning;
+
+pub use error::{Note, SemanticError, SemanticErrorKind, UninitializedVariable};
+pub use warning::{ShadowedVariable, UnusedKind, UnusedSymbol};
@@ -0,0 +1,248 @@
+//! Peephole optimization of the Jasmin emitted for a method body. The body
+//! is a list of instructions, `label:` lines and `.line` directives, and is
+//! rewritten until no pattern applies.
+use std::collections::HashMap;
+
+/// Rewrites a method body in place:
+///
+/// - jumps to an unconditional `goto` go straight to its target
+/// - instructions after a `goto` or a return are dropped up to the next label
+/// - a `goto` to a label that directly follows it is dropped
+/// - a conditional jump over a `goto` becomes the opposite jump
+/// - `istore n; iload n` becomes `dup; istore n`
+/// - a comparison with 0 uses the single-operand `if<cond>` form
+/// - comparing a materialized boolean with 1 branches on the boolean itself
+pub fn optimize(body: &mut Vec<String>) {
+    while thread_jumps(body)
+        | remove_dead_code(body)
+        | remove_redundant_gotos(body)
+        | invert_branches(body)
+        | forward_stores(body)
+        | simplify_comparisons(body)
+    {}
+}
+
+/// Number of JVM instructions in a body, leaving out labels and directives
+pub fn instruction_count(body: &[String]) -> usize {
+    body.iter().filter(|s| is_instruction(s)).count()
+}
+
+fn is_label(line: &str) -> bool {
+    line.ends_with(':')
+}
+
+fn is_instruction(line: &str) -> bool {
+    !is_label(line) && !line.starts_with('.')
+}
+
+fn split(line: &str) -> (&str, &str) {
+    line.split_once(' ').unwrap_or((line, ""))
+}
+
+/// Label a jump instruction branches to
+fn jump_target(line: &str) -> Option<&str> {
+    match split(line) {
+        ("goto", target) => Some(target),
+        (op, target) if op.starts_with("if") => Some(target),
+        _ => None,
+    }
+}
+
+/// Index of the next instruction from `start` on
+fn next_instruction(body: &[String], start: usize) -> Option<usize> {
+    (start..body.len()).find(|&i| is_instruction(&body[i]))
+}
+
+fn thread_jumps(body: &mut [String]) -> bool {
+    let mut gotos = HashMap::new();
+    for (index, line) in body.iter().enumerate() {
+        if let Some(label) = line.strip_suffix(':') {
+            let next = next_instruction(body, index + 1).map(|i| &body[i]);
+            if let Some(("goto", target)) = next.map(|s| split(s)) {
+                gotos.insert(label.to_owned(), target.to_owned());
+            }
+        }
+    }
+    let mut changed = false;
+    for line in body.iter_mut() {
+        let Some(mut target) = jump_target(line).map(str::to_owned) else {
+            continue;
+        };
+        // A chain of gotos may loop back on itself
+        let mut steps = 0;
+        while let Some(next) = gotos.get(&target).filter(|_| steps < gotos.len()) {
+            target = next.clone();
+            steps += 1;
+        }
+        let threaded = format!("{} {target}", split(line).0);
+        if *line != threaded {
+            *line = threaded;
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn remove_dead_code(body: &mut Vec<String>) -> bool {
+    let mut reachable = true;
+    let before = body.len();
+    body.retain(|line| {
+        if is_label(line) {
+            reachable = true;
+        } else if is_instruction(line) {
+            let keep = reachable;
+            reachable &= !matches!(split(line).0, "goto" | "return" | "ireturn");
+            return keep;
+        }
+        true
+    });
+    body.len() != before
+}
+
+fn remove_redundant_gotos(body: &mut Vec<String>) -> bool {
+    let redundant = (0..body.len()).filter(|&index| match split(&body[index]) {
+        ("goto", target) => body[index + 1..]
+            .iter()
+            .take_while(|line| !is_instruction(line))
+            .any(|line| line.strip_suffix(':') == Some(target)),
+        _ => false,
+    });
+    let redundant = redundant.collect::<Vec<_>>();
+    for &index in redundant.iter().rev() {
+        body.remove(index);
+    }
+    !redundant.is_empty()
+}
+
+fn invert_branches(body: &mut Vec<String>) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index + 2 < body.len() {
+        let (op, over) = split(&body[index]);
+        let inverted = op.strip_prefix("if").and_then(|condition| {
+            let (prefix, condition) = condition.split_at(condition.len().saturating_sub(2));
+            Some(format!("if{prefix}{}", inverse(condition)?))
+        });
+        if let (Some(inverted), ("goto", target)) = (inverted, split(&body[index + 1])) {
+            if body[index + 2].strip_suffix(':') == Some(over) {
+                body[index] = format!("{inverted} {target}");
+                body.remove(index + 1);
+                changed = true;
+            }
+        }
+        index += 1;
+    }
+    changed
+}
+
+fn inverse(condition: &str) -> Option<&'static str> {
+    Some(match condition {
+        "eq" => "ne",
+        "ne" => "eq",
+        "lt" => "ge",
+        "ge" => "lt",
+        "gt" => "le",
+        "le" => "gt",
+        _ => return None,
+    })
+}
+
+fn forward_stores(body: &mut Vec<String>) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index < body.len() {
+        if let ("istore", slot) = split(&body[index]) {
+            let next = (index + 1..body.len()).find(|&i| !body[i].starts_with('.'));
+            if let Some(next) = next.filter(|&i| split(&body[i]) == ("iload", slot)) {
+                body.remove(next);
+                body.insert(index, "dup".to_owned());
+                changed = true;
+            }
+        }
+        index += 1;
+    }
+    changed
+}
+
+fn simplify_comparisons(body: &mut Vec<String>) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index + 1 < body.len() {
+        let (op, target) = split(&body[index + 1]);
+        let condition = op.strip_prefix("if_icmp");
+        let replacement = match (body[index].as_str(), condition) {
+            ("iconst_0", Some(condition)) => Some(format!("if{condition} {target}")),
+            ("iconst_1", Some("eq")) if is_boolean(body, index) => Some(format!("ifne {target}")),
+            ("iconst_1", Some("ne")) if is_boolean(body, index) => Some(format!("ifeq {target}")),
+            _ => None,
+        };
+        if let Some(replacement) = replacement {
+            body[index] = replacement;
+            body.remove(index + 1);
+            changed = true;
+        }
+        index += 1;
+    }
+    changed
+}
+
+/// Whether the value on the stack before `body[index]` is 0 or 1, as left
+/// by the `iconst_0; goto E; T: iconst_1; E:` sequence materializing a
+/// condition, with nothing else jumping to `E`
+fn is_boolean(body: &[String], index: usize) -> bool {
+    if index < 5 {
+        return false;
+    }
+    let [zero, goto, _, one, end] = &body[index - 5..index] else {
+        unreachable!()
+    };
+    let Some(label) = end.strip_suffix(':') else {
+        return false;
+    };
+    let jumps = body.iter().filter(|s| jump_target(s) == Some(label));
+    zero == "iconst_0"
+        && split(goto) == ("goto", label)
+        && is_label(&body[index - 3])
+        && one == "iconst_1"
+        && jumps.count() == 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn optimized(body: &str) -> Vec<String> {
+        let mut body = body.lines().map(str::to_owned).collect();
+        optimize(&mut body);
+        body
+    }
+
+    #[test]
+    fn test_jumps() {
+        let body = optimized("goto A\niconst_1\nA:\ngoto B\nB:\n.line 3\nC:\niconst_2\nireturn");
+        assert_eq!(
+            body,
+            vec!["A:", "B:", ".line 3", "C:", "iconst_2", "ireturn"]
+        );
+
+        let body = optimized("ifne A\ngoto C\nA:\ngoto B\nC:\niconst_0\nB:\niconst_1\nireturn");
+        assert_eq!(body[0], "ifne B");
+        let body = optimized("if_icmple A\ngoto B\nA:\niconst_0\nB:\niconst_1\nireturn");
+        assert_eq!(body[..2], ["if_icmpgt B", "A:"]);
+        // A loop of gotos stays a loop
+        let body = optimized("A:\ngoto B\nB:\ngoto A");
+        assert_eq!(body, vec!["A:", "B:", "goto A"]);
+    }
+
+    #[test]
+    fn test_stores_and_comparisons() {
+        let body = optimized("istore 1\n.line 2\niload 1\niconst_0\nif_icmpgt A\nA:");
+        assert_eq!(body, vec!["dup", "istore 1", ".line 2", "ifgt A", "A:"]);
+
+        let boolean = "iload 0\nifne T\niconst_0\ngoto E\nT:\niconst_1\nE:\niconst_1\nif_icmpeq A";
+        assert_eq!(optimized(boolean).last().unwrap(), "ifne A");
+        // Another jump to `E` may bring any value
+        let body = optimized(&format!("ifeq E\n{boolean}"));
+        assert_eq!(body.last().unwrap(), "if_icmpeq A");
+    }
+}
@@ -1,10 +1,16 @@
 use crate::error::{SemanticError, SemanticErrorKind};
-use crate::symbol_table::{Function, Symbol, SymbolTable};
+use crate::peephole;
+use crate::symbol_table::{Function, Scope, ScopeKind, Symbol, SymbolKind, SymbolTable};
+use crate::warning::{ShadowedVariable, UnusedKind, UnusedSymbol};
 use itertools::Itertools;
-use toycc_frontend::ast::{Definition, Expression, FuncDef, Operator, Program, Statement, VarDef};
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Span, Statement,
+    StatementKind, VarDef,
+};
 use toycc_frontend::Type;
 
-const CLASS_INIT_HEADER: &str = r#"
+/// The default constructor every generated class starts with
+pub const CLASS_INIT_HEADER: &str = r#"
 .method <init>()V
     aload_0
     invokespecial java/lang/Object/<init>()V
@@ -16,26 +22,142 @@ const CLASS_INIT_HEADER: &str = r#"
 #[derive(Default)]
 pub struct SemanticAnalyzer<'a> {
     class_name: &'a str,
+    source_name: &'a str,
+    /// The global scope, then the scopes of the function and the blocks
+    /// around the code being analyzed, innermost last
     symbol_table: Vec<SymbolTable<'a>>,
     conditional_count: usize,
+    loop_labels: Vec<String>,
     dump_sym: bool,
+    /// Next free local variable slot in each scope
     scope_symbols: Vec<usize>,
+    /// Scopes closed inside each open one
+    closed_scopes: Vec<Vec<Scope>>,
+    scope_count: usize,
+    scope_labels: Vec<usize>,
+    local_vars: Vec<String>,
+    peephole: bool,
+    peephole_counts: (usize, usize),
+    tail_calls: bool,
+    tail_call_count: usize,
+    dead_code: bool,
+    function: Option<(&'a FuncDef, usize)>,
+    unused: Vec<(UnusedKind, String, Span)>,
+    shadowed: Vec<(String, Span, Option<Span>)>,
+    initialize_locals: bool,
 }
 
 impl<'a> SemanticAnalyzer<'a> {
-    pub fn new(class_name: &'a str, dump_sym: bool) -> Self {
+    pub fn new(class_name: &'a str, source_name: &'a str, dump_sym: bool) -> Self {
         Self {
             dump_sym,
             conditional_count: 0,
+            loop_labels: vec![],
             class_name,
+            source_name,
             symbol_table: vec![SymbolTable::default(); 1],
             scope_symbols: vec![0],
+            closed_scopes: vec![vec![]],
+            scope_count: 0,
+            scope_labels: vec![],
+            local_vars: vec![],
+            peephole: false,
+            peephole_counts: (0, 0),
+            tail_calls: false,
+            tail_call_count: 0,
+            dead_code: false,
+            function: None,
+            unused: vec![],
+            shadowed: vec![],
+            initialize_locals: false,
         }
     }
+
+    /// Runs the peephole optimizer over every method body
+    pub fn peephole(&mut self, enabled: bool) -> &mut Self {
+        self.peephole = enabled;
+        self
+    }
+
+    /// Instructions in the method bodies before and after the peephole
+    /// optimizer ran
+    pub fn peephole_counts(&self) -> (usize, usize) {
+        self.peephole_counts
+    }
+
+    /// Turns `return f(...)` inside `f` into stores to the parameters and a
+    /// jump to the start of the method
+    pub fn tail_calls(&mut self, enabled: bool) -> &mut Self {
+        self.tail_calls = enabled;
+        self
+    }
+
+    /// Number of self tail calls turned into jumps
+    pub fn tail_call_count(&self) -> usize {
+        self.tail_call_count
+    }
+
+    /// Leaves out statements control never reaches and the branches of
+    /// constant conditions that are never taken
+    pub fn dead_code(&mut self, enabled: bool) -> &mut Self {
+        self.dead_code = enabled;
+        self
+    }
+
+    /// Stores zero into every local variable where it is declared, so that
+    /// reads the definite-assignment analysis let through pass verification
+    pub fn initialize_locals(&mut self, enabled: bool) -> &mut Self {
+        self.initialize_locals = enabled;
+        self
+    }
+
+    /// Declarations the analyzed program never uses, in source order
+    pub fn unused_symbols(&self, file_name: &str, source: &str) -> Vec<UnusedSymbol> {
+        self.unused
+            .iter()
+            .map(|(kind, name, span)| {
+                let line = source.lines().nth(span.line() - 1).map(str::to_owned);
+                UnusedSymbol::new(*kind, name.clone(), *span, line, file_name.to_owned())
+            })
+            .collect()
+    }
+
+    /// Every scope of the analyzed program that declares variables, nested
+    /// in the global one, with symbols under their names in the source
+    pub fn symbol_scopes(&self) -> Scope {
+        let scopes = self.closed_scopes[0].clone();
+        let mut global = self.symbol_table[0].scope(ScopeKind::Global, None, scopes);
+        for symbol in &mut global.symbols {
+            if symbol.name == "toyc_main" {
+                symbol.name = "main".to_owned();
+            }
+        }
+        global
+    }
+
+    /// Declarations hiding a variable or function of an enclosing scope, in
+    /// source order
+    pub fn shadowed_symbols(&self, file_name: &str, source: &str) -> Vec<ShadowedVariable> {
+        let line = |span: Span| source.lines().nth(span.line() - 1).map(str::to_owned);
+        self.shadowed
+            .iter()
+            .map(|(name, span, shadowed)| {
+                let shadowed = shadowed.map(|s| (s, line(s)));
+                ShadowedVariable::new(
+                    name.clone(),
+                    *span,
+                    line(*span),
+                    file_name.to_owned(),
+                    shadowed,
+                )
+            })
+            .collect()
+    }
+
     pub fn analyze_program(&mut self, program: &'a Program) -> Result<String, Box<SemanticError>> {
         let mut jasmin_program = format!(
-            ".class public {}\n.super java/lang/Object{}\n",
-            self.class_name, CLASS_INIT_HEADER
+            ".source {}\n.class public {}\n.super java/lang/Object{}\n",
+            self.source_name, self.class_name, CLASS_INIT_HEADER
         );
 
         let has_main = program.definitions.iter().any(|def| match def {
@@ -63,6 +185,15 @@ impl<'a> SemanticAnalyzer<'a> {
             self.class_name
         )
         .as_str();
+        let globals = self.symbol_table.last().unwrap().unused();
+        for (name, symbol, span) in globals.filter(|(name, ..)| *name != "toyc_main") {
+            let kind = match symbol {
+                Symbol::Function(_) => UnusedKind::Function,
+                Symbol::Variable(..) => UnusedKind::Global,
+            };
+            self.unused.push((kind, name.to_owned(), span));
+        }
+        self.unused.sort_by_key(|(_, _, span)| span.location);
         if self.dump_sym {
             println!("{}", self.symbol_table.iter().next_back().unwrap());
         }
@@ -88,12 +219,6 @@ impl<'a> SemanticAnalyzer<'a> {
             Type::Int => "I",
             Type::Char => "C",
         };
-        self.push_scope();
-
-        for var_def in &func_def.var_def {
-            self.analyze_var_def(var_def)?;
-        }
-
         let args: Vec<_> = func_def
             .var_def
             .iter()
@@ -107,54 +232,62 @@ impl<'a> SemanticAnalyzer<'a> {
             "main" => "toyc_main",
             s => s,
         };
-        let mut body = self.analyze_statement(&func_def.statement)?;
-
-        self.pop_scope();
+        // Declared before its body, so that the function may call itself
         let function = Function::new(
             function_name.to_string(),
             args.clone(),
-            body.clone(),
+            vec![],
             func_def.toyc_type.clone(),
         );
-        let expected_return_type = match function.return_type {
+        self.declare_symbol(function_name, Symbol::Function(function), func_def.span)?;
+
+        self.push_scope(0);
+        let scope = self.open_scope_labels();
+        for var_def in &func_def.var_def {
+            self.analyze_var_def(var_def)?;
+        }
+        self.function = Some((func_def, scope));
+
+        // The body's declarations share the function's label range
+        let mut body = match &func_def.statement.kind {
+            StatementKind::BlockState(var_defs, statements) => {
+                self.analyze_block(var_defs, statements)?
+            }
+            _ => self.analyze_statement(&func_def.statement)?,
+        };
+
+        let table = self.pop_scope(ScopeKind::Function, func_def.span);
+        self.scope_labels.pop();
+        self.function = None;
+        let params = func_def.var_def.iter().flat_map(|v| &v.identifiers);
+        let params = params.collect::<Vec<_>>();
+        let closed = self.closed_scopes.last_mut().unwrap().last_mut().unwrap();
+        closed.name = Some(func_def.identifier.clone());
+        for symbol in &mut closed.symbols {
+            if params.contains(&&symbol.name) {
+                symbol.kind = SymbolKind::Parameter;
+            }
+        }
+        for (name, _, span) in table.unused() {
+            let kind = match params.iter().any(|param| *param == name) {
+                true => UnusedKind::Parameter,
+                false => UnusedKind::Local,
+            };
+            self.unused.push((kind, name.to_owned(), span));
+        }
+        let expected_return_type = match func_def.toyc_type {
             Type::Int => "I",
             Type::Char => "C",
         };
 
-        let actual_return_type = match body.last() {
-            Some(return_type) => match return_type.as_str() {
-                "ireturn" => "I",
-                "return" => "V",
-                s if s.contains(':') => {
-                    body.push("nop".to_string());
-                    match body.iter().nth_back(2) {
-                        None => {
-                            return Err(Box::new(SemanticError::new(
-                                SemanticErrorKind::MissingReturn,
-                            )))
-                        }
-                        Some(return_type) => match return_type.as_str() {
-                            "ireturn" => "I",
-                            "return" => "V",
-                            _ => {
-                                return Err(Box::new(SemanticError::new(
-                                    SemanticErrorKind::MissingReturn,
-                                )))
-                            }
-                        },
-                    }
-                }
-                _ => {
-                    return Err(Box::new(SemanticError::new(
-                        SemanticErrorKind::MissingReturn,
-                    )))
-                }
-            },
-            None => {
-                return Err(Box::new(SemanticError::new(
-                    SemanticErrorKind::MissingReturn,
-                )))
-            }
+        if func_def.statement.completes() {
+            return Err(Box::new(SemanticError::new(
+                SemanticErrorKind::MissingReturn,
+            )));
+        }
+        let actual_return_type = match returns_without_value(&func_def.statement) {
+            true => "V",
+            false => "I",
         };
 
         if expected_return_type != actual_return_type {
@@ -166,36 +299,121 @@ impl<'a> SemanticAnalyzer<'a> {
             )));
         }
 
-        self.insert_symbol(function_name, Symbol::Function(function))?;
+        if let Some(Symbol::Function(function)) = self
+            .symbol_table
+            .last_mut()
+            .unwrap()
+            .find_mut(function_name)
+        {
+            function.body = body.clone();
+        }
 
+        if self.peephole {
+            self.peephole_counts.0 += peephole::instruction_count(&body);
+            peephole::optimize(&mut body);
+            self.peephole_counts.1 += peephole::instruction_count(&body);
+        }
+        body.insert(0, format!("VS{scope}:"));
+        body.push(format!("VE{scope}:"));
         body.iter_mut()
-            .filter(|f| !f.starts_with('.') && !f.ends_with(':'))
+            .filter(|f| !f.ends_with(':'))
             .for_each(|f| f.insert(0, '\t'));
+        let local_vars = self
+            .local_vars
+            .drain(..)
+            .map(|var| format!("\t{var}\n"))
+            .join("");
 
-        instructions.push(format!(".method public static {}({}){}\n\t.limit stack 1000\n\t.limit locals 1000\n{}\n.end method\n",
+        instructions.push(format!(".method public static {}({}){}\n\t.limit stack 1000\n\t.limit locals 1000\n{}{}\n.end method\n",
                               function_name,
                               args.join(""),
                               return_type,
+                              local_vars,
                               body.join("\n")));
 
         Ok(instructions)
     }
     fn analyze_var_def(&mut self, var_def: &'a VarDef) -> Result<Vec<String>, Box<SemanticError>> {
+        let mut instructions = vec![];
         for id in &var_def.identifiers {
-            let pos = self.scope_symbols.iter().next_back().unwrap();
-            self.insert_symbol(
+            let pos = *self.scope_symbols.iter().next_back().unwrap();
+            self.declare_symbol(
                 id.as_str(),
-                Symbol::Variable(id.to_owned(), var_def.toyc_type.clone(), *pos),
+                Symbol::Variable(id.to_owned(), var_def.toyc_type.clone(), pos),
+                var_def.span,
             )?;
+            if let Some(scope) = self.scope_labels.last() {
+                let descriptor = match var_def.toyc_type {
+                    Type::Int => "I",
+                    Type::Char => "C",
+                };
+                self.local_vars.push(format!(
+                    ".var {pos} is {id} {descriptor} from VS{scope} to VE{scope}"
+                ));
+                if self.initialize_locals {
+                    instructions.push("iconst_0".to_owned());
+                    instructions.push(format!("istore {pos}"));
+                }
+            }
         }
 
-        Ok(vec![])
+        Ok(instructions)
     }
 
-    fn push_scope(&mut self) {
-        self.scope_symbols.push(0);
-        self.symbol_table
-            .push(self.symbol_table.iter().next_back().unwrap().clone())
+    fn next_label(&mut self) -> usize {
+        self.conditional_count += 1;
+        self.conditional_count
+    }
+
+    fn open_scope_labels(&mut self) -> usize {
+        self.scope_count += 1;
+        self.scope_labels.push(self.scope_count);
+        self.scope_count
+    }
+
+    fn analyze_block(
+        &mut self,
+        var_defs: &'a [VarDef],
+        statements: &'a [Statement],
+    ) -> Result<Vec<String>, Box<SemanticError>> {
+        let mut instructions = var_defs
+            .iter()
+            .map(|a| self.analyze_var_def(a))
+            .collect::<Result<Vec<_>, Box<SemanticError>>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let mut reachable = true;
+        for statement in statements {
+            match reachable {
+                true => instructions.append(&mut self.analyze_statement(statement)?),
+                false => self.analyze_unreachable(statement)?,
+            }
+            reachable &= statement.completes() || !self.dead_code;
+        }
+        Ok(instructions)
+    }
+
+    /// Checks a statement control never reaches without emitting it
+    fn analyze_unreachable(&mut self, statement: &'a Statement) -> Result<(), Box<SemanticError>> {
+        let local_vars = self.local_vars.len();
+        let tail_call_count = self.tail_call_count;
+        self.analyze_statement(statement)?;
+        self.local_vars.truncate(local_vars);
+        self.tail_call_count = tail_call_count;
+        Ok(())
+    }
+
+    /// The value of a constant condition when dead branches are left out
+    fn constant(&self, expr: &Expression) -> Option<i32> {
+        expr.constant_value().filter(|_| self.dead_code)
+    }
+
+    /// Enters a scope whose variables take the slots from `first_slot` on
+    fn push_scope(&mut self, first_slot: usize) {
+        self.scope_symbols.push(first_slot);
+        self.symbol_table.push(SymbolTable::default());
+        self.closed_scopes.push(vec![]);
     }
 
     fn analyze_statement(
@@ -203,105 +421,125 @@ impl<'a> SemanticAnalyzer<'a> {
         statement: &'a Statement,
     ) -> Result<Vec<String>, Box<SemanticError>> {
         let mut instructions = vec![];
-        match statement {
-            Statement::Expression(expr) => instructions.append(&mut self.analyze_expression(expr)?),
-            Statement::Break => instructions.push(format!("goto CE{}", self.conditional_count - 1)),
-            Statement::BlockState(var_defs, statements) => {
-                instructions.append(
-                    &mut var_defs
-                        .iter()
-                        .map(|a| self.analyze_var_def(a))
-                        .collect::<Result<Vec<_>, Box<SemanticError>>>()?
-                        .iter()
-                        .flatten()
-                        .map(Clone::clone)
-                        .collect::<Vec<_>>(),
-                );
-                instructions.append(
-                    &mut statements
-                        .iter()
-                        .map(|s| self.analyze_statement(s))
-                        .collect::<Result<Vec<_>, Box<SemanticError>>>()?
-                        .iter()
-                        .flatten()
-                        .map(Clone::clone)
-                        .collect::<Vec<_>>(),
-                );
+        match &statement.kind {
+            StatementKind::Expression(expr) => match &expr.kind {
+                ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                    instructions.append(&mut self.analyze_assignment(lhs, rhs, false)?)
+                }
+                _ => {
+                    instructions.append(&mut self.analyze_expression(expr)?);
+                    instructions.push("pop".to_string());
+                }
+            },
+            StatementKind::Break => match self.loop_labels.last() {
+                Some(end_label) => instructions.push(format!("goto {end_label}")),
+                None => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::BreakOutsideLoop,
+                    )))
+                }
+            },
+            StatementKind::BlockState(var_defs, statements) => {
+                if var_defs.is_empty() {
+                    instructions.append(&mut self.analyze_block(var_defs, statements)?);
+                } else {
+                    // The block's variables take the slots after those in use,
+                    // which are free again once it ends
+                    self.push_scope(*self.scope_symbols.last().unwrap());
+                    let scope = self.open_scope_labels();
+                    instructions.push(format!("VS{scope}:"));
+                    instructions.append(&mut self.analyze_block(var_defs, statements)?);
+                    instructions.push(format!("VE{scope}:"));
+                    self.scope_labels.pop();
+                    let table = self.pop_scope(ScopeKind::Block, statement.span);
+                    for (name, _, span) in table.unused() {
+                        self.unused.push((UnusedKind::Local, name.to_owned(), span));
+                    }
+                }
             }
-            Statement::IfState(expr, statement, else_stmt) => {
-                self.conditional_count += 1;
-                let then_label = format!("CT{}", self.conditional_count);
-                let else_label = format!("CL{}", self.conditional_count);
-                let end_label = format!("CE{}", self.conditional_count);
-                match expr {
-                    Expression::Expr(..) => {
-                        instructions.append(&mut self.analyze_expression(expr)?);
-                        match else_stmt.as_ref() {
-                            Some(_) => instructions.push(format!("goto {else_label}")),
-                            None => instructions.push(format!("goto {end_label}")),
-                        }
-                        instructions.push(format!("{then_label}:"))
+            StatementKind::IfState(expr, then_stmt, else_stmt) => match self.constant(expr) {
+                Some(0) => {
+                    self.analyze_unreachable(then_stmt)?;
+                    if let Some(else_statement) = else_stmt.as_ref() {
+                        instructions.append(&mut self.analyze_statement(else_statement)?);
                     }
-                    _ => {
-                        instructions.append(&mut self.analyze_expression(expr)?);
-                        instructions.push("iconst_1".to_string());
-                        instructions.push(format!("if_icmpeq {then_label}"));
-                        match else_stmt.as_ref() {
-                            Some(_) => instructions.push(format!("goto {else_label}")),
-                            None => instructions.push(format!("goto {end_label}")),
-                        }
-                        instructions.push(format!("{then_label}:"));
+                }
+                Some(_) => {
+                    instructions.append(&mut self.analyze_statement(then_stmt)?);
+                    if let Some(else_statement) = else_stmt.as_ref() {
+                        self.analyze_unreachable(else_statement)?;
                     }
-                };
-                instructions.append(&mut self.analyze_statement(statement)?);
+                }
+                None => {
+                    let count = self.next_label();
+                    let then_label = format!("CT{count}");
+                    let else_label = format!("CL{count}");
+                    let end_label = format!("CE{count}");
+                    instructions.append(&mut self.analyze_branch(expr, &then_label)?);
+                    match else_stmt.as_ref() {
+                        Some(_) => instructions.push(format!("goto {else_label}")),
+                        None => instructions.push(format!("goto {end_label}")),
+                    }
+                    instructions.push(format!("{then_label}:"));
+                    instructions.append(&mut self.analyze_statement(then_stmt)?);
 
-                if let Some(else_statement) = else_stmt.as_ref() {
-                    instructions.push(format!("goto {end_label}"));
-                    instructions.push(format!("{else_label}:"));
-                    instructions.append(&mut self.analyze_statement(else_statement)?);
+                    if let Some(else_statement) = else_stmt.as_ref() {
+                        if then_stmt.completes() || !self.dead_code {
+                            instructions.push(format!("goto {end_label}"));
+                        }
+                        instructions.push(format!("{else_label}:"));
+                        instructions.append(&mut self.analyze_statement(else_statement)?);
+                    }
+                    instructions.push(format!("{end_label}:"));
+                }
+            },
+            StatementKind::NullState => {}
+            StatementKind::ReturnState(arg) => match arg {
+                Some(arg) if self.is_self_tail_call(arg) => {
+                    instructions.append(&mut self.analyze_tail_call(arg)?)
                 }
-                instructions.push(format!("{end_label}:"));
-                self.conditional_count -= 1;
-            }
-            Statement::NullState => {}
-            Statement::ReturnState(arg) => match arg {
                 Some(arg) => {
                     instructions.append(&mut self.analyze_expression(arg)?);
                     instructions.push("ireturn".to_string());
                 }
                 None => instructions.push("return".to_string()),
             },
-            Statement::WhileState(expr, statement) => {
-                self.conditional_count += 1;
-                let top_label = format!("CW{}", self.conditional_count);
-                let then_label = format!("CT{}", self.conditional_count);
-                let end_label = format!("CE{}", self.conditional_count);
-                instructions.push(format!("{top_label}:"));
-                instructions.append(&mut self.analyze_expression(expr)?);
+            StatementKind::WhileState(expr, body) => {
+                let count = self.next_label();
+                let top_label = format!("CW{count}");
+                let then_label = format!("CT{count}");
+                let end_label = format!("CE{count}");
+                let condition = self.constant(expr);
+                if condition != Some(0) {
+                    instructions.push(format!("{top_label}:"));
+                }
+                if condition.is_none() {
+                    instructions.append(&mut self.analyze_branch(expr, &then_label)?);
+                    instructions.push(format!("goto {end_label}"));
+                    instructions.push(format!("{then_label}:"));
+                }
 
-                match expr {
-                    Expression::Expr(..) => {
-                        instructions.push(format!("goto {end_label}"));
-                    }
-                    _ => {
-                        instructions.push("iconst_1".to_string());
-                        instructions.push(format!("if_icmpne {end_label}"));
+                self.loop_labels.push(end_label.clone());
+                let body_instructions = match condition {
+                    Some(0) => self.analyze_unreachable(body).map(|_| vec![]),
+                    _ => self.analyze_statement(body),
+                };
+                self.loop_labels.pop();
+                instructions.append(&mut body_instructions?);
+
+                if condition != Some(0) {
+                    if body.completes() || !self.dead_code {
+                        instructions.push(format!("goto {top_label}"));
                     }
+                    instructions.push(format!("{end_label}:"));
                 }
-                instructions.push(format!("{then_label}:"));
-                instructions.append(&mut self.analyze_statement(statement)?);
-
-                instructions.push(format!("goto {top_label}"));
-                instructions.push(format!("{end_label}:"));
             }
-            Statement::ReadState(name, others) => {
-                if self
-                    .insert_symbol(
+            StatementKind::ReadState(name, others) => {
+                if self.get_symbol("JAVA_SCANNER").is_err() {
+                    self.insert_symbol(
                         "JAVA_SCANNER",
                         Symbol::Variable("JAVA_SCANNER".to_owned(), Type::Int, 900),
-                    )
-                    .is_ok()
-                {
+                    )?;
                     instructions.push("new java/util/Scanner".to_owned());
                     instructions.push("dup".to_owned());
                     instructions
@@ -354,7 +592,7 @@ impl<'a> SemanticAnalyzer<'a> {
                     }
                 }
             }
-            Statement::WriteState(expr, others) => {
+            StatementKind::WriteState(expr, others) => {
                 let arg_type = self.get_jvm_type(expr)?;
                 match arg_type {
                     "S" => {
@@ -410,12 +648,15 @@ impl<'a> SemanticAnalyzer<'a> {
                     }
                 }
             }
-            Statement::NewLineState => {
+            StatementKind::NewLineState => {
                 instructions
                     .push("getstatic java/lang/System/out Ljava/io/PrintStream;".to_string());
                 instructions.push("invokevirtual java/io/PrintStream/println()V".to_string());
             }
         }
+        if !instructions.is_empty() && !matches!(statement.kind, StatementKind::BlockState(..)) {
+            instructions.insert(0, format!(".line {}", statement.span.line()));
+        }
         Ok(instructions)
     }
 
@@ -424,15 +665,9 @@ impl<'a> SemanticAnalyzer<'a> {
         expression: &'a Expression,
     ) -> Result<Vec<String>, Box<SemanticError>> {
         let mut instructions = vec![];
-        match expression {
-            Expression::Number(num) => {
-                if *num <= 5.0 {
-                    instructions.push(format!("iconst_{num}"));
-                } else {
-                    instructions.push(format!("bipush {num}"));
-                }
-            }
-            Expression::Identifier(id) => match self.get_symbol(id)? {
+        match &expression.kind {
+            ExpressionKind::Number(num) => instructions.push(push_constant(*num as i32)),
+            ExpressionKind::Identifier(id) => match self.use_symbol(id, expression.span)? {
                 Symbol::Variable(_, _, num) => instructions.push(format!("iload {num}")),
                 _ => {
                     return Err(Box::new(SemanticError::new(
@@ -440,16 +675,16 @@ impl<'a> SemanticAnalyzer<'a> {
                     )))
                 }
             },
-            Expression::CharLiteral(c) => {
+            ExpressionKind::CharLiteral(c) => {
                 if let Some(c) = c {
-                    instructions.push(format!("bipush {}", *c as u32));
+                    instructions.push(push_constant(*c as i32));
                 }
             }
-            Expression::StringLiteral(s) => {
+            ExpressionKind::StringLiteral(s) => {
                 instructions.push(format!("ldc \"{s}\""));
             }
 
-            Expression::FuncCall(name, arguments) => {
+            ExpressionKind::FuncCall(name, arguments) => {
                 let program_name = self.class_name;
                 instructions.append(
                     &mut arguments
@@ -462,7 +697,12 @@ impl<'a> SemanticAnalyzer<'a> {
                         .collect::<Vec<_>>(),
                 );
 
-                if let Symbol::Function(func) = self.get_symbol(name)? {
+                // A function calling itself does not count as called
+                let symbol = match self.function {
+                    Some((func_def, _)) if func_def.identifier == *name => self.get_symbol(name)?,
+                    _ => self.use_symbol(name, expression.span)?,
+                };
+                if let Symbol::Function(func) = symbol {
                     let call = format!(
                         "invokestatic {}/{name}({}){}",
                         program_name,
@@ -480,88 +720,208 @@ impl<'a> SemanticAnalyzer<'a> {
                 }
             }
 
-            Expression::Expr(op, expra, exprb) => {
-                let then_label = format!("CT{}", self.conditional_count);
-
-                if *op != Operator::Assign {
-                    instructions.append(&mut self.analyze_expression(expra)?);
-                }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                instructions.append(&mut self.analyze_assignment(lhs, rhs, true)?)
+            }
+            ExpressionKind::Expr(op, ..) if op.is_relational() || op.is_logical() => {
+                let count = self.next_label();
+                let then_label = format!("CT{count}");
+                let end_label = format!("CE{count}");
+                instructions.append(&mut self.analyze_branch(expression, &then_label)?);
+                instructions.push("iconst_0".to_owned());
+                instructions.push(format!("goto {end_label}"));
+                instructions.push(format!("{then_label}:"));
+                instructions.push("iconst_1".to_owned());
+                instructions.push(format!("{end_label}:"));
+            }
+            ExpressionKind::Expr(op, expra, exprb) => {
+                instructions.append(&mut self.analyze_expression(expra)?);
                 instructions.append(&mut self.analyze_expression(exprb)?);
                 match op {
                     Operator::Plus => instructions.push("iadd".to_owned()),
                     Operator::Minus => instructions.push("isub".to_owned()),
                     Operator::Multiply => instructions.push("imul".to_owned()),
-                    Operator::Divide => {
-                        if let Expression::Number(num) = exprb.as_ref() {
-                            if *num == 0.0 {
-                                return Err(Box::new(SemanticError::new(
-                                    SemanticErrorKind::DivisionByZero,
-                                )));
-                            }
+                    Operator::Divide | Operator::Modulo => {
+                        if exprb.constant_value() == Some(0) {
+                            return Err(Box::new(SemanticError::new(
+                                SemanticErrorKind::DivisionByZero,
+                            )));
                         }
-                        instructions.push("idiv".to_owned())
-                    }
-                    Operator::Modulo => {
-                        if let Expression::Number(num) = exprb.as_ref() {
-                            if *num == 0.0 {
-                                return Err(Box::new(SemanticError::new(
-                                    SemanticErrorKind::DivisionByZero,
-                                )));
-                            }
+                        match op {
+                            Operator::Divide => instructions.push("idiv".to_owned()),
+                            _ => instructions.push("irem".to_owned()),
                         }
-                        instructions.push("irem".to_owned())
                     }
-                    Operator::Or => instructions.push("ior".to_owned()),
-                    Operator::And => instructions.push("iand".to_owned()),
-                    Operator::LessEqual => instructions.push(format!("if_icmple {then_label}")),
-                    Operator::LessThan => instructions.push(format!("if_icmplt {then_label}")),
-                    Operator::GreaterEqual => instructions.push(format!("if_icmpge {then_label}")),
-                    Operator::GreaterThan => instructions.push(format!("if_icmpgt {then_label}")),
-                    Operator::Equal => instructions.push(format!("if_icmpeq {then_label}")),
-                    Operator::NotEqual => instructions.push(format!("if_icmpne {then_label}")),
-                    Operator::Assign => match expra.as_ref() {
-                        Expression::Identifier(id) => match self.get_symbol(id)? {
-                            Symbol::Variable(.., num) => {
-                                instructions.push(format!("istore {num}"));
-                            }
-                            _ => {
-                                return Err(Box::new(SemanticError::new(
-                                    SemanticErrorKind::UndeclaredIdentifier(id.clone()),
-                                )))
-                            }
-                        },
-                        _ => {
-                            return Err(Box::new(SemanticError::new(
-                                SemanticErrorKind::ExpectedIdentifier,
-                            )))
-                        }
-                    },
+                    _ => unreachable!("relational, logical and assignment handled above"),
                 };
             }
-            Expression::Not(expr) => {
-                instructions.append(&mut self.analyze_expression(expr)?);
-                instructions.push("iconst_m1".to_owned());
-                instructions.push("ixor".to_owned());
+            ExpressionKind::Not(_) => {
+                let count = self.next_label();
+                let then_label = format!("CT{count}");
+                let end_label = format!("CE{count}");
+                instructions.append(&mut self.analyze_branch(expression, &then_label)?);
+                instructions.push("iconst_0".to_owned());
+                instructions.push(format!("goto {end_label}"));
+                instructions.push(format!("{then_label}:"));
+                instructions.push("iconst_1".to_owned());
+                instructions.push(format!("{end_label}:"));
             }
-            Expression::Minus(expr) => {
+            ExpressionKind::Minus(expr) => {
                 instructions.append(&mut self.analyze_expression(expr)?);
-                instructions.push("inot".to_owned());
+                instructions.push("ineg".to_owned());
+            }
+        }
+
+        Ok(instructions)
+    }
+
+    fn is_self_tail_call(&self, expression: &Expression) -> bool {
+        match (&expression.kind, self.function) {
+            (ExpressionKind::FuncCall(name, arguments), Some((func_def, _))) => {
+                let params = func_def.var_def.iter().flat_map(|v| &v.identifiers);
+                self.tail_calls && *name == func_def.identifier && arguments.len() == params.count()
+            }
+            _ => false,
+        }
+    }
+
+    /// Evaluates the arguments of a self tail call, stores them into the
+    /// parameters once all of them are on the stack, and jumps back to the
+    /// start of the method instead of invoking it
+    fn analyze_tail_call(
+        &mut self,
+        expression: &'a Expression,
+    ) -> Result<Vec<String>, Box<SemanticError>> {
+        let (ExpressionKind::FuncCall(_, arguments), Some((func_def, scope))) =
+            (&expression.kind, self.function)
+        else {
+            unreachable!("checked by is_self_tail_call")
+        };
+        let mut instructions = vec![];
+        for argument in arguments {
+            instructions.append(&mut self.analyze_expression(argument)?);
+        }
+        // Parameters live in the function's scope, whatever blocks shadow them
+        let params = func_def.var_def.iter().flat_map(|v| &v.identifiers);
+        for param in params.rev() {
+            match self.symbol_table[1].find(param) {
+                Some(Symbol::Variable(.., num)) => instructions.push(format!("istore {num}")),
+                _ => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::ExpectedIdentifier,
+                    )))
+                }
+            }
+        }
+        instructions.push(format!("goto VS{scope}"));
+        self.tail_call_count += 1;
+        Ok(instructions)
+    }
+
+    /// Emits code that jumps to `label` when `expression` is nonzero and falls
+    /// through otherwise.
+    fn analyze_branch(
+        &mut self,
+        expression: &'a Expression,
+        label: &str,
+    ) -> Result<Vec<String>, Box<SemanticError>> {
+        let mut instructions = vec![];
+        match &expression.kind {
+            ExpressionKind::Expr(op, expra, exprb) if op.is_relational() => {
+                instructions.append(&mut self.analyze_expression(expra)?);
+                instructions.append(&mut self.analyze_expression(exprb)?);
+                instructions.push(format!("if_icmp{} {label}", condition(op)));
+            }
+            ExpressionKind::Expr(Operator::Or, expra, exprb) => {
+                instructions.append(&mut self.analyze_branch(expra, label)?);
+                instructions.append(&mut self.analyze_branch(exprb, label)?);
+            }
+            ExpressionKind::Expr(Operator::And, expra, exprb) => {
+                let count = self.next_label();
+                let next_label = format!("CA{count}");
+                let false_label = format!("CF{count}");
+                instructions.append(&mut self.analyze_branch(expra, &next_label)?);
+                instructions.push(format!("goto {false_label}"));
+                instructions.push(format!("{next_label}:"));
+                instructions.append(&mut self.analyze_branch(exprb, label)?);
+                instructions.push(format!("{false_label}:"));
+            }
+            ExpressionKind::Not(expr) => {
+                let count = self.next_label();
+                let false_label = format!("CF{count}");
+                instructions.append(&mut self.analyze_branch(expr, &false_label)?);
+                instructions.push(format!("goto {label}"));
+                instructions.push(format!("{false_label}:"));
+            }
+            _ => {
+                instructions.append(&mut self.analyze_expression(expression)?);
+                instructions.push(format!("ifne {label}"));
+            }
+        }
+
+        Ok(instructions)
+    }
+
+    /// Stores `rhs` into the variable named by `lhs`, leaving the assigned
+    /// value on the stack when `keep_value` is set.
+    fn analyze_assignment(
+        &mut self,
+        lhs: &'a Expression,
+        rhs: &'a Expression,
+        keep_value: bool,
+    ) -> Result<Vec<String>, Box<SemanticError>> {
+        let mut instructions = self.analyze_expression(rhs)?;
+        if keep_value {
+            instructions.push("dup".to_owned());
+        }
+        match &lhs.kind {
+            ExpressionKind::Identifier(id) => match self.get_symbol(id)? {
+                Symbol::Variable(.., num) => {
+                    instructions.push(format!("istore {num}"));
+                }
+                _ => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::UndeclaredIdentifier(id.clone()),
+                    )))
+                }
+            },
+            _ => {
+                return Err(Box::new(SemanticError::new(
+                    SemanticErrorKind::ExpectedIdentifier,
+                )))
             }
         }
 
         Ok(instructions)
     }
 
-    fn pop_scope(&mut self) {
-        self.symbol_table.pop();
+    /// Leaves the innermost scope, recording it in the enclosing one, and
+    /// returns its symbols
+    fn pop_scope(&mut self, kind: ScopeKind, span: Span) -> SymbolTable<'a> {
+        self.scope_symbols.pop();
+        let table = self.symbol_table.pop().unwrap();
+        let scopes = self.closed_scopes.pop().unwrap();
+        let scope = table.scope(kind, Some(span), scopes);
+        self.closed_scopes.last_mut().unwrap().push(scope);
+        table
+    }
+
+    fn use_symbol(&mut self, name: &'a str, span: Span) -> Result<&Symbol, Box<SemanticError>> {
+        self.symbol_table
+            .iter_mut()
+            .rev()
+            .find(|table| table.contains(name))
+            .and_then(|table| table.use_symbol(name, span))
+            .ok_or(Box::new(SemanticError::new(
+                SemanticErrorKind::UndeclaredIdentifier(name.to_string()),
+            )))
     }
 
     fn get_symbol(&mut self, name: &'a str) -> Result<&Symbol, Box<SemanticError>> {
         self.symbol_table
             .iter_mut()
-            .next_back()
-            .unwrap()
-            .find(name)
+            .rev()
+            .find_map(|table| table.find(name))
             .ok_or(Box::new(SemanticError::new(
                 SemanticErrorKind::UndeclaredIdentifier(name.to_string()),
             )))
@@ -571,7 +931,6 @@ impl<'a> SemanticAnalyzer<'a> {
         name: &'a str,
         symbol: Symbol,
     ) -> Result<&Symbol, Box<SemanticError>> {
-        *self.scope_symbols.iter_mut().next_back().unwrap() += 1;
         self.symbol_table
             .iter_mut()
             .next_back()
@@ -579,10 +938,30 @@ impl<'a> SemanticAnalyzer<'a> {
             .insert(name, symbol)
     }
 
+    fn declare_symbol(
+        &mut self,
+        name: &'a str,
+        symbol: Symbol,
+        span: Span,
+    ) -> Result<&Symbol, Box<SemanticError>> {
+        let (table, outer) = self.symbol_table.split_last_mut().unwrap();
+        if !table.contains(name) {
+            if let Some(shadowed) = outer.iter().rev().find(|table| table.contains(name)) {
+                let declared = shadowed.declared(name);
+                self.shadowed.push((name.to_owned(), span, declared));
+            }
+        }
+        *self.scope_symbols.iter_mut().next_back().unwrap() += 1;
+        self.symbol_table
+            .last_mut()
+            .unwrap()
+            .declare(name, symbol, span)
+    }
+
     fn get_jvm_type(&mut self, expr: &'a Expression) -> Result<&'static str, Box<SemanticError>> {
-        Ok(match expr {
-            Expression::Number(_) => "I",
-            Expression::Identifier(id) => match self.get_symbol(id)? {
+        Ok(match &expr.kind {
+            ExpressionKind::Number(_) => "I",
+            ExpressionKind::Identifier(id) => match self.get_symbol(id)? {
                 Symbol::Variable(_, t_type, _) => match t_type {
                     Type::Int => "I",
                     Type::Char => "C",
@@ -592,9 +971,9 @@ impl<'a> SemanticAnalyzer<'a> {
                     Type::Char => "C",
                 },
             },
-            Expression::CharLiteral(_) => "C",
-            Expression::StringLiteral(_) => "S",
-            Expression::FuncCall(name, _) => match self.get_symbol(name)? {
+            ExpressionKind::CharLiteral(_) => "C",
+            ExpressionKind::StringLiteral(_) => "S",
+            ExpressionKind::FuncCall(name, _) => match self.get_symbol(name)? {
                 Symbol::Function(f) => match f.return_type {
                     Type::Int => "I",
                     Type::Char => "C",
@@ -605,17 +984,54 @@ impl<'a> SemanticAnalyzer<'a> {
                     )))
                 }
             },
-            Expression::Expr(_, a, _) => self.get_jvm_type(a)?,
-            Expression::Not(val) => self.get_jvm_type(val)?,
-            Expression::Minus(val) => self.get_jvm_type(val)?,
+            ExpressionKind::Expr(_, a, _) => self.get_jvm_type(a)?,
+            ExpressionKind::Not(val) => self.get_jvm_type(val)?,
+            ExpressionKind::Minus(val) => self.get_jvm_type(val)?,
         })
     }
 }
 
+/// Whether a function body has a `return` without a value
+fn returns_without_value(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::ReturnState(value) => value.is_none(),
+        StatementKind::BlockState(_, statements) => statements.iter().any(returns_without_value),
+        StatementKind::IfState(_, then_stmt, else_stmt) => {
+            returns_without_value(then_stmt)
+                || matches!(&**else_stmt, Some(else_stmt) if returns_without_value(else_stmt))
+        }
+        StatementKind::WhileState(_, body) => returns_without_value(body),
+        _ => false,
+    }
+}
+
+fn push_constant(value: i32) -> String {
+    match value {
+        -1 => "iconst_m1".to_owned(),
+        0..=5 => format!("iconst_{value}"),
+        -128..=127 => format!("bipush {value}"),
+        -32768..=32767 => format!("sipush {value}"),
+        _ => format!("ldc {value}"),
+    }
+}
+
+fn condition(op: &Operator) -> &'static str {
+    match op {
+        Operator::LessEqual => "le",
+        Operator::LessThan => "lt",
+        Operator::GreaterEqual => "ge",
+        Operator::GreaterThan => "gt",
+        Operator::Equal => "eq",
+        Operator::NotEqual => "ne",
+        _ => unreachable!("not a relational operator"),
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
     use std::io::Cursor;
+    use toycc_report::Diagnostic;
     #[test]
     fn test_valid_program() {
         let program = toycc_frontend::Parser::new(
@@ -623,10 +1039,238 @@ pub mod test {
             "test.tc",
             Some(2)).parse().expect("failed to parse");
         // println!("{:#?}",program);
-        let mut analyzer = SemanticAnalyzer::new("test", false);
+        let mut analyzer = SemanticAnalyzer::new("test", "test.tc", false);
         let c = analyzer.analyze_program(&program);
         assert!(c.is_ok());
 
         println!("{}", c.unwrap());
     }
+
+    #[test]
+    fn test_debug_info() {
+        let program = toycc_frontend::Parser::new(
+            Cursor::new("int addTwo(int n){\n  int a;\n  a = n + 2;\n  return a;\n}\nint main(){\n  return addTwo(1);\n}\n"),
+            "test.tc",
+            None).parse().expect("failed to parse");
+        let mut analyzer = SemanticAnalyzer::new("test", "test.tc", false);
+        let jasmin = analyzer
+            .analyze_program(&program)
+            .expect("failed to analyze");
+
+        assert!(jasmin.starts_with(".source test.tc\n.class public test"));
+        assert!(jasmin.contains(".var 0 is n I from VS1 to VE1"));
+        assert!(jasmin.contains(".var 1 is a I from VS1 to VE1"));
+        assert!(jasmin.contains("\t.line 3\n\tiload 0"));
+        assert!(jasmin.contains(".line 4\n\tiload 1\n\tireturn"));
+        assert!(jasmin.contains(".line 7\n"));
+    }
+
+    #[test]
+    fn test_folded_division_by_zero() {
+        let program = toycc_frontend::Parser::new(
+            Cursor::new("int main(){ int x; x = 4; return x / (2 - 2); }"),
+            "test.tc",
+            None,
+        )
+        .parse()
+        .expect("failed to parse");
+        let error = SemanticAnalyzer::new("test", "test.tc", false)
+            .analyze_program(&program)
+            .unwrap_err();
+        assert_eq!(error.info(), "illegal division by 0");
+    }
+
+    #[test]
+    fn test_return_paths() {
+        let analyze_with = |source: &str, dead_code: bool| {
+            let program = toycc_frontend::Parser::new(Cursor::new(source), "test.tc", None)
+                .parse()
+                .expect("failed to parse");
+            SemanticAnalyzer::new("test", "test.tc", false)
+                .dead_code(dead_code)
+                .analyze_program(&program)
+                .map_err(|e| e.info())
+        };
+        let analyze = |source: &str| analyze_with(source, true);
+        assert_eq!(
+            analyze("int main(){ int a; if (a) return 1; }"),
+            Err("missing return".to_owned())
+        );
+        assert_eq!(
+            analyze("int main(){ return; }"),
+            Err("incompatible return types: expected: I actual: V".to_owned())
+        );
+        // Dead code is checked but not emitted
+        assert_eq!(
+            analyze("int main(){ return 0; write(b); }"),
+            Err("undeclared identifier 'b'".to_owned())
+        );
+        let source = "int main(){ int a; if (a) return 1; else return 2; a = 3; while (0) a = 4; }";
+        let jasmin = analyze(source).expect("failed to analyze");
+        assert!(!jasmin.contains("nop"));
+        assert!(!jasmin.contains("istore"));
+        // Without dead code elimination every statement is emitted
+        let jasmin = analyze_with(source, false).expect("failed to analyze");
+        assert!(jasmin.contains("istore 0"));
+        assert!(jasmin.contains("bipush 4\n\tistore 0") || jasmin.contains("iconst_4\n\tistore 0"));
+    }
+
+    #[test]
+    fn test_block_scopes() {
+        let analyze = |source: &str| {
+            let program = toycc_frontend::Parser::new(Cursor::new(source), "test.tc", None)
+                .parse()
+                .expect("failed to parse");
+            let mut analyzer = SemanticAnalyzer::new("test", "test.tc", false);
+            let result = analyzer.analyze_program(&program).map_err(|e| e.info());
+            let shadowed = analyzer
+                .shadowed_symbols("test.tc", source)
+                .iter()
+                .map(|w| (w.info(), w.span.location))
+                .collect::<Vec<_>>();
+            (result, shadowed)
+        };
+        let (jasmin, shadowed) = analyze(
+            "int a;
+int main(int b){ int c;
+  { int a; int c; a = 1; c = 2; write(a, c); }
+  { int d; d = 3; write(d); }
+  c = b; return c; }",
+        );
+        let jasmin = jasmin.expect("failed to analyze");
+        // Sibling blocks take the same slot, after those of the function
+        assert!(jasmin.contains(".var 2 is a I from VS2 to VE2"));
+        assert!(jasmin.contains(".var 3 is c I from VS2 to VE2"));
+        assert!(jasmin.contains(".var 2 is d I from VS3 to VE3"));
+        assert!(jasmin.contains("\tiload 0\n\tistore 1"));
+        assert_eq!(
+            shadowed,
+            vec![
+                (
+                    "declaration of 'a' shadows an outer declaration".to_owned(),
+                    (3, 9)
+                ),
+                (
+                    "declaration of 'c' shadows an outer declaration".to_owned(),
+                    (3, 16)
+                ),
+            ]
+        );
+
+        let redeclaration = "redeclaration of identifier a".to_owned();
+        for source in [
+            "int main(){ int a; int a; return 0; }",
+            "int main(int a){ int a; return 0; }",
+            "int main(){ { int a; int a; } return 0; }",
+        ] {
+            assert_eq!(analyze(source).0, Err(redeclaration.clone()), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_symbol_scopes() {
+        let source = "int g;
+int sq(int n){ return n * n; }
+int main(){
+  int a;
+  read(a);
+  { int b; b = sq(a); write(b); }
+  { char c; c = 'x'; write(c, g); }
+  return 0;
+}";
+        let program = toycc_frontend::Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        let scopes = || {
+            let mut analyzer = SemanticAnalyzer::new("test", "test.tc", false);
+            analyzer
+                .analyze_program(&program)
+                .expect("failed to analyze");
+            analyzer.symbol_scopes()
+        };
+        let global = scopes();
+        assert_eq!(global.to_json(), scopes().to_json());
+
+        let names = |scope: &Scope| scope.symbols.iter().map(|s| s.name.clone()).join(" ");
+        assert_eq!(names(&global), "g sq main");
+        let [sq, main] = &global.scopes[..] else {
+            panic!("expected two functions: {}", global.to_json())
+        };
+        assert_eq!(sq.name.as_deref(), Some("sq"));
+        let n = &sq.symbols[0];
+        assert_eq!(
+            (n.kind, n.slot, n.uses.len()),
+            (SymbolKind::Parameter, Some(0), 2)
+        );
+        assert_eq!(names(main), "a");
+        // Sibling blocks share the slot after `a`
+        let blocks = main.scopes.iter().map(|block| {
+            let symbol = &block.symbols[0];
+            (
+                block.kind,
+                symbol.name.as_str(),
+                symbol.toyc_type.as_str(),
+                symbol.slot,
+            )
+        });
+        assert_eq!(
+            blocks.collect::<Vec<_>>(),
+            vec![
+                (ScopeKind::Block, "b", "int", Some(1)),
+                (ScopeKind::Block, "c", "char", Some(1))
+            ]
+        );
+        let g = &global.symbols[0];
+        assert_eq!((g.declared.line, g.declared.column), (1, 5));
+        assert_eq!((g.uses[0].line, g.uses[0].column), (7, 31));
+    }
+
+    #[test]
+    fn test_initialize_locals() {
+        let program = toycc_frontend::Parser::new(
+            Cursor::new("int f(int n){ int a; return a + n; } int main(){ return f(1); }"),
+            "test.tc",
+            None,
+        )
+        .parse()
+        .expect("failed to parse");
+        let jasmin = SemanticAnalyzer::new("test", "test.tc", false)
+            .initialize_locals(true)
+            .analyze_program(&program)
+            .expect("failed to analyze");
+        // The parameter holds the argument
+        assert!(jasmin.contains("VS1:\n\ticonst_0\n\tistore 1\n\t.line 1\n\tiload 1"));
+        assert!(!jasmin.contains("istore 0"));
+    }
+
+    #[test]
+    fn test_unused_symbols() {
+        let source = "int g; int h;
+int helper(int x, int y){ int t; int u; u = x; return u; }
+int count(int n){ if (n) return count(n - 1); return h; }
+int main(){ int a; a = 1; read(a); return helper(2, 3); }";
+        let program = toycc_frontend::Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        let mut analyzer = SemanticAnalyzer::new("test", "test.tc", false);
+        analyzer
+            .analyze_program(&program)
+            .expect("failed to analyze");
+        let warnings = analyzer
+            .unused_symbols("test.tc", source)
+            .iter()
+            .map(|w| (w.info(), w.span.location))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                ("unused global variable 'g'".to_owned(), (1, 5)),
+                ("unused parameter 'y'".to_owned(), (2, 23)),
+                ("unused variable 't'".to_owned(), (2, 31)),
+                ("function 'count' is never called".to_owned(), (3, 5)),
+                // Only assigned and read into, never read from
+                ("unused variable 'a'".to_owned(), (4, 17)),
+            ]
+        );
+    }
 }
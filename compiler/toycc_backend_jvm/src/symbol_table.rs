@@ -1,13 +1,24 @@
 use crate::error::{SemanticError, SemanticErrorKind};
 use itertools::Itertools;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use toycc_frontend::ast::Span;
 use toycc_frontend::Type;
 
 /// Symbol names in table are mangled to avoid collisions
 #[derive(Debug, Default, Clone)]
 pub struct SymbolTable<'a> {
-    table: HashMap<&'a str, Symbol>,
+    table: HashMap<&'a str, Entry>,
+}
+
+/// A symbol with the places it was used and, for symbols declared in the
+/// source, where
+#[derive(Debug, Clone)]
+struct Entry {
+    symbol: Symbol,
+    uses: Vec<Span>,
+    declared: Option<Span>,
 }
 #[derive(Debug, Clone, PartialEq)]
 pub enum Symbol {
@@ -55,15 +66,118 @@ impl Function {
 
 impl<'a> SymbolTable<'a> {
     pub fn insert(&mut self, name: &'a str, symbol: Symbol) -> Result<&Symbol, Box<SemanticError>> {
-        match self.table.insert(name, symbol) {
+        self.insert_entry(name, symbol, None)
+    }
+
+    /// Inserts a symbol declared at `span`, which is reported when unused
+    pub fn declare(
+        &mut self,
+        name: &'a str,
+        symbol: Symbol,
+        span: Span,
+    ) -> Result<&Symbol, Box<SemanticError>> {
+        self.insert_entry(name, symbol, Some(span))
+    }
+
+    fn insert_entry(
+        &mut self,
+        name: &'a str,
+        symbol: Symbol,
+        declared: Option<Span>,
+    ) -> Result<&Symbol, Box<SemanticError>> {
+        let entry = Entry {
+            symbol,
+            uses: vec![],
+            declared,
+        };
+        match self.table.insert(name, entry) {
             Some(_) => Err(Box::new(
                 self.create_error(SemanticErrorKind::MultipleBindings(name.to_string())),
             )),
-            None => Ok(self.table.get(name).unwrap()),
+            None => Ok(&self.table.get(name).unwrap().symbol),
         }
     }
     pub fn find(&mut self, name: &str) -> Option<&Symbol> {
-        self.table.get(name)
+        self.table.get(name).map(|entry| &entry.symbol)
+    }
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+        self.table.get_mut(name).map(|entry| &mut entry.symbol)
+    }
+
+    /// Looks a symbol up and records a use of it at `span`
+    pub fn use_symbol(&mut self, name: &str, span: Span) -> Option<&Symbol> {
+        let entry = self.table.get_mut(name)?;
+        entry.uses.push(span);
+        Some(&entry.symbol)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.table.contains_key(name)
+    }
+
+    pub fn uses(&self, name: &str) -> Option<usize> {
+        self.table.get(name).map(|entry| entry.uses.len())
+    }
+
+    /// Where a symbol declared in the source was declared
+    pub fn declared(&self, name: &str) -> Option<Span> {
+        self.table.get(name).and_then(|entry| entry.declared)
+    }
+
+    /// Symbols declared in the source and never used, with where they were
+    /// declared
+    pub fn unused(&self) -> impl Iterator<Item = (&'a str, &Symbol, Span)> + '_ {
+        self.table.iter().filter_map(|(name, entry)| match entry {
+            Entry {
+                declared: Some(span),
+                symbol,
+                uses,
+            } if uses.is_empty() => Some((*name, symbol, *span)),
+            _ => None,
+        })
+    }
+
+    /// The symbols declared in the source, in declaration order, as a scope
+    /// enclosing `scopes`
+    pub fn scope(&self, kind: ScopeKind, span: Option<Span>, scopes: Vec<Scope>) -> Scope {
+        let symbols = self
+            .entries()
+            .filter_map(|(name, entry)| {
+                let declared = entry.declared?;
+                let (kind, toyc_type, slot) = match &entry.symbol {
+                    Symbol::Function(function) => {
+                        (SymbolKind::Function, &function.return_type, None)
+                    }
+                    Symbol::Variable(_, toyc_type, slot) => {
+                        let slot = (kind != ScopeKind::Global).then_some(*slot);
+                        (SymbolKind::Variable, toyc_type, slot)
+                    }
+                };
+                Some(ScopeSymbol {
+                    name: name.to_string(),
+                    kind,
+                    toyc_type: toyc_type.to_string().to_lowercase(),
+                    slot,
+                    declared: declared.into(),
+                    uses: entry.uses.iter().map(|&span| span.into()).collect(),
+                })
+            })
+            .collect();
+        Scope {
+            kind,
+            name: None,
+            span: span.map(Location::from),
+            symbols,
+            scopes,
+        }
+    }
+
+    /// Entries in declaration order, those the compiler added last
+    fn entries(&self) -> impl Iterator<Item = (&&'a str, &Entry)> {
+        self.table.iter().sorted_by_key(|(name, entry)| {
+            let declared = entry.declared.map(|span| span.location);
+            (declared.is_none(), declared, **name)
+        })
     }
 
     fn create_error(&mut self, kind: SemanticErrorKind) -> SemanticError {
@@ -73,18 +187,14 @@ impl<'a> SymbolTable<'a> {
 
 impl<'a> Display for SymbolTable<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let functions = self
-            .table
-            .iter()
-            .map(|e| e.1)
-            .filter(|e| matches!(e, Symbol::Function(_)))
-            .join("\n");
-        let variables = self
-            .table
-            .iter()
-            .map(|e| e.1)
-            .filter(|e| matches!(e, Symbol::Variable(..)))
-            .join("\n");
+        let entries = |function: bool| {
+            self.entries()
+                .map(|(_, e)| e)
+                .filter(|e| matches!(e.symbol, Symbol::Function(_)) == function)
+                .map(|e| format!("{}\tUses: {}", e.symbol, e.uses.len()))
+                .join("\n")
+        };
+        let (functions, variables) = (entries(true), entries(false));
 
         write!(
             f,
@@ -93,3 +203,70 @@ impl<'a> Display for SymbolTable<'a> {
         )
     }
 }
+
+/// A scope with the symbols declared in it and the scopes nested in it, as
+/// printed by `-symbol=json`
+#[derive(Debug, Clone, Serialize)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Location>,
+    pub symbols: Vec<ScopeSymbol>,
+    pub scopes: Vec<Scope>,
+}
+
+impl Scope {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a scope is always serializable")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScopeKind {
+    Global,
+    Function,
+    Block,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScopeSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Type of a variable, or return type of a function
+    #[serde(rename = "type")]
+    pub toyc_type: String,
+    /// Local variable slot in the method
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot: Option<usize>,
+    pub declared: Location,
+    pub uses: Vec<Location>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Function,
+    Parameter,
+    Variable,
+}
+
+/// 1-based position and length of a name in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+impl From<Span> for Location {
+    fn from(span: Span) -> Self {
+        Self {
+            line: span.location.0,
+            column: span.location.1,
+            length: span.len,
+        }
+    }
+}
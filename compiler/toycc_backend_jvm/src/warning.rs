@@ -0,0 +1,129 @@
+use crate::error::Note;
+use toycc_frontend::ast::Span;
+use toycc_report::{Diagnostic, Report, ReportLevel, WarningKind};
+
+/// What kind of declaration is never used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnusedKind {
+    Local,
+    Parameter,
+    Global,
+    Function,
+}
+
+/// A declaration whose value is never read, or a function other than
+/// `main` that is never called
+#[derive(Debug, Report)]
+pub struct UnusedSymbol {
+    pub kind: UnusedKind,
+    pub name: String,
+    pub span: Span,
+    line: Option<String>,
+    stream_name: String,
+}
+
+impl UnusedSymbol {
+    pub fn new(
+        kind: UnusedKind,
+        name: String,
+        span: Span,
+        line: Option<String>,
+        stream_name: String,
+    ) -> Self {
+        Self {
+            kind,
+            name,
+            span,
+            line,
+            stream_name,
+        }
+    }
+}
+
+impl Diagnostic for UnusedSymbol {
+    fn info(&self) -> String {
+        let name = &self.name;
+        match self.kind {
+            UnusedKind::Local => format!("unused variable '{name}'"),
+            UnusedKind::Parameter => format!("unused parameter '{name}'"),
+            UnusedKind::Global => format!("unused global variable '{name}'"),
+            UnusedKind::Function => format!("function '{name}' is never called"),
+        }
+    }
+
+    fn level(&self) -> ReportLevel {
+        ReportLevel::Warning(WarningKind::ParsingWarning {
+            file_name: self.stream_name.clone(),
+            pos: self.span.location,
+            len: self.span.len,
+            source: self.line.clone(),
+        })
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        None
+    }
+}
+
+/// A declaration hiding a variable or function of an enclosing scope
+#[derive(Debug, Report)]
+pub struct ShadowedVariable {
+    pub name: String,
+    pub span: Span,
+    line: Option<String>,
+    stream_name: String,
+    /// Where the hidden symbol is declared, when it comes from the source
+    shadowed: Option<Note>,
+}
+
+impl ShadowedVariable {
+    pub fn new(
+        name: String,
+        span: Span,
+        line: Option<String>,
+        stream_name: String,
+        shadowed: Option<(Span, Option<String>)>,
+    ) -> Self {
+        let shadowed = shadowed.map(|(span, line)| {
+            let message = "shadowed declaration is here".to_owned();
+            Note::new(message, span, line, stream_name.clone(), None)
+        });
+        Self {
+            name,
+            span,
+            line,
+            stream_name,
+            shadowed,
+        }
+    }
+}
+
+impl Diagnostic for ShadowedVariable {
+    fn info(&self) -> String {
+        format!(
+            "declaration of '{}' shadows an outer declaration",
+            self.name
+        )
+    }
+
+    fn level(&self) -> ReportLevel {
+        ReportLevel::Warning(WarningKind::ParsingWarning {
+            file_name: self.stream_name.clone(),
+            pos: self.span.location,
+            len: self.span.len,
+            source: self.line.clone(),
+        })
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        self.shadowed.as_ref().map(|note| note as &dyn Report)
+    }
+}
@@ -0,0 +1,59 @@
+//! Checks the Jasmin emitted for conditions, negation, constants, `break`
+//! and expression statements.
+
+use toycc_backend_jvm::semantic_analyzer::SemanticAnalyzer;
+use toycc_test_support::parse;
+
+fn compile(source: &str) -> Result<String, String> {
+    let program = parse(source);
+    SemanticAnalyzer::new("Test", "test.tc", false)
+        .analyze_program(&program)
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_short_circuit_conditions() {
+    let jasmin = compile(
+        "int f(int n){ write(n); return n; }
+int main(){
+    int a;
+    read(a);
+    if ((a > 0) && (f(a) > 1)) write(1);
+    if (a || f(2)) newline;
+    return 0;
+}",
+    )
+    .unwrap();
+    assert!(!jasmin.contains("iand"), "{jasmin}");
+    assert!(!jasmin.contains("ior"), "{jasmin}");
+}
+
+#[test]
+fn test_negation() {
+    let jasmin = compile("int main(){ int a; read(a); write(-a, !a); return 0; }").unwrap();
+    assert!(jasmin.contains("ineg"), "{jasmin}");
+    assert!(!jasmin.contains("inot"), "{jasmin}");
+    assert!(!jasmin.contains("ixor"), "{jasmin}");
+}
+
+#[test]
+fn test_constants() {
+    let jasmin = compile("int main(){ write(100, 1000, 100000); return 0; }").unwrap();
+    assert!(jasmin.contains("bipush 100\n"), "{jasmin}");
+    assert!(jasmin.contains("sipush 1000\n"), "{jasmin}");
+    assert!(jasmin.contains("ldc 100000\n"), "{jasmin}");
+}
+
+#[test]
+fn test_break_outside_loop() {
+    let error = compile("int main(){ break; return 0; }").unwrap_err();
+    assert!(error.contains("break statement not within loop"), "{error}");
+}
+
+#[test]
+fn test_expression_statements() {
+    let jasmin = compile("int f(int n){ return n; } int main(){ f(1); return 0; }").unwrap();
+    assert!(jasmin.contains("pop"), "{jasmin}");
+    let jasmin = compile("int main(){ int a; a = 1; write(a); return 0; }").unwrap();
+    assert!(!jasmin.contains("dup"), "{jasmin}");
+}
@@ -0,0 +1,197 @@
+//! Runs generated Jasmin classes in the bytecode interpreter and compares
+//! their output with the tree-walking interpreter, with and without the
+//! peephole optimizer and tail-call elimination.
+
+use toycc_backend_jvm::semantic_analyzer::SemanticAnalyzer;
+use toycc_jvm_interpreter::{Class, Vm, VmError};
+use toycc_test_support::parse;
+
+fn run_both(source: &str, input: &str) -> String {
+    let mut expected = String::new();
+    for optimize in [false, true] {
+        (expected, _) = toycc_test_support::run_both(source, input, |program| {
+            let jasmin = SemanticAnalyzer::new("test", "test.tc", false)
+                .peephole(optimize)
+                .tail_calls(optimize)
+                .analyze_program(program)
+                .expect("failed to analyze");
+            let class = Class::parse(&jasmin).unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+            let mut vm = Vm::new(&class, input);
+            vm.run_main().unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+            let output = vm.output().to_owned();
+            (jasmin, output, None)
+        });
+    }
+    expected
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    assert_eq!(run_both(source, "7 1"), "input a: input a: a = 3\na = 21\n");
+}
+
+#[test]
+fn test_loop_and_function_call() {
+    let source = "int isEven(int n){ if ((n % 2) == 0) return 1; else return 0; }
+int main(){
+    int i;
+    i = 0;
+    while (i < 6) {
+        write(i, isEven(i));
+        newline;
+        i = i + 1;
+    }
+    return 0;
+}";
+    assert_eq!(run_both(source, ""), "01\n10\n21\n30\n41\n50\n");
+}
+
+#[test]
+fn test_sequential_ifs() {
+    let source = "int main(){
+    int a;
+    a = 3;
+    if (a == 3) write(1); else write(0);
+    if (a != 3) write(1); else write(0);
+    if (a >= 2) write(1);
+    if (a < 2) write(1);
+    newline;
+    return 0;
+}";
+    assert_eq!(run_both(source, ""), "101\n");
+}
+
+#[test]
+fn test_nested_loops_with_break() {
+    let source = "int main(){
+    int i;
+    int j;
+    i = 0;
+    while (i < 3) {
+        j = 0;
+        while (1) {
+            if (j > i) break;
+            write(j);
+            j = j + 1;
+        }
+        newline;
+        i = i + 1;
+    }
+    return 0;
+}";
+    assert_eq!(run_both(source, ""), "0\n01\n012\n");
+}
+
+#[test]
+fn test_constants_and_negation() {
+    let source = "int main(){
+    int a;
+    a = 100000;
+    write(a, \" \", 300, \" \", -1, \" \", -a, \" \", 127);
+    newline;
+    return 0;
+}";
+    assert_eq!(run_both(source, ""), "100000 300 -1 -100000 127\n");
+}
+
+#[test]
+fn test_boolean_values() {
+    let source = "int main(){
+    int a;
+    int b;
+    a = 4;
+    b = (a > 3);
+    write(b, (a < 3), !a, !(a == 5), (a > 1) && (a < 3), (a > 5) || (a == 4));
+    newline;
+    if (!(a > 5) && a) write(1);
+    newline;
+    return 0;
+}";
+    assert_eq!(run_both(source, ""), "100101\n1\n");
+}
+
+#[test]
+fn test_chars_and_input() {
+    let source = "int main(){
+    char c;
+    int n;
+    read(c, n);
+    write(c);
+    write(n + 1);
+    newline;
+    return 0;
+}";
+    assert_eq!(run_both(source, "x 41"), "x42\n");
+}
+
+#[test]
+fn test_unreachable_code() {
+    let source = "int pick(int n){
+    while (1) {
+        if (n > 2) return n;
+        n = n + 1;
+    }
+}
+int main(){
+    if (1) { write(pick(0)); } else { write(0); }
+    while (0) { write(1); }
+    if (pick(5) > 0) return 0; else { newline; return 1; }
+    write(2);
+}";
+    assert_eq!(run_both(source, ""), "3");
+}
+
+#[test]
+fn test_recursion() {
+    let source = "int fib(int n){ if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+int gcd(int a, int b){ if (b == 0) return a; return gcd(b, a % b); }
+int main(){ write(fib(15), \" \", gcd(1071, 462)); return 0; }";
+    assert_eq!(run_both(source, ""), "610 21");
+}
+
+#[test]
+fn test_deep_tail_recursion() {
+    let source = "int count(int n, int acc){
+    if (n == 0) return acc;
+    return count(n - 1, acc + 1);
+}
+int main(){ write(count(1000000, 0)); return 0; }";
+    let program = parse(source);
+    let run = |tail_calls: bool| {
+        let jasmin = SemanticAnalyzer::new("test", "test.tc", false)
+            .tail_calls(tail_calls)
+            .analyze_program(&program)
+            .expect("failed to analyze");
+        let class = Class::parse(&jasmin).unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+        let mut vm = Vm::new(&class, "");
+        vm.run_main().map(|_| vm.output().to_owned())
+    };
+    assert!(matches!(run(false), Err(VmError::StackOverflowError)));
+    assert_eq!(run(true).as_deref(), Ok("1000000"));
+}
+
+#[test]
+fn test_block_scopes() {
+    let source = "int main(){
+    int a;
+    a = 1;
+    {
+        int a; int x;
+        a = 2; x = 3;
+        write(a, x);
+        { int a; a = 4; write(a); }
+        write(a);
+    }
+    while (a < 3) {
+        int b;
+        b = a * 10;
+        write(\" \", b, a);
+        a = a + 1;
+    }
+    write(\" \", a);
+    newline;
+    return 0;
+}";
+    assert_eq!(run_both(source, ""), "2342 101 202 3\n");
+}
@@ -0,0 +1,48 @@
+//! Compiles a corpus of programs with and without the peephole optimizer,
+//! checking that it removes instructions without changing what the
+//! programs print.
+
+use toycc_backend_jvm::peephole::instruction_count;
+use toycc_backend_jvm::semantic_analyzer::SemanticAnalyzer;
+use toycc_jvm_interpreter::{Class, Vm};
+use toycc_test_support::{parse, CORPUS};
+
+fn compile(source: &str, peephole: bool) -> String {
+    let program = parse(source);
+    SemanticAnalyzer::new("test", "test.tc", false)
+        .peephole(peephole)
+        .initialize_locals(true)
+        .analyze_program(&program)
+        .expect("failed to analyze")
+}
+
+fn count(jasmin: &str) -> usize {
+    let lines = jasmin
+        .lines()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    instruction_count(&lines)
+}
+
+fn run(jasmin: &str, input: &str) -> String {
+    let class = Class::parse(jasmin).unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+    let mut vm = Vm::new(&class, input);
+    vm.run_main().unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+    vm.output().to_owned()
+}
+
+#[test]
+fn test_corpus() {
+    for (source, input) in CORPUS {
+        let plain = compile(source, false);
+        let optimized = compile(source, true);
+        assert!(
+            count(&optimized) < count(&plain),
+            "{} -> {} instructions\n{optimized}",
+            count(&plain),
+            count(&optimized)
+        );
+        assert_eq!(run(&optimized, input), run(&plain, input), "{optimized}");
+    }
+}
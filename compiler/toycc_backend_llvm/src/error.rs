@@ -0,0 +1,55 @@
+use toycc_report::{Diagnostic, ErrorKind, Report, ReportLevel};
+
+#[derive(Report, Debug)]
+pub struct CodegenError {
+    pub kind: CodegenErrorKind,
+}
+
+impl CodegenError {
+    pub fn new(kind: CodegenErrorKind) -> Self {
+        Self { kind }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CodegenErrorKind {
+    UndeclaredIdentifier(String),
+    UndeclaredFunction(String),
+    MultipleBindings(String),
+    ArgumentCount(String, usize, usize),
+    ExpectedIdentifier,
+    StringValue,
+    BreakOutsideLoop,
+    MissingMain,
+}
+
+impl Diagnostic for CodegenError {
+    fn info(&self) -> String {
+        match &self.kind {
+            CodegenErrorKind::UndeclaredIdentifier(id) => {
+                format!("undeclared identifier \'{id}\'")
+            }
+            CodegenErrorKind::UndeclaredFunction(name) => format!("undeclared function {name}"),
+            CodegenErrorKind::MultipleBindings(id) => format!("redeclaration of identifier {id}"),
+            CodegenErrorKind::ArgumentCount(name, expected, actual) => {
+                format!("function {name} expects {expected} arguments but {actual} were given")
+            }
+            CodegenErrorKind::ExpectedIdentifier => "expected identifier".to_owned(),
+            CodegenErrorKind::StringValue => "string literal used as a value".to_owned(),
+            CodegenErrorKind::BreakOutsideLoop => "break statement not within loop".to_owned(),
+            CodegenErrorKind::MissingMain => "missing main function".to_owned(),
+        }
+    }
+
+    fn level(&self) -> ReportLevel {
+        ReportLevel::Error(ErrorKind::NoHelpError)
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        None
+    }
+}
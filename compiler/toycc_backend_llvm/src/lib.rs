@@ -0,0 +1,5 @@
+mod error;
+pub mod llvm_generator;
+
+pub use error::{CodegenError, CodegenErrorKind};
+pub use llvm_generator::LlvmGenerator;
@@ -0,0 +1,645 @@
+use crate::error::{CodegenError, CodegenErrorKind};
+use std::collections::HashMap;
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+
+const RUNTIME_DECLARATIONS: &str = r#"@.fmt.int = private unnamed_addr constant [3 x i8] c"%d\00"
+@.fmt.char = private unnamed_addr constant [3 x i8] c"%c\00"
+@.fmt.str = private unnamed_addr constant [3 x i8] c"%s\00"
+@.fmt.read.int = private unnamed_addr constant [3 x i8] c"%d\00"
+@.fmt.read.char = private unnamed_addr constant [4 x i8] c" %c\00"
+
+declare i32 @printf(ptr, ...)
+declare i32 @scanf(ptr, ...)
+declare i32 @putchar(i32)
+declare i32 @fflush(ptr)
+"#;
+
+#[derive(Debug, Clone)]
+struct Variable {
+    pointer: String,
+    toyc_type: Type,
+}
+
+/// Lowers a parsed program to textual LLVM IR. Every ToyC value, `char`
+/// included, is an `i32`; locals live in `alloca` slots and are left to
+/// `mem2reg` to promote.
+#[derive(Default)]
+pub struct LlvmGenerator<'a> {
+    source_name: &'a str,
+    functions: HashMap<&'a str, (Type, usize)>,
+    globals: HashMap<&'a str, Type>,
+    strings: Vec<String>,
+    scopes: Vec<HashMap<&'a str, Variable>>,
+    allocas: Vec<String>,
+    body: Vec<String>,
+    loop_ends: Vec<String>,
+    temp_count: usize,
+    label_count: usize,
+    block: String,
+    terminated: bool,
+}
+
+impl<'a> LlvmGenerator<'a> {
+    pub fn new(source_name: &'a str) -> Self {
+        Self {
+            source_name,
+            ..Default::default()
+        }
+    }
+
+    pub fn generate(&mut self, program: &'a Program) -> Result<String, Box<CodegenError>> {
+        for definition in &program.definitions {
+            let names = match definition {
+                Definition::FuncDef(func) => {
+                    let arity = func.var_def.iter().map(|v| v.identifiers.len()).sum();
+                    self.functions
+                        .insert(&func.identifier, (func.toyc_type.clone(), arity))
+                        .map(|_| &func.identifier)
+                        .into_iter()
+                        .collect::<Vec<_>>()
+                }
+                Definition::VarDef(var_def) => var_def
+                    .identifiers
+                    .iter()
+                    .filter(|id| self.globals.insert(id, var_def.toyc_type.clone()).is_some())
+                    .collect(),
+            };
+            if let Some(name) = names.first() {
+                return Err(create_error(CodegenErrorKind::MultipleBindings(
+                    name.to_string(),
+                )));
+            }
+        }
+        if let Some(name) = self
+            .globals
+            .keys()
+            .find(|g| self.functions.contains_key(*g))
+        {
+            return Err(create_error(CodegenErrorKind::MultipleBindings(
+                name.to_string(),
+            )));
+        }
+        if !self.functions.contains_key("main") {
+            return Err(create_error(CodegenErrorKind::MissingMain));
+        }
+
+        let functions = program
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::FuncDef(func) => Some(self.generate_function(func)),
+                Definition::VarDef(_) => None,
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut module = format!(
+            "; ModuleID = '{0}'\nsource_filename = \"{0}\"\n\n{RUNTIME_DECLARATIONS}",
+            self.source_name
+        );
+        for (index, string) in self.strings.iter().enumerate() {
+            let (encoded, len) = encode_string(string);
+            module.push_str(&format!(
+                "@.str.{index} = private unnamed_addr constant [{len} x i8] c\"{encoded}\"\n"
+            ));
+        }
+        for definition in &program.definitions {
+            if let Definition::VarDef(var_def) = definition {
+                for id in &var_def.identifiers {
+                    module.push_str(&format!("{} = global i32 0\n", symbol(id)));
+                }
+            }
+        }
+        for function in functions {
+            module.push('\n');
+            module.push_str(&function);
+        }
+        Ok(module)
+    }
+
+    fn generate_function(&mut self, func: &'a FuncDef) -> Result<String, Box<CodegenError>> {
+        self.allocas.clear();
+        self.body.clear();
+        self.temp_count = 0;
+        self.label_count = 0;
+        self.block = "entry".to_owned();
+        self.terminated = false;
+
+        let mut parameters = vec![];
+        let mut scope = HashMap::new();
+        for (index, (id, toyc_type)) in func
+            .var_def
+            .iter()
+            .flat_map(|v| v.identifiers.iter().map(|id| (id, v.toyc_type.clone())))
+            .enumerate()
+        {
+            let pointer = self.declare_local(id);
+            parameters.push(format!("i32 %arg{index}"));
+            self.allocas
+                .push(format!("store i32 %arg{index}, ptr {pointer}"));
+            if scope
+                .insert(id.as_str(), Variable { pointer, toyc_type })
+                .is_some()
+            {
+                return Err(create_error(CodegenErrorKind::MultipleBindings(id.clone())));
+            }
+        }
+        self.scopes = vec![scope];
+
+        self.generate_statement(&func.statement)?;
+        if !self.terminated {
+            self.terminate("ret i32 0".to_owned());
+        }
+
+        let mut function = format!(
+            "define i32 {}({}) {{\nentry:\n",
+            symbol(&func.identifier),
+            parameters.join(", ")
+        );
+        for line in self.allocas.iter().chain(self.body.iter()) {
+            match line.ends_with(':') {
+                true => function.push_str(&format!("{line}\n")),
+                false => function.push_str(&format!("  {line}\n")),
+            }
+        }
+        function.push_str("}\n");
+        Ok(function)
+    }
+
+    fn generate_statement(&mut self, statement: &'a Statement) -> Result<(), Box<CodegenError>> {
+        match &statement.kind {
+            StatementKind::Expression(expr) => {
+                self.generate_expression(expr)?;
+            }
+            StatementKind::Break => match self.loop_ends.last() {
+                Some(end_label) => self.terminate(format!("br label %{end_label}")),
+                None => return Err(create_error(CodegenErrorKind::BreakOutsideLoop)),
+            },
+            StatementKind::BlockState(var_defs, statements) => {
+                self.push_scope(var_defs)?;
+                let result = statements
+                    .iter()
+                    .try_for_each(|s| self.generate_statement(s));
+                self.scopes.pop();
+                result?;
+            }
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
+                let condition = self.generate_condition(expr)?;
+                let count = self.next_label();
+                let then_label = format!("if.then{count}");
+                let else_label = format!("if.else{count}");
+                let end_label = format!("if.end{count}");
+                let false_label = match else_stmt.as_ref() {
+                    Some(_) => &else_label,
+                    None => &end_label,
+                };
+                self.terminate(format!(
+                    "br i1 {condition}, label %{then_label}, label %{false_label}"
+                ));
+                self.start_block(&then_label);
+                self.generate_statement(if_stmt)?;
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    if !self.terminated {
+                        self.terminate(format!("br label %{end_label}"));
+                    }
+                    self.start_block(&else_label);
+                    self.generate_statement(else_stmt)?;
+                }
+                self.start_block(&end_label);
+            }
+            StatementKind::NullState => {}
+            StatementKind::ReturnState(expr) => {
+                let value = match expr {
+                    Some(expr) => self.generate_expression(expr)?,
+                    None => "0".to_owned(),
+                };
+                self.terminate(format!("ret i32 {value}"));
+            }
+            StatementKind::WhileState(expr, body) => {
+                let count = self.next_label();
+                let cond_label = format!("while.cond{count}");
+                let body_label = format!("while.body{count}");
+                let end_label = format!("while.end{count}");
+                self.start_block(&cond_label);
+                let condition = self.generate_condition(expr)?;
+                self.terminate(format!(
+                    "br i1 {condition}, label %{body_label}, label %{end_label}"
+                ));
+                self.start_block(&body_label);
+                self.loop_ends.push(end_label.clone());
+                let result = self.generate_statement(body);
+                self.loop_ends.pop();
+                result?;
+                if !self.terminated {
+                    self.terminate(format!("br label %{cond_label}"));
+                }
+                self.start_block(&end_label);
+            }
+            StatementKind::ReadState(name, others) => {
+                self.emit("call i32 @fflush(ptr null)".to_owned());
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    let variable = self.lookup(name)?;
+                    match variable.toyc_type {
+                        Type::Int => self.emit(format!(
+                            "call i32 (ptr, ...) @scanf(ptr @.fmt.read.int, ptr {})",
+                            variable.pointer
+                        )),
+                        Type::Char => {
+                            let buffer = format!("%read{}", self.next_label());
+                            self.allocas.push(format!("{buffer} = alloca i8"));
+                            self.allocas.push(format!("store i8 0, ptr {buffer}"));
+                            self.emit(format!(
+                                "call i32 (ptr, ...) @scanf(ptr @.fmt.read.char, ptr {buffer})"
+                            ));
+                            let byte = self.temp(format!("load i8, ptr {buffer}"));
+                            let value = self.temp(format!("zext i8 {byte} to i32"));
+                            self.emit(format!("store i32 {value}, ptr {}", variable.pointer));
+                        }
+                    }
+                }
+            }
+            StatementKind::WriteState(expr, others) => {
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    match &expr.kind {
+                        ExpressionKind::StringLiteral(s) => {
+                            self.strings.push(unescape(s));
+                            self.emit(format!(
+                                "call i32 (ptr, ...) @printf(ptr @.fmt.str, ptr @.str.{})",
+                                self.strings.len() - 1
+                            ));
+                        }
+                        _ => {
+                            let format = match self.type_of(expr)? {
+                                Type::Int => "@.fmt.int",
+                                Type::Char => "@.fmt.char",
+                            };
+                            let value = self.generate_expression(expr)?;
+                            self.emit(format!(
+                                "call i32 (ptr, ...) @printf(ptr {format}, i32 {value})"
+                            ));
+                        }
+                    }
+                }
+            }
+            StatementKind::NewLineState => self.emit("call i32 @putchar(i32 10)".to_owned()),
+        }
+        Ok(())
+    }
+
+    /// Evaluates an expression to an `i32` operand
+    fn generate_expression(
+        &mut self,
+        expression: &'a Expression,
+    ) -> Result<String, Box<CodegenError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(num) => (*num as i32).to_string(),
+            ExpressionKind::CharLiteral(c) => c.map(|c| c as i32).unwrap_or_default().to_string(),
+            ExpressionKind::StringLiteral(_) => {
+                return Err(create_error(CodegenErrorKind::StringValue))
+            }
+            ExpressionKind::Identifier(id) => {
+                let pointer = self.lookup(id)?.pointer;
+                self.temp(format!("load i32, ptr {pointer}"))
+            }
+            ExpressionKind::FuncCall(name, arguments) => {
+                let arity = match self.functions.get(name.as_str()) {
+                    Some((_, arity)) => *arity,
+                    None => {
+                        return Err(create_error(CodegenErrorKind::UndeclaredFunction(
+                            name.clone(),
+                        )))
+                    }
+                };
+                if arity != arguments.len() {
+                    return Err(create_error(CodegenErrorKind::ArgumentCount(
+                        name.clone(),
+                        arity,
+                        arguments.len(),
+                    )));
+                }
+                let arguments = arguments
+                    .iter()
+                    .map(|a| self.generate_expression(a).map(|a| format!("i32 {a}")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.temp(format!(
+                    "call i32 {}({})",
+                    symbol(name),
+                    arguments.join(", ")
+                ))
+            }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                let pointer = match &lhs.kind {
+                    ExpressionKind::Identifier(id) => self.lookup(id)?.pointer,
+                    _ => return Err(create_error(CodegenErrorKind::ExpectedIdentifier)),
+                };
+                let value = self.generate_expression(rhs)?;
+                self.emit(format!("store i32 {value}, ptr {pointer}"));
+                value
+            }
+            ExpressionKind::Expr(op, ..) if op.is_relational() || op.is_logical() => {
+                let condition = self.generate_condition(expression)?;
+                self.temp(format!("zext i1 {condition} to i32"))
+            }
+            ExpressionKind::Not(_) => {
+                let condition = self.generate_condition(expression)?;
+                self.temp(format!("zext i1 {condition} to i32"))
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                let a = self.generate_expression(lhs)?;
+                let b = self.generate_expression(rhs)?;
+                let instruction = match op {
+                    Operator::Plus => "add",
+                    Operator::Minus => "sub",
+                    Operator::Multiply => "mul",
+                    Operator::Divide => "sdiv",
+                    Operator::Modulo => "srem",
+                    _ => unreachable!("relational, logical and assignment handled above"),
+                };
+                match op {
+                    Operator::Divide | Operator::Modulo => {
+                        self.generate_division(instruction, a, b)
+                    }
+                    _ => self.temp(format!("{instruction} i32 {a}, {b}")),
+                }
+            }
+            ExpressionKind::Minus(expr) => {
+                let value = self.generate_expression(expr)?;
+                self.temp(format!("sub i32 0, {value}"))
+            }
+        })
+    }
+
+    /// Divides by 1 instead of -1, where `sdiv` and `srem` overflow on
+    /// `INT_MIN`, and selects the negated dividend or 0 for that case
+    fn generate_division(&mut self, instruction: &str, a: String, b: String) -> String {
+        let minus_one = self.temp(format!("icmp eq i32 {b}, -1"));
+        let divisor = self.temp(format!("select i1 {minus_one}, i32 1, i32 {b}"));
+        let result = self.temp(format!("{instruction} i32 {a}, {divisor}"));
+        let special = match instruction {
+            "sdiv" => self.temp(format!("sub i32 0, {a}")),
+            _ => "0".to_owned(),
+        };
+        self.temp(format!(
+            "select i1 {minus_one}, i32 {special}, i32 {result}"
+        ))
+    }
+
+    /// Evaluates an expression to an `i1` operand, short-circuiting `&&` and `||`
+    fn generate_condition(
+        &mut self,
+        expression: &'a Expression,
+    ) -> Result<String, Box<CodegenError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Expr(op, lhs, rhs) if op.is_relational() => {
+                let a = self.generate_expression(lhs)?;
+                let b = self.generate_expression(rhs)?;
+                let predicate = match op {
+                    Operator::LessEqual => "sle",
+                    Operator::LessThan => "slt",
+                    Operator::GreaterEqual => "sge",
+                    Operator::GreaterThan => "sgt",
+                    Operator::Equal => "eq",
+                    _ => "ne",
+                };
+                self.temp(format!("icmp {predicate} i32 {a}, {b}"))
+            }
+            ExpressionKind::Expr(op, lhs, rhs) if op.is_logical() => {
+                let a = self.generate_condition(lhs)?;
+                let from = self.block.clone();
+                let count = self.next_label();
+                let rhs_label = format!("logic.rhs{count}");
+                let end_label = format!("logic.end{count}");
+                let (short_value, branch) = match op {
+                    Operator::And => ("false", format!("label %{rhs_label}, label %{end_label}")),
+                    _ => ("true", format!("label %{end_label}, label %{rhs_label}")),
+                };
+                self.terminate(format!("br i1 {a}, {branch}"));
+                self.start_block(&rhs_label);
+                let b = self.generate_condition(rhs)?;
+                let rhs_end = self.block.clone();
+                self.start_block(&end_label);
+                self.temp(format!(
+                    "phi i1 [ {short_value}, %{from} ], [ {b}, %{rhs_end} ]"
+                ))
+            }
+            ExpressionKind::Not(expr) => {
+                let condition = self.generate_condition(expr)?;
+                self.temp(format!("xor i1 {condition}, true"))
+            }
+            _ => {
+                let value = self.generate_expression(expression)?;
+                self.temp(format!("icmp ne i32 {value}, 0"))
+            }
+        })
+    }
+
+    /// Static type of an expression: a compound expression takes the type of
+    /// its left-most operand, as in the JVM backend
+    fn type_of(&self, expression: &'a Expression) -> Result<Type, Box<CodegenError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(_) => Type::Int,
+            ExpressionKind::CharLiteral(_) => Type::Char,
+            ExpressionKind::StringLiteral(_) => {
+                return Err(create_error(CodegenErrorKind::StringValue))
+            }
+            ExpressionKind::Identifier(id) => self.lookup(id)?.toyc_type,
+            ExpressionKind::FuncCall(name, _) => match self.functions.get(name.as_str()) {
+                Some((toyc_type, _)) => toyc_type.clone(),
+                None => {
+                    return Err(create_error(CodegenErrorKind::UndeclaredFunction(
+                        name.clone(),
+                    )))
+                }
+            },
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs)?,
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr)?,
+        })
+    }
+
+    fn push_scope(&mut self, var_defs: &'a [VarDef]) -> Result<(), Box<CodegenError>> {
+        let mut scope = HashMap::new();
+        for var_def in var_defs {
+            for id in &var_def.identifiers {
+                let pointer = self.declare_local(id);
+                self.emit(format!("store i32 0, ptr {pointer}"));
+                let variable = Variable {
+                    pointer,
+                    toyc_type: var_def.toyc_type.clone(),
+                };
+                if scope.insert(id.as_str(), variable).is_some() {
+                    return Err(create_error(CodegenErrorKind::MultipleBindings(id.clone())));
+                }
+            }
+        }
+        self.scopes.push(scope);
+        Ok(())
+    }
+
+    fn declare_local(&mut self, id: &str) -> String {
+        self.temp_count += 1;
+        let pointer = format!("%{id}.{}", self.temp_count);
+        self.allocas.push(format!("{pointer} = alloca i32"));
+        pointer
+    }
+
+    fn lookup(&self, name: &str) -> Result<Variable, Box<CodegenError>> {
+        if let Some(variable) = self.scopes.iter().rev().find_map(|s| s.get(name)) {
+            return Ok(variable.clone());
+        }
+        match self.globals.get(name) {
+            Some(toyc_type) => Ok(Variable {
+                pointer: symbol(name),
+                toyc_type: toyc_type.clone(),
+            }),
+            None => Err(create_error(CodegenErrorKind::UndeclaredIdentifier(
+                name.to_owned(),
+            ))),
+        }
+    }
+
+    fn next_label(&mut self) -> usize {
+        self.label_count += 1;
+        self.label_count
+    }
+
+    /// Emits `instruction` into a fresh temporary and returns its name
+    fn temp(&mut self, instruction: String) -> String {
+        self.temp_count += 1;
+        let temp = format!("%t{}", self.temp_count);
+        self.emit(format!("{temp} = {instruction}"));
+        temp
+    }
+
+    /// Appends an instruction, opening an unreachable block first if the
+    /// current one has already been terminated (e.g. code after `break`)
+    fn emit(&mut self, instruction: String) {
+        if self.terminated {
+            let label = format!("dead{}", self.next_label());
+            self.start_block(&label);
+        }
+        self.body.push(instruction);
+    }
+
+    fn terminate(&mut self, instruction: String) {
+        self.emit(instruction);
+        self.terminated = true;
+    }
+
+    /// Starts a new basic block, falling through into it from the current one
+    fn start_block(&mut self, label: &str) {
+        if !self.terminated {
+            self.body.push(format!("br label %{label}"));
+        }
+        self.body.push(format!("{label}:"));
+        self.block = label.to_owned();
+        self.terminated = false;
+    }
+}
+
+/// Global name of a ToyC function or global variable, prefixed so that it
+/// cannot clash with the runtime; `main` keeps its name as the entry point
+fn symbol(name: &str) -> String {
+    match name {
+        "main" => "@main".to_owned(),
+        _ => format!("@toyc.{name}"),
+    }
+}
+
+fn create_error(kind: CodegenErrorKind) -> Box<CodegenError> {
+    Box::new(CodegenError::new(kind))
+}
+
+/// Expands the escape sequences accepted in ToyC string literals
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Encodes a string as the body of an LLVM `c"..."` constant, returning it
+/// with its length in bytes including the terminating NUL
+fn encode_string(s: &str) -> (String, usize) {
+    let mut encoded = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b' '..=b'~' if byte != b'"' && byte != b'\\' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("\\{byte:02X}")),
+        }
+    }
+    encoded.push_str("\\00");
+    (encoded, s.len() + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn generate(source: &str) -> Result<String, Box<CodegenError>> {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        LlvmGenerator::new("test.tc").generate(&program)
+    }
+
+    #[test]
+    fn test_function_and_locals() {
+        let ir = generate("int addTwo(int n){ int a; a = n + 2; return a; } int main(){ write(addTwo(1)); newline; return 0; }").unwrap();
+        assert!(ir.contains("define i32 @toyc.addTwo(i32 %arg0) {"));
+        assert!(ir.contains("%n.1 = alloca i32"));
+        assert!(ir.contains("store i32 %arg0, ptr %n.1"));
+        assert!(ir.contains("call i32 @toyc.addTwo(i32 1)"));
+        assert!(ir.contains("call i32 (ptr, ...) @printf(ptr @.fmt.int, i32 "));
+        assert!(ir.contains("call i32 @putchar(i32 10)"));
+    }
+
+    #[test]
+    fn test_branches_and_break() {
+        let ir = generate(
+            "int main(){ int a; while (1) { read(a); if (a <= 2) break; else ; } return 0; }",
+        )
+        .unwrap();
+        assert!(ir.contains("while.cond1:"));
+        assert!(ir.contains("icmp sle i32"));
+        assert!(ir.contains("br label %while.end1"));
+        assert!(ir.contains("@scanf(ptr @.fmt.read.int, ptr %a.1)"));
+        // every block ends in a terminator
+        let lines = ir.lines().collect::<Vec<_>>();
+        for (index, line) in lines.iter().enumerate().skip(1) {
+            if line.ends_with(':') && !line.starts_with("entry") {
+                let previous = lines[index - 1].trim();
+                assert!(previous.starts_with("br ") || previous.starts_with("ret "));
+            }
+        }
+    }
+
+    #[test]
+    fn test_strings_and_errors() {
+        let ir = generate("int main(){ write(\"a = \\n\"); return 0; }").unwrap();
+        assert!(ir.contains("@.str.0 = private unnamed_addr constant [6 x i8] c\"a = \\0A\\00\""));
+        assert_eq!(
+            generate("int main(){ break; }").unwrap_err().kind,
+            CodegenErrorKind::BreakOutsideLoop
+        );
+        assert_eq!(
+            generate("int main(){ return b; }").unwrap_err().kind,
+            CodegenErrorKind::UndeclaredIdentifier("b".to_owned())
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! Runs generated modules with `lli` and compares their output with the
+//! tree-walking interpreter. The tests pass vacuously without `lli`.
+
+use std::process::Command;
+use toycc_backend_llvm::LlvmGenerator;
+use toycc_test_support::run_program;
+
+/// Major version of the installed `lli`
+fn lli_version() -> Option<u32> {
+    let output = Command::new("lli").arg("--version").output().ok()?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    let version = version.split("LLVM version ").nth(1)?;
+    version.split('.').next()?.trim().parse().ok()
+}
+
+fn run_both(name: &str, source: &str, input: &str) -> Option<String> {
+    let Some(version) = lli_version() else {
+        eprintln!("lli not found, skipping {name}");
+        return None;
+    };
+    let (expected, _) = toycc_test_support::run_both(source, input, |program| {
+        let ir = LlvmGenerator::new("test.tc")
+            .generate(program)
+            .expect("failed to generate");
+        let path = std::env::temp_dir().join(format!("toycc-{}-{name}.ll", std::process::id()));
+        std::fs::write(&path, &ir).unwrap();
+        let mut lli = Command::new("lli");
+        // LLVM 15 made opaque pointers the default
+        if version < 15 {
+            lli.arg("-opaque-pointers");
+        }
+        let (output, status) = run_program(lli.arg(&path), input);
+        std::fs::remove_file(&path).unwrap();
+        (ir, output, Some(status))
+    });
+    Some(expected)
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    run_both("demo", source, "7 1");
+}
+
+#[test]
+fn test_if_else() {
+    let source = "int sign(int n){ if (n < 0) return -1; else if (n == 0) return 0; return 1; }
+int main(){
+    int i;
+    i = -1;
+    while (i < 2) {
+        if (i) write(\"nonzero \"); else write(\"zero \");
+        write(sign(i));
+        newline;
+        i = i + 1;
+    }
+    return 0;
+}";
+    if let Some(output) = run_both("if_else", source, "") {
+        assert_eq!(output, "nonzero -1\nzero 0\nnonzero 1\n");
+    }
+}
+
+#[test]
+fn test_divide_by_minus_one() {
+    let source = "int main(){
+    int a;
+    int m;
+    m = -1;
+    a = -2147483647 - 1;
+    write(a / m, \" \", a % m, \" \", 7 / m, \" \", -7 % 2, \" \", 7 / 2);
+    return 3;
+}";
+    if let Some(output) = run_both("divide", source, "") {
+        assert_eq!(output, "-2147483648 0 -7 -1 3");
+    }
+}
+
+#[test]
+fn test_runtime_names() {
+    let source = "int printf;
+int scanf;
+int putchar(int c){ printf = printf + c; return c; }
+int main(){
+    read(scanf);
+    write(putchar(scanf), \" \", printf);
+    return 0;
+}";
+    if let Some(output) = run_both("runtime_names", source, "7") {
+        assert_eq!(output, "7 7");
+    }
+}
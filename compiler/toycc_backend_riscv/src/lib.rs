@@ -0,0 +1,3 @@
+pub mod riscv_generator;
+
+pub use riscv_generator::RiscvGenerator;
@@ -0,0 +1,664 @@
+use toycc_backend_jvm::symbol_table::{Function, Symbol, SymbolTable};
+use toycc_backend_jvm::{SemanticError, SemanticErrorKind};
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+
+const ARGUMENT_REGISTERS: [&str; 8] = ["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"];
+
+/// Register names without a number; ToyC names that collide with any
+/// register name get a trailing underscore
+const NAMED_REGISTERS: [&str; 7] = ["zero", "ra", "sp", "gp", "tp", "fp", "pc"];
+
+/// Entry point and I/O stubs. The `ecall` numbers follow the RARS/Venus
+/// environment calls: 1 print int, 4 print string, 5 read int, 11 print
+/// char, 12 read char and 93 exit with code.
+const RUNTIME: &str = r#"	.globl	_start
+_start:
+	call	main
+	li	a7, 93
+	ecall
+_toyc_write_int:
+	li	a7, 1
+	ecall
+	ret
+_toyc_write_char:
+	li	a7, 11
+	ecall
+	ret
+_toyc_write_str:
+	li	a7, 4
+	ecall
+	ret
+_toyc_read_int:
+	li	a7, 5
+	ecall
+	ret
+_toyc_read_char:
+	li	a7, 12
+	ecall
+	li	t0, 32
+	bleu	a0, t0, _toyc_read_char
+	ret
+"#;
+
+/// Where a variable lives: an offset from the frame pointer `s0` or a
+/// label in the data section
+enum Location {
+    Frame(i32),
+    Global(String),
+}
+
+/// Emits RV32IM assembly accepted by the GNU assembler and by RARS.
+/// Expressions are evaluated into `a0`; every variable and every pending
+/// temporary lives in its own word below `s0`, so `sp` only moves to pass
+/// arguments past the eighth and stays 16-byte aligned at every call.
+pub struct RiscvGenerator<'a> {
+    source_name: &'a str,
+    symbol_table: Vec<SymbolTable<'a>>,
+    strings: Vec<String>,
+    text: Vec<String>,
+    loop_ends: Vec<usize>,
+    label_count: usize,
+    return_label: usize,
+    slot_count: usize,
+    temporaries: usize,
+    frame_slots: usize,
+}
+
+impl<'a> RiscvGenerator<'a> {
+    pub fn new(source_name: &'a str) -> Self {
+        Self {
+            source_name,
+            symbol_table: vec![SymbolTable::default()],
+            strings: vec![],
+            text: vec![],
+            loop_ends: vec![],
+            label_count: 0,
+            return_label: 0,
+            slot_count: 0,
+            temporaries: 0,
+            frame_slots: 0,
+        }
+    }
+
+    pub fn generate(&mut self, program: &'a Program) -> Result<String, Box<SemanticError>> {
+        let mut globals = vec![];
+        for definition in &program.definitions {
+            match definition {
+                Definition::VarDef(var_def) => {
+                    for id in &var_def.identifiers {
+                        let variable = Symbol::Variable(id.clone(), var_def.toyc_type.clone(), 0);
+                        self.insert_symbol(id, variable)?;
+                        globals.push(symbol_name(id));
+                    }
+                }
+                Definition::FuncDef(func_def) => {
+                    let arguments = func_def
+                        .var_def
+                        .iter()
+                        .flat_map(|v| v.identifiers.iter().map(|_| v.toyc_type.to_string()))
+                        .collect();
+                    let function = Function::new(
+                        func_def.identifier.clone(),
+                        arguments,
+                        vec![],
+                        func_def.toyc_type.clone(),
+                    );
+                    self.insert_symbol(&func_def.identifier, Symbol::Function(function))?;
+                }
+            }
+        }
+
+        let mut functions = vec![];
+        for definition in &program.definitions {
+            if let Definition::FuncDef(func_def) = definition {
+                functions.push(self.generate_function(func_def)?);
+            }
+        }
+
+        let mut assembly = format!("# {}\n", self.source_name);
+        if !self.strings.is_empty() || !globals.is_empty() {
+            assembly.push_str("\t.data\n");
+        }
+        for (index, string) in self.strings.iter().enumerate() {
+            assembly.push_str(&format!("_S{index}:\n\t.asciz\t\"{}\"\n", escape(string)));
+        }
+        if !globals.is_empty() {
+            assembly.push_str("\t.align\t2\n");
+            for global in globals {
+                assembly.push_str(&format!("{global}:\n\t.word\t0\n"));
+            }
+        }
+        assembly.push_str("\t.text\n");
+        assembly.push_str(RUNTIME);
+        for function in functions {
+            assembly.push_str(&function);
+        }
+        Ok(assembly)
+    }
+
+    fn generate_function(&mut self, func_def: &'a FuncDef) -> Result<String, Box<SemanticError>> {
+        self.text.clear();
+        self.slot_count = 0;
+        self.temporaries = 0;
+        self.frame_slots = 0;
+        self.return_label = self.next_label();
+        self.symbol_table.push(SymbolTable::default());
+
+        let parameters = func_def.var_def.iter().flat_map(|v| {
+            v.identifiers
+                .iter()
+                .map(move |id| (id, v.toyc_type.clone()))
+        });
+        for (index, (id, toyc_type)) in parameters.enumerate() {
+            let offset = self.declare_variable(id, toyc_type)?;
+            match ARGUMENT_REGISTERS.get(index) {
+                Some(register) => self.emit(format!("sw\t{register}, {offset}(s0)")),
+                None => {
+                    // arguments past the eighth start at the caller's `sp`
+                    let incoming = 4 * (index - ARGUMENT_REGISTERS.len());
+                    self.emit(format!("lw\tt0, {incoming}(s0)"));
+                    self.emit(format!("sw\tt0, {offset}(s0)"));
+                }
+            }
+        }
+
+        let result = self.generate_statement(&func_def.statement);
+        self.symbol_table.pop();
+        result?;
+        self.emit("li\ta0, 0".to_owned());
+
+        let name = symbol_name(&func_def.identifier);
+        let frame_size = (4 * (2 + self.frame_slots)).div_ceil(16) * 16;
+        let mut function = format!(
+            "\t.globl\t{name}\n{name}:\n\taddi\tsp, sp, -{frame_size}\n\tsw\tra, {}(sp)\n\tsw\ts0, {}(sp)\n\taddi\ts0, sp, {frame_size}\n",
+            frame_size - 4,
+            frame_size - 8,
+        );
+        for line in &self.text {
+            match line.ends_with(':') {
+                true => function.push_str(&format!("{line}\n")),
+                false => function.push_str(&format!("\t{line}\n")),
+            }
+        }
+        function.push_str(&format!(
+            "_L{}:\n\tlw\tra, {}(sp)\n\tlw\ts0, {}(sp)\n\taddi\tsp, sp, {frame_size}\n\tret\n",
+            self.return_label,
+            frame_size - 4,
+            frame_size - 8,
+        ));
+        Ok(function)
+    }
+
+    fn generate_statement(&mut self, statement: &'a Statement) -> Result<(), Box<SemanticError>> {
+        match &statement.kind {
+            StatementKind::Expression(expr) => self.generate_expression(expr)?,
+            StatementKind::Break => match self.loop_ends.last() {
+                Some(end_label) => self.emit(format!("j\t_L{end_label}")),
+                None => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::BreakOutsideLoop,
+                    )))
+                }
+            },
+            StatementKind::BlockState(var_defs, statements) => {
+                self.symbol_table.push(SymbolTable::default());
+                let result = self.generate_block(var_defs, statements);
+                self.symbol_table.pop();
+                result?;
+            }
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
+                let else_label = self.next_label();
+                let end_label = self.next_label();
+                self.generate_expression(expr)?;
+                self.emit(format!("beqz\ta0, _L{else_label}"));
+                self.generate_statement(if_stmt)?;
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    self.emit(format!("j\t_L{end_label}"));
+                    self.emit(format!("_L{else_label}:"));
+                    self.generate_statement(else_stmt)?;
+                    self.emit(format!("_L{end_label}:"));
+                } else {
+                    self.emit(format!("_L{else_label}:"));
+                }
+            }
+            StatementKind::NullState => {}
+            StatementKind::ReturnState(expr) => {
+                match expr {
+                    Some(expr) => self.generate_expression(expr)?,
+                    None => self.emit("li\ta0, 0".to_owned()),
+                }
+                self.emit(format!("j\t_L{}", self.return_label));
+            }
+            StatementKind::WhileState(expr, body) => {
+                let top_label = self.next_label();
+                let end_label = self.next_label();
+                self.emit(format!("_L{top_label}:"));
+                self.generate_expression(expr)?;
+                self.emit(format!("beqz\ta0, _L{end_label}"));
+                self.loop_ends.push(end_label);
+                let result = self.generate_statement(body);
+                self.loop_ends.pop();
+                result?;
+                self.emit(format!("j\t_L{top_label}"));
+                self.emit(format!("_L{end_label}:"));
+            }
+            StatementKind::ReadState(name, others) => {
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    let (location, toyc_type) = self.variable(name)?;
+                    match toyc_type {
+                        Type::Int => self.emit("call\t_toyc_read_int".to_owned()),
+                        Type::Char => self.emit("call\t_toyc_read_char".to_owned()),
+                    }
+                    self.store(&location);
+                }
+            }
+            StatementKind::WriteState(expr, others) => {
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    match &expr.kind {
+                        ExpressionKind::StringLiteral(s) => {
+                            self.strings.push(unescape(s));
+                            self.emit(format!("la\ta0, _S{}", self.strings.len() - 1));
+                            self.emit("call\t_toyc_write_str".to_owned());
+                        }
+                        _ => {
+                            let stub = match self.type_of(expr)? {
+                                Type::Int => "_toyc_write_int",
+                                Type::Char => "_toyc_write_char",
+                            };
+                            self.generate_expression(expr)?;
+                            self.emit(format!("call\t{stub}"));
+                        }
+                    }
+                }
+            }
+            StatementKind::NewLineState => {
+                self.emit("li\ta0, 10".to_owned());
+                self.emit("call\t_toyc_write_char".to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_block(
+        &mut self,
+        var_defs: &'a [VarDef],
+        statements: &'a [Statement],
+    ) -> Result<(), Box<SemanticError>> {
+        for var_def in var_defs {
+            for id in &var_def.identifiers {
+                let offset = self.declare_variable(id, var_def.toyc_type.clone())?;
+                self.emit(format!("sw\tzero, {offset}(s0)"));
+            }
+        }
+        statements
+            .iter()
+            .try_for_each(|statement| self.generate_statement(statement))
+    }
+
+    /// Evaluates an expression into `a0`
+    fn generate_expression(
+        &mut self,
+        expression: &'a Expression,
+    ) -> Result<(), Box<SemanticError>> {
+        match &expression.kind {
+            ExpressionKind::Number(num) => self.emit(format!("li\ta0, {}", *num as i32)),
+            ExpressionKind::CharLiteral(c) => {
+                let value = c.map(|c| c as i32).unwrap_or_default();
+                self.emit(format!("li\ta0, {value}"));
+            }
+            ExpressionKind::StringLiteral(_) => {
+                return Err(Box::new(SemanticError::new(
+                    SemanticErrorKind::IncompatibleTypes,
+                )))
+            }
+            ExpressionKind::Identifier(id) => {
+                let (location, _) = self.variable(id)?;
+                self.load(&location);
+            }
+            ExpressionKind::FuncCall(name, arguments) => self.generate_call(name, arguments)?,
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                let location = match &lhs.kind {
+                    ExpressionKind::Identifier(id) => self.variable(id)?.0,
+                    _ => {
+                        return Err(Box::new(SemanticError::new(
+                            SemanticErrorKind::ExpectedIdentifier,
+                        )))
+                    }
+                };
+                self.generate_expression(rhs)?;
+                self.store(&location);
+            }
+            ExpressionKind::Expr(op, lhs, rhs) if op.is_logical() => {
+                let short_label = self.next_label();
+                let end_label = self.next_label();
+                let (branch, short_value) = match op {
+                    Operator::And => ("beqz", 0),
+                    _ => ("bnez", 1),
+                };
+                self.generate_expression(lhs)?;
+                self.emit(format!("{branch}\ta0, _L{short_label}"));
+                self.generate_expression(rhs)?;
+                self.emit(format!("{branch}\ta0, _L{short_label}"));
+                self.emit(format!("li\ta0, {}", 1 - short_value));
+                self.emit(format!("j\t_L{end_label}"));
+                self.emit(format!("_L{short_label}:"));
+                self.emit(format!("li\ta0, {short_value}"));
+                self.emit(format!("_L{end_label}:"));
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                self.generate_expression(lhs)?;
+                self.push("a0");
+                self.generate_expression(rhs)?;
+                self.pop("t0");
+                match op {
+                    Operator::Plus => self.emit("add\ta0, t0, a0".to_owned()),
+                    Operator::Minus => self.emit("sub\ta0, t0, a0".to_owned()),
+                    Operator::Multiply => self.emit("mul\ta0, t0, a0".to_owned()),
+                    Operator::Divide => self.emit("div\ta0, t0, a0".to_owned()),
+                    Operator::Modulo => self.emit("rem\ta0, t0, a0".to_owned()),
+                    Operator::LessThan => self.emit("slt\ta0, t0, a0".to_owned()),
+                    Operator::GreaterThan => self.emit("slt\ta0, a0, t0".to_owned()),
+                    Operator::LessEqual => {
+                        self.emit("slt\ta0, a0, t0".to_owned());
+                        self.emit("xori\ta0, a0, 1".to_owned());
+                    }
+                    Operator::GreaterEqual => {
+                        self.emit("slt\ta0, t0, a0".to_owned());
+                        self.emit("xori\ta0, a0, 1".to_owned());
+                    }
+                    Operator::Equal => {
+                        self.emit("sub\ta0, t0, a0".to_owned());
+                        self.emit("seqz\ta0, a0".to_owned());
+                    }
+                    _ => {
+                        self.emit("sub\ta0, t0, a0".to_owned());
+                        self.emit("snez\ta0, a0".to_owned());
+                    }
+                }
+            }
+            ExpressionKind::Not(expr) => {
+                self.generate_expression(expr)?;
+                self.emit("seqz\ta0, a0".to_owned());
+            }
+            ExpressionKind::Minus(expr) => {
+                self.generate_expression(expr)?;
+                self.emit("neg\ta0, a0".to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the arguments left to right into temporaries, copies those
+    /// past the eighth into an aligned area at `sp` and loads the first
+    /// eight into `a0`-`a7`
+    fn generate_call(
+        &mut self,
+        name: &str,
+        arguments: &'a [Expression],
+    ) -> Result<(), Box<SemanticError>> {
+        self.function_type(name)?;
+        for argument in arguments {
+            self.generate_expression(argument)?;
+            self.push("a0");
+        }
+        let first = self.temporaries + 1 - arguments.len();
+        let stack_arguments = arguments.len().saturating_sub(ARGUMENT_REGISTERS.len());
+        let area = (4 * stack_arguments).div_ceil(16) * 16;
+        if area > 0 {
+            self.emit(format!("addi\tsp, sp, -{area}"));
+            for index in 0..stack_arguments {
+                let offset = self.temporary_offset(first + ARGUMENT_REGISTERS.len() + index);
+                self.emit(format!("lw\tt0, {offset}(s0)"));
+                self.emit(format!("sw\tt0, {}(sp)", 4 * index));
+            }
+        }
+        for (index, register) in ARGUMENT_REGISTERS.iter().enumerate() {
+            if index < arguments.len() {
+                let offset = self.temporary_offset(first + index);
+                self.emit(format!("lw\t{register}, {offset}(s0)"));
+            }
+        }
+        self.temporaries -= arguments.len();
+        self.emit(format!("call\t{}", symbol_name(name)));
+        if area > 0 {
+            self.emit(format!("addi\tsp, sp, {area}"));
+        }
+        Ok(())
+    }
+
+    /// Static type of an expression: a compound expression takes the type of
+    /// its left-most operand, as in the JVM backend
+    fn type_of(&mut self, expression: &'a Expression) -> Result<Type, Box<SemanticError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(_) | ExpressionKind::StringLiteral(_) => Type::Int,
+            ExpressionKind::CharLiteral(_) => Type::Char,
+            ExpressionKind::Identifier(id) => self.variable(id)?.1,
+            ExpressionKind::FuncCall(name, _) => self.function_type(name)?,
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs)?,
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr)?,
+        })
+    }
+
+    fn variable(&mut self, name: &str) -> Result<(Location, Type), Box<SemanticError>> {
+        for (scope, table) in self.symbol_table.iter_mut().enumerate().rev() {
+            match table.find(name) {
+                Some(Symbol::Variable(_, toyc_type, _)) if scope == 0 => {
+                    return Ok((Location::Global(symbol_name(name)), toyc_type.clone()))
+                }
+                Some(Symbol::Variable(_, toyc_type, slot)) => {
+                    return Ok((Location::Frame(slot_offset(*slot)), toyc_type.clone()))
+                }
+                Some(_) => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::ExpectedIdentifier,
+                    )))
+                }
+                None => {}
+            }
+        }
+        Err(Box::new(SemanticError::new(
+            SemanticErrorKind::UndeclaredIdentifier(name.to_owned()),
+        )))
+    }
+
+    fn function_type(&mut self, name: &str) -> Result<Type, Box<SemanticError>> {
+        match self.symbol_table[0].find(name) {
+            Some(Symbol::Function(function)) => Ok(function.return_type.clone()),
+            Some(_) => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::ExpectedFunction,
+            ))),
+            None => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::UndeclaredFunction(name.to_owned()),
+            ))),
+        }
+    }
+
+    /// Declares a local in a fresh frame slot and returns its offset from `s0`
+    fn declare_variable(
+        &mut self,
+        name: &'a str,
+        toyc_type: Type,
+    ) -> Result<i32, Box<SemanticError>> {
+        self.slot_count += 1;
+        self.frame_slots = self.frame_slots.max(self.slot_count);
+        let slot = self.slot_count;
+        self.insert_symbol(name, Symbol::Variable(name.to_owned(), toyc_type, slot))?;
+        Ok(slot_offset(slot))
+    }
+
+    fn insert_symbol(&mut self, name: &'a str, symbol: Symbol) -> Result<(), Box<SemanticError>> {
+        self.symbol_table
+            .iter_mut()
+            .next_back()
+            .unwrap()
+            .insert(name, symbol)
+            .map(|_| ())
+    }
+
+    fn load(&mut self, location: &Location) {
+        match location {
+            Location::Frame(offset) => self.emit(format!("lw\ta0, {offset}(s0)")),
+            Location::Global(label) => {
+                self.emit(format!("la\tt1, {label}"));
+                self.emit("lw\ta0, 0(t1)".to_owned());
+            }
+        }
+    }
+
+    fn store(&mut self, location: &Location) {
+        match location {
+            Location::Frame(offset) => self.emit(format!("sw\ta0, {offset}(s0)")),
+            Location::Global(label) => {
+                self.emit(format!("la\tt1, {label}"));
+                self.emit("sw\ta0, 0(t1)".to_owned());
+            }
+        }
+    }
+
+    fn next_label(&mut self) -> usize {
+        self.label_count += 1;
+        self.label_count
+    }
+
+    /// Temporaries sit in the slots right after the variables declared so
+    /// far; declarations only happen between statements, when none are live
+    fn temporary_offset(&self, temporary: usize) -> i32 {
+        slot_offset(self.slot_count + temporary)
+    }
+
+    fn push(&mut self, register: &str) {
+        self.temporaries += 1;
+        self.frame_slots = self.frame_slots.max(self.slot_count + self.temporaries);
+        let offset = self.temporary_offset(self.temporaries);
+        self.emit(format!("sw\t{register}, {offset}(s0)"));
+    }
+
+    fn pop(&mut self, register: &str) {
+        let offset = self.temporary_offset(self.temporaries);
+        self.emit(format!("lw\t{register}, {offset}(s0)"));
+        self.temporaries -= 1;
+    }
+
+    fn emit(&mut self, instruction: String) {
+        self.text.push(instruction);
+    }
+}
+
+/// Slot 1 is the word below the saved `ra` and `s0`
+fn slot_offset(slot: usize) -> i32 {
+    -4 * (slot as i32 + 2)
+}
+
+fn symbol_name(name: &str) -> String {
+    match is_register(name) {
+        true => format!("{name}_"),
+        false => name.to_owned(),
+    }
+}
+
+/// Integer and floating-point register names, both numeric and ABI
+fn is_register(name: &str) -> bool {
+    if NAMED_REGISTERS.contains(&name) {
+        return true;
+    }
+    let (prefix, number) = name.split_at(name.find(|c: char| c.is_ascii_digit()).unwrap_or(0));
+    match number.parse::<u8>() {
+        Ok(n) if n.to_string() == number => match prefix {
+            "x" | "f" => n < 32,
+            "t" => n < 7,
+            "s" | "ft" | "fs" => n < 12,
+            "a" | "fa" => n < 8,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Expands the escape sequences accepted in ToyC string literals
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Escapes a string for an `.asciz` directive
+fn escape(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'"' => "\\\"".to_owned(),
+            b'\\' => "\\\\".to_owned(),
+            b'\n' => "\\n".to_owned(),
+            b'\t' => "\\t".to_owned(),
+            b'\r' => "\\r".to_owned(),
+            b' '..=b'~' => (byte as char).to_string(),
+            _ => format!("\\{byte:03o}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn generate(source: &str) -> Result<String, Box<SemanticError>> {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        RiscvGenerator::new("test.tc").generate(&program)
+    }
+
+    #[test]
+    fn test_function_frame() {
+        let asm = generate(
+            "int addTwo(int n){ int a; a = n + 2; return a; } int main(){ return addTwo(1); }",
+        )
+        .unwrap();
+        assert!(asm.contains("addTwo:\n\taddi\tsp, sp, -32\n\tsw\tra, 28(sp)\n\tsw\ts0, 24(sp)\n\taddi\ts0, sp, 32\n"));
+        assert!(asm.contains("\tsw\ta0, -12(s0)\n\tsw\tzero, -16(s0)\n"));
+        // `n` is spilled to the temporary slot after `a` while `2` is loaded
+        assert!(asm.contains("\tlw\ta0, -12(s0)\n\tsw\ta0, -20(s0)\n\tli\ta0, 2\n\tlw\tt0, -20(s0)\n\tadd\ta0, t0, a0\n"));
+        assert!(asm.contains("\tj\t_L1\n"));
+        assert!(
+            asm.contains("_L1:\n\tlw\tra, 28(sp)\n\tlw\ts0, 24(sp)\n\taddi\tsp, sp, 32\n\tret\n")
+        );
+    }
+
+    #[test]
+    fn test_stack_arguments() {
+        let asm = generate("int f(int a, int b, int c, int d, int e, int g, int h, int i, int j, int k){ return k; } int main(){ return f(1,2,3,4,5,6,7,8,9,10); }").unwrap();
+        // the ninth and tenth parameters are read from the caller's frame
+        assert!(asm
+            .contains("\tlw\tt0, 0(s0)\n\tsw\tt0, -44(s0)\n\tlw\tt0, 4(s0)\n\tsw\tt0, -48(s0)\n"));
+        // the caller keeps `sp` 16-byte aligned around the outgoing area
+        assert!(asm.contains("\taddi\tsp, sp, -16\n\tlw\tt0, -44(s0)\n\tsw\tt0, 0(sp)\n"));
+        assert!(asm.contains("\tlw\ta7, -40(s0)\n\tcall\tf\n\taddi\tsp, sp, 16\n"));
+    }
+
+    #[test]
+    fn test_io_and_symbol_names() {
+        let asm = generate("int sp; int a1; int x32; int main(){ char c; read(c, sp); write(\"c=\\n\", c, a1 + x32); newline; return 0; }").unwrap();
+        assert!(asm.contains("sp_:\n\t.word\t0\na1_:\n\t.word\t0\nx32:\n\t.word\t0\n"));
+        assert!(asm.contains("_S0:\n\t.asciz\t\"c=\\n\"\n"));
+        assert!(asm.contains("\tcall\t_toyc_read_char\n\tsw\ta0, -12(s0)\n"));
+        assert!(asm.contains("\tcall\t_toyc_read_int\n\tla\tt1, sp_\n\tsw\ta0, 0(t1)\n"));
+        assert!(asm.contains("\tli\ta0, 10\n\tcall\t_toyc_write_char\n"));
+    }
+}
@@ -0,0 +1,82 @@
+//! Assembles generated programs in the reference simulator and compares
+//! their output with the tree-walking interpreter.
+
+mod simulator;
+
+use simulator::Machine;
+use toycc_backend_riscv::RiscvGenerator;
+
+fn run_both(source: &str, input: &str) -> (String, i32) {
+    toycc_test_support::run_both(source, input, |program| {
+        let assembly = RiscvGenerator::new("test.tc")
+            .generate(program)
+            .expect("failed to generate");
+        let mut machine =
+            Machine::new(&assembly, input).unwrap_or_else(|e| panic!("{e}\n{assembly}"));
+        let code = machine.run().unwrap_or_else(|e| panic!("{e}\n{assembly}"));
+        (assembly, machine.output, Some(code))
+    })
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    assert_eq!(
+        run_both(source, "7 1").0,
+        "input a: input a: a = 3\na = 21\n"
+    );
+}
+
+#[test]
+fn test_stack_arguments() {
+    let source =
+        "int weigh(int a, int b, int c, int d, int e, int f, int g, int h, int i, int j, int k){
+    return a + (2*b) + (3*c) + (4*d) + (5*e) + (6*f) + (7*g) + (8*h) + (9*i) + (10*j) + (11*k);
+}
+int pick(int a, int b, int c, int d, int e, int f, int g, int h, int i){ return (i - a); }
+int main(){
+    write(weigh(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, pick(1, 0, 0, 0, 0, 0, 0, 0, 12)));
+    newline;
+    return pick(2, 0, 0, 0, 0, 0, 0, 0, 1 + weigh(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1));
+}";
+    assert_eq!(run_both(source, ""), ("506\n".to_owned(), 10));
+}
+
+#[test]
+fn test_globals_and_loops() {
+    let source = "int g;
+int sp;
+int bump(int by){ g = g + by; return g; }
+int main(){
+    int i;
+    i = 0;
+    while (i < 4) {
+        int i2;
+        while (1) { if (i2 > i) break; i2 = i2 + 1; sp = sp + 1; }
+        write(i, bump(1), \"\\t\");
+        i = i + 1;
+    }
+    write(sp);
+    newline;
+    return g;
+}";
+    assert_eq!(run_both(source, ""), ("01\t12\t23\t34\t10\n".to_owned(), 4));
+}
+
+#[test]
+fn test_operators_and_input() {
+    let source = "int main(){
+    int a;
+    char c;
+    read(c, a);
+    write(c, \" \", -a, \" \", a / 5, \" \", a % 5, \" \", (a > 3) && (a < 50), (a < 3) || !a, \"\\n\");
+    write(a >= 41, a <= 40, a == 41, a != 41, \"\\n\");
+    write(a = 3, a);
+    newline;
+    return a;
+}";
+    assert_eq!(
+        run_both(source, "  x 41"),
+        ("x -41 8 1 10\n1010\n33\n".to_owned(), 3)
+    );
+}
@@ -0,0 +1,406 @@
+//! A small RV32IM simulator for the subset of instructions and directives the
+//! generator emits. Assembling rejects unknown mnemonics, registers and
+//! labels, and immediates that do not fit their encoding; running follows
+//! the RARS environment calls and checks `sp` is 16-byte aligned at calls.
+
+use std::collections::HashMap;
+
+const MEMORY_SIZE: usize = 1 << 20;
+const DATA_BASE: usize = 0x1000;
+const STEP_LIMIT: usize = 50_000_000;
+
+const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+const RA: usize = 1;
+const SP: usize = 2;
+const A0: usize = 10;
+const A7: usize = 17;
+
+#[derive(Debug, Clone, Copy)]
+enum AluOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Slt,
+    Xor,
+}
+
+#[derive(Debug)]
+enum Instruction {
+    Li(usize, i32),
+    La(usize, usize),
+    Lw(usize, i32, usize),
+    Sw(usize, i32, usize),
+    Alu(AluOp, usize, usize, usize),
+    AluImmediate(AluOp, usize, usize, i32),
+    Seqz(usize, usize),
+    Snez(usize, usize),
+    Beqz(usize, usize),
+    Bnez(usize, usize),
+    Bleu(usize, usize, usize),
+    Jump(usize),
+    Call(usize),
+    Ret,
+    Ecall,
+}
+
+pub struct Machine {
+    text: Vec<Instruction>,
+    entry: usize,
+    memory: Vec<u8>,
+    registers: [i32; 32],
+    input: Vec<u8>,
+    position: usize,
+    pub output: String,
+}
+
+impl Machine {
+    pub fn new(assembly: &str, input: &str) -> Result<Self, String> {
+        let mut memory = vec![0; MEMORY_SIZE];
+        let mut data_end = DATA_BASE;
+        let mut text_labels = HashMap::new();
+        let mut data_labels = HashMap::new();
+        let mut lines = vec![];
+        let mut in_text = true;
+
+        // first pass: lay out the data section and find every label
+        for (number, line) in assembly.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(label) = line.strip_suffix(':') {
+                let previous = match in_text {
+                    true => text_labels.insert(label.to_owned(), lines.len()),
+                    false => data_labels.insert(label.to_owned(), data_end),
+                };
+                if previous.is_some() {
+                    return Err(format!("line {}: duplicate label {label}", number + 1));
+                }
+                continue;
+            }
+            let (mnemonic, operands) = line.split_once('\t').unwrap_or((line, ""));
+            match mnemonic {
+                ".text" => in_text = true,
+                ".data" => in_text = false,
+                ".globl" => {}
+                ".align" if !in_text => {
+                    let alignment = 1 << parse_number(operands)?;
+                    data_end = data_end.div_ceil(alignment) * alignment;
+                }
+                ".word" if !in_text => {
+                    let value = parse_number(operands)?;
+                    memory[data_end..data_end + 4].copy_from_slice(&value.to_le_bytes());
+                    data_end += 4;
+                }
+                ".asciz" if !in_text => {
+                    for byte in parse_string(operands)? {
+                        memory[data_end] = byte;
+                        data_end += 1;
+                    }
+                    data_end += 1;
+                }
+                _ if in_text && !mnemonic.starts_with('.') => {
+                    lines.push((number + 1, mnemonic, operands))
+                }
+                _ => return Err(format!("line {}: unexpected {line}", number + 1)),
+            }
+        }
+
+        // second pass: resolve operands
+        let mut text = vec![];
+        for (number, mnemonic, operands) in lines {
+            let operands: Vec<&str> = match operands.is_empty() {
+                true => vec![],
+                false => operands.split(", ").collect(),
+            };
+            let instruction = assemble(mnemonic, &operands, &text_labels, &data_labels)
+                .map_err(|e| format!("line {number}: {e}"))?;
+            text.push(instruction);
+        }
+
+        let entry = *text_labels.get("_start").ok_or("missing _start")?;
+        Ok(Self {
+            text,
+            entry,
+            memory,
+            registers: [0; 32],
+            input: input.as_bytes().to_vec(),
+            position: 0,
+            output: String::new(),
+        })
+    }
+
+    /// Runs from `_start` until the exit call and returns the exit code
+    pub fn run(&mut self) -> Result<i32, String> {
+        self.registers[SP] = MEMORY_SIZE as i32;
+        let mut pc = self.entry;
+        for _ in 0..STEP_LIMIT {
+            let instruction = self.text.get(pc).ok_or("fell off the end of .text")?;
+            let mut next = pc + 1;
+            match *instruction {
+                Instruction::Li(rd, value) => self.set(rd, value),
+                Instruction::La(rd, address) => self.set(rd, address as i32),
+                Instruction::Lw(rd, offset, base) => {
+                    let address = self.address(base, offset)?;
+                    let word = self.memory[address..address + 4].try_into().unwrap();
+                    self.set(rd, i32::from_le_bytes(word));
+                }
+                Instruction::Sw(rs, offset, base) => {
+                    let address = self.address(base, offset)?;
+                    let value = self.registers[rs].to_le_bytes();
+                    self.memory[address..address + 4].copy_from_slice(&value);
+                }
+                Instruction::Alu(op, rd, rs1, rs2) => {
+                    self.set(rd, alu(op, self.registers[rs1], self.registers[rs2]))
+                }
+                Instruction::AluImmediate(op, rd, rs, value) => {
+                    self.set(rd, alu(op, self.registers[rs], value))
+                }
+                Instruction::Seqz(rd, rs) => self.set(rd, (self.registers[rs] == 0) as i32),
+                Instruction::Snez(rd, rs) => self.set(rd, (self.registers[rs] != 0) as i32),
+                Instruction::Beqz(rs, target) if self.registers[rs] == 0 => next = target,
+                Instruction::Bnez(rs, target) if self.registers[rs] != 0 => next = target,
+                Instruction::Bleu(rs1, rs2, target)
+                    if (self.registers[rs1] as u32) <= (self.registers[rs2] as u32) =>
+                {
+                    next = target
+                }
+                Instruction::Beqz(..) | Instruction::Bnez(..) | Instruction::Bleu(..) => {}
+                Instruction::Jump(target) => next = target,
+                Instruction::Call(target) => {
+                    if self.registers[SP] % 16 != 0 {
+                        return Err(format!("misaligned sp at call from {pc}"));
+                    }
+                    self.registers[RA] = next as i32;
+                    next = target;
+                }
+                Instruction::Ret => next = self.registers[RA] as usize,
+                Instruction::Ecall => {
+                    if let Some(code) = self.ecall()? {
+                        return Ok(code);
+                    }
+                }
+            }
+            pc = next;
+        }
+        Err("step limit exceeded".to_owned())
+    }
+
+    fn ecall(&mut self) -> Result<Option<i32>, String> {
+        let a0 = self.registers[A0];
+        match self.registers[A7] {
+            1 => self.output.push_str(&a0.to_string()),
+            4 => {
+                let start = a0 as usize;
+                let end = start + self.memory[start..].iter().position(|&b| b == 0).unwrap();
+                self.output
+                    .push_str(&String::from_utf8_lossy(&self.memory[start..end]));
+            }
+            5 => {
+                self.skip_whitespace();
+                let start = self.position;
+                while self.position < self.input.len()
+                    && !self.input[self.position].is_ascii_whitespace()
+                {
+                    self.position += 1;
+                }
+                let word = String::from_utf8_lossy(&self.input[start..self.position]);
+                let value = word.parse().map_err(|_| format!("invalid int {word:?}"))?;
+                self.set(A0, value);
+            }
+            11 => self.output.push(a0 as u8 as char),
+            12 => {
+                let value = self.input.get(self.position).map_or(-1, |&b| b as i32);
+                self.position += 1;
+                self.set(A0, value);
+            }
+            93 => return Ok(Some(a0)),
+            call => return Err(format!("unknown environment call {call}")),
+        }
+        Ok(None)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.position)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn address(&self, base: usize, offset: i32) -> Result<usize, String> {
+        let address = self.registers[base].wrapping_add(offset) as usize;
+        match address.is_multiple_of(4) && address >= DATA_BASE && address + 4 <= MEMORY_SIZE {
+            true => Ok(address),
+            false => Err(format!("bad memory access at {address:#x}")),
+        }
+    }
+
+    fn set(&mut self, register: usize, value: i32) {
+        if register != 0 {
+            self.registers[register] = value;
+        }
+    }
+}
+
+fn assemble(
+    mnemonic: &str,
+    operands: &[&str],
+    text_labels: &HashMap<String, usize>,
+    data_labels: &HashMap<String, usize>,
+) -> Result<Instruction, String> {
+    let count = match mnemonic {
+        "ret" | "ecall" => 0,
+        "j" | "call" => 1,
+        "li" | "la" | "lw" | "sw" | "seqz" | "snez" | "neg" | "beqz" | "bnez" => 2,
+        _ => 3,
+    };
+    if operands.len() != count {
+        return Err(format!("{mnemonic} expects {count} operands"));
+    }
+    let operand = |i: usize| register(operands[i]);
+    let target = |i: usize| {
+        text_labels
+            .get(operands[i])
+            .copied()
+            .ok_or_else(|| format!("unknown label {}", operands[i]))
+    };
+    let immediate = |i: usize| {
+        let value = parse_number(operands[i])?;
+        match (-2048..2048).contains(&value) {
+            true => Ok(value),
+            false => Err(format!("immediate {value} out of range")),
+        }
+    };
+    let memory = |i: usize| -> Result<(i32, usize), String> {
+        let (offset, base) = operands[i]
+            .strip_suffix(')')
+            .and_then(|o| o.split_once('('))
+            .ok_or_else(|| format!("expected memory operand, got {}", operands[i]))?;
+        let offset = parse_number(offset)?;
+        match (-2048..2048).contains(&offset) {
+            true => Ok((offset, register(base)?)),
+            false => Err(format!("offset {offset} out of range")),
+        }
+    };
+    let alu = |op| Ok(Instruction::Alu(op, operand(0)?, operand(1)?, operand(2)?));
+
+    match mnemonic {
+        "li" => Ok(Instruction::Li(operand(0)?, parse_number(operands[1])?)),
+        "la" => match data_labels.get(operands[1]) {
+            Some(&address) => Ok(Instruction::La(operand(0)?, address)),
+            None => Err(format!("unknown data label {}", operands[1])),
+        },
+        "lw" => {
+            let (offset, base) = memory(1)?;
+            Ok(Instruction::Lw(operand(0)?, offset, base))
+        }
+        "sw" => {
+            let (offset, base) = memory(1)?;
+            Ok(Instruction::Sw(operand(0)?, offset, base))
+        }
+        "add" => alu(AluOp::Add),
+        "sub" => alu(AluOp::Sub),
+        "mul" => alu(AluOp::Mul),
+        "div" => alu(AluOp::Div),
+        "rem" => alu(AluOp::Rem),
+        "slt" => alu(AluOp::Slt),
+        "addi" => Ok(Instruction::AluImmediate(
+            AluOp::Add,
+            operand(0)?,
+            operand(1)?,
+            immediate(2)?,
+        )),
+        "xori" => Ok(Instruction::AluImmediate(
+            AluOp::Xor,
+            operand(0)?,
+            operand(1)?,
+            immediate(2)?,
+        )),
+        "neg" => Ok(Instruction::Alu(AluOp::Sub, operand(0)?, 0, operand(1)?)),
+        "seqz" => Ok(Instruction::Seqz(operand(0)?, operand(1)?)),
+        "snez" => Ok(Instruction::Snez(operand(0)?, operand(1)?)),
+        "beqz" => Ok(Instruction::Beqz(operand(0)?, target(1)?)),
+        "bnez" => Ok(Instruction::Bnez(operand(0)?, target(1)?)),
+        "bleu" => Ok(Instruction::Bleu(operand(0)?, operand(1)?, target(2)?)),
+        "j" => Ok(Instruction::Jump(target(0)?)),
+        "call" => Ok(Instruction::Call(target(0)?)),
+        "ret" => Ok(Instruction::Ret),
+        "ecall" => Ok(Instruction::Ecall),
+        _ => Err(format!("unknown instruction {mnemonic}")),
+    }
+}
+
+fn alu(op: AluOp, lhs: i32, rhs: i32) -> i32 {
+    match op {
+        AluOp::Add => lhs.wrapping_add(rhs),
+        AluOp::Sub => lhs.wrapping_sub(rhs),
+        AluOp::Mul => lhs.wrapping_mul(rhs),
+        AluOp::Div if rhs == 0 => -1,
+        AluOp::Div => lhs.wrapping_div(rhs),
+        AluOp::Rem if rhs == 0 => lhs,
+        AluOp::Rem => lhs.wrapping_rem(rhs),
+        AluOp::Slt => (lhs < rhs) as i32,
+        AluOp::Xor => lhs ^ rhs,
+    }
+}
+
+fn register(name: &str) -> Result<usize, String> {
+    if name == "fp" {
+        return Ok(8);
+    }
+    ABI_NAMES
+        .iter()
+        .position(|&n| n == name)
+        .or_else(|| {
+            name.strip_prefix('x')
+                .and_then(|n| n.parse().ok())
+                .filter(|&n: &usize| n < 32)
+        })
+        .ok_or_else(|| format!("unknown register {name}"))
+}
+
+fn parse_number(s: &str) -> Result<i32, String> {
+    s.parse().map_err(|_| format!("expected number, got {s}"))
+}
+
+fn parse_string(s: &str) -> Result<Vec<u8>, String> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("expected string, got {s}"))?;
+    let mut bytes = vec![];
+    let mut chars = inner.bytes().peekable();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(digit @ b'0'..=b'7') => {
+                let mut value = digit - b'0';
+                for _ in 0..2 {
+                    match chars.next_if(u8::is_ascii_digit) {
+                        Some(digit) => value = value * 8 + (digit - b'0'),
+                        None => break,
+                    }
+                }
+                bytes.push(value);
+            }
+            Some(byte) => bytes.push(byte),
+            None => return Err("dangling escape".to_owned()),
+        }
+    }
+    Ok(bytes)
+}
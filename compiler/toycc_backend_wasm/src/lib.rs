@@ -0,0 +1,5 @@
+pub mod module;
+pub mod wasm_generator;
+
+pub use module::{Instruction, Module};
+pub use wasm_generator::WasmGenerator;
@@ -0,0 +1,402 @@
+use std::fmt::{Display, Formatter};
+
+/// Name of the module the host functions are imported from
+pub const HOST_MODULE: &str = "host";
+
+/// Host functions every module imports, as `(name, parameter count, returns a value)`.
+/// Imported functions take the first indices of the function index space.
+pub const HOST_FUNCTIONS: [(&str, usize, bool); 6] = [
+    ("write_int", 1, false),
+    ("write_char", 1, false),
+    ("write_str", 2, false),
+    ("read_int", 0, true),
+    ("read_char", 0, true),
+    ("newline", 0, false),
+];
+
+/// The subset of WebAssembly instructions toycc emits. Branch targets are
+/// relative label depths, as in the binary format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Unreachable,
+    Block,
+    Loop,
+    If,
+    /// `if` whose arms leave an `i32` on the stack
+    IfI32,
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Const(i32),
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32GtS,
+    I32LeS,
+    I32GeS,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32RemS,
+}
+
+/// A function defined in the module. All parameters, locals and results
+/// are `i32`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub locals: Vec<String>,
+    pub body: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub globals: Vec<String>,
+    pub functions: Vec<Function>,
+    /// Contents of linear memory starting at address 0
+    pub data: Vec<u8>,
+}
+
+impl Module {
+    /// Index of the exported `main` in the function index space
+    pub fn main_index(&self) -> Option<u32> {
+        self.functions
+            .iter()
+            .position(|f| f.name == "main")
+            .map(|index| (index + HOST_FUNCTIONS.len()) as u32)
+    }
+
+    /// Encodes the module in the WebAssembly binary format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = b"\0asm".to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+
+        // one function type per (parameter count, has result) signature
+        let mut types: Vec<(usize, bool)> = vec![];
+        let mut type_of =
+            |signature: (usize, bool)| match types.iter().position(|t| *t == signature) {
+                Some(index) => index,
+                None => {
+                    types.push(signature);
+                    types.len() - 1
+                }
+            };
+        let import_types = HOST_FUNCTIONS
+            .iter()
+            .map(|(_, params, result)| type_of((*params, *result)))
+            .collect::<Vec<_>>();
+        let function_types = self
+            .functions
+            .iter()
+            .map(|f| type_of((f.params.len(), true)))
+            .collect::<Vec<_>>();
+
+        section(&mut bytes, 1, types.len(), |out| {
+            for (params, result) in &types {
+                out.push(0x60);
+                unsigned(out, *params as u64);
+                out.extend(std::iter::repeat_n(0x7f, *params));
+                unsigned(out, *result as u64);
+                if *result {
+                    out.push(0x7f);
+                }
+            }
+        });
+        section(&mut bytes, 2, HOST_FUNCTIONS.len(), |out| {
+            for ((name, ..), type_index) in HOST_FUNCTIONS.iter().zip(&import_types) {
+                name_bytes(out, HOST_MODULE);
+                name_bytes(out, name);
+                out.push(0x00);
+                unsigned(out, *type_index as u64);
+            }
+        });
+        section(&mut bytes, 3, function_types.len(), |out| {
+            for type_index in &function_types {
+                unsigned(out, *type_index as u64);
+            }
+        });
+        section(&mut bytes, 5, 1, |out| {
+            out.push(0x00);
+            unsigned(out, self.pages() as u64);
+        });
+        if !self.globals.is_empty() {
+            section(&mut bytes, 6, self.globals.len(), |out| {
+                for _ in &self.globals {
+                    out.extend_from_slice(&[0x7f, 0x01, 0x41, 0x00, 0x0b]);
+                }
+            });
+        }
+        let main = self.main_index();
+        section(&mut bytes, 7, 1 + main.is_some() as usize, |out| {
+            name_bytes(out, "memory");
+            out.extend_from_slice(&[0x02, 0x00]);
+            if let Some(main) = main {
+                name_bytes(out, "main");
+                out.push(0x00);
+                unsigned(out, main as u64);
+            }
+        });
+        section(&mut bytes, 10, self.functions.len(), |out| {
+            for function in &self.functions {
+                let mut body = vec![];
+                match function.locals.is_empty() {
+                    true => body.push(0x00),
+                    false => {
+                        body.push(0x01);
+                        unsigned(&mut body, function.locals.len() as u64);
+                        body.push(0x7f);
+                    }
+                }
+                for instruction in &function.body {
+                    encode_instruction(&mut body, instruction);
+                }
+                body.push(0x0b);
+                unsigned(out, body.len() as u64);
+                out.extend(body);
+            }
+        });
+        if !self.data.is_empty() {
+            section(&mut bytes, 11, 1, |out| {
+                out.extend_from_slice(&[0x00, 0x41, 0x00, 0x0b]);
+                unsigned(out, self.data.len() as u64);
+                out.extend_from_slice(&self.data);
+            });
+        }
+        bytes
+    }
+
+    fn pages(&self) -> usize {
+        self.data.len().div_ceil(0x10000).max(1)
+    }
+}
+
+fn section(bytes: &mut Vec<u8>, id: u8, count: usize, contents: impl FnOnce(&mut Vec<u8>)) {
+    let mut body = vec![];
+    unsigned(&mut body, count as u64);
+    contents(&mut body);
+    bytes.push(id);
+    unsigned(bytes, body.len() as u64);
+    bytes.extend(body);
+}
+
+fn name_bytes(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+/// Unsigned LEB128
+pub fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Signed LEB128
+pub fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn encode_instruction(out: &mut Vec<u8>, instruction: &Instruction) {
+    use Instruction::*;
+    match instruction {
+        Unreachable => out.push(0x00),
+        Block => out.extend_from_slice(&[0x02, 0x40]),
+        Loop => out.extend_from_slice(&[0x03, 0x40]),
+        If => out.extend_from_slice(&[0x04, 0x40]),
+        IfI32 => out.extend_from_slice(&[0x04, 0x7f]),
+        Else => out.push(0x05),
+        End => out.push(0x0b),
+        Br(depth) => {
+            out.push(0x0c);
+            unsigned(out, *depth as u64);
+        }
+        BrIf(depth) => {
+            out.push(0x0d);
+            unsigned(out, *depth as u64);
+        }
+        Return => out.push(0x0f),
+        Call(index) => {
+            out.push(0x10);
+            unsigned(out, *index as u64);
+        }
+        Drop => out.push(0x1a),
+        LocalGet(index) | LocalSet(index) | LocalTee(index) | GlobalGet(index)
+        | GlobalSet(index) => {
+            out.push(match instruction {
+                LocalGet(_) => 0x20,
+                LocalSet(_) => 0x21,
+                LocalTee(_) => 0x22,
+                GlobalGet(_) => 0x23,
+                _ => 0x24,
+            });
+            unsigned(out, *index as u64);
+        }
+        I32Const(value) => {
+            out.push(0x41);
+            signed(out, *value as i64);
+        }
+        I32Eqz => out.push(0x45),
+        I32Eq => out.push(0x46),
+        I32Ne => out.push(0x47),
+        I32LtS => out.push(0x48),
+        I32GtS => out.push(0x4a),
+        I32LeS => out.push(0x4c),
+        I32GeS => out.push(0x4e),
+        I32Add => out.push(0x6a),
+        I32Sub => out.push(0x6b),
+        I32Mul => out.push(0x6c),
+        I32DivS => out.push(0x6d),
+        I32RemS => out.push(0x6f),
+    }
+}
+
+/// Prints the module in the WebAssembly text format
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "(module")?;
+        for (name, params, result) in HOST_FUNCTIONS {
+            write!(
+                f,
+                "  (import \"{HOST_MODULE}\" \"{name}\" (func ${HOST_MODULE}.{name}"
+            )?;
+            if params > 0 {
+                write!(f, " (param{})", " i32".repeat(params))?;
+            }
+            if result {
+                write!(f, " (result i32)")?;
+            }
+            writeln!(f, "))")?;
+        }
+        writeln!(f, "  (memory (export \"memory\") {})", self.pages())?;
+        for global in &self.globals {
+            writeln!(f, "  (global ${global} (mut i32) (i32.const 0))")?;
+        }
+        if !self.data.is_empty() {
+            let data = self
+                .data
+                .iter()
+                .map(|byte| match byte {
+                    b' '..=b'~' if *byte != b'"' && *byte != b'\\' => (*byte as char).to_string(),
+                    _ => format!("\\{byte:02x}"),
+                })
+                .collect::<String>();
+            writeln!(f, "  (data (i32.const 0) \"{data}\")")?;
+        }
+        for function in &self.functions {
+            write!(f, "  (func ${}", function.name)?;
+            if function.name == "main" {
+                write!(f, " (export \"main\")")?;
+            }
+            for param in &function.params {
+                write!(f, " (param ${param} i32)")?;
+            }
+            writeln!(f, " (result i32)")?;
+            for local in &function.locals {
+                writeln!(f, "    (local ${local} i32)")?;
+            }
+            let mut depth = 2;
+            for instruction in &function.body {
+                if matches!(instruction, Instruction::Else | Instruction::End) {
+                    depth -= 1;
+                }
+                writeln!(
+                    f,
+                    "{}{}",
+                    "  ".repeat(depth),
+                    self.instruction_text(function, instruction)
+                )?;
+                if matches!(
+                    instruction,
+                    Instruction::Block
+                        | Instruction::Loop
+                        | Instruction::If
+                        | Instruction::IfI32
+                        | Instruction::Else
+                ) {
+                    depth += 1;
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Module {
+    fn instruction_text(&self, function: &Function, instruction: &Instruction) -> String {
+        use Instruction::*;
+        let local = |index: &u32| {
+            let name = function
+                .params
+                .iter()
+                .chain(&function.locals)
+                .nth(*index as usize);
+            format!("${}", name.map(String::as_str).unwrap_or("?"))
+        };
+        match instruction {
+            Unreachable => "unreachable".to_owned(),
+            Block => "block".to_owned(),
+            Loop => "loop".to_owned(),
+            If => "if".to_owned(),
+            IfI32 => "if (result i32)".to_owned(),
+            Else => "else".to_owned(),
+            End => "end".to_owned(),
+            Br(depth) => format!("br {depth}"),
+            BrIf(depth) => format!("br_if {depth}"),
+            Return => "return".to_owned(),
+            Call(index) => {
+                let index = *index as usize;
+                let name = match HOST_FUNCTIONS.get(index) {
+                    Some((name, ..)) => format!("{HOST_MODULE}.{name}"),
+                    None => self.functions[index - HOST_FUNCTIONS.len()].name.clone(),
+                };
+                format!("call ${name}")
+            }
+            Drop => "drop".to_owned(),
+            LocalGet(index) => format!("local.get {}", local(index)),
+            LocalSet(index) => format!("local.set {}", local(index)),
+            LocalTee(index) => format!("local.tee {}", local(index)),
+            GlobalGet(index) => format!("global.get ${}", self.globals[*index as usize]),
+            GlobalSet(index) => format!("global.set ${}", self.globals[*index as usize]),
+            I32Const(value) => format!("i32.const {value}"),
+            I32Eqz => "i32.eqz".to_owned(),
+            I32Eq => "i32.eq".to_owned(),
+            I32Ne => "i32.ne".to_owned(),
+            I32LtS => "i32.lt_s".to_owned(),
+            I32GtS => "i32.gt_s".to_owned(),
+            I32LeS => "i32.le_s".to_owned(),
+            I32GeS => "i32.ge_s".to_owned(),
+            I32Add => "i32.add".to_owned(),
+            I32Sub => "i32.sub".to_owned(),
+            I32Mul => "i32.mul".to_owned(),
+            I32DivS => "i32.div_s".to_owned(),
+            I32RemS => "i32.rem_s".to_owned(),
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use crate::module::{Function, Instruction, Module, HOST_FUNCTIONS};
+use std::collections::HashMap;
+use toycc_backend_jvm::{SemanticError, SemanticErrorKind};
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+
+#[derive(Debug, Clone, Copy)]
+enum Storage {
+    Local(u32),
+    Global(u32),
+}
+
+/// Kind of an enclosing structured control instruction
+#[derive(PartialEq)]
+enum Control {
+    /// The `block` around a `while`, which `break` exits
+    Loop,
+    Other,
+}
+
+/// Lowers a checked program to a WebAssembly [`Module`]
+#[derive(Default)]
+pub struct WasmGenerator<'a> {
+    module: Module,
+    functions: HashMap<&'a str, (u32, Type, usize)>,
+    globals: HashMap<&'a str, (u32, Type)>,
+    scopes: Vec<HashMap<&'a str, (u32, Type)>>,
+    control: Vec<Control>,
+    function: Function,
+}
+
+impl<'a> WasmGenerator<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn generate(&mut self, program: &'a Program) -> Result<Module, Box<SemanticError>> {
+        let mut function_count = HOST_FUNCTIONS.len() as u32;
+        for definition in &program.definitions {
+            match definition {
+                Definition::VarDef(var_def) => {
+                    for id in &var_def.identifiers {
+                        let index = self.module.globals.len() as u32;
+                        let global = (index, var_def.toyc_type.clone());
+                        if self.globals.insert(id, global).is_some() {
+                            return Err(error(SemanticErrorKind::MultipleBindings(id.clone())));
+                        }
+                        self.module.globals.push(id.clone());
+                    }
+                }
+                Definition::FuncDef(func_def) => {
+                    let arity = func_def.var_def.iter().map(|v| v.identifiers.len()).sum();
+                    let function = (function_count, func_def.toyc_type.clone(), arity);
+                    if self
+                        .functions
+                        .insert(&func_def.identifier, function)
+                        .is_some()
+                    {
+                        return Err(error(SemanticErrorKind::MultipleBindings(
+                            func_def.identifier.clone(),
+                        )));
+                    }
+                    function_count += 1;
+                }
+            }
+        }
+        if !self.functions.contains_key("main") {
+            return Err(error(SemanticErrorKind::MissingMain));
+        }
+
+        for definition in &program.definitions {
+            if let Definition::FuncDef(func_def) = definition {
+                let function = self.generate_function(func_def)?;
+                self.module.functions.push(function);
+            }
+        }
+        Ok(std::mem::take(&mut self.module))
+    }
+
+    fn generate_function(&mut self, func_def: &'a FuncDef) -> Result<Function, Box<SemanticError>> {
+        self.function = Function {
+            name: func_def.identifier.clone(),
+            ..Default::default()
+        };
+        let mut scope = HashMap::new();
+        for var_def in &func_def.var_def {
+            for id in &var_def.identifiers {
+                let index = self.function.params.len() as u32;
+                if scope
+                    .insert(id.as_str(), (index, var_def.toyc_type.clone()))
+                    .is_some()
+                {
+                    return Err(error(SemanticErrorKind::MultipleBindings(id.clone())));
+                }
+                self.function.params.push(id.clone());
+            }
+        }
+        self.scopes = vec![scope];
+
+        self.generate_statement(&func_def.statement)?;
+        // falling off the end of a ToyC function returns 0
+        self.emit(Instruction::I32Const(0));
+        Ok(std::mem::take(&mut self.function))
+    }
+
+    fn generate_statement(&mut self, statement: &'a Statement) -> Result<(), Box<SemanticError>> {
+        match &statement.kind {
+            StatementKind::Expression(expr) => match &expr.kind {
+                ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                    self.generate_assignment(lhs, rhs, false)?
+                }
+                _ => {
+                    self.generate_expression(expr)?;
+                    self.emit(Instruction::Drop);
+                }
+            },
+            StatementKind::Break => {
+                match self.control.iter().rev().position(|c| *c == Control::Loop) {
+                    Some(depth) => self.emit(Instruction::Br(depth as u32)),
+                    None => return Err(error(SemanticErrorKind::BreakOutsideLoop)),
+                }
+            }
+            StatementKind::BlockState(var_defs, statements) => {
+                self.push_scope(var_defs)?;
+                let result = statements
+                    .iter()
+                    .try_for_each(|statement| self.generate_statement(statement));
+                self.scopes.pop();
+                result?;
+            }
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
+                self.generate_expression(expr)?;
+                self.emit(Instruction::If);
+                self.control.push(Control::Other);
+                self.generate_statement(if_stmt)?;
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    self.emit(Instruction::Else);
+                    self.generate_statement(else_stmt)?;
+                }
+                self.control.pop();
+                self.emit(Instruction::End);
+            }
+            StatementKind::NullState => {}
+            StatementKind::ReturnState(expr) => {
+                match expr {
+                    Some(expr) => self.generate_expression(expr)?,
+                    None => self.emit(Instruction::I32Const(0)),
+                }
+                self.emit(Instruction::Return);
+            }
+            StatementKind::WhileState(expr, body) => {
+                self.emit(Instruction::Block);
+                self.control.push(Control::Loop);
+                self.emit(Instruction::Loop);
+                self.control.push(Control::Other);
+                self.generate_expression(expr)?;
+                self.emit(Instruction::I32Eqz);
+                self.emit(Instruction::BrIf(1));
+                let result = self.generate_statement(body);
+                self.control.truncate(self.control.len() - 2);
+                result?;
+                self.emit(Instruction::Br(0));
+                self.emit(Instruction::End);
+                self.emit(Instruction::End);
+            }
+            StatementKind::ReadState(name, others) => {
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    let (storage, toyc_type) = self.variable(name)?;
+                    self.emit(Instruction::Call(match toyc_type {
+                        Type::Int => host_function("read_int"),
+                        Type::Char => host_function("read_char"),
+                    }));
+                    self.emit(match storage {
+                        Storage::Local(index) => Instruction::LocalSet(index),
+                        Storage::Global(index) => Instruction::GlobalSet(index),
+                    });
+                }
+            }
+            StatementKind::WriteState(expr, others) => {
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    match &expr.kind {
+                        ExpressionKind::StringLiteral(s) => {
+                            let string = unescape(s);
+                            self.emit(Instruction::I32Const(self.module.data.len() as i32));
+                            self.emit(Instruction::I32Const(string.len() as i32));
+                            self.module.data.extend_from_slice(string.as_bytes());
+                            self.emit(Instruction::Call(host_function("write_str")));
+                        }
+                        _ => {
+                            let function = match self.type_of(expr)? {
+                                Type::Int => host_function("write_int"),
+                                Type::Char => host_function("write_char"),
+                            };
+                            self.generate_expression(expr)?;
+                            self.emit(Instruction::Call(function));
+                        }
+                    }
+                }
+            }
+            StatementKind::NewLineState => {
+                self.emit(Instruction::Call(host_function("newline")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes the value of an expression onto the operand stack
+    fn generate_expression(
+        &mut self,
+        expression: &'a Expression,
+    ) -> Result<(), Box<SemanticError>> {
+        match &expression.kind {
+            ExpressionKind::Number(num) => self.emit(Instruction::I32Const(*num as i32)),
+            ExpressionKind::CharLiteral(c) => self.emit(Instruction::I32Const(
+                c.map(|c| c as i32).unwrap_or_default(),
+            )),
+            ExpressionKind::StringLiteral(_) => {
+                return Err(error(SemanticErrorKind::IncompatibleTypes))
+            }
+            ExpressionKind::Identifier(id) => match self.variable(id)?.0 {
+                Storage::Local(index) => self.emit(Instruction::LocalGet(index)),
+                Storage::Global(index) => self.emit(Instruction::GlobalGet(index)),
+            },
+            ExpressionKind::FuncCall(name, arguments) => {
+                let (index, arity) = match self.functions.get(name.as_str()) {
+                    Some((index, _, arity)) => (*index, *arity),
+                    None => return Err(error(SemanticErrorKind::UndeclaredFunction(name.clone()))),
+                };
+                if arity != arguments.len() {
+                    return Err(error(SemanticErrorKind::IncompatibleTypes));
+                }
+                for argument in arguments {
+                    self.generate_expression(argument)?;
+                }
+                self.emit(Instruction::Call(index));
+            }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                self.generate_assignment(lhs, rhs, true)?
+            }
+            ExpressionKind::Expr(Operator::And, lhs, rhs) => {
+                self.generate_expression(lhs)?;
+                self.emit(Instruction::IfI32);
+                self.control.push(Control::Other);
+                self.generate_expression(rhs)?;
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::I32Ne);
+                self.emit(Instruction::Else);
+                self.emit(Instruction::I32Const(0));
+                self.control.pop();
+                self.emit(Instruction::End);
+            }
+            ExpressionKind::Expr(Operator::Or, lhs, rhs) => {
+                self.generate_expression(lhs)?;
+                self.emit(Instruction::IfI32);
+                self.control.push(Control::Other);
+                self.emit(Instruction::I32Const(1));
+                self.emit(Instruction::Else);
+                self.generate_expression(rhs)?;
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::I32Ne);
+                self.control.pop();
+                self.emit(Instruction::End);
+            }
+            ExpressionKind::Expr(op @ (Operator::Divide | Operator::Modulo), lhs, rhs) => {
+                self.generate_expression(lhs)?;
+                self.generate_expression(rhs)?;
+                self.generate_division(op);
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                self.generate_expression(lhs)?;
+                self.generate_expression(rhs)?;
+                self.emit(match op {
+                    Operator::Plus => Instruction::I32Add,
+                    Operator::Minus => Instruction::I32Sub,
+                    Operator::Multiply => Instruction::I32Mul,
+                    Operator::LessEqual => Instruction::I32LeS,
+                    Operator::LessThan => Instruction::I32LtS,
+                    Operator::GreaterEqual => Instruction::I32GeS,
+                    Operator::GreaterThan => Instruction::I32GtS,
+                    Operator::Equal => Instruction::I32Eq,
+                    Operator::NotEqual => Instruction::I32Ne,
+                    _ => unreachable!("handled above"),
+                });
+            }
+            ExpressionKind::Not(expr) => {
+                self.generate_expression(expr)?;
+                self.emit(Instruction::I32Eqz);
+            }
+            ExpressionKind::Minus(expr) => {
+                self.emit(Instruction::I32Const(0));
+                self.generate_expression(expr)?;
+                self.emit(Instruction::I32Sub);
+            }
+        }
+        Ok(())
+    }
+
+    /// Divides the two operands on the stack. `i32.div_s` traps on
+    /// `INT_MIN / -1`, so a divisor of -1 negates instead and leaves 0 as
+    /// the remainder.
+    fn generate_division(&mut self, op: &Operator) {
+        let lhs = self.scratch_local("div.lhs");
+        let rhs = self.scratch_local("div.rhs");
+        self.emit(Instruction::LocalSet(rhs));
+        self.emit(Instruction::LocalSet(lhs));
+        self.emit(Instruction::LocalGet(rhs));
+        self.emit(Instruction::I32Const(-1));
+        self.emit(Instruction::I32Eq);
+        self.emit(Instruction::IfI32);
+        self.control.push(Control::Other);
+        self.emit(Instruction::I32Const(0));
+        if *op == Operator::Divide {
+            self.emit(Instruction::LocalGet(lhs));
+            self.emit(Instruction::I32Sub);
+        }
+        self.emit(Instruction::Else);
+        self.emit(Instruction::LocalGet(lhs));
+        self.emit(Instruction::LocalGet(rhs));
+        self.emit(match op {
+            Operator::Divide => Instruction::I32DivS,
+            _ => Instruction::I32RemS,
+        });
+        self.control.pop();
+        self.emit(Instruction::End);
+    }
+
+    /// Index of a local the generator itself uses, added on first use. The
+    /// `.` keeps the name apart from those of ToyC variables.
+    fn scratch_local(&mut self, name: &str) -> u32 {
+        let position = match self.function.locals.iter().position(|local| local == name) {
+            Some(position) => position,
+            None => {
+                self.function.locals.push(name.to_owned());
+                self.function.locals.len() - 1
+            }
+        };
+        (self.function.params.len() + position) as u32
+    }
+
+    /// Stores `rhs` into the variable named by `lhs`, leaving the assigned
+    /// value on the stack when `keep_value` is set
+    fn generate_assignment(
+        &mut self,
+        lhs: &'a Expression,
+        rhs: &'a Expression,
+        keep_value: bool,
+    ) -> Result<(), Box<SemanticError>> {
+        let storage = match &lhs.kind {
+            ExpressionKind::Identifier(id) => self.variable(id)?.0,
+            _ => return Err(error(SemanticErrorKind::ExpectedIdentifier)),
+        };
+        self.generate_expression(rhs)?;
+        match (storage, keep_value) {
+            (Storage::Local(index), true) => self.emit(Instruction::LocalTee(index)),
+            (Storage::Local(index), false) => self.emit(Instruction::LocalSet(index)),
+            (Storage::Global(index), keep_value) => {
+                self.emit(Instruction::GlobalSet(index));
+                if keep_value {
+                    self.emit(Instruction::GlobalGet(index));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Static type of an expression: a compound expression takes the type of
+    /// its left-most operand, as in the JVM backend
+    fn type_of(&self, expression: &'a Expression) -> Result<Type, Box<SemanticError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(_) | ExpressionKind::StringLiteral(_) => Type::Int,
+            ExpressionKind::CharLiteral(_) => Type::Char,
+            ExpressionKind::Identifier(id) => self.variable(id)?.1,
+            ExpressionKind::FuncCall(name, _) => match self.functions.get(name.as_str()) {
+                Some((_, toyc_type, _)) => toyc_type.clone(),
+                None => return Err(error(SemanticErrorKind::UndeclaredFunction(name.clone()))),
+            },
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs)?,
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr)?,
+        })
+    }
+
+    /// Declares the variables of a block as fresh zeroed locals; names are
+    /// made unique within the function so the text format stays valid
+    fn push_scope(&mut self, var_defs: &'a [VarDef]) -> Result<(), Box<SemanticError>> {
+        let mut scope = HashMap::new();
+        for var_def in var_defs {
+            for id in &var_def.identifiers {
+                let index = (self.function.params.len() + self.function.locals.len()) as u32;
+                if scope
+                    .insert(id.as_str(), (index, var_def.toyc_type.clone()))
+                    .is_some()
+                {
+                    return Err(error(SemanticErrorKind::MultipleBindings(id.clone())));
+                }
+                let taken = |name: &String| {
+                    self.function.params.contains(name) || self.function.locals.contains(name)
+                };
+                let mut name = id.clone();
+                let mut suffix = 0;
+                while taken(&name) {
+                    suffix += 1;
+                    name = format!("{id}.{suffix}");
+                }
+                self.function.locals.push(name);
+                // locals are only zeroed on function entry, but a block may
+                // be entered many times
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::LocalSet(index));
+            }
+        }
+        self.scopes.push(scope);
+        Ok(())
+    }
+
+    fn variable(&self, name: &str) -> Result<(Storage, Type), Box<SemanticError>> {
+        if let Some((index, toyc_type)) = self.scopes.iter().rev().find_map(|s| s.get(name)) {
+            return Ok((Storage::Local(*index), toyc_type.clone()));
+        }
+        match self.globals.get(name) {
+            Some((index, toyc_type)) => Ok((Storage::Global(*index), toyc_type.clone())),
+            None => Err(error(SemanticErrorKind::UndeclaredIdentifier(
+                name.to_owned(),
+            ))),
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.function.body.push(instruction);
+    }
+}
+
+fn host_function(name: &str) -> u32 {
+    HOST_FUNCTIONS
+        .iter()
+        .position(|(host, ..)| *host == name)
+        .expect("unknown host function") as u32
+}
+
+fn error(kind: SemanticErrorKind) -> Box<SemanticError> {
+    Box::new(SemanticError::new(kind))
+}
+
+/// Expands the escape sequences accepted in ToyC string literals
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn generate(source: &str) -> Module {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        WasmGenerator::new()
+            .generate(&program)
+            .expect("failed to generate")
+    }
+
+    #[test]
+    fn test_text_format() {
+        let module = generate("int g; int main(){ int a; { int a; a = 1; } while (1) { if (a) break; write(\"hi\", a); } return g; }");
+        let wat = module.to_string();
+        assert!(
+            wat.contains("  (import \"host\" \"write_int\" (func $host.write_int (param i32)))\n")
+        );
+        assert!(wat.contains("  (memory (export \"memory\") 1)\n"));
+        assert!(wat.contains("  (global $g (mut i32) (i32.const 0))\n"));
+        assert!(wat.contains("  (data (i32.const 0) \"hi\")\n"));
+        assert!(wat.contains("  (func $main (export \"main\") (result i32)\n    (local $a i32)\n    (local $a.1 i32)\n"));
+        // break leaves the if, the loop and the surrounding block
+        assert!(wat.contains("        if\n          br 2\n        end\n"));
+        assert!(wat.contains("    global.get $g\n    return\n"));
+    }
+
+    #[test]
+    fn test_binary_header() {
+        let bytes = generate("int main(){ return 0; }").encode();
+        assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+        // type section comes first
+        assert_eq!(bytes[8], 1);
+    }
+}
@@ -0,0 +1,537 @@
+//! A small reference evaluator for the modules toycc emits. It decodes the
+//! binary format itself, so running a program also checks the encoding.
+
+use std::rc::Rc;
+use toycc_backend_wasm::module::{HOST_FUNCTIONS, HOST_MODULE};
+use toycc_backend_wasm::Instruction;
+
+const MAX_CALL_DEPTH: usize = 1_000;
+
+#[derive(Debug)]
+struct Body {
+    params: usize,
+    locals: usize,
+    code: Vec<Instruction>,
+    /// For every `block`/`loop`/`if`, the index of its `else` (if any) and `end`
+    targets: Vec<(Option<usize>, usize)>,
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    types: Vec<(usize, usize)>,
+    imports: Vec<usize>,
+    functions: Rc<Vec<Body>>,
+    globals: Vec<i32>,
+    memory: Vec<u8>,
+    main: Option<usize>,
+    input: Vec<u8>,
+    position: usize,
+    pub output: String,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or("unexpected end of module")?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn expect(&mut self, expected: &[u8], what: &str) -> Result<(), String> {
+        for byte in expected {
+            if self.byte()? != *byte {
+                return Err(format!("malformed {what}"));
+            }
+        }
+        Ok(())
+    }
+
+    fn unsigned(&mut self) -> Result<u64, String> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn index(&mut self) -> Result<usize, String> {
+        Ok(self.unsigned()? as usize)
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            result |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1 << shift;
+                }
+                return Ok(result);
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let len = self.index()?;
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + len)
+            .ok_or("name out of bounds")?;
+        self.position += len;
+        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+impl Instance {
+    /// Decodes and validates a binary module
+    pub fn new(bytes: &[u8], input: &str) -> Result<Self, String> {
+        let mut reader = Reader { bytes, position: 0 };
+        reader.expect(b"\0asm\x01\0\0\0", "header")?;
+        let mut instance = Instance {
+            types: vec![],
+            imports: vec![],
+            functions: Rc::default(),
+            globals: vec![],
+            memory: vec![],
+            main: None,
+            input: input.as_bytes().to_vec(),
+            position: 0,
+            output: String::new(),
+        };
+        let mut function_types = vec![];
+        let mut functions = vec![];
+        let mut last_section = 0;
+        while reader.position < bytes.len() {
+            let id = reader.byte()?;
+            if id <= last_section {
+                return Err(format!("section {id} out of order"));
+            }
+            last_section = id;
+            let size = reader.index()?;
+            let end = reader.position + size;
+            let count = reader.index()?;
+            for _ in 0..count {
+                match id {
+                    1 => {
+                        reader.expect(&[0x60], "function type")?;
+                        let params = reader.index()?;
+                        for _ in 0..params {
+                            reader.expect(&[0x7f], "parameter type")?;
+                        }
+                        let results = reader.index()?;
+                        for _ in 0..results {
+                            reader.expect(&[0x7f], "result type")?;
+                        }
+                        instance.types.push((params, results));
+                    }
+                    2 => {
+                        let module = reader.name()?;
+                        let name = reader.name()?;
+                        reader.expect(&[0x00], "import kind")?;
+                        let type_index = reader.index()?;
+                        let index = instance.imports.len();
+                        let (expected, params, result) =
+                            HOST_FUNCTIONS.get(index).ok_or("too many imports")?;
+                        if module != HOST_MODULE || name != *expected {
+                            return Err(format!("unexpected import {module}.{name}"));
+                        }
+                        if instance.types.get(type_index) != Some(&(*params, *result as usize)) {
+                            return Err(format!("import {name} has the wrong type"));
+                        }
+                        instance.imports.push(type_index);
+                    }
+                    3 => function_types.push(reader.index()?),
+                    5 => {
+                        reader.expect(&[0x00], "memory limits")?;
+                        let pages = reader.index()?;
+                        instance.memory = vec![0; pages * 0x10000];
+                    }
+                    6 => {
+                        reader.expect(&[0x7f, 0x01, 0x41, 0x00, 0x0b], "global")?;
+                        instance.globals.push(0);
+                    }
+                    7 => {
+                        let name = reader.name()?;
+                        let kind = reader.byte()?;
+                        let index = reader.index()?;
+                        match (name.as_str(), kind) {
+                            ("memory", 0x02) => {}
+                            ("main", 0x00) => instance.main = Some(index),
+                            _ => return Err(format!("unexpected export {name}")),
+                        }
+                    }
+                    10 => {
+                        let body_size = reader.index()?;
+                        let body_end = reader.position + body_size;
+                        let mut locals = 0;
+                        for _ in 0..reader.index()? {
+                            locals += reader.index()?;
+                            reader.expect(&[0x7f], "local type")?;
+                        }
+                        let type_index = *function_types
+                            .get(functions.len())
+                            .ok_or("code without function")?;
+                        let (params, results) = instance.types[type_index];
+                        if results != 1 {
+                            return Err("functions must return one value".to_owned());
+                        }
+                        let code = decode_code(&mut reader, body_end)?;
+                        let targets = match_blocks(&code)?;
+                        functions.push(Body {
+                            params,
+                            locals,
+                            code,
+                            targets,
+                        });
+                    }
+                    11 => {
+                        reader.expect(&[0x00, 0x41], "data segment")?;
+                        let offset = reader.signed()? as usize;
+                        reader.expect(&[0x0b], "data offset")?;
+                        let len = reader.index()?;
+                        let data = &bytes[reader.position..reader.position + len];
+                        instance
+                            .memory
+                            .get_mut(offset..offset + len)
+                            .ok_or("data out of bounds")?
+                            .copy_from_slice(data);
+                        reader.position += len;
+                    }
+                    _ => return Err(format!("unexpected section {id}")),
+                }
+            }
+            if reader.position != end {
+                return Err(format!("section {id} has the wrong size"));
+            }
+        }
+        if function_types.len() != functions.len() {
+            return Err("function and code sections disagree".to_owned());
+        }
+        instance.functions = Rc::new(functions);
+        Ok(instance)
+    }
+
+    pub fn function_count(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn run_main(&mut self) -> Result<i32, String> {
+        let main = self.main.ok_or("no main export")?;
+        self.call(main, vec![], 0)
+    }
+
+    fn call(&mut self, index: usize, arguments: Vec<i32>, depth: usize) -> Result<i32, String> {
+        if depth > MAX_CALL_DEPTH {
+            return Err("call stack exhausted".to_owned());
+        }
+        let functions = Rc::clone(&self.functions);
+        let body = index
+            .checked_sub(self.imports.len())
+            .and_then(|i| functions.get(i))
+            .ok_or(format!("call to unknown function {index}"))?;
+        if arguments.len() != body.params {
+            return Err("wrong number of arguments".to_owned());
+        }
+        let mut locals = arguments;
+        locals.resize(body.params + body.locals, 0);
+
+        let mut stack: Vec<i32> = vec![];
+        // (is a loop, index of its opening instruction, stack height, leaves a value)
+        let mut labels: Vec<(bool, usize, usize, bool)> = vec![];
+        let mut pc = 0;
+        let pop = |stack: &mut Vec<i32>| stack.pop().ok_or("operand stack underflow".to_owned());
+        loop {
+            let instruction = body.code.get(pc).ok_or("fell off the end of a function")?;
+            pc += 1;
+            match instruction {
+                Instruction::Unreachable => return Err("unreachable executed".to_owned()),
+                Instruction::Block | Instruction::Loop => labels.push((
+                    *instruction == Instruction::Loop,
+                    pc - 1,
+                    stack.len(),
+                    false,
+                )),
+                Instruction::If | Instruction::IfI32 => {
+                    let condition = pop(&mut stack)?;
+                    let (else_pc, end_pc) = body.targets[pc - 1];
+                    let label = (
+                        false,
+                        pc - 1,
+                        stack.len(),
+                        *instruction == Instruction::IfI32,
+                    );
+                    if condition != 0 {
+                        labels.push(label);
+                    } else if let Some(else_pc) = else_pc {
+                        labels.push(label);
+                        pc = else_pc + 1;
+                    } else {
+                        pc = end_pc + 1;
+                    }
+                }
+                Instruction::Else => {
+                    // end of the taken `then` arm
+                    let (_, start, ..) = labels.last().ok_or("else outside if")?;
+                    pc = body.targets[*start].1;
+                }
+                Instruction::End => match labels.pop() {
+                    Some((_, _, height, value)) => {
+                        if stack.len() != height + value as usize {
+                            return Err("block left the wrong number of values".to_owned());
+                        }
+                    }
+                    None => {
+                        return match stack.as_slice() {
+                            [value] => Ok(*value),
+                            _ => Err("function left the wrong number of values".to_owned()),
+                        }
+                    }
+                },
+                Instruction::Br(depth) | Instruction::BrIf(depth) => {
+                    if matches!(instruction, Instruction::BrIf(_)) && pop(&mut stack)? == 0 {
+                        continue;
+                    }
+                    let target = labels
+                        .len()
+                        .checked_sub(*depth as usize + 1)
+                        .ok_or("branch out of range")?;
+                    let (is_loop, start, height, value) = labels[target];
+                    let carried = match value {
+                        true => Some(pop(&mut stack)?),
+                        false => None,
+                    };
+                    stack.truncate(height);
+                    stack.extend(carried);
+                    if is_loop {
+                        labels.truncate(target + 1);
+                        pc = start + 1;
+                    } else {
+                        labels.truncate(target);
+                        pc = body.targets[start].1 + 1;
+                    }
+                }
+                Instruction::Return => return pop(&mut stack),
+                Instruction::Call(index) => {
+                    let index = *index as usize;
+                    let (params, _) = match self.imports.get(index) {
+                        Some(type_index) => self.types[*type_index],
+                        None => {
+                            let body = self
+                                .functions
+                                .get(index - self.imports.len())
+                                .ok_or("call to unknown function")?;
+                            (body.params, 1)
+                        }
+                    };
+                    let split = stack
+                        .len()
+                        .checked_sub(params)
+                        .ok_or("operand stack underflow")?;
+                    let arguments = stack.split_off(split);
+                    match index < self.imports.len() {
+                        true => {
+                            if let Some(value) = self.host(index, &arguments)? {
+                                stack.push(value);
+                            }
+                        }
+                        false => stack.push(self.call(index, arguments, depth + 1)?),
+                    }
+                }
+                Instruction::Drop => {
+                    pop(&mut stack)?;
+                }
+                Instruction::LocalGet(index) => {
+                    stack.push(*locals.get(*index as usize).ok_or("bad local")?)
+                }
+                Instruction::LocalSet(index) | Instruction::LocalTee(index) => {
+                    let value = pop(&mut stack)?;
+                    *locals.get_mut(*index as usize).ok_or("bad local")? = value;
+                    if matches!(instruction, Instruction::LocalTee(_)) {
+                        stack.push(value);
+                    }
+                }
+                Instruction::GlobalGet(index) => {
+                    stack.push(*self.globals.get(*index as usize).ok_or("bad global")?)
+                }
+                Instruction::GlobalSet(index) => {
+                    let value = pop(&mut stack)?;
+                    *self.globals.get_mut(*index as usize).ok_or("bad global")? = value;
+                }
+                Instruction::I32Const(value) => stack.push(*value),
+                Instruction::I32Eqz => {
+                    let value = pop(&mut stack)?;
+                    stack.push((value == 0) as i32);
+                }
+                _ => {
+                    let b = pop(&mut stack)?;
+                    let a = pop(&mut stack)?;
+                    stack.push(match instruction {
+                        Instruction::I32Eq => (a == b) as i32,
+                        Instruction::I32Ne => (a != b) as i32,
+                        Instruction::I32LtS => (a < b) as i32,
+                        Instruction::I32GtS => (a > b) as i32,
+                        Instruction::I32LeS => (a <= b) as i32,
+                        Instruction::I32GeS => (a >= b) as i32,
+                        Instruction::I32Add => a.wrapping_add(b),
+                        Instruction::I32Sub => a.wrapping_sub(b),
+                        Instruction::I32Mul => a.wrapping_mul(b),
+                        Instruction::I32DivS => a
+                            .checked_div(b)
+                            .ok_or("integer divide by zero or overflow")?,
+                        Instruction::I32RemS => a.checked_rem(b).ok_or("integer divide by zero")?,
+                        _ => unreachable!(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn host(&mut self, index: usize, arguments: &[i32]) -> Result<Option<i32>, String> {
+        Ok(match HOST_FUNCTIONS[index].0 {
+            "write_int" => {
+                self.output.push_str(&arguments[0].to_string());
+                None
+            }
+            "write_char" => {
+                self.output.extend(char::from_u32(arguments[0] as u32));
+                None
+            }
+            "write_str" => {
+                let start = arguments[0] as usize;
+                let end = start + arguments[1] as usize;
+                let bytes = self.memory.get(start..end).ok_or("string out of bounds")?;
+                self.output.push_str(&String::from_utf8_lossy(bytes));
+                None
+            }
+            "read_int" => {
+                let word = self.read_word(false);
+                Some(word.parse().map_err(|_| format!("invalid input {word}"))?)
+            }
+            "read_char" => Some(self.read_word(true).chars().next().unwrap_or('\0') as i32),
+            "newline" => {
+                self.output.push('\n');
+                None
+            }
+            name => return Err(format!("unknown host function {name}")),
+        })
+    }
+
+    fn read_word(&mut self, single: bool) -> String {
+        while self
+            .input
+            .get(self.position)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+        let start = self.position;
+        while self
+            .input
+            .get(self.position)
+            .is_some_and(|b| !b.is_ascii_whitespace())
+        {
+            self.position += 1;
+            if single {
+                break;
+            }
+        }
+        String::from_utf8_lossy(&self.input[start..self.position]).to_string()
+    }
+}
+
+fn decode_code(reader: &mut Reader, end: usize) -> Result<Vec<Instruction>, String> {
+    let mut code = vec![];
+    let mut depth = 0;
+    loop {
+        let opcode = reader.byte()?;
+        let instruction = match opcode {
+            0x00 => Instruction::Unreachable,
+            0x02..=0x04 => {
+                let block_type = reader.byte()?;
+                depth += 1;
+                match (opcode, block_type) {
+                    (0x02, 0x40) => Instruction::Block,
+                    (0x03, 0x40) => Instruction::Loop,
+                    (0x04, 0x40) => Instruction::If,
+                    (0x04, 0x7f) => Instruction::IfI32,
+                    _ => return Err(format!("unsupported block type {block_type:#x}")),
+                }
+            }
+            0x05 => Instruction::Else,
+            0x0b if depth == 0 => break,
+            0x0b => {
+                depth -= 1;
+                Instruction::End
+            }
+            0x0c => Instruction::Br(reader.index()? as u32),
+            0x0d => Instruction::BrIf(reader.index()? as u32),
+            0x0f => Instruction::Return,
+            0x10 => Instruction::Call(reader.index()? as u32),
+            0x1a => Instruction::Drop,
+            0x20 => Instruction::LocalGet(reader.index()? as u32),
+            0x21 => Instruction::LocalSet(reader.index()? as u32),
+            0x22 => Instruction::LocalTee(reader.index()? as u32),
+            0x23 => Instruction::GlobalGet(reader.index()? as u32),
+            0x24 => Instruction::GlobalSet(reader.index()? as u32),
+            0x41 => Instruction::I32Const(reader.signed()? as i32),
+            0x45 => Instruction::I32Eqz,
+            0x46 => Instruction::I32Eq,
+            0x47 => Instruction::I32Ne,
+            0x48 => Instruction::I32LtS,
+            0x4a => Instruction::I32GtS,
+            0x4c => Instruction::I32LeS,
+            0x4e => Instruction::I32GeS,
+            0x6a => Instruction::I32Add,
+            0x6b => Instruction::I32Sub,
+            0x6c => Instruction::I32Mul,
+            0x6d => Instruction::I32DivS,
+            0x6f => Instruction::I32RemS,
+            _ => return Err(format!("unsupported opcode {opcode:#x}")),
+        };
+        code.push(instruction);
+    }
+    if reader.position != end {
+        return Err("function body has the wrong size".to_owned());
+    }
+    // the final `end` terminates the function itself
+    code.push(Instruction::End);
+    Ok(code)
+}
+
+fn match_blocks(code: &[Instruction]) -> Result<Vec<(Option<usize>, usize)>, String> {
+    let mut targets = vec![(None, 0); code.len()];
+    let mut open = vec![];
+    for (pc, instruction) in code.iter().enumerate() {
+        match instruction {
+            Instruction::Block | Instruction::Loop | Instruction::If | Instruction::IfI32 => {
+                open.push(pc)
+            }
+            Instruction::Else => {
+                let start = *open.last().ok_or("else outside block")?;
+                targets[start].0 = Some(pc);
+            }
+            Instruction::End => {
+                if let Some(start) = open.pop() {
+                    targets[start].1 = pc;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(targets)
+}
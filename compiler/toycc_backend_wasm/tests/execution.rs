@@ -0,0 +1,98 @@
+//! Encodes generated modules, decodes them again in the reference
+//! evaluator and compares their output with the tree-walking interpreter.
+
+mod evaluator;
+
+use evaluator::Instance;
+use toycc_backend_wasm::WasmGenerator;
+
+fn run_both(source: &str, input: &str) -> (String, i32) {
+    toycc_test_support::run_both(source, input, |program| {
+        let module = WasmGenerator::new()
+            .generate(program)
+            .expect("failed to generate");
+        let mut instance =
+            Instance::new(&module.encode(), input).unwrap_or_else(|e| panic!("{e}\n{module}"));
+        assert_eq!(instance.function_count(), module.functions.len());
+        let code = instance
+            .run_main()
+            .unwrap_or_else(|e| panic!("{e}\n{module}"));
+        (module, instance.output, Some(code))
+    })
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    assert_eq!(
+        run_both(source, "7 1").0,
+        "input a: input a: a = 3\na = 21\n"
+    );
+}
+
+#[test]
+fn test_globals_and_calls() {
+    let source = "int g;
+int isEven(int n){ if ((n % 2) == 0) return 1; else return 0; }
+int bump(int by){ g = g + by; return g; }
+int main(){
+    int i;
+    i = 0;
+    while (i < 4) { write(i, isEven(i), bump(1)); newline; i = i + 1; }
+    return g;
+}";
+    assert_eq!(run_both(source, ""), ("011\n102\n213\n304\n".to_owned(), 4));
+}
+
+#[test]
+fn test_nested_loops_and_shadowing() {
+    let source = "int main(){
+    int i;
+    int j;
+    i = 0;
+    while (i < 3) {
+        int i2;
+        j = 0;
+        while (1) {
+            if (j > i) break;
+            { int j; j = 7; write(j); }
+            j = j + 1;
+        }
+        write(i2);
+        newline;
+        i = i + 1;
+    }
+    return 0;
+}";
+    assert_eq!(run_both(source, "").0, "70\n770\n7770\n");
+}
+
+#[test]
+fn test_operators_and_input() {
+    let source = "int main(){
+    int a;
+    char c;
+    read(c, a);
+    write(c, \" \", -a, \" \", a / 5, \" \", a % 5, \" \", (a > 3) && (a < 50), (a < 3) || !a, \"\\n\");
+    write(a = 3, a);
+    newline;
+    return a;
+}";
+    assert_eq!(
+        run_both(source, "x 41"),
+        ("x -41 8 1 10\n33\n".to_owned(), 3)
+    );
+}
+
+#[test]
+fn test_divide_by_minus_one() {
+    let source = "int main(){
+    int a;
+    int m;
+    m = -1;
+    a = -2147483647 - 1;
+    write(a / m, \" \", a % m, \" \", 7 / m, \" \", 100 / (8 / (m * 2)), \" \", -7 % 2);
+    return 0;
+}";
+    assert_eq!(run_both(source, "").0, "-2147483648 0 -7 -25 -1");
+}
@@ -0,0 +1,3 @@
+pub mod x86_generator;
+
+pub use x86_generator::X86Generator;
@@ -0,0 +1,616 @@
+use toycc_backend_jvm::symbol_table::{Function, Symbol, SymbolTable};
+use toycc_backend_jvm::{SemanticError, SemanticErrorKind};
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+
+const ARGUMENT_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+/// libc symbols the generated code calls; ToyC names that collide with one
+/// of these get a trailing underscore
+const RESERVED: [&str; 4] = ["printf", "scanf", "putchar", "fflush"];
+
+const RUNTIME_DATA: &str = r#"	.section	.rodata
+.Lfmt_int:
+	.string	"%d"
+.Lfmt_char:
+	.string	"%c"
+.Lfmt_str:
+	.string	"%s"
+.Lfmt_read_int:
+	.string	"%d"
+.Lfmt_read_char:
+	.string	" %c"
+"#;
+
+/// Emits AT&T-syntax x86-64 assembly for Linux (System V ABI). Expressions
+/// are evaluated into `%eax` using the machine stack for temporaries, and
+/// every variable lives in its own 8-byte slot below `%rbp`.
+pub struct X86Generator<'a> {
+    source_name: &'a str,
+    symbol_table: Vec<SymbolTable<'a>>,
+    strings: Vec<String>,
+    text: Vec<String>,
+    loop_ends: Vec<usize>,
+    label_count: usize,
+    slot_count: usize,
+    stack_depth: usize,
+}
+
+impl<'a> X86Generator<'a> {
+    pub fn new(source_name: &'a str) -> Self {
+        Self {
+            source_name,
+            symbol_table: vec![SymbolTable::default()],
+            strings: vec![],
+            text: vec![],
+            loop_ends: vec![],
+            label_count: 0,
+            slot_count: 0,
+            stack_depth: 0,
+        }
+    }
+
+    pub fn generate(&mut self, program: &'a Program) -> Result<String, Box<SemanticError>> {
+        let mut globals = vec![];
+        for definition in &program.definitions {
+            match definition {
+                Definition::VarDef(var_def) => {
+                    for id in &var_def.identifiers {
+                        let variable = Symbol::Variable(id.clone(), var_def.toyc_type.clone(), 0);
+                        self.insert_symbol(id, variable)?;
+                        globals.push(symbol_name(id));
+                    }
+                }
+                Definition::FuncDef(func_def) => {
+                    let arguments = func_def
+                        .var_def
+                        .iter()
+                        .flat_map(|v| v.identifiers.iter().map(|_| v.toyc_type.to_string()))
+                        .collect();
+                    let function = Function::new(
+                        func_def.identifier.clone(),
+                        arguments,
+                        vec![],
+                        func_def.toyc_type.clone(),
+                    );
+                    self.insert_symbol(&func_def.identifier, Symbol::Function(function))?;
+                }
+            }
+        }
+
+        let mut functions = vec![];
+        for definition in &program.definitions {
+            if let Definition::FuncDef(func_def) = definition {
+                functions.push(self.generate_function(func_def)?);
+            }
+        }
+
+        let mut assembly = format!("\t.file\t\"{}\"\n{RUNTIME_DATA}", self.source_name);
+        for (index, string) in self.strings.iter().enumerate() {
+            assembly.push_str(&format!(".LC{index}:\n\t.string\t\"{}\"\n", escape(string)));
+        }
+        if !globals.is_empty() {
+            assembly.push_str("\t.bss\n\t.align\t4\n");
+            for global in globals {
+                assembly.push_str(&format!("{global}:\n\t.zero\t4\n"));
+            }
+        }
+        assembly.push_str("\t.text\n");
+        for function in functions {
+            assembly.push_str(&function);
+        }
+        assembly.push_str("\t.section\t.note.GNU-stack,\"\",@progbits\n");
+        Ok(assembly)
+    }
+
+    fn generate_function(&mut self, func_def: &'a FuncDef) -> Result<String, Box<SemanticError>> {
+        self.text.clear();
+        self.slot_count = 0;
+        self.stack_depth = 0;
+        self.symbol_table.push(SymbolTable::default());
+
+        let parameters = func_def.var_def.iter().flat_map(|v| {
+            v.identifiers
+                .iter()
+                .map(move |id| (id, v.toyc_type.clone()))
+        });
+        for (index, (id, toyc_type)) in parameters.enumerate() {
+            let slot = self.declare_variable(id, toyc_type)?;
+            match ARGUMENT_REGISTERS.get(index) {
+                Some(register) => self.emit(format!("movq\t{register}, {}", slot_address(slot))),
+                None => {
+                    // arguments past the sixth sit above the return address
+                    self.emit(format!("movq\t{}(%rbp), %rax", 16 + 8 * (index - 6)));
+                    self.emit(format!("movq\t%rax, {}", slot_address(slot)));
+                }
+            }
+        }
+
+        let result = self.generate_statement(&func_def.statement);
+        self.symbol_table.pop();
+        result?;
+        self.emit("movl\t$0, %eax".to_owned());
+        self.emit("leave".to_owned());
+        self.emit("ret".to_owned());
+
+        let name = symbol_name(&func_def.identifier);
+        let frame_size = (self.slot_count * 8).div_ceil(16) * 16;
+        let mut function = format!(
+            "\t.globl\t{name}\n\t.type\t{name}, @function\n{name}:\n\tpushq\t%rbp\n\tmovq\t%rsp, %rbp\n"
+        );
+        if frame_size > 0 {
+            function.push_str(&format!("\tsubq\t${frame_size}, %rsp\n"));
+        }
+        for line in &self.text {
+            match line.ends_with(':') {
+                true => function.push_str(&format!("{line}\n")),
+                false => function.push_str(&format!("\t{line}\n")),
+            }
+        }
+        function.push_str(&format!("\t.size\t{name}, .-{name}\n"));
+        Ok(function)
+    }
+
+    fn generate_statement(&mut self, statement: &'a Statement) -> Result<(), Box<SemanticError>> {
+        match &statement.kind {
+            StatementKind::Expression(expr) => self.generate_expression(expr)?,
+            StatementKind::Break => match self.loop_ends.last() {
+                Some(end_label) => self.emit(format!("jmp\t.L{end_label}")),
+                None => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::BreakOutsideLoop,
+                    )))
+                }
+            },
+            StatementKind::BlockState(var_defs, statements) => {
+                self.symbol_table.push(SymbolTable::default());
+                let result = self.generate_block(var_defs, statements);
+                self.symbol_table.pop();
+                result?;
+            }
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
+                let else_label = self.next_label();
+                let end_label = self.next_label();
+                self.generate_expression(expr)?;
+                self.emit("testl\t%eax, %eax".to_owned());
+                self.emit(format!("je\t.L{else_label}"));
+                self.generate_statement(if_stmt)?;
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    self.emit(format!("jmp\t.L{end_label}"));
+                    self.emit(format!(".L{else_label}:"));
+                    self.generate_statement(else_stmt)?;
+                    self.emit(format!(".L{end_label}:"));
+                } else {
+                    self.emit(format!(".L{else_label}:"));
+                }
+            }
+            StatementKind::NullState => {}
+            StatementKind::ReturnState(expr) => {
+                match expr {
+                    Some(expr) => self.generate_expression(expr)?,
+                    None => self.emit("movl\t$0, %eax".to_owned()),
+                }
+                self.emit("leave".to_owned());
+                self.emit("ret".to_owned());
+            }
+            StatementKind::WhileState(expr, body) => {
+                let top_label = self.next_label();
+                let end_label = self.next_label();
+                self.emit(format!(".L{top_label}:"));
+                self.generate_expression(expr)?;
+                self.emit("testl\t%eax, %eax".to_owned());
+                self.emit(format!("je\t.L{end_label}"));
+                self.loop_ends.push(end_label);
+                let result = self.generate_statement(body);
+                self.loop_ends.pop();
+                result?;
+                self.emit(format!("jmp\t.L{top_label}"));
+                self.emit(format!(".L{end_label}:"));
+            }
+            StatementKind::ReadState(name, others) => {
+                self.emit("xorl\t%edi, %edi".to_owned());
+                self.call("fflush@PLT");
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    let (address, toyc_type) = self.variable(name)?;
+                    let format = match toyc_type {
+                        Type::Int => ".Lfmt_read_int",
+                        Type::Char => {
+                            // `%c` only stores the low byte of the slot
+                            self.emit(format!("movl\t$0, {address}"));
+                            ".Lfmt_read_char"
+                        }
+                    };
+                    self.emit(format!("leaq\t{address}, %rsi"));
+                    self.emit(format!("leaq\t{format}(%rip), %rdi"));
+                    self.emit("xorl\t%eax, %eax".to_owned());
+                    self.call("scanf@PLT");
+                }
+            }
+            StatementKind::WriteState(expr, others) => {
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    match &expr.kind {
+                        ExpressionKind::StringLiteral(s) => {
+                            self.strings.push(unescape(s));
+                            let index = self.strings.len() - 1;
+                            self.emit(format!("leaq\t.LC{index}(%rip), %rsi"));
+                            self.emit("leaq\t.Lfmt_str(%rip), %rdi".to_owned());
+                        }
+                        _ => {
+                            let format = match self.type_of(expr)? {
+                                Type::Int => ".Lfmt_int",
+                                Type::Char => ".Lfmt_char",
+                            };
+                            self.generate_expression(expr)?;
+                            self.emit("movl\t%eax, %esi".to_owned());
+                            self.emit(format!("leaq\t{format}(%rip), %rdi"));
+                        }
+                    }
+                    self.emit("xorl\t%eax, %eax".to_owned());
+                    self.call("printf@PLT");
+                }
+            }
+            StatementKind::NewLineState => {
+                self.emit("movl\t$10, %edi".to_owned());
+                self.call("putchar@PLT");
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_block(
+        &mut self,
+        var_defs: &'a [VarDef],
+        statements: &'a [Statement],
+    ) -> Result<(), Box<SemanticError>> {
+        for var_def in var_defs {
+            for id in &var_def.identifiers {
+                let slot = self.declare_variable(id, var_def.toyc_type.clone())?;
+                self.emit(format!("movq\t$0, {}", slot_address(slot)));
+            }
+        }
+        statements
+            .iter()
+            .try_for_each(|statement| self.generate_statement(statement))
+    }
+
+    /// Evaluates an expression into `%eax`
+    fn generate_expression(
+        &mut self,
+        expression: &'a Expression,
+    ) -> Result<(), Box<SemanticError>> {
+        match &expression.kind {
+            ExpressionKind::Number(num) => self.emit(format!("movl\t${}, %eax", *num as i32)),
+            ExpressionKind::CharLiteral(c) => {
+                let value = c.map(|c| c as i32).unwrap_or_default();
+                self.emit(format!("movl\t${value}, %eax"));
+            }
+            ExpressionKind::StringLiteral(_) => {
+                return Err(Box::new(SemanticError::new(
+                    SemanticErrorKind::IncompatibleTypes,
+                )))
+            }
+            ExpressionKind::Identifier(id) => {
+                let (address, _) = self.variable(id)?;
+                self.emit(format!("movl\t{address}, %eax"));
+            }
+            ExpressionKind::FuncCall(name, arguments) => self.generate_call(name, arguments)?,
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                let address = match &lhs.kind {
+                    ExpressionKind::Identifier(id) => self.variable(id)?.0,
+                    _ => {
+                        return Err(Box::new(SemanticError::new(
+                            SemanticErrorKind::ExpectedIdentifier,
+                        )))
+                    }
+                };
+                self.generate_expression(rhs)?;
+                self.emit(format!("movl\t%eax, {address}"));
+            }
+            ExpressionKind::Expr(op, lhs, rhs) if op.is_logical() => {
+                let short_label = self.next_label();
+                let end_label = self.next_label();
+                let (jump, short_value) = match op {
+                    Operator::And => ("je", 0),
+                    _ => ("jne", 1),
+                };
+                self.generate_expression(lhs)?;
+                self.emit("testl\t%eax, %eax".to_owned());
+                self.emit(format!("{jump}\t.L{short_label}"));
+                self.generate_expression(rhs)?;
+                self.emit("testl\t%eax, %eax".to_owned());
+                self.emit(format!("{jump}\t.L{short_label}"));
+                self.emit(format!("movl\t${}, %eax", 1 - short_value));
+                self.emit(format!("jmp\t.L{end_label}"));
+                self.emit(format!(".L{short_label}:"));
+                self.emit(format!("movl\t${short_value}, %eax"));
+                self.emit(format!(".L{end_label}:"));
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                self.generate_expression(lhs)?;
+                self.push("%rax");
+                self.generate_expression(rhs)?;
+                self.emit("movl\t%eax, %ecx".to_owned());
+                self.pop("%rax");
+                match op {
+                    Operator::Plus => self.emit("addl\t%ecx, %eax".to_owned()),
+                    Operator::Minus => self.emit("subl\t%ecx, %eax".to_owned()),
+                    Operator::Multiply => self.emit("imull\t%ecx, %eax".to_owned()),
+                    Operator::Divide | Operator::Modulo => {
+                        // idivl traps on INT_MIN / -1, so -1 is handled apart
+                        let divide_label = self.next_label();
+                        let end_label = self.next_label();
+                        self.emit("cmpl\t$-1, %ecx".to_owned());
+                        self.emit(format!("jne\t.L{divide_label}"));
+                        match op {
+                            Operator::Divide => self.emit("negl\t%eax".to_owned()),
+                            _ => self.emit("xorl\t%eax, %eax".to_owned()),
+                        }
+                        self.emit(format!("jmp\t.L{end_label}"));
+                        self.emit(format!(".L{divide_label}:"));
+                        self.emit("cltd".to_owned());
+                        self.emit("idivl\t%ecx".to_owned());
+                        if *op == Operator::Modulo {
+                            self.emit("movl\t%edx, %eax".to_owned());
+                        }
+                        self.emit(format!(".L{end_label}:"));
+                    }
+                    _ => {
+                        let condition = match op {
+                            Operator::LessEqual => "le",
+                            Operator::LessThan => "l",
+                            Operator::GreaterEqual => "ge",
+                            Operator::GreaterThan => "g",
+                            Operator::Equal => "e",
+                            _ => "ne",
+                        };
+                        self.emit("cmpl\t%ecx, %eax".to_owned());
+                        self.emit(format!("set{condition}\t%al"));
+                        self.emit("movzbl\t%al, %eax".to_owned());
+                    }
+                }
+            }
+            ExpressionKind::Not(expr) => {
+                self.generate_expression(expr)?;
+                self.emit("testl\t%eax, %eax".to_owned());
+                self.emit("sete\t%al".to_owned());
+                self.emit("movzbl\t%al, %eax".to_owned());
+            }
+            ExpressionKind::Minus(expr) => {
+                self.generate_expression(expr)?;
+                self.emit("negl\t%eax".to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the arguments left to right onto the stack, then moves the
+    /// first six into registers and the rest into the outgoing argument area
+    fn generate_call(
+        &mut self,
+        name: &str,
+        arguments: &'a [Expression],
+    ) -> Result<(), Box<SemanticError>> {
+        self.function_type(name)?;
+        let stack_arguments = arguments.len().saturating_sub(ARGUMENT_REGISTERS.len());
+        let padding = (self.stack_depth + stack_arguments) % 2;
+        let reserved = stack_arguments + padding;
+        if reserved > 0 {
+            self.emit(format!("subq\t${}, %rsp", reserved * 8));
+            self.stack_depth += reserved;
+        }
+        for argument in arguments {
+            self.generate_expression(argument)?;
+            self.push("%rax");
+        }
+        for index in (0..arguments.len()).rev() {
+            match ARGUMENT_REGISTERS.get(index) {
+                Some(register) => self.pop(register),
+                None => {
+                    self.pop("%rax");
+                    let offset = 8 * index + 8 * (index - ARGUMENT_REGISTERS.len());
+                    self.emit(format!("movq\t%rax, {offset}(%rsp)"));
+                }
+            }
+        }
+        self.emit(format!("call\t{}", symbol_name(name)));
+        if reserved > 0 {
+            self.emit(format!("addq\t${}, %rsp", reserved * 8));
+            self.stack_depth -= reserved;
+        }
+        Ok(())
+    }
+
+    /// Calls a libc function; only used at statement level, where no
+    /// temporaries are on the stack and `%rsp` is 16-byte aligned
+    fn call(&mut self, function: &str) {
+        self.emit(format!("call\t{function}"));
+    }
+
+    /// Static type of an expression: a compound expression takes the type of
+    /// its left-most operand, as in the JVM backend
+    fn type_of(&mut self, expression: &'a Expression) -> Result<Type, Box<SemanticError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(_) | ExpressionKind::StringLiteral(_) => Type::Int,
+            ExpressionKind::CharLiteral(_) => Type::Char,
+            ExpressionKind::Identifier(id) => self.variable(id)?.1,
+            ExpressionKind::FuncCall(name, _) => self.function_type(name)?,
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs)?,
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr)?,
+        })
+    }
+
+    /// Address operand and type of a variable
+    fn variable(&mut self, name: &str) -> Result<(String, Type), Box<SemanticError>> {
+        for (scope, table) in self.symbol_table.iter_mut().enumerate().rev() {
+            match table.find(name) {
+                Some(Symbol::Variable(_, toyc_type, _)) if scope == 0 => {
+                    return Ok((format!("{}(%rip)", symbol_name(name)), toyc_type.clone()))
+                }
+                Some(Symbol::Variable(_, toyc_type, slot)) => {
+                    return Ok((slot_address(*slot), toyc_type.clone()))
+                }
+                Some(_) => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::ExpectedIdentifier,
+                    )))
+                }
+                None => {}
+            }
+        }
+        Err(Box::new(SemanticError::new(
+            SemanticErrorKind::UndeclaredIdentifier(name.to_owned()),
+        )))
+    }
+
+    fn function_type(&mut self, name: &str) -> Result<Type, Box<SemanticError>> {
+        match self.symbol_table[0].find(name) {
+            Some(Symbol::Function(function)) => Ok(function.return_type.clone()),
+            Some(_) => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::ExpectedFunction,
+            ))),
+            None => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::UndeclaredFunction(name.to_owned()),
+            ))),
+        }
+    }
+
+    fn declare_variable(
+        &mut self,
+        name: &'a str,
+        toyc_type: Type,
+    ) -> Result<usize, Box<SemanticError>> {
+        self.slot_count += 1;
+        let slot = self.slot_count;
+        self.insert_symbol(name, Symbol::Variable(name.to_owned(), toyc_type, slot))?;
+        Ok(slot)
+    }
+
+    fn insert_symbol(&mut self, name: &'a str, symbol: Symbol) -> Result<(), Box<SemanticError>> {
+        self.symbol_table
+            .iter_mut()
+            .next_back()
+            .unwrap()
+            .insert(name, symbol)
+            .map(|_| ())
+    }
+
+    fn next_label(&mut self) -> usize {
+        self.label_count += 1;
+        self.label_count
+    }
+
+    fn push(&mut self, register: &str) {
+        self.emit(format!("pushq\t{register}"));
+        self.stack_depth += 1;
+    }
+
+    fn pop(&mut self, register: &str) {
+        self.emit(format!("popq\t{register}"));
+        self.stack_depth -= 1;
+    }
+
+    fn emit(&mut self, instruction: String) {
+        self.text.push(instruction);
+    }
+}
+
+fn slot_address(slot: usize) -> String {
+    format!("-{}(%rbp)", slot * 8)
+}
+
+fn symbol_name(name: &str) -> String {
+    match RESERVED.contains(&name) {
+        true => format!("{name}_"),
+        false => name.to_owned(),
+    }
+}
+
+/// Expands the escape sequences accepted in ToyC string literals
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Escapes a string for a GNU as `.string` directive
+fn escape(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'"' => "\\\"".to_owned(),
+            b'\\' => "\\\\".to_owned(),
+            b' '..=b'~' => (byte as char).to_string(),
+            _ => format!("\\{byte:03o}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn generate(source: &str) -> Result<String, Box<SemanticError>> {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        X86Generator::new("test.tc").generate(&program)
+    }
+
+    #[test]
+    fn test_function_frame() {
+        let asm = generate(
+            "int addTwo(int n){ int a; a = n + 2; return a; } int main(){ return addTwo(1); }",
+        )
+        .unwrap();
+        assert!(asm.contains("addTwo:\n\tpushq\t%rbp\n\tmovq\t%rsp, %rbp\n\tsubq\t$16, %rsp\n"));
+        assert!(asm.contains("\tmovq\t%rdi, -8(%rbp)\n"));
+        assert!(asm.contains("\tmovq\t$0, -16(%rbp)\n"));
+        assert!(asm.contains("\tpopq\t%rdi\n\tcall\taddTwo\n"));
+        assert!(asm.ends_with("\t.section\t.note.GNU-stack,\"\",@progbits\n"));
+    }
+
+    #[test]
+    fn test_stack_arguments() {
+        let asm = generate("int f(int a, int b, int c, int d, int e, int g, int h){ return h; } int main(){ return f(1,2,3,4,5,6,7); }").unwrap();
+        // the seventh argument is copied out of the caller's frame...
+        assert!(asm.contains("\tmovq\t16(%rbp), %rax\n\tmovq\t%rax, -56(%rbp)\n"));
+        // ...after the caller reserved a padded, aligned slot for it
+        assert!(asm.contains("\tsubq\t$16, %rsp\n"));
+        assert!(asm.contains("\tpopq\t%rax\n\tmovq\t%rax, 48(%rsp)\n"));
+        assert!(asm.contains("\tcall\tf\n\taddq\t$16, %rsp\n"));
+    }
+
+    #[test]
+    fn test_io_and_globals() {
+        let asm = generate("int printf; int main(){ char c; read(c, printf); write(\"c=\", c); newline; return 0; }").unwrap();
+        assert!(asm.contains("printf_:\n\t.zero\t4\n"));
+        assert!(asm.contains("\tleaq\tprintf_(%rip), %rsi\n"));
+        assert!(asm.contains(".LC0:\n\t.string\t\"c=\"\n"));
+        assert!(asm.contains("\tmovl\t$10, %edi\n\tcall\tputchar@PLT\n"));
+    }
+
+    #[test]
+    fn test_divide_by_minus_one() {
+        let asm =
+            generate("int main(){ int a; a = -2147483647 - 1; write(a / -1, a % -1); return 0; }")
+                .unwrap();
+        assert!(asm.contains("\tcmpl\t$-1, %ecx\n\tjne\t.L1\n\tnegl\t%eax\n\tjmp\t.L2\n.L1:\n\tcltd\n\tidivl\t%ecx\n.L2:\n"));
+        assert!(asm.contains("\txorl\t%eax, %eax\n"));
+    }
+}
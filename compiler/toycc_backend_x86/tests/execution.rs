@@ -0,0 +1,93 @@
+//! Assembles generated programs with `gcc` and compares their output with
+//! the tree-walking interpreter. The tests pass vacuously without `gcc`.
+
+use std::process::Command;
+use toycc_backend_x86::X86Generator;
+use toycc_test_support::run_program;
+
+fn run_both(name: &str, source: &str, input: &str) -> Option<String> {
+    if Command::new("gcc").arg("--version").output().is_err() {
+        eprintln!("gcc not found, skipping {name}");
+        return None;
+    }
+    let (expected, _) = toycc_test_support::run_both(source, input, |program| {
+        let assembly = X86Generator::new("test.tc")
+            .generate(program)
+            .expect("failed to generate");
+        let base = std::env::temp_dir().join(format!("toycc-{}-{name}", std::process::id()));
+        let s_path = base.with_extension("s");
+        std::fs::write(&s_path, &assembly).unwrap();
+        let output = Command::new("gcc")
+            .arg("-no-pie")
+            .arg("-o")
+            .arg(&base)
+            .arg(&s_path)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}\n{assembly}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let (output, status) = run_program(&mut Command::new(&base), input);
+        std::fs::remove_file(&s_path).unwrap();
+        std::fs::remove_file(&base).unwrap();
+        (assembly, output, Some(status))
+    });
+    Some(expected)
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    run_both("demo", source, "7 1");
+}
+
+#[test]
+fn test_divide_by_minus_one() {
+    let source = "int main(){
+    int a;
+    int m;
+    m = -1;
+    a = -2147483647 - 1;
+    write(a / m, \" \", a % m, \" \", 7 / m, \" \", -7 % 2, \" \", 7 / 2);
+    return 3;
+}";
+    if let Some(output) = run_both("divide", source, "") {
+        assert_eq!(output, "-2147483648 0 -7 -1 3");
+    }
+}
+
+#[test]
+fn test_stack_arguments() {
+    let source =
+        "int weigh(int a, int b, int c, int d, int e, int f, int g, int h, int i, int j, int k){
+    return a + (2*b) + (3*c) + (4*d) + (5*e) + (6*f) + (7*g) + (8*h) + (9*i) + (10*j) + (11*k);
+}
+int pick(int a, int b, int c, int d, int e, int f, int g, int h, int i){ return (i - a); }
+int main(){
+    write(weigh(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, pick(1, 0, 0, 0, 0, 0, 0, 0, 12)));
+    newline;
+    return pick(2, 0, 0, 0, 0, 0, 0, 0, 1 + weigh(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1));
+}";
+    if let Some(output) = run_both("arguments", source, "") {
+        assert_eq!(output, "506\n");
+    }
+}
+
+#[test]
+fn test_input_and_globals() {
+    let source = "int g;
+int bump(int by){ g = g + by; return g; }
+int main(){
+    int a;
+    char c;
+    read(c, a);
+    while (a > 0) { write(c, bump(a)); a = a - 1; }
+    newline;
+    return g;
+}";
+    if let Some(output) = run_both("input", source, "x 3") {
+        assert_eq!(output, "x3x5x6\n");
+    }
+}
@@ -0,0 +1,737 @@
+use crate::instruction::Instruction;
+use crate::module::{self, Constant, Module};
+use std::collections::HashMap;
+use toycc_backend_jvm::symbol_table::{Function, Symbol, SymbolTable};
+use toycc_backend_jvm::{SemanticError, SemanticErrorKind};
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+use toycc_ir as ir;
+
+/// Compiles a checked program to a bytecode [`Module`]. Every variable gets
+/// its own local slot, so nested blocks never reuse a slot, and jumps are
+/// emitted against labels that are resolved to byte offsets once a function
+/// is complete.
+pub struct BytecodeCompiler<'a> {
+    symbol_table: Vec<SymbolTable<'a>>,
+    function_indices: HashMap<&'a str, u16>,
+    constants: Vec<Constant>,
+    code: Vec<Instruction>,
+    labels: Vec<usize>,
+    loop_ends: Vec<usize>,
+    local_count: u16,
+}
+
+impl<'a> Default for BytecodeCompiler<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> BytecodeCompiler<'a> {
+    pub fn new() -> Self {
+        Self {
+            symbol_table: vec![SymbolTable::default()],
+            function_indices: HashMap::new(),
+            constants: vec![],
+            code: vec![],
+            labels: vec![],
+            loop_ends: vec![],
+            local_count: 0,
+        }
+    }
+
+    pub fn compile(&mut self, program: &'a Program) -> Result<Module, Box<SemanticError>> {
+        let mut globals = 0;
+        for definition in &program.definitions {
+            match definition {
+                Definition::VarDef(var_def) => {
+                    for id in &var_def.identifiers {
+                        let variable =
+                            Symbol::Variable(id.clone(), var_def.toyc_type.clone(), globals);
+                        self.insert_symbol(id, variable)?;
+                        globals += 1;
+                    }
+                }
+                Definition::FuncDef(func_def) => {
+                    let arguments = func_def
+                        .var_def
+                        .iter()
+                        .flat_map(|v| v.identifiers.iter().map(|_| v.toyc_type.to_string()))
+                        .collect();
+                    let function = Function::new(
+                        func_def.identifier.clone(),
+                        arguments,
+                        vec![],
+                        func_def.toyc_type.clone(),
+                    );
+                    self.insert_symbol(&func_def.identifier, Symbol::Function(function))?;
+                    let index = self.function_indices.len() as u16;
+                    self.function_indices.insert(&func_def.identifier, index);
+                }
+            }
+        }
+        let main = match self.function_indices.get("main") {
+            Some(main) => *main,
+            None => return Err(Box::new(SemanticError::new(SemanticErrorKind::MissingMain))),
+        };
+
+        let mut functions = vec![];
+        for definition in &program.definitions {
+            if let Definition::FuncDef(func_def) = definition {
+                functions.push(self.compile_function(func_def)?);
+            }
+        }
+
+        Ok(Module {
+            constants: std::mem::take(&mut self.constants),
+            globals: globals as u16,
+            functions,
+            main,
+        })
+    }
+
+    fn compile_function(
+        &mut self,
+        func_def: &'a FuncDef,
+    ) -> Result<module::Function, Box<SemanticError>> {
+        self.code.clear();
+        self.labels.clear();
+        self.local_count = 0;
+        self.symbol_table.push(SymbolTable::default());
+
+        for var_def in &func_def.var_def {
+            for id in &var_def.identifiers {
+                self.declare_variable(id, var_def.toyc_type.clone())?;
+            }
+        }
+        let params = self.local_count;
+        let result = self.compile_statement(&func_def.statement);
+        self.symbol_table.pop();
+        result?;
+        self.emit(Instruction::Byte(0));
+        self.emit(Instruction::Return);
+
+        let name = self.constant(Constant::Str(func_def.identifier.clone()));
+        Ok(module::Function {
+            name,
+            params,
+            locals: self.local_count,
+            code: self.resolve_labels(),
+        })
+    }
+
+    /// Encodes the current function, replacing label numbers in jumps with
+    /// the byte offsets of the instructions they mark
+    fn resolve_labels(&self) -> Vec<u8> {
+        let mut offsets = Vec::with_capacity(self.code.len() + 1);
+        let mut offset = 0;
+        for instruction in &self.code {
+            offsets.push(offset as u32);
+            offset += instruction.size();
+        }
+        offsets.push(offset as u32);
+
+        let mut code = Vec::with_capacity(offset);
+        for instruction in &self.code {
+            let target = |label: u32| offsets[self.labels[label as usize]];
+            let instruction = match *instruction {
+                Instruction::Jump(label) => Instruction::Jump(target(label)),
+                Instruction::JumpIfFalse(label) => Instruction::JumpIfFalse(target(label)),
+                Instruction::JumpIfTrue(label) => Instruction::JumpIfTrue(target(label)),
+                instruction => instruction,
+            };
+            instruction.encode(&mut code);
+        }
+        code
+    }
+
+    fn compile_statement(&mut self, statement: &'a Statement) -> Result<(), Box<SemanticError>> {
+        match &statement.kind {
+            StatementKind::Expression(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(Instruction::Pop);
+            }
+            StatementKind::Break => match self.loop_ends.last() {
+                Some(&end_label) => self.emit(Instruction::Jump(end_label as u32)),
+                None => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::BreakOutsideLoop,
+                    )))
+                }
+            },
+            StatementKind::BlockState(var_defs, statements) => {
+                self.symbol_table.push(SymbolTable::default());
+                let result = self.compile_block(var_defs, statements);
+                self.symbol_table.pop();
+                result?;
+            }
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
+                let else_label = self.new_label();
+                self.compile_expression(expr)?;
+                self.emit(Instruction::JumpIfFalse(else_label as u32));
+                self.compile_statement(if_stmt)?;
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    let end_label = self.new_label();
+                    self.emit(Instruction::Jump(end_label as u32));
+                    self.place_label(else_label);
+                    self.compile_statement(else_stmt)?;
+                    self.place_label(end_label);
+                } else {
+                    self.place_label(else_label);
+                }
+            }
+            StatementKind::NullState => {}
+            StatementKind::ReturnState(expr) => {
+                match expr {
+                    Some(expr) => self.compile_expression(expr)?,
+                    None => self.emit(Instruction::Byte(0)),
+                }
+                self.emit(Instruction::Return);
+            }
+            StatementKind::WhileState(expr, body) => {
+                let top_label = self.new_label();
+                let end_label = self.new_label();
+                self.place_label(top_label);
+                self.compile_expression(expr)?;
+                self.emit(Instruction::JumpIfFalse(end_label as u32));
+                self.loop_ends.push(end_label);
+                let result = self.compile_statement(body);
+                self.loop_ends.pop();
+                result?;
+                self.emit(Instruction::Jump(top_label as u32));
+                self.place_label(end_label);
+            }
+            StatementKind::ReadState(name, others) => {
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    let ((_, store), toyc_type) = self.variable(name)?;
+                    match toyc_type {
+                        Type::Int => self.emit(Instruction::ReadInt),
+                        Type::Char => self.emit(Instruction::ReadChar),
+                    }
+                    self.emit(store);
+                }
+            }
+            StatementKind::WriteState(expr, others) => {
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    match &expr.kind {
+                        ExpressionKind::StringLiteral(s) => {
+                            let index = self.constant(Constant::Str(unescape(s)));
+                            self.emit(Instruction::WriteStr(index));
+                        }
+                        _ => {
+                            let toyc_type = self.type_of(expr)?;
+                            self.compile_expression(expr)?;
+                            match toyc_type {
+                                Type::Int => self.emit(Instruction::WriteInt),
+                                Type::Char => self.emit(Instruction::WriteChar),
+                            }
+                        }
+                    }
+                }
+            }
+            StatementKind::NewLineState => self.emit(Instruction::NewLine),
+        }
+        Ok(())
+    }
+
+    fn compile_block(
+        &mut self,
+        var_defs: &'a [VarDef],
+        statements: &'a [Statement],
+    ) -> Result<(), Box<SemanticError>> {
+        for var_def in var_defs {
+            for id in &var_def.identifiers {
+                let index = self.declare_variable(id, var_def.toyc_type.clone())?;
+                self.emit(Instruction::Byte(0));
+                self.emit(Instruction::StoreLocal(index));
+            }
+        }
+        statements
+            .iter()
+            .try_for_each(|statement| self.compile_statement(statement))
+    }
+
+    /// Leaves the value of an expression on the operand stack
+    fn compile_expression(&mut self, expression: &'a Expression) -> Result<(), Box<SemanticError>> {
+        match &expression.kind {
+            ExpressionKind::Number(num) => self.push_constant(*num as i32),
+            ExpressionKind::CharLiteral(c) => {
+                self.push_constant(c.map(|c| c as i32).unwrap_or_default())
+            }
+            ExpressionKind::StringLiteral(_) => {
+                return Err(Box::new(SemanticError::new(
+                    SemanticErrorKind::IncompatibleTypes,
+                )))
+            }
+            ExpressionKind::Identifier(id) => {
+                let ((load, _), _) = self.variable(id)?;
+                self.emit(load);
+            }
+            ExpressionKind::FuncCall(name, arguments) => {
+                let index = match self.function_indices.get(name.as_str()) {
+                    Some(index) => *index,
+                    None => {
+                        return Err(Box::new(SemanticError::new(
+                            SemanticErrorKind::UndeclaredFunction(name.clone()),
+                        )))
+                    }
+                };
+                for argument in arguments {
+                    self.compile_expression(argument)?;
+                }
+                self.emit(Instruction::Call(index));
+            }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                let store = match &lhs.kind {
+                    ExpressionKind::Identifier(id) => self.variable(id)?.0 .1,
+                    _ => {
+                        return Err(Box::new(SemanticError::new(
+                            SemanticErrorKind::ExpectedIdentifier,
+                        )))
+                    }
+                };
+                self.compile_expression(rhs)?;
+                self.emit(Instruction::Dup);
+                self.emit(store);
+            }
+            ExpressionKind::Expr(op, lhs, rhs) if op.is_logical() => {
+                let short_label = self.new_label();
+                let end_label = self.new_label();
+                let (jump, short_value): (fn(u32) -> Instruction, i8) = match op {
+                    Operator::And => (Instruction::JumpIfFalse, 0),
+                    _ => (Instruction::JumpIfTrue, 1),
+                };
+                self.compile_expression(lhs)?;
+                self.emit(jump(short_label as u32));
+                self.compile_expression(rhs)?;
+                self.emit(jump(short_label as u32));
+                self.emit(Instruction::Byte(1 - short_value));
+                self.emit(Instruction::Jump(end_label as u32));
+                self.place_label(short_label);
+                self.emit(Instruction::Byte(short_value));
+                self.place_label(end_label);
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                self.compile_expression(lhs)?;
+                self.compile_expression(rhs)?;
+                self.emit(match op {
+                    Operator::Plus => Instruction::Add,
+                    Operator::Minus => Instruction::Sub,
+                    Operator::Multiply => Instruction::Mul,
+                    Operator::Divide => Instruction::Div,
+                    Operator::Modulo => Instruction::Rem,
+                    Operator::LessEqual => Instruction::Le,
+                    Operator::LessThan => Instruction::Lt,
+                    Operator::GreaterEqual => Instruction::Ge,
+                    Operator::GreaterThan => Instruction::Gt,
+                    Operator::Equal => Instruction::Eq,
+                    _ => Instruction::Ne,
+                });
+            }
+            ExpressionKind::Not(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(Instruction::Not);
+            }
+            ExpressionKind::Minus(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(Instruction::Neg);
+            }
+        }
+        Ok(())
+    }
+
+    /// Static type of an expression: a compound expression takes the type of
+    /// its left-most operand, as in the JVM backend
+    fn type_of(&mut self, expression: &'a Expression) -> Result<Type, Box<SemanticError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(_) | ExpressionKind::StringLiteral(_) => Type::Int,
+            ExpressionKind::CharLiteral(_) => Type::Char,
+            ExpressionKind::Identifier(id) => self.variable(id)?.1,
+            ExpressionKind::FuncCall(name, _) => match self.symbol_table[0].find(name) {
+                Some(Symbol::Function(function)) => function.return_type.clone(),
+                _ => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::UndeclaredFunction(name.clone()),
+                    )))
+                }
+            },
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs)?,
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr)?,
+        })
+    }
+
+    /// Load and store instructions for a variable, and its type
+    fn variable(
+        &mut self,
+        name: &str,
+    ) -> Result<((Instruction, Instruction), Type), Box<SemanticError>> {
+        for (scope, table) in self.symbol_table.iter_mut().enumerate().rev() {
+            match table.find(name) {
+                Some(Symbol::Variable(_, toyc_type, index)) => {
+                    let index = *index as u16;
+                    let access = match scope {
+                        0 => (
+                            Instruction::LoadGlobal(index),
+                            Instruction::StoreGlobal(index),
+                        ),
+                        _ => (
+                            Instruction::LoadLocal(index),
+                            Instruction::StoreLocal(index),
+                        ),
+                    };
+                    return Ok((access, toyc_type.clone()));
+                }
+                Some(_) => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::ExpectedIdentifier,
+                    )))
+                }
+                None => {}
+            }
+        }
+        Err(Box::new(SemanticError::new(
+            SemanticErrorKind::UndeclaredIdentifier(name.to_owned()),
+        )))
+    }
+
+    fn declare_variable(
+        &mut self,
+        name: &'a str,
+        toyc_type: Type,
+    ) -> Result<u16, Box<SemanticError>> {
+        let index = self.local_count;
+        self.local_count += 1;
+        self.insert_symbol(
+            name,
+            Symbol::Variable(name.to_owned(), toyc_type, index as usize),
+        )?;
+        Ok(index)
+    }
+
+    fn insert_symbol(&mut self, name: &'a str, symbol: Symbol) -> Result<(), Box<SemanticError>> {
+        self.symbol_table
+            .iter_mut()
+            .next_back()
+            .unwrap()
+            .insert(name, symbol)
+            .map(|_| ())
+    }
+
+    /// Index of a constant in the pool, adding it if it is not there yet
+    fn constant(&mut self, constant: Constant) -> u16 {
+        match self.constants.iter().position(|c| *c == constant) {
+            Some(index) => index as u16,
+            None => {
+                self.constants.push(constant);
+                (self.constants.len() - 1) as u16
+            }
+        }
+    }
+
+    fn push_constant(&mut self, value: i32) {
+        match i8::try_from(value) {
+            Ok(value) => self.emit(Instruction::Byte(value)),
+            Err(_) => {
+                let index = self.constant(Constant::Int(value));
+                self.emit(Instruction::Const(index));
+            }
+        }
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.labels.push(usize::MAX);
+        self.labels.len() - 1
+    }
+
+    fn place_label(&mut self, label: usize) {
+        self.labels[label] = self.code.len();
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.code.push(instruction);
+    }
+}
+
+impl<'a> BytecodeCompiler<'a> {
+    /// Compiles a module of the IR, out of SSA form, to a bytecode
+    /// [`Module`]: every register gets a local slot after the parameters and
+    /// every block a label
+    pub fn compile_ir(&mut self, module: &'a ir::Module) -> Module {
+        assert!(
+            module.functions.iter().all(|function| !function.ssa),
+            "the module must be out of SSA form"
+        );
+        for (index, function) in module.functions.iter().enumerate() {
+            self.function_indices.insert(&function.name, index as u16);
+        }
+        let globals = module
+            .globals
+            .iter()
+            .enumerate()
+            .map(|(index, global)| (global.as_str(), index as u16))
+            .collect::<HashMap<_, _>>();
+
+        let functions = module
+            .functions
+            .iter()
+            .map(|function| self.compile_ir_function(function, &globals))
+            .collect();
+        Module {
+            constants: std::mem::take(&mut self.constants),
+            globals: module.globals.len() as u16,
+            functions,
+            main: self.function_indices["main"],
+        }
+    }
+
+    fn compile_ir_function(
+        &mut self,
+        function: &ir::Function,
+        globals: &HashMap<&str, u16>,
+    ) -> module::Function {
+        self.code.clear();
+        self.labels.clear();
+        let mut slots = vec![None; function.vreg_count as usize];
+        for (slot, param) in function.params.iter().enumerate() {
+            slots[param.0 as usize] = Some(slot as u16);
+        }
+        let mut next = function.params.len() as u16;
+        let slots = slots
+            .into_iter()
+            .map(|slot| {
+                slot.unwrap_or_else(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        let slot = |vreg: ir::VReg| slots[vreg.0 as usize];
+
+        for _ in &function.blocks {
+            self.new_label();
+        }
+        for (index, block) in function.blocks.iter().enumerate() {
+            self.place_label(index);
+            for instruction in &block.instructions {
+                self.compile_ir_instruction(instruction, &slot, globals);
+            }
+            let next_block = ir::BlockId(index as u32 + 1);
+            match block.terminator {
+                ir::Terminator::Jump(target) if target == next_block => {}
+                ir::Terminator::Jump(target) => self.emit(Instruction::Jump(target.0)),
+                ir::Terminator::Branch(condition, then_block, else_block) => {
+                    self.push_operand(condition, &slot);
+                    self.emit(Instruction::JumpIfFalse(else_block.0));
+                    if then_block != next_block {
+                        self.emit(Instruction::Jump(then_block.0));
+                    }
+                }
+                ir::Terminator::Return(value) => {
+                    self.push_operand(value, &slot);
+                    self.emit(Instruction::Return);
+                }
+            }
+        }
+
+        let name = self.constant(Constant::Str(function.name.clone()));
+        module::Function {
+            name,
+            params: function.params.len() as u16,
+            locals: next,
+            code: self.resolve_labels(),
+        }
+    }
+
+    fn compile_ir_instruction(
+        &mut self,
+        instruction: &ir::Instruction,
+        slot: &impl Fn(ir::VReg) -> u16,
+        globals: &HashMap<&str, u16>,
+    ) {
+        let dst = match instruction {
+            ir::Instruction::Copy { dst, src } => {
+                self.push_operand(*src, slot);
+                dst
+            }
+            ir::Instruction::Unary { dst, op, src } => {
+                self.push_operand(*src, slot);
+                self.emit(match op {
+                    ir::UnaryOp::Neg => Instruction::Neg,
+                    ir::UnaryOp::Not => Instruction::Not,
+                });
+                dst
+            }
+            ir::Instruction::Binary { dst, op, lhs, rhs } => {
+                self.push_operand(*lhs, slot);
+                self.push_operand(*rhs, slot);
+                self.emit(match op {
+                    ir::BinaryOp::Add => Instruction::Add,
+                    ir::BinaryOp::Sub => Instruction::Sub,
+                    ir::BinaryOp::Mul => Instruction::Mul,
+                    ir::BinaryOp::Div => Instruction::Div,
+                    ir::BinaryOp::Rem => Instruction::Rem,
+                    ir::BinaryOp::Eq => Instruction::Eq,
+                    ir::BinaryOp::Ne => Instruction::Ne,
+                    ir::BinaryOp::Lt => Instruction::Lt,
+                    ir::BinaryOp::Le => Instruction::Le,
+                    ir::BinaryOp::Gt => Instruction::Gt,
+                    ir::BinaryOp::Ge => Instruction::Ge,
+                });
+                dst
+            }
+            ir::Instruction::LoadGlobal { dst, global } => {
+                self.emit(Instruction::LoadGlobal(globals[global.as_str()]));
+                dst
+            }
+            ir::Instruction::Call {
+                dst,
+                function,
+                args,
+            } => {
+                for arg in args {
+                    self.push_operand(*arg, slot);
+                }
+                self.emit(Instruction::Call(self.function_indices[function.as_str()]));
+                dst
+            }
+            ir::Instruction::Read { dst, kind } => {
+                self.emit(match kind {
+                    ir::IoKind::Int => Instruction::ReadInt,
+                    ir::IoKind::Char => Instruction::ReadChar,
+                });
+                dst
+            }
+            ir::Instruction::StoreGlobal { global, src } => {
+                self.push_operand(*src, slot);
+                self.emit(Instruction::StoreGlobal(globals[global.as_str()]));
+                return;
+            }
+            ir::Instruction::Write { kind, src } => {
+                self.push_operand(*src, slot);
+                self.emit(match kind {
+                    ir::IoKind::Int => Instruction::WriteInt,
+                    ir::IoKind::Char => Instruction::WriteChar,
+                });
+                return;
+            }
+            ir::Instruction::WriteStr(s) => {
+                let index = self.constant(Constant::Str(s.clone()));
+                self.emit(Instruction::WriteStr(index));
+                return;
+            }
+            ir::Instruction::NewLine => {
+                self.emit(Instruction::NewLine);
+                return;
+            }
+            ir::Instruction::Phi { .. } => unreachable!("phi outside SSA form"),
+        };
+        self.emit(Instruction::StoreLocal(slot(*dst)));
+    }
+
+    fn push_operand(&mut self, operand: ir::Operand, slot: &impl Fn(ir::VReg) -> u16) {
+        match operand {
+            ir::Operand::Const(value) => self.push_constant(value),
+            ir::Operand::Reg(vreg) => self.emit(Instruction::LoadLocal(slot(vreg))),
+        }
+    }
+}
+
+/// Expands the escape sequences accepted in ToyC string literals
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+    use toycc_report::Diagnostic;
+
+    fn compile(source: &str) -> Result<Module, Box<SemanticError>> {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        BytecodeCompiler::new().compile(&program)
+    }
+
+    fn instructions(code: &[u8]) -> Vec<Instruction> {
+        let mut offset = 0;
+        let mut instructions = vec![];
+        while offset < code.len() {
+            let instruction = Instruction::decode(code, offset).unwrap();
+            offset += instruction.size();
+            instructions.push(instruction);
+        }
+        instructions
+    }
+
+    #[test]
+    fn test_function_table_and_constants() {
+        let module = compile(
+            "int g; int addTwo(int n){ return n + 2; } int main(){ g = 100000; write(\"g\", addTwo(g)); return 0; }",
+        )
+        .unwrap();
+        assert_eq!(module.globals, 1);
+        assert_eq!(module.main, 1);
+        assert_eq!(module.functions[0].params, 1);
+        assert_eq!(module.function_name(&module.functions[1]), "main");
+        assert!(module.constants.contains(&Constant::Int(100000)));
+        assert_eq!(
+            instructions(&module.functions[0].code),
+            [
+                Instruction::LoadLocal(0),
+                Instruction::Byte(2),
+                Instruction::Add,
+                Instruction::Return,
+                Instruction::Byte(0),
+                Instruction::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jump_targets() {
+        let module = compile(
+            "int main(){ int i; while (i < 3) { if (i == 1) break; i = i + 1; } return i; }",
+        )
+        .unwrap();
+        let code = instructions(&module.functions[0].code);
+        // the loop condition starts after zeroing `i` (byte 0; store 0)
+        assert_eq!(code[2], Instruction::LoadLocal(0));
+        assert!(code.contains(&Instruction::Jump(5)));
+        let Some(Instruction::JumpIfFalse(end)) = code.get(5) else {
+            panic!("expected the loop exit after the condition: {code:?}");
+        };
+        // `break` jumps to the same place as the loop exit
+        assert_eq!(
+            code.iter()
+                .filter(|i| **i == Instruction::Jump(*end))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_missing_main() {
+        let error = compile("int f(int a){ return a; }").unwrap_err();
+        assert_eq!(error.info(), "missing main function");
+    }
+}
@@ -0,0 +1,60 @@
+use crate::instruction::Instruction;
+use crate::module::{Constant, Module, VERSION};
+use std::fmt::{Display, Formatter};
+
+/// Disassembly listing: the constant pool, then every function with the
+/// byte offset of each instruction, which jump targets refer to
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "; toycc bytecode {}.{}", VERSION.0, VERSION.1)?;
+        writeln!(f, ".constants {}", self.constants.len())?;
+        for (index, constant) in self.constants.iter().enumerate() {
+            match constant {
+                Constant::Int(value) => writeln!(f, "    #{index} int {value}")?,
+                Constant::Str(string) => writeln!(f, "    #{index} str {string:?}")?,
+            }
+        }
+        writeln!(f, ".globals {}", self.globals)?;
+        for (index, function) in self.functions.iter().enumerate() {
+            let name = self.function_name(function);
+            writeln!(f)?;
+            write!(
+                f,
+                ".function {index} {name} params {} locals {}",
+                function.params, function.locals
+            )?;
+            match index == self.main as usize {
+                true => writeln!(f, " ; entry")?,
+                false => writeln!(f)?,
+            }
+            let mut offset = 0;
+            while offset < function.code.len() {
+                match Instruction::decode(&function.code, offset) {
+                    Ok(instruction) => {
+                        write!(f, "    {offset:04}  {instruction}")?;
+                        match instruction {
+                            Instruction::Const(index) | Instruction::WriteStr(index) => {
+                                match self.constants.get(index as usize) {
+                                    Some(Constant::Int(value)) => writeln!(f, "  ; {value}")?,
+                                    Some(Constant::Str(string)) => writeln!(f, "  ; {string:?}")?,
+                                    None => writeln!(f)?,
+                                }
+                            }
+                            Instruction::Call(index) => match self.functions.get(index as usize) {
+                                Some(callee) => writeln!(f, "  ; {}", self.function_name(callee))?,
+                                None => writeln!(f)?,
+                            },
+                            _ => writeln!(f)?,
+                        }
+                        offset += instruction.size();
+                    }
+                    Err(e) => {
+                        writeln!(f, "    {offset:04}  ; {e:?}")?;
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+use toycc_report::{Diagnostic, ErrorKind, Report, ReportLevel};
+
+#[derive(Report, Debug, PartialEq)]
+pub enum BytecodeError {
+    BadMagic,
+    UnsupportedVersion(u16, u16),
+    UnexpectedEnd,
+    InvalidConstantTag(u8),
+    InvalidString,
+    InvalidOpcode(u8, usize),
+    Verify(String),
+    DivisionByZero,
+    StackOverflow(usize),
+    InvalidInput(String),
+}
+
+impl Diagnostic for BytecodeError {
+    fn info(&self) -> String {
+        match self {
+            Self::BadMagic => "not a toycc bytecode file".to_string(),
+            Self::UnsupportedVersion(major, minor) => {
+                format!("unsupported bytecode version {major}.{minor}")
+            }
+            Self::UnexpectedEnd => "unexpected end of bytecode".to_string(),
+            Self::InvalidConstantTag(tag) => format!("invalid constant tag {tag:#04x}"),
+            Self::InvalidString => "string constant is not valid UTF-8".to_string(),
+            Self::InvalidOpcode(opcode, offset) => {
+                format!("invalid opcode {opcode:#04x} at offset {offset}")
+            }
+            Self::Verify(message) => format!("verification failed: {message}"),
+            Self::DivisionByZero => "division by 0".to_string(),
+            Self::StackOverflow(depth) => format!("stack overflow: call depth exceeded {depth}"),
+            Self::InvalidInput(input) => format!("invalid input '{input}'"),
+        }
+    }
+
+    fn level(&self) -> ReportLevel {
+        ReportLevel::Error(ErrorKind::NoHelpError)
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        None
+    }
+}
@@ -0,0 +1,269 @@
+use crate::error::BytecodeError;
+use std::fmt::{Display, Formatter};
+
+/// A single stack-machine instruction. Operands are little-endian: constant,
+/// variable and function indices are `u16`, jump targets are `u32` byte
+/// offsets from the start of the function's code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Pushes an integer from the constant pool
+    Const(u16),
+    /// Pushes a small integer stored inline
+    Byte(i8),
+    Pop,
+    Dup,
+    LoadLocal(u16),
+    StoreLocal(u16),
+    LoadGlobal(u16),
+    StoreGlobal(u16),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Neg,
+    /// Logical negation: pushes 1 for 0 and 0 otherwise
+    Not,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Jump(u32),
+    /// Pops a value and jumps when it is 0
+    JumpIfFalse(u32),
+    /// Pops a value and jumps when it is not 0
+    JumpIfTrue(u32),
+    Call(u16),
+    Return,
+    WriteInt,
+    WriteChar,
+    /// Writes a string from the constant pool
+    WriteStr(u16),
+    ReadInt,
+    ReadChar,
+    NewLine,
+}
+
+impl Instruction {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Self::Const(_) => 0x01,
+            Self::Byte(_) => 0x02,
+            Self::Pop => 0x03,
+            Self::Dup => 0x04,
+            Self::LoadLocal(_) => 0x10,
+            Self::StoreLocal(_) => 0x11,
+            Self::LoadGlobal(_) => 0x12,
+            Self::StoreGlobal(_) => 0x13,
+            Self::Add => 0x20,
+            Self::Sub => 0x21,
+            Self::Mul => 0x22,
+            Self::Div => 0x23,
+            Self::Rem => 0x24,
+            Self::Neg => 0x25,
+            Self::Not => 0x26,
+            Self::Eq => 0x28,
+            Self::Ne => 0x29,
+            Self::Lt => 0x2a,
+            Self::Le => 0x2b,
+            Self::Gt => 0x2c,
+            Self::Ge => 0x2d,
+            Self::Jump(_) => 0x30,
+            Self::JumpIfFalse(_) => 0x31,
+            Self::JumpIfTrue(_) => 0x32,
+            Self::Call(_) => 0x40,
+            Self::Return => 0x41,
+            Self::WriteInt => 0x50,
+            Self::WriteChar => 0x51,
+            Self::WriteStr(_) => 0x52,
+            Self::ReadInt => 0x53,
+            Self::ReadChar => 0x54,
+            Self::NewLine => 0x55,
+        }
+    }
+
+    /// Encoded size in bytes, including the opcode
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Byte(_) => 2,
+            Self::Const(_)
+            | Self::LoadLocal(_)
+            | Self::StoreLocal(_)
+            | Self::LoadGlobal(_)
+            | Self::StoreGlobal(_)
+            | Self::Call(_)
+            | Self::WriteStr(_) => 3,
+            Self::Jump(_) | Self::JumpIfFalse(_) | Self::JumpIfTrue(_) => 5,
+            _ => 1,
+        }
+    }
+
+    pub fn encode(&self, code: &mut Vec<u8>) {
+        code.push(self.opcode());
+        match *self {
+            Self::Byte(value) => code.push(value as u8),
+            Self::Const(index)
+            | Self::LoadLocal(index)
+            | Self::StoreLocal(index)
+            | Self::LoadGlobal(index)
+            | Self::StoreGlobal(index)
+            | Self::Call(index)
+            | Self::WriteStr(index) => code.extend(index.to_le_bytes()),
+            Self::Jump(target) | Self::JumpIfFalse(target) | Self::JumpIfTrue(target) => {
+                code.extend(target.to_le_bytes())
+            }
+            _ => {}
+        }
+    }
+
+    /// Decodes the instruction starting at `offset`
+    pub fn decode(code: &[u8], offset: usize) -> Result<Self, BytecodeError> {
+        let byte = |i: usize| {
+            code.get(offset + i)
+                .copied()
+                .ok_or(BytecodeError::UnexpectedEnd)
+        };
+        let index = || Ok::<_, BytecodeError>(u16::from_le_bytes([byte(1)?, byte(2)?]));
+        let target =
+            || Ok::<_, BytecodeError>(u32::from_le_bytes([byte(1)?, byte(2)?, byte(3)?, byte(4)?]));
+        Ok(match byte(0)? {
+            0x01 => Self::Const(index()?),
+            0x02 => Self::Byte(byte(1)? as i8),
+            0x03 => Self::Pop,
+            0x04 => Self::Dup,
+            0x10 => Self::LoadLocal(index()?),
+            0x11 => Self::StoreLocal(index()?),
+            0x12 => Self::LoadGlobal(index()?),
+            0x13 => Self::StoreGlobal(index()?),
+            0x20 => Self::Add,
+            0x21 => Self::Sub,
+            0x22 => Self::Mul,
+            0x23 => Self::Div,
+            0x24 => Self::Rem,
+            0x25 => Self::Neg,
+            0x26 => Self::Not,
+            0x28 => Self::Eq,
+            0x29 => Self::Ne,
+            0x2a => Self::Lt,
+            0x2b => Self::Le,
+            0x2c => Self::Gt,
+            0x2d => Self::Ge,
+            0x30 => Self::Jump(target()?),
+            0x31 => Self::JumpIfFalse(target()?),
+            0x32 => Self::JumpIfTrue(target()?),
+            0x40 => Self::Call(index()?),
+            0x41 => Self::Return,
+            0x50 => Self::WriteInt,
+            0x51 => Self::WriteChar,
+            0x52 => Self::WriteStr(index()?),
+            0x53 => Self::ReadInt,
+            0x54 => Self::ReadChar,
+            0x55 => Self::NewLine,
+            opcode => return Err(BytecodeError::InvalidOpcode(opcode, offset)),
+        })
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Const(_) => "const",
+            Self::Byte(_) => "byte",
+            Self::Pop => "pop",
+            Self::Dup => "dup",
+            Self::LoadLocal(_) => "load",
+            Self::StoreLocal(_) => "store",
+            Self::LoadGlobal(_) => "gload",
+            Self::StoreGlobal(_) => "gstore",
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Rem => "rem",
+            Self::Neg => "neg",
+            Self::Not => "not",
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Lt => "lt",
+            Self::Le => "le",
+            Self::Gt => "gt",
+            Self::Ge => "ge",
+            Self::Jump(_) => "jmp",
+            Self::JumpIfFalse(_) => "jz",
+            Self::JumpIfTrue(_) => "jnz",
+            Self::Call(_) => "call",
+            Self::Return => "ret",
+            Self::WriteInt => "write.int",
+            Self::WriteChar => "write.char",
+            Self::WriteStr(_) => "write.str",
+            Self::ReadInt => "read.int",
+            Self::ReadChar => "read.char",
+            Self::NewLine => "newline",
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Byte(value) => write!(f, "{} {value}", self.mnemonic()),
+            Self::Const(index) | Self::WriteStr(index) => {
+                write!(f, "{} #{index}", self.mnemonic())
+            }
+            Self::LoadGlobal(index) | Self::StoreGlobal(index) => {
+                write!(f, "{} @{index}", self.mnemonic())
+            }
+            Self::LoadLocal(index) | Self::StoreLocal(index) | Self::Call(index) => {
+                write!(f, "{} {index}", self.mnemonic())
+            }
+            Self::Jump(target) | Self::JumpIfFalse(target) | Self::JumpIfTrue(target) => {
+                write!(f, "{} {target:04}", self.mnemonic())
+            }
+            _ => write!(f, "{}", self.mnemonic()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let instructions = [
+            Instruction::Const(513),
+            Instruction::Byte(-3),
+            Instruction::StoreGlobal(2),
+            Instruction::JumpIfFalse(70000),
+            Instruction::Ge,
+            Instruction::WriteStr(1),
+            Instruction::Return,
+        ];
+        let mut code = vec![];
+        for instruction in &instructions {
+            instruction.encode(&mut code);
+        }
+        assert_eq!(
+            code.len(),
+            instructions.iter().map(|i| i.size()).sum::<usize>()
+        );
+        let mut offset = 0;
+        for instruction in &instructions {
+            let decoded = Instruction::decode(&code, offset).unwrap();
+            assert_eq!(&decoded, instruction);
+            offset += decoded.size();
+        }
+    }
+
+    #[test]
+    fn test_invalid_code() {
+        assert_eq!(
+            Instruction::decode(&[0x03, 0xff], 1),
+            Err(BytecodeError::InvalidOpcode(0xff, 1))
+        );
+        assert_eq!(
+            Instruction::decode(&[0x30, 0x01], 0),
+            Err(BytecodeError::UnexpectedEnd)
+        );
+    }
+}
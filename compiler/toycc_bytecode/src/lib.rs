@@ -0,0 +1,16 @@
+//! toycc's own bytecode: a compact `.tcb` container holding a constant pool,
+//! a global count and a function table of stack-machine code, together with
+//! compilers from the checked AST and from the IR, a disassembler and a VM
+//! to run it.
+mod compiler;
+mod disassembler;
+mod error;
+mod instruction;
+mod module;
+mod vm;
+
+pub use compiler::BytecodeCompiler;
+pub use error::BytecodeError;
+pub use instruction::Instruction;
+pub use module::{Constant, Function, Module, MAGIC, VERSION};
+pub use vm::{Vm, MAX_FRAMES};
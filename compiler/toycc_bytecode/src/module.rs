@@ -0,0 +1,210 @@
+use crate::error::BytecodeError;
+
+/// First four bytes of every `.tcb` file
+pub const MAGIC: [u8; 4] = *b"TCB\0";
+/// Format version written after the magic; readers accept files with the
+/// same major version and a minor version no newer than their own
+pub const VERSION: (u16, u16) = (1, 0);
+
+const TAG_INT: u8 = 0x01;
+const TAG_STR: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constant {
+    Int(i32),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    /// Index of the name in the constant pool
+    pub name: u16,
+    pub params: u16,
+    /// Number of local slots, parameters included
+    pub locals: u16,
+    pub code: Vec<u8>,
+}
+
+/// A decoded `.tcb` file. The encoding is little-endian:
+///
+/// ```text
+/// magic "TCB\0" | major u16 | minor u16
+/// constants: count u16, then tag u8 + (i32 | len u32 + UTF-8 bytes)
+/// globals:   count u16
+/// functions: count u16, then name u16 | params u16 | locals u16 | len u32 + code
+/// main:      function index u16
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Module {
+    pub constants: Vec<Constant>,
+    pub globals: u16,
+    pub functions: Vec<Function>,
+    pub main: u16,
+}
+
+impl Module {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.0.to_le_bytes());
+        bytes.extend(VERSION.1.to_le_bytes());
+
+        bytes.extend((self.constants.len() as u16).to_le_bytes());
+        for constant in &self.constants {
+            match constant {
+                Constant::Int(value) => {
+                    bytes.push(TAG_INT);
+                    bytes.extend(value.to_le_bytes());
+                }
+                Constant::Str(string) => {
+                    bytes.push(TAG_STR);
+                    bytes.extend((string.len() as u32).to_le_bytes());
+                    bytes.extend(string.as_bytes());
+                }
+            }
+        }
+
+        bytes.extend(self.globals.to_le_bytes());
+
+        bytes.extend((self.functions.len() as u16).to_le_bytes());
+        for function in &self.functions {
+            bytes.extend(function.name.to_le_bytes());
+            bytes.extend(function.params.to_le_bytes());
+            bytes.extend(function.locals.to_le_bytes());
+            bytes.extend((function.code.len() as u32).to_le_bytes());
+            bytes.extend(&function.code);
+        }
+
+        bytes.extend(self.main.to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(BytecodeError::BadMagic);
+        }
+        let (major, minor) = (reader.u16()?, reader.u16()?);
+        if major != VERSION.0 || minor > VERSION.1 {
+            return Err(BytecodeError::UnsupportedVersion(major, minor));
+        }
+
+        let mut module = Module::default();
+        for _ in 0..reader.u16()? {
+            let constant = match reader.u8()? {
+                TAG_INT => Constant::Int(reader.u32()? as i32),
+                TAG_STR => {
+                    let len = reader.u32()? as usize;
+                    let string = std::str::from_utf8(reader.take(len)?)
+                        .map_err(|_| BytecodeError::InvalidString)?;
+                    Constant::Str(string.to_owned())
+                }
+                tag => return Err(BytecodeError::InvalidConstantTag(tag)),
+            };
+            module.constants.push(constant);
+        }
+
+        module.globals = reader.u16()?;
+
+        for _ in 0..reader.u16()? {
+            let name = reader.u16()?;
+            let params = reader.u16()?;
+            let locals = reader.u16()?;
+            let len = reader.u32()? as usize;
+            let code = reader.take(len)?.to_vec();
+            module.functions.push(Function {
+                name,
+                params,
+                locals,
+                code,
+            });
+        }
+
+        module.main = reader.u16()?;
+        if reader.position != bytes.len() {
+            return Err(BytecodeError::Verify(
+                "trailing bytes after module".to_owned(),
+            ));
+        }
+        Ok(module)
+    }
+
+    /// Name of a function, or `?` when its constant is not a string
+    pub fn function_name(&self, function: &Function) -> &str {
+        match self.constants.get(function.name as usize) {
+            Some(Constant::Str(name)) => name,
+            _ => "?",
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BytecodeError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + len)
+            .ok_or(BytecodeError::UnexpectedEnd)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn module() -> Module {
+        Module {
+            constants: vec![Constant::Str("main".to_owned()), Constant::Int(-70000)],
+            globals: 2,
+            functions: vec![Function {
+                name: 0,
+                params: 0,
+                locals: 1,
+                code: vec![0x01, 0x01, 0x00, 0x41],
+            }],
+            main: 0,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = module().encode();
+        assert_eq!(&bytes[..8], b"TCB\0\x01\x00\x00\x00");
+        assert_eq!(Module::decode(&bytes), Ok(module()));
+    }
+
+    #[test]
+    fn test_header_checks() {
+        let mut bytes = module().encode();
+        bytes[6] = 1;
+        assert_eq!(
+            Module::decode(&bytes),
+            Err(BytecodeError::UnsupportedVersion(1, 1))
+        );
+        bytes[0] = b'X';
+        assert_eq!(Module::decode(&bytes), Err(BytecodeError::BadMagic));
+
+        let bytes = module().encode();
+        assert_eq!(
+            Module::decode(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::UnexpectedEnd)
+        );
+    }
+}
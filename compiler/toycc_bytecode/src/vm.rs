@@ -0,0 +1,433 @@
+use crate::error::BytecodeError;
+use crate::instruction::Instruction;
+use crate::module::{Constant, Module};
+use std::io::{BufRead, Write};
+
+/// Deepest chain of calls before a stack overflow is reported, matching the
+/// limit of the tree-walking interpreter
+pub const MAX_FRAMES: usize = 10_000;
+
+struct Code {
+    params: usize,
+    locals: usize,
+    /// Decoded instructions; jump targets are instruction indices
+    instructions: Vec<Instruction>,
+}
+
+struct Frame {
+    function: usize,
+    pc: usize,
+    base: usize,
+}
+
+/// Runs a [`Module`]. Every function is decoded and verified up front:
+/// indices must be in range, jumps must land on instructions, and the
+/// operand stack must have the same depth on every path to an instruction,
+/// never drop below the frame's base and never run off the end of the code.
+pub struct Vm<'a> {
+    module: &'a Module,
+    functions: Vec<Code>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(module: &'a Module) -> Result<Self, BytecodeError> {
+        let functions = (0..module.functions.len())
+            .map(|index| verify(module, index))
+            .collect::<Result<Vec<_>, _>>()?;
+        match functions.get(module.main as usize) {
+            Some(main) if main.params == 0 => Ok(Self { module, functions }),
+            Some(_) => Err(BytecodeError::Verify("main takes parameters".to_owned())),
+            None => Err(BytecodeError::Verify(format!(
+                "entry function {} does not exist",
+                module.main
+            ))),
+        }
+    }
+
+    /// Runs the entry function and returns its result
+    pub fn run<R, W>(&mut self, input: &mut R, output: &mut W) -> Result<i32, BytecodeError>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let result = self.execute(input, output);
+        let _ = output.flush();
+        result
+    }
+
+    fn execute<R, W>(&mut self, input: &mut R, output: &mut W) -> Result<i32, BytecodeError>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let mut globals = vec![0; self.module.globals as usize];
+        let mut stack: Vec<i32> = vec![];
+        let mut frames: Vec<Frame> = vec![];
+        let mut function = self.module.main as usize;
+        let mut locals = vec![0; self.functions[function].locals];
+        let mut base = 0;
+        let mut pc = 0;
+
+        // verification guarantees every pop below has a value to take
+        let pop = |stack: &mut Vec<i32>| stack.pop().unwrap();
+        loop {
+            let instruction = self.functions[function].instructions[pc];
+            pc += 1;
+            match instruction {
+                Instruction::Const(index) => match &self.module.constants[index as usize] {
+                    Constant::Int(value) => stack.push(*value),
+                    Constant::Str(_) => unreachable!(),
+                },
+                Instruction::Byte(value) => stack.push(value as i32),
+                Instruction::Pop => {
+                    pop(&mut stack);
+                }
+                Instruction::Dup => stack.push(*stack.last().unwrap()),
+                Instruction::LoadLocal(index) => stack.push(locals[base + index as usize]),
+                Instruction::StoreLocal(index) => locals[base + index as usize] = pop(&mut stack),
+                Instruction::LoadGlobal(index) => stack.push(globals[index as usize]),
+                Instruction::StoreGlobal(index) => globals[index as usize] = pop(&mut stack),
+                Instruction::Neg => {
+                    let value = pop(&mut stack);
+                    stack.push(value.wrapping_neg());
+                }
+                Instruction::Not => {
+                    let value = pop(&mut stack);
+                    stack.push((value == 0) as i32);
+                }
+                Instruction::Add
+                | Instruction::Sub
+                | Instruction::Mul
+                | Instruction::Div
+                | Instruction::Rem
+                | Instruction::Eq
+                | Instruction::Ne
+                | Instruction::Lt
+                | Instruction::Le
+                | Instruction::Gt
+                | Instruction::Ge => {
+                    let b = pop(&mut stack);
+                    let a = pop(&mut stack);
+                    stack.push(match instruction {
+                        Instruction::Add => a.wrapping_add(b),
+                        Instruction::Sub => a.wrapping_sub(b),
+                        Instruction::Mul => a.wrapping_mul(b),
+                        Instruction::Div | Instruction::Rem if b == 0 => {
+                            return Err(BytecodeError::DivisionByZero)
+                        }
+                        Instruction::Div => a.wrapping_div(b),
+                        Instruction::Rem => a.wrapping_rem(b),
+                        Instruction::Eq => (a == b) as i32,
+                        Instruction::Ne => (a != b) as i32,
+                        Instruction::Lt => (a < b) as i32,
+                        Instruction::Le => (a <= b) as i32,
+                        Instruction::Gt => (a > b) as i32,
+                        _ => (a >= b) as i32,
+                    });
+                }
+                Instruction::Jump(target) => pc = target as usize,
+                Instruction::JumpIfFalse(target) => {
+                    if pop(&mut stack) == 0 {
+                        pc = target as usize;
+                    }
+                }
+                Instruction::JumpIfTrue(target) => {
+                    if pop(&mut stack) != 0 {
+                        pc = target as usize;
+                    }
+                }
+                Instruction::Call(index) => {
+                    if frames.len() + 1 >= MAX_FRAMES {
+                        return Err(BytecodeError::StackOverflow(MAX_FRAMES));
+                    }
+                    let callee = &self.functions[index as usize];
+                    let new_base = locals.len();
+                    locals.resize(new_base + callee.locals, 0);
+                    let arguments = stack.len() - callee.params;
+                    locals[new_base..new_base + callee.params].copy_from_slice(&stack[arguments..]);
+                    stack.truncate(arguments);
+                    frames.push(Frame { function, pc, base });
+                    function = index as usize;
+                    base = new_base;
+                    pc = 0;
+                }
+                Instruction::Return => {
+                    let value = pop(&mut stack);
+                    locals.truncate(base);
+                    match frames.pop() {
+                        Some(frame) => {
+                            function = frame.function;
+                            pc = frame.pc;
+                            base = frame.base;
+                            stack.push(value);
+                        }
+                        None => return Ok(value),
+                    }
+                }
+                Instruction::WriteInt => {
+                    let _ = write!(output, "{}", pop(&mut stack));
+                }
+                Instruction::WriteChar => {
+                    let value = pop(&mut stack);
+                    if let Some(c) = char::from_u32(value as u32) {
+                        let _ = write!(output, "{c}");
+                    }
+                }
+                Instruction::WriteStr(index) => match &self.module.constants[index as usize] {
+                    Constant::Str(string) => {
+                        let _ = output.write_all(string.as_bytes());
+                    }
+                    Constant::Int(_) => unreachable!(),
+                },
+                Instruction::ReadInt | Instruction::ReadChar => {
+                    let _ = output.flush();
+                    stack.push(read_value(input, instruction == Instruction::ReadChar)?);
+                }
+                Instruction::NewLine => {
+                    let _ = output.write_all(b"\n");
+                    let _ = output.flush();
+                }
+            }
+        }
+    }
+}
+
+fn verify(module: &Module, index: usize) -> Result<Code, BytecodeError> {
+    let function = &module.functions[index];
+    let name = module.function_name(function);
+    let error = |message: String| BytecodeError::Verify(format!("{name}: {message}"));
+    if function.params > function.locals {
+        return Err(error("more parameters than locals".to_owned()));
+    }
+
+    // decode, remembering which byte offsets start an instruction
+    let mut instructions = vec![];
+    let mut starts = vec![None; function.code.len() + 1];
+    let mut offset = 0;
+    while offset < function.code.len() {
+        let instruction = Instruction::decode(&function.code, offset)?;
+        starts[offset] = Some(instructions.len());
+        instructions.push(instruction);
+        offset += instruction.size();
+    }
+
+    let target = |target: u32| {
+        starts
+            .get(target as usize)
+            .copied()
+            .flatten()
+            .map(|index| index as u32)
+            .ok_or_else(|| error(format!("jump to {target} is not an instruction")))
+    };
+    for instruction in instructions.iter_mut() {
+        let constant = |index: u16| module.constants.get(index as usize);
+        let valid = match *instruction {
+            Instruction::Jump(offset) => {
+                *instruction = Instruction::Jump(target(offset)?);
+                true
+            }
+            Instruction::JumpIfFalse(offset) => {
+                *instruction = Instruction::JumpIfFalse(target(offset)?);
+                true
+            }
+            Instruction::JumpIfTrue(offset) => {
+                *instruction = Instruction::JumpIfTrue(target(offset)?);
+                true
+            }
+            Instruction::Const(index) => matches!(constant(index), Some(Constant::Int(_))),
+            Instruction::WriteStr(index) => matches!(constant(index), Some(Constant::Str(_))),
+            Instruction::LoadLocal(index) | Instruction::StoreLocal(index) => {
+                index < function.locals
+            }
+            Instruction::LoadGlobal(index) | Instruction::StoreGlobal(index) => {
+                index < module.globals
+            }
+            Instruction::Call(index) => (index as usize) < module.functions.len(),
+            _ => true,
+        };
+        if !valid {
+            return Err(error(format!("invalid operand in '{instruction}'")));
+        }
+    }
+
+    // operand stack depths, propagated along every edge
+    let mut depths = vec![None; instructions.len()];
+    let mut pending = vec![(0, 0)];
+    while let Some((pc, depth)) = pending.pop() {
+        let Some(instruction) = instructions.get(pc) else {
+            return Err(error("control reaches the end of the code".to_owned()));
+        };
+        match depths[pc] {
+            Some(known) if known == depth => continue,
+            Some(known) => {
+                return Err(error(format!(
+                    "stack depth {depth} at {pc} conflicts with {known}"
+                )))
+            }
+            None => depths[pc] = Some(depth),
+        }
+        let (pops, pushes) = match *instruction {
+            Instruction::Const(_)
+            | Instruction::Byte(_)
+            | Instruction::LoadLocal(_)
+            | Instruction::LoadGlobal(_)
+            | Instruction::ReadInt
+            | Instruction::ReadChar => (0, 1),
+            Instruction::Dup => (1, 2),
+            Instruction::Pop
+            | Instruction::StoreLocal(_)
+            | Instruction::StoreGlobal(_)
+            | Instruction::WriteInt
+            | Instruction::WriteChar
+            | Instruction::JumpIfFalse(_)
+            | Instruction::JumpIfTrue(_)
+            | Instruction::Return => (1, 0),
+            Instruction::Neg | Instruction::Not => (1, 1),
+            Instruction::Jump(_) | Instruction::WriteStr(_) | Instruction::NewLine => (0, 0),
+            Instruction::Call(index) => (module.functions[index as usize].params as usize, 1),
+            _ => (2, 1),
+        };
+        if depth < pops {
+            return Err(error(format!("stack underflow at {pc} in '{instruction}'")));
+        }
+        // the caller's stack continues where it was before the call
+        if *instruction == Instruction::Return && depth != 1 {
+            return Err(error(format!("stack depth {depth} at return at {pc}")));
+        }
+        let depth = depth - pops + pushes;
+        match *instruction {
+            Instruction::Return => {}
+            Instruction::Jump(target) => pending.push((target as usize, depth)),
+            Instruction::JumpIfFalse(target) | Instruction::JumpIfTrue(target) => {
+                pending.push((target as usize, depth));
+                pending.push((pc + 1, depth));
+            }
+            _ => pending.push((pc + 1, depth)),
+        }
+    }
+
+    Ok(Code {
+        params: function.params as usize,
+        locals: function.locals as usize,
+        instructions,
+    })
+}
+
+/// Reads a whitespace-separated integer, or the next non-whitespace
+/// character, as the interpreter does
+fn read_value<R: BufRead>(input: &mut R, char: bool) -> Result<i32, BytecodeError> {
+    let mut word = String::new();
+    loop {
+        let (consumed, done) = match input.fill_buf() {
+            Ok([]) | Err(_) => (0, true),
+            Ok(buffer) => {
+                let mut consumed = 0;
+                let mut done = false;
+                for &byte in buffer {
+                    if byte.is_ascii_whitespace() {
+                        if !word.is_empty() {
+                            done = true;
+                            break;
+                        }
+                    } else {
+                        word.push(byte as char);
+                        if char {
+                            consumed += 1;
+                            done = true;
+                            break;
+                        }
+                    }
+                    consumed += 1;
+                }
+                (consumed, done)
+            }
+        };
+        input.consume(consumed);
+        if done {
+            break;
+        }
+    }
+
+    match char {
+        false => word.parse::<i32>().ok(),
+        true => word.chars().next().map(|c| c as i32),
+    }
+    .ok_or(BytecodeError::InvalidInput(word))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::module::Function;
+
+    fn module(code: &[Instruction], locals: u16) -> Module {
+        let mut bytes = vec![];
+        for instruction in code {
+            instruction.encode(&mut bytes);
+        }
+        Module {
+            constants: vec![Constant::Str("main".to_owned())],
+            globals: 0,
+            functions: vec![Function {
+                name: 0,
+                params: 0,
+                locals,
+                code: bytes,
+            }],
+            main: 0,
+        }
+    }
+
+    #[test]
+    fn test_verifier() {
+        let underflow = module(&[Instruction::Add, Instruction::Return], 0);
+        assert!(
+            matches!(Vm::new(&underflow), Err(BytecodeError::Verify(m)) if m.contains("underflow"))
+        );
+
+        let fall_through = module(&[Instruction::Byte(1), Instruction::Pop], 0);
+        assert!(
+            matches!(Vm::new(&fall_through), Err(BytecodeError::Verify(m)) if m.contains("end of the code"))
+        );
+
+        let leftover = module(
+            &[
+                Instruction::Byte(1),
+                Instruction::Byte(2),
+                Instruction::Return,
+            ],
+            0,
+        );
+        assert!(
+            matches!(Vm::new(&leftover), Err(BytecodeError::Verify(m)) if m.contains("depth 2 at return"))
+        );
+
+        let bad_local = module(&[Instruction::LoadLocal(1), Instruction::Return], 1);
+        assert!(Vm::new(&bad_local).is_err());
+
+        // byte 1 (2 bytes) + jnz (5 bytes): offset 3 is inside the jump
+        let bad_jump = module(&[Instruction::Byte(1), Instruction::JumpIfTrue(3)], 0);
+        assert!(
+            matches!(Vm::new(&bad_jump), Err(BytecodeError::Verify(m)) if m.contains("jump to 3"))
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let divide = module(
+            &[
+                Instruction::Byte(1),
+                Instruction::Byte(0),
+                Instruction::Div,
+                Instruction::Return,
+            ],
+            0,
+        );
+        let result = Vm::new(&divide).unwrap().run(&mut &b""[..], &mut vec![]);
+        assert_eq!(result, Err(BytecodeError::DivisionByZero));
+
+        let read = module(&[Instruction::ReadInt, Instruction::Return], 0);
+        let result = Vm::new(&read).unwrap().run(&mut &b" 12x"[..], &mut vec![]);
+        assert_eq!(result, Err(BytecodeError::InvalidInput("12x".to_owned())));
+    }
+}
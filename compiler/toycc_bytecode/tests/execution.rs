@@ -0,0 +1,154 @@
+//! Compiles programs to bytecode, both from the AST and from the optimized
+//! IR, round-trips them through the binary format and compares the VM's
+//! output with the tree-walking interpreter.
+
+use std::io::Cursor;
+use toycc_bytecode::{BytecodeCompiler, BytecodeError, Instruction, Module, Vm, MAX_FRAMES};
+use toycc_frontend::ast::Program;
+use toycc_ir::loops::{LoopInvariantCodeMotion, StrengthReduction};
+use toycc_ir::ssa::{SsaConstruction, SsaDestruction};
+use toycc_ir::tail_call::TailCallElimination;
+use toycc_ir::{IrBuilder, PassManager};
+use toycc_test_support::parse;
+
+fn compile(program: &Program) -> Module {
+    let module = BytecodeCompiler::new()
+        .compile(program)
+        .expect("failed to compile");
+    round_trip(module)
+}
+
+/// Compiles through the IR after the passes of `-O2`
+fn compile_ir(program: &Program) -> Module {
+    let mut ir = IrBuilder::new().build(program).expect("failed to build");
+    PassManager::new()
+        .add(TailCallElimination)
+        .add(SsaConstruction)
+        .add(LoopInvariantCodeMotion)
+        .add(StrengthReduction)
+        .add(SsaDestruction)
+        .run(&mut ir)
+        .expect("failed to optimize");
+    round_trip(BytecodeCompiler::new().compile_ir(&ir))
+}
+
+fn round_trip(module: Module) -> Module {
+    let decoded = Module::decode(&module.encode()).expect("failed to decode");
+    assert_eq!(decoded, module);
+    decoded
+}
+
+fn run(module: &Module, input: &str) -> (String, i32) {
+    let mut output = vec![];
+    let code = Vm::new(module)
+        .unwrap_or_else(|e| panic!("{e:?}\n{module}"))
+        .run(&mut Cursor::new(input.to_owned()), &mut output)
+        .unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+    (String::from_utf8(output).unwrap(), code)
+}
+
+fn run_both(source: &str, input: &str) -> (String, i32) {
+    let mut expected = (String::new(), 0);
+    for compile in [compile, compile_ir] {
+        expected = toycc_test_support::run_both(source, input, |program| {
+            let module = compile(program);
+            let (output, code) = run(&module, input);
+            (module, output, Some(code))
+        });
+    }
+    expected
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    assert_eq!(
+        run_both(source, "7 1").0,
+        "input a: input a: a = 3\na = 21\n"
+    );
+}
+
+#[test]
+fn test_recursion_and_globals() {
+    let source = "int calls;
+int fib(int n){ calls = calls + 1; if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+int main(){ write(fib(20), \" \", calls); newline; return fib(10); }";
+    assert_eq!(run_both(source, ""), ("6765 21891\n".to_owned(), 55));
+}
+
+#[test]
+fn test_operators_and_input() {
+    let source = "int main(){
+    int a;
+    char c;
+    read(c, a);
+    write(c, \" \", -a, \" \", a / 5, \" \", a % 5, \" \", (a > 3) && (a < 50), (a < 3) || !a, \"\\n\");
+    write(a >= 41, a <= 40, a == 41, a != 41, 100000 * a, \"\\n\");
+    write(a = 3, a);
+    newline;
+    return a;
+}";
+    assert_eq!(
+        run_both(source, "  x 41"),
+        ("x -41 8 1 10\n10104100000\n33\n".to_owned(), 3)
+    );
+}
+
+#[test]
+fn test_nested_loops_and_shadowing() {
+    let source = "int main(){
+    int i;
+    int j;
+    while (i < 3) {
+        int i2;
+        j = 0;
+        while (1) {
+            if (j > i) break;
+            { int j; j = 7; write(j); }
+            j = j + 1;
+        }
+        write(i2);
+        newline;
+        i = i + 1;
+    }
+    return 0;
+}";
+    assert_eq!(run_both(source, "").0, "70\n770\n7770\n");
+}
+
+#[test]
+fn test_stack_overflow() {
+    let module = compile(&parse(
+        "int down(int n){ return down(n + 1); } int main(){ return down(0); }",
+    ));
+    let result = Vm::new(&module).unwrap().run(&mut &b""[..], &mut vec![]);
+    assert_eq!(result, Err(BytecodeError::StackOverflow(MAX_FRAMES)));
+}
+
+#[test]
+fn test_loops_through_the_ir() {
+    let source = "int g;
+int sum(int n, int acc){ if (n == 0) return acc; return sum(n - 1, acc + n); }
+int main(){
+    int i;
+    i = 0;
+    while (i < 5) { g = g + i * 25; i = i + 1; }
+    write(g, \" \", sum(1000, 0));
+    return i;
+}";
+    assert_eq!(run_both(source, ""), ("250 500500".to_owned(), 5));
+    // `i * 25` becomes an addition
+    let multiplications = |module: Module| {
+        let code = &module.functions[module.main as usize].code;
+        let mut offset = 0;
+        let mut count = 0;
+        while offset < code.len() {
+            let instruction = Instruction::decode(code, offset).unwrap();
+            offset += instruction.size();
+            count += (instruction == Instruction::Mul) as usize;
+        }
+        count
+    };
+    assert_eq!(multiplications(compile(&parse(source))), 1);
+    assert_eq!(multiplications(compile_ir(&parse(source))), 0);
+}
@@ -1,32 +1,72 @@
-use crate::scanner::token::{AddOP, MulOP, RelOP, Type};
+use crate::scanner::token::{AddOP, MulOP, RelOP, Token, Type};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Display, Formatter};
 
 const TAB_WIDTH: usize = 2;
 
-#[derive(Debug)]
+/// Location of a construct in the source file, in the same `(line, column)`
+/// form used by tokens and diagnostics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub location: (usize, usize),
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(location: (usize, usize), len: usize) -> Self {
+        Self { location, len }
+    }
+
+    pub fn line(&self) -> usize {
+        self.location.0
+    }
+}
+
+impl From<&Token> for Span {
+    fn from(token: &Token) -> Self {
+        Self::new(token.location, token.len)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Program {
     pub definitions: Vec<Definition>,
 }
 
-#[derive(Debug)]
+impl Program {
+    /// The program as JSON, with the span of every node
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a program is always serializable")
+    }
+
+    /// Reads a program written by [`Program::to_json`], possibly transformed
+    /// by another tool
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub enum Definition {
     FuncDef(FuncDef),
     VarDef(VarDef),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FuncDef {
     pub identifier: String,
     pub toyc_type: Type,
     pub var_def: Vec<VarDef>,
     pub statement: Statement,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct VarDef {
     pub identifiers: Vec<String>,
     pub toyc_type: Type,
+    pub span: Span,
 }
 
 impl FuncDef {
@@ -35,27 +75,92 @@ impl FuncDef {
         toyc_type: Type,
         var_def: Vec<VarDef>,
         statement: Statement,
+        span: Span,
     ) -> Self {
         Self {
             identifier,
             toyc_type,
             var_def,
             statement,
+            span,
         }
     }
 }
 
 impl VarDef {
-    pub fn new(identifiers: Vec<String>, toyc_type: Type) -> Self {
+    pub fn new(identifiers: Vec<String>, toyc_type: Type, span: Span) -> Self {
         Self {
             identifiers,
             toyc_type,
+            span,
         }
     }
 }
 
-#[derive(Debug)]
-pub enum Statement {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// Whether control may reach the end of the statement. Branches and
+    /// loops whose condition is a constant only take the path it selects,
+    /// and a loop that never ends only completes through a reachable `break`.
+    pub fn completes(&self) -> bool {
+        self.flow().0
+    }
+
+    /// Whether the statement completes, and whether it reaches a `break`
+    /// leaving the innermost loop around it
+    fn flow(&self) -> (bool, bool) {
+        match &self.kind {
+            StatementKind::Break => (false, true),
+            StatementKind::ReturnState(_) => (false, false),
+            StatementKind::BlockState(_, statements) => {
+                let mut breaks = false;
+                for statement in statements {
+                    let (completes, reaches_break) = statement.flow();
+                    breaks |= reaches_break;
+                    if !completes {
+                        return (false, breaks);
+                    }
+                }
+                (true, breaks)
+            }
+            StatementKind::IfState(expr, then_stmt, else_stmt) => {
+                let else_flow = match else_stmt.as_ref() {
+                    Some(else_stmt) => else_stmt.flow(),
+                    None => (true, false),
+                };
+                match expr.constant_value() {
+                    Some(0) => else_flow,
+                    Some(_) => then_stmt.flow(),
+                    None => {
+                        let then_flow = then_stmt.flow();
+                        (then_flow.0 || else_flow.0, then_flow.1 || else_flow.1)
+                    }
+                }
+            }
+            StatementKind::WhileState(expr, body) => match expr.constant_value() {
+                Some(0) | None => (true, false),
+                Some(_) => (body.flow().1, false),
+            },
+            StatementKind::Expression(_)
+            | StatementKind::NullState
+            | StatementKind::ReadState(..)
+            | StatementKind::WriteState(..)
+            | StatementKind::NewLineState => (true, false),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum StatementKind {
     Expression(Expression),
     Break,
     BlockState(Vec<VarDef>, Vec<Statement>),
@@ -68,8 +173,49 @@ pub enum Statement {
     NewLineState,
 }
 
-#[derive(Debug)]
-pub enum Expression {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// Value of an expression made only of literals, or `None`
+    pub fn constant_value(&self) -> Option<i32> {
+        self.value_with(&|_| None)
+    }
+
+    /// Like [`Expression::constant_value`], reading the value of variables
+    /// from `variable`
+    pub fn value_with(&self, variable: &dyn Fn(&str) -> Option<i32>) -> Option<i32> {
+        match &self.kind {
+            ExpressionKind::Number(num) => Some(*num as i32),
+            ExpressionKind::CharLiteral(c) => c.map(|c| c as i32),
+            ExpressionKind::Identifier(id) => variable(id),
+            ExpressionKind::Expr(Operator::And, lhs, _) if lhs.value_with(variable) == Some(0) => {
+                Some(0)
+            }
+            ExpressionKind::Expr(Operator::Or, lhs, _)
+                if lhs.value_with(variable).is_some_and(|v| v != 0) =>
+            {
+                Some(1)
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                op.evaluate(lhs.value_with(variable)?, rhs.value_with(variable)?)
+            }
+            ExpressionKind::Not(expr) => Some((expr.value_with(variable)? == 0) as i32),
+            ExpressionKind::Minus(expr) => Some(expr.value_with(variable)?.wrapping_neg()),
+            ExpressionKind::StringLiteral(_) | ExpressionKind::FuncCall(..) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExpressionKind {
     Number(f64),
     Identifier(String),
     CharLiteral(Option<char>),
@@ -80,7 +226,7 @@ pub enum Expression {
     Minus(Box<Expression>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Operator {
     Assign,
     Plus,
@@ -98,6 +244,46 @@ pub enum Operator {
     NotEqual,
 }
 
+impl Operator {
+    pub fn is_relational(&self) -> bool {
+        matches!(
+            self,
+            Operator::LessEqual
+                | Operator::LessThan
+                | Operator::GreaterEqual
+                | Operator::GreaterThan
+                | Operator::Equal
+                | Operator::NotEqual
+        )
+    }
+
+    pub fn is_logical(&self) -> bool {
+        matches!(self, Operator::Or | Operator::And)
+    }
+
+    /// Applies the operator with 32-bit wrapping semantics; `None` for an
+    /// assignment and for a division or remainder by zero
+    pub fn evaluate(&self, a: i32, b: i32) -> Option<i32> {
+        Some(match self {
+            Operator::Assign => return None,
+            Operator::Plus => a.wrapping_add(b),
+            Operator::Minus => a.wrapping_sub(b),
+            Operator::Multiply => a.wrapping_mul(b),
+            Operator::Divide | Operator::Modulo if b == 0 => return None,
+            Operator::Divide => a.wrapping_div(b),
+            Operator::Modulo => a.wrapping_rem(b),
+            Operator::Or => (a != 0 || b != 0) as i32,
+            Operator::And => (a != 0 && b != 0) as i32,
+            Operator::LessEqual => (a <= b) as i32,
+            Operator::LessThan => (a < b) as i32,
+            Operator::GreaterEqual => (a >= b) as i32,
+            Operator::GreaterThan => (a > b) as i32,
+            Operator::Equal => (a == b) as i32,
+            Operator::NotEqual => (a != b) as i32,
+        })
+    }
+}
+
 impl Display for Operator {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -246,15 +432,21 @@ impl Display for VarDef {
 }
 
 impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.kind, f)
+    }
+}
+
+impl Display for StatementKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let width = f.width().unwrap_or_default();
         let indent = width + TAB_WIDTH;
         let dindent = indent + TAB_WIDTH;
         match self {
-            Statement::Expression(e) => {
+            StatementKind::Expression(e) => {
                 write!(f, "{:>width$}", e, width = width + TAB_WIDTH)
             }
-            Statement::Break => {
+            StatementKind::Break => {
                 write!(
                     f,
                     "{:>width$}",
@@ -263,7 +455,7 @@ impl Display for Statement {
                 )
             }
 
-            Statement::BlockState(vars, stmts) => {
+            StatementKind::BlockState(vars, stmts) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -283,7 +475,7 @@ impl Display for Statement {
                 write!(f, "{:>width$}", ")", width = width + 1)
             }
 
-            Statement::IfState(expr, if_stmt, else_stmt) => {
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -299,7 +491,7 @@ impl Display for Statement {
                 write!(f, "{:>indent$}", "]", indent = indent + 1)
             }
 
-            Statement::NullState => {
+            StatementKind::NullState => {
                 write!(
                     f,
                     "{:>width$}",
@@ -308,7 +500,7 @@ impl Display for Statement {
                 )
             }
 
-            Statement::ReturnState(e) => {
+            StatementKind::ReturnState(e) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -321,7 +513,7 @@ impl Display for Statement {
                 write!(f, "{:>width$}", ")", width = width + 1)
             }
 
-            Statement::WhileState(expr, stmt) => {
+            StatementKind::WhileState(expr, stmt) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -337,7 +529,7 @@ impl Display for Statement {
                 write!(f, "{:>width$}", ")", width = width + 1)
             }
 
-            Statement::ReadState(id, s1) => {
+            StatementKind::ReadState(id, s1) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -356,7 +548,7 @@ impl Display for Statement {
                 write!(f, "{:>width$}", ")", width = width + 1)
             }
 
-            Statement::WriteState(e, others) => {
+            StatementKind::WriteState(e, others) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -372,7 +564,7 @@ impl Display for Statement {
                 write!(f, "{:>width$}", ")", width = width + 1)
             }
 
-            Statement::NewLineState => {
+            StatementKind::NewLineState => {
                 write!(
                     f,
                     "{:>width$}",
@@ -385,6 +577,12 @@ impl Display for Statement {
 }
 
 impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.kind, f)
+    }
+}
+
+impl Display for ExpressionKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let width = f.width().unwrap_or_default();
         writeln!(
@@ -396,7 +594,7 @@ impl Display for Expression {
         let width = width + TAB_WIDTH;
         let indent = width + TAB_WIDTH;
         match self {
-            Expression::Number(num) => {
+            ExpressionKind::Number(num) => {
                 writeln!(f, "{:>width$}", "Number(", width = width + "Number(".len())?;
                 writeln!(
                     f,
@@ -406,7 +604,7 @@ impl Display for Expression {
                 )?;
                 writeln!(f, "{:>width$}", ")", width = width + 1)
             }
-            Expression::Identifier(id) => {
+            ExpressionKind::Identifier(id) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -416,7 +614,7 @@ impl Display for Expression {
                 writeln!(f, "{:>indent$}", id, indent = indent + id.len())?;
                 writeln!(f, "{:>width$}", ")", width = width + 1)
             }
-            Expression::CharLiteral(cl) => {
+            ExpressionKind::CharLiteral(cl) => {
                 let cl = match cl {
                     Some(cl) => format!("{cl}"),
                     None => "".to_string(),
@@ -431,7 +629,7 @@ impl Display for Expression {
                 writeln!(f, "{:>width$}", ")", width = width + 1)
             }
 
-            Expression::StringLiteral(s) => {
+            ExpressionKind::StringLiteral(s) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -441,7 +639,7 @@ impl Display for Expression {
                 writeln!(f, "{:>indent$}", s, indent = indent + s.len())?;
                 writeln!(f, "{:>width$}", ")", width = width + 1)
             }
-            Expression::FuncCall(name, expressions) => {
+            ExpressionKind::FuncCall(name, expressions) => {
                 writeln!(
                     f,
                     "{:>width$}",
@@ -454,7 +652,7 @@ impl Display for Expression {
                 }
                 writeln!(f, "{:>width$}", ")", width = width + 1)
             }
-            Expression::Expr(op, expra, exprb) => {
+            ExpressionKind::Expr(op, expra, exprb) => {
                 writeln!(f, "{:>width$}", "Expr(", width = width + "Expr(".len())?;
                 writeln!(
                     f,
@@ -466,12 +664,12 @@ impl Display for Expression {
                 writeln!(f, "{:>indent$},", exprb)?;
                 writeln!(f, "{:>width$}", ")", width = width + 1)
             }
-            Expression::Not(expr) => {
+            ExpressionKind::Not(expr) => {
                 writeln!(f, "{:>width$}", "Not(", width = width + "Not(".len())?;
                 writeln!(f, "{:>indent$},", expr)?;
                 writeln!(f, "{:>width$}", ")", width = width + 1)
             }
-            Expression::Minus(expr) => {
+            ExpressionKind::Minus(expr) => {
                 writeln!(f, "{:>width$}", "Minus(", width = width + "Minus(".len())?;
                 writeln!(f, "{:>indent$},", expr)?;
                 writeln!(f, "{:>width$}", ")", width = width + 1)
@@ -493,19 +691,19 @@ impl From<AddOP> for Operator {
 }
 impl From<&AddOP> for Operator {
     fn from(value: &AddOP) -> Self {
-        value.into()
+        value.clone().into()
     }
 }
 
 impl From<&RelOP> for Operator {
     fn from(value: &RelOP) -> Self {
-        value.into()
+        value.clone().into()
     }
 }
 
 impl From<&MulOP> for Operator {
     fn from(value: &MulOP) -> Self {
-        value.into()
+        value.clone().into()
     }
 }
 
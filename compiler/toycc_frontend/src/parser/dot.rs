@@ -0,0 +1,217 @@
+use crate::parser::ast::*;
+use crate::scanner::token::Type;
+use std::fmt::Write;
+
+impl Program {
+    /// The tree as a Graphviz digraph: a node per definition, statement and
+    /// expression, with edges from every node to its children. The parts
+    /// of `if` and `while` statements are labeled.
+    pub fn to_dot(&self) -> String {
+        let mut graph = Graph::default();
+        let root = graph.node("program");
+        for definition in &self.definitions {
+            let child = match definition {
+                Definition::VarDef(var_def) => graph.var_def(var_def),
+                Definition::FuncDef(func_def) => graph.func_def(func_def),
+            };
+            graph.edge(root, child, None);
+        }
+        format!(
+            "digraph ast {{\n    node [shape=box];\n{}{}}}\n",
+            graph.nodes, graph.edges
+        )
+    }
+}
+
+#[derive(Default)]
+struct Graph {
+    count: usize,
+    nodes: String,
+    edges: String,
+}
+
+impl Graph {
+    fn node(&mut self, label: &str) -> usize {
+        let node = self.count;
+        self.count += 1;
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(self.nodes, "    n{node} [label=\"{label}\"];").unwrap();
+        node
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: Option<&str>) {
+        match label {
+            Some(label) => writeln!(self.edges, "    n{from} -> n{to} [label=\"{label}\"];"),
+            None => writeln!(self.edges, "    n{from} -> n{to};"),
+        }
+        .unwrap();
+    }
+
+    fn var_def(&mut self, var_def: &VarDef) -> usize {
+        let names = var_def.identifiers.join(", ");
+        self.node(&format!("var {} {names}", type_name(&var_def.toyc_type)))
+    }
+
+    fn func_def(&mut self, func_def: &FuncDef) -> usize {
+        let params = func_def
+            .var_def
+            .iter()
+            .flat_map(|v| v.identifiers.iter().map(|id| (type_name(&v.toyc_type), id)))
+            .map(|(toyc_type, id)| format!("{toyc_type} {id}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let node = self.node(&format!(
+            "function {} {}({params})",
+            type_name(&func_def.toyc_type),
+            func_def.identifier
+        ));
+        let body = self.statement(&func_def.statement);
+        self.edge(node, body, None);
+        node
+    }
+
+    fn statement(&mut self, statement: &Statement) -> usize {
+        match &statement.kind {
+            StatementKind::Expression(expr) => self.expression(expr),
+            StatementKind::Break => self.node("break"),
+            StatementKind::BlockState(var_defs, statements) => {
+                let node = self.node("block");
+                for var_def in var_defs {
+                    let child = self.var_def(var_def);
+                    self.edge(node, child, None);
+                }
+                for statement in statements {
+                    let child = self.statement(statement);
+                    self.edge(node, child, None);
+                }
+                node
+            }
+            StatementKind::IfState(expr, then_stmt, else_stmt) => {
+                let node = self.node("if");
+                let child = self.expression(expr);
+                self.edge(node, child, Some("condition"));
+                let child = self.statement(then_stmt);
+                self.edge(node, child, Some("then"));
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    let child = self.statement(else_stmt);
+                    self.edge(node, child, Some("else"));
+                }
+                node
+            }
+            StatementKind::NullState => self.node("empty"),
+            StatementKind::ReturnState(expr) => {
+                let node = self.node("return");
+                if let Some(expr) = expr {
+                    let child = self.expression(expr);
+                    self.edge(node, child, None);
+                }
+                node
+            }
+            StatementKind::WhileState(expr, body) => {
+                let node = self.node("while");
+                let child = self.expression(expr);
+                self.edge(node, child, Some("condition"));
+                let child = self.statement(body);
+                self.edge(node, child, Some("body"));
+                node
+            }
+            StatementKind::ReadState(name, others) => {
+                let names = std::iter::once(name).chain(others.iter().flatten());
+                let names = names.cloned().collect::<Vec<_>>();
+                self.node(&format!("read {}", names.join(", ")))
+            }
+            StatementKind::WriteState(expr, others) => {
+                let node = self.node("write");
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    let child = self.expression(expr);
+                    self.edge(node, child, None);
+                }
+                node
+            }
+            StatementKind::NewLineState => self.node("newline"),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> usize {
+        match &expression.kind {
+            ExpressionKind::Number(num) => self.node(&num.to_string()),
+            ExpressionKind::Identifier(id) => self.node(id),
+            ExpressionKind::CharLiteral(Some(c)) => self.node(&format!("{c:?}")),
+            ExpressionKind::CharLiteral(None) => self.node("''"),
+            ExpressionKind::StringLiteral(s) => self.node(&format!("{s:?}")),
+            ExpressionKind::FuncCall(name, arguments) => {
+                let node = self.node(&format!("call {name}"));
+                for argument in arguments {
+                    let child = self.expression(argument);
+                    self.edge(node, child, None);
+                }
+                node
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                let node = self.node(&op.to_string());
+                let child = self.expression(lhs);
+                self.edge(node, child, None);
+                let child = self.expression(rhs);
+                self.edge(node, child, None);
+                node
+            }
+            ExpressionKind::Not(expr) => self.unary("!", expr),
+            ExpressionKind::Minus(expr) => self.unary("-", expr),
+        }
+    }
+
+    fn unary(&mut self, op: &str, expr: &Expression) -> usize {
+        let node = self.node(op);
+        let child = self.expression(expr);
+        self.edge(node, child, None);
+        node
+    }
+}
+
+fn type_name(toyc_type: &Type) -> &'static str {
+    match toyc_type {
+        Type::Int => "int",
+        Type::Char => "char",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Parser;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_dot() {
+        let program = Parser::new(
+            Cursor::new("int main(){ if (1) write(\"yes\"); return 0; }"),
+            "test.tc",
+            None,
+        )
+        .parse()
+        .expect("failed to parse");
+        assert_eq!(
+            program.to_dot(),
+            "digraph ast {
+    node [shape=box];
+    n0 [label=\"program\"];
+    n1 [label=\"function int main()\"];
+    n2 [label=\"block\"];
+    n3 [label=\"if\"];
+    n4 [label=\"1\"];
+    n5 [label=\"write\"];
+    n6 [label=\"\\\"yes\\\"\"];
+    n7 [label=\"return\"];
+    n8 [label=\"0\"];
+    n3 -> n4 [label=\"condition\"];
+    n5 -> n6;
+    n3 -> n5 [label=\"then\"];
+    n2 -> n3;
+    n7 -> n8;
+    n2 -> n7;
+    n1 -> n2;
+    n0 -> n1;
+}
+"
+        );
+    }
+}
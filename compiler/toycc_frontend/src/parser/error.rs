@@ -2,9 +2,10 @@ use crate::scanner::error::ScannerError;
 use crate::scanner::token::{Delimiter, Keyword};
 use toycc_report::{Diagnostic, ErrorKind, Report, ReportLevel};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum ParserErrorKind {
     ScannerError(ScannerError),
+    #[default]
     Generic,
     ExpectedType,
     ExpectedIdentifier,
@@ -12,11 +13,6 @@ pub enum ParserErrorKind {
     ExpectedKeyword(Keyword),
     ExpectedNumber,
 }
-impl Default for ParserErrorKind {
-    fn default() -> Self {
-        Self::Generic
-    }
-}
 
 #[derive(Debug, Default, Report)]
 pub struct ParserError {
@@ -0,0 +1,436 @@
+use crate::parser::ast::*;
+use crate::parser::error::ParserError;
+use crate::parser::Parser;
+use crate::scanner::token::{Comment, Type};
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+type Location = (usize, usize);
+
+impl<S: Read + Seek> Parser<S> {
+    /// Parses the stream and prints it in the canonical style: four spaces
+    /// of indentation, opening braces at the end of the line, one
+    /// declaration or statement per line, a blank line around every
+    /// function and at most one between other lines. Comments are kept,
+    /// on their own line or after the code they follow on the same line.
+    pub fn format(&mut self) -> Result<String, Box<ParserError>> {
+        let program = self.parse()?;
+        let mut formatter = Formatter {
+            comments: self.scanner.comments(),
+            block_ends: &self.block_ends,
+            lines: vec![],
+            indent: 0,
+            last_line: 0,
+        };
+        formatter.program(&program);
+        Ok(match formatter.lines.is_empty() {
+            true => String::new(),
+            false => formatter.lines.join("\n") + "\n",
+        })
+    }
+}
+
+struct Formatter<'a> {
+    /// Comments not printed yet
+    comments: &'a [Comment],
+    block_ends: &'a HashMap<Location, Location>,
+    lines: Vec<String>,
+    indent: usize,
+    /// Source line of the code printed last, which a comment starting on
+    /// it follows on the same line
+    last_line: usize,
+}
+
+impl Formatter<'_> {
+    fn line(&mut self, text: &str) {
+        self.lines
+            .push(format!("{}{text}", "    ".repeat(self.indent)));
+    }
+
+    /// Prints `text` on a new line, or after the last one when `continued`
+    fn open(&mut self, text: &str, continued: bool) {
+        match (continued, self.lines.last_mut()) {
+            (true, Some(last)) => {
+                last.push(' ');
+                last.push_str(text);
+            }
+            _ => self.line(text),
+        }
+    }
+
+    /// Prints the comments before `location`. Returns whether there were any.
+    fn comments(&mut self, location: Location) -> bool {
+        let count = self.comments.iter().take_while(|c| c.location < location);
+        let count = count.count();
+        self.print_comments(count, false)
+    }
+
+    /// Prints the comments before `location` following the last code printed
+    /// on the same line
+    fn trailing_comments(&mut self, location: Location) {
+        let count = self
+            .comments
+            .iter()
+            .take_while(|c| c.location < location && c.location.0 == self.last_line)
+            .count();
+        self.print_comments(count, true);
+    }
+
+    /// Keeps one blank line before the code at `location`, or the comments
+    /// before it, where the source has any
+    fn blank_line(&mut self, location: Location) {
+        let next = self
+            .comments
+            .iter()
+            .find(|c| c.location.0 != self.last_line)
+            .filter(|c| c.location < location)
+            .map_or(location.0, |c| c.location.0);
+        if next > self.last_line + 1 {
+            self.trailing_comments(location);
+            self.lines.push(String::new());
+        }
+    }
+
+    fn print_comments(&mut self, count: usize, trailing: bool) -> bool {
+        let (comments, rest) = self.comments.split_at(count);
+        self.comments = rest;
+        for comment in comments {
+            match self.lines.last_mut() {
+                Some(last) if trailing || comment.location.0 == self.last_line => {
+                    last.push(' ');
+                    last.push_str(&comment.text);
+                }
+                _ => self.line(&comment.text),
+            }
+            self.last_line = comment.end_line();
+        }
+        !comments.is_empty()
+    }
+
+    fn program(&mut self, program: &Program) {
+        let mut previous_function = None;
+        for definition in &program.definitions {
+            let (start, function) = match definition {
+                Definition::VarDef(var_def) => (var_def.span.location, false),
+                Definition::FuncDef(func_def) => (func_def.statement.span.location, true),
+            };
+            match previous_function {
+                Some(previous) if previous || function => {
+                    self.trailing_comments(start);
+                    self.lines.push(String::new());
+                }
+                Some(_) => self.blank_line(start),
+                None => {}
+            }
+            self.comments(start);
+            match definition {
+                Definition::VarDef(var_def) => self.var_def(var_def),
+                Definition::FuncDef(func_def) => self.func_def(func_def),
+            }
+            previous_function = Some(function);
+        }
+        self.comments((usize::MAX, usize::MAX));
+    }
+
+    fn var_def(&mut self, var_def: &VarDef) {
+        for id in &var_def.identifiers {
+            self.line(&format!("{} {id};", type_name(&var_def.toyc_type)));
+        }
+        self.last_line = var_def.span.line();
+    }
+
+    fn func_def(&mut self, func_def: &FuncDef) {
+        let params = func_def
+            .var_def
+            .iter()
+            .flat_map(|v| v.identifiers.iter().map(|id| (type_name(&v.toyc_type), id)))
+            .map(|(toyc_type, id)| format!("{toyc_type} {id}"))
+            .collect::<Vec<_>>();
+        let header = format!(
+            "{} {}({})",
+            type_name(&func_def.toyc_type),
+            func_def.identifier,
+            params.join(", ")
+        );
+        self.line(&header);
+        self.statement(&func_def.statement, true);
+    }
+
+    /// Prints a statement, starting on the last line when `continued`
+    fn statement(&mut self, statement: &Statement, continued: bool) {
+        match &statement.kind {
+            StatementKind::BlockState(var_defs, statements) => {
+                self.open("{", continued);
+                self.last_line = statement.span.line();
+                let end = self.block_ends.get(&statement.span.location).copied();
+                self.indent += 1;
+                for (index, var_def) in var_defs.iter().enumerate() {
+                    if index > 0 {
+                        self.blank_line(var_def.span.location);
+                    }
+                    self.comments(var_def.span.location);
+                    self.var_def(var_def);
+                }
+                if let (false, Some(first)) = (var_defs.is_empty(), statements.first()) {
+                    self.trailing_comments(start(first));
+                    self.lines.push(String::new());
+                }
+                for (index, statement) in statements.iter().enumerate() {
+                    if index > 0 {
+                        self.blank_line(start(statement));
+                    }
+                    self.comments(start(statement));
+                    self.statement(statement, false);
+                }
+                if let Some(end) = end {
+                    self.comments(end);
+                }
+                self.indent -= 1;
+                self.line("}");
+                self.last_line = end.map_or(self.last_line, |(line, _)| line);
+            }
+            StatementKind::IfState(expr, then_stmt, else_stmt) => {
+                self.open(&format!("if ({})", expression(expr, 0)), continued);
+                self.last_line = last_line(statement);
+                self.body(then_stmt);
+                if let Some(else_stmt) = else_stmt.as_ref() {
+                    let commented = self.comments(start(else_stmt));
+                    let block = matches!(then_stmt.kind, StatementKind::BlockState(..));
+                    self.open("else", block && !commented);
+                    match else_stmt.kind {
+                        StatementKind::IfState(..) => self.statement(else_stmt, true),
+                        _ => self.body(else_stmt),
+                    }
+                }
+            }
+            StatementKind::WhileState(expr, body) => {
+                self.open(&format!("while ({})", expression(expr, 0)), continued);
+                self.last_line = last_line(statement);
+                self.body(body);
+            }
+            kind => {
+                let text = match kind {
+                    StatementKind::Expression(expr) => format!("{};", expression(expr, 0)),
+                    StatementKind::Break => "break;".to_owned(),
+                    StatementKind::NullState => ";".to_owned(),
+                    StatementKind::ReturnState(Some(expr)) => {
+                        format!("return {};", expression(expr, 0))
+                    }
+                    StatementKind::ReturnState(None) => "return;".to_owned(),
+                    StatementKind::ReadState(name, others) => {
+                        let names = std::iter::once(name).chain(others.iter().flatten());
+                        format!("read({});", names.cloned().collect::<Vec<_>>().join(", "))
+                    }
+                    StatementKind::WriteState(expr, others) => {
+                        let exprs = std::iter::once(expr).chain(others.iter().flatten());
+                        let exprs = exprs.map(|e| expression(e, 0)).collect::<Vec<_>>();
+                        format!("write({});", exprs.join(", "))
+                    }
+                    StatementKind::NewLineState => "newline;".to_owned(),
+                    _ => unreachable!(),
+                };
+                self.open(&text, continued);
+                self.last_line = last_line(statement);
+            }
+        }
+    }
+
+    /// Prints the body of an `if`, `else` or `while`: a block after the
+    /// header, or any other statement indented on the next line
+    fn body(&mut self, body: &Statement) {
+        match body.kind {
+            StatementKind::BlockState(..) => self.statement(body, true),
+            _ => {
+                self.indent += 1;
+                self.comments(start(body));
+                self.statement(body, false);
+                self.indent -= 1;
+            }
+        }
+    }
+}
+
+/// Where the first line printed for a statement starts in the source.
+/// Comments before it are printed before that line; for a statement
+/// ending its first line with a block, that is up to the block.
+fn start(statement: &Statement) -> Location {
+    match &statement.kind {
+        StatementKind::IfState(_, body, _) | StatementKind::WhileState(_, body)
+            if matches!(body.kind, StatementKind::BlockState(..)) =>
+        {
+            body.span.location
+        }
+        StatementKind::Expression(expr) => locations(expr).min().unwrap(),
+        _ => statement.span.location,
+    }
+}
+
+/// Last source line of the first line printed for a statement
+fn last_line(statement: &Statement) -> usize {
+    let expressions = match &statement.kind {
+        StatementKind::Expression(expr)
+        | StatementKind::IfState(expr, ..)
+        | StatementKind::WhileState(expr, _)
+        | StatementKind::ReturnState(Some(expr)) => vec![expr],
+        StatementKind::WriteState(expr, others) => std::iter::once(expr)
+            .chain(others.iter().flatten())
+            .collect(),
+        _ => vec![],
+    };
+    let lines = expressions.into_iter().flat_map(locations).map(|l| l.0);
+    lines.chain([statement.span.line()]).max().unwrap()
+}
+
+/// Locations of the tokens recorded in an expression
+fn locations(expression: &Expression) -> Box<dyn Iterator<Item = Location> + '_> {
+    let children: Box<dyn Iterator<Item = Location>> = match &expression.kind {
+        ExpressionKind::Expr(_, lhs, rhs) => Box::new(locations(lhs).chain(locations(rhs))),
+        ExpressionKind::FuncCall(_, arguments) => Box::new(arguments.iter().flat_map(locations)),
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => locations(expr),
+        _ => Box::new(std::iter::empty()),
+    };
+    Box::new(std::iter::once(expression.span.location).chain(children))
+}
+
+/// How loosely an expression binds: assignments, comparisons, additive
+/// and multiplicative operators, then operands
+fn level(expression: &Expression) -> u8 {
+    match &expression.kind {
+        ExpressionKind::Expr(Operator::Assign, ..) => 0,
+        ExpressionKind::Expr(op, ..) if op.is_relational() => 1,
+        ExpressionKind::Expr(Operator::Plus | Operator::Minus | Operator::Or, ..) => 2,
+        ExpressionKind::Expr(..) => 3,
+        _ => 4,
+    }
+}
+
+/// The expression, in parentheses when the parser would not read it back
+/// as a single operand of at least `min_level`. As operators are right
+/// associative and the right operand of a multiplicative operator may be
+/// an additive expression, the right operand is also parenthesized when
+/// it binds as loosely as the operator, unless both are the same
+/// associative operator.
+fn expression(expression_: &Expression, min_level: u8) -> String {
+    let text = match &expression_.kind {
+        ExpressionKind::Number(num) => num.to_string(),
+        ExpressionKind::Identifier(id) => id.clone(),
+        ExpressionKind::CharLiteral(Some(c)) => format!("'{c}'"),
+        ExpressionKind::CharLiteral(None) => "''".to_owned(),
+        ExpressionKind::StringLiteral(s) => format!("\"{s}\""),
+        ExpressionKind::FuncCall(name, arguments) => {
+            let arguments = arguments.iter().map(|a| expression(a, 0));
+            format!("{name}({})", arguments.collect::<Vec<_>>().join(", "))
+        }
+        ExpressionKind::Expr(op, lhs, rhs) => {
+            let level = level(expression_);
+            let lhs_level = match level {
+                0 => 1,
+                1 => 2,
+                _ => 4,
+            };
+            let associative = matches!(
+                op,
+                Operator::Assign
+                    | Operator::Plus
+                    | Operator::Multiply
+                    | Operator::And
+                    | Operator::Or
+            );
+            let rhs_level = match &rhs.kind {
+                ExpressionKind::Expr(next, ..) if associative && next == op => level,
+                _ => level + 1,
+            };
+            let (lhs, rhs) = (expression(lhs, lhs_level), expression(rhs, rhs_level));
+            format!("{lhs} {op} {rhs}")
+        }
+        ExpressionKind::Not(expr) => format!("!{}", expression(expr, 4)),
+        ExpressionKind::Minus(expr) => format!("-{}", expression(expr, 4)),
+    };
+    match level(expression_) < min_level {
+        true => format!("({text})"),
+        false => text,
+    }
+}
+
+fn type_name(toyc_type: &Type) -> &'static str {
+    match toyc_type {
+        Type::Int => "int",
+        Type::Char => "char",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Parser;
+    use std::io::Cursor;
+
+    fn format(source: &str) -> String {
+        Parser::new(Cursor::new(source), "test.tc", None)
+            .format()
+            .expect("failed to parse")
+    }
+
+    #[test]
+    fn test_format() {
+        let source = "// globals
+int g; /* counter */ char c;
+int next(int n){return n+1;} // increments
+int main( ) {
+  int a; int b; // two
+  read(a);
+
+  /* loop
+     until zero */
+  while(!(a==0)){a=a-1;if(a)break;else{;}}
+  if (a) write(\"x\", 'y', -a * (b + 1), (a * b) + 1, a * b + 1); else if (a < b) newline;
+  else
+  // nothing
+  return (g = next(a = 2));
+  { }
+  // end of main
+}
+/* trailing */";
+        let formatted = format(source);
+        assert_eq!(
+            formatted,
+            "// globals
+int g; /* counter */
+char c;
+
+int next(int n) {
+    return n + 1;
+} // increments
+
+int main() {
+    int a;
+    int b; // two
+
+    read(a);
+
+    /* loop
+     until zero */
+    while (!(a == 0)) {
+        a = a - 1;
+        if (a)
+            break;
+        else {
+            ;
+        }
+    }
+    if (a)
+        write(\"x\", 'y', -a * (b + 1), (a * b) + 1, a * (b + 1));
+    else if (a < b)
+        newline;
+    // nothing
+    else
+        return g = next(a = 2);
+    {
+    }
+    // end of main
+}
+/* trailing */
+"
+        );
+        assert_eq!(format(&formatted), formatted);
+    }
+}
@@ -1,5 +1,8 @@
 pub mod ast;
+mod dot;
 pub mod error;
+mod format;
+mod sexpr;
 
 use crate::parser::ast::*;
 use crate::parser::error::{ParserError, ParserErrorKind};
@@ -7,6 +10,7 @@ use crate::scanner::error::ScannerError;
 use crate::scanner::token::*;
 use crate::scanner::Scanner;
 use crate::BufferedStream;
+use std::collections::HashMap;
 use std::io::{Read, Seek};
 
 pub struct Parser<S: Read + Seek> {
@@ -15,6 +19,8 @@ pub struct Parser<S: Read + Seek> {
     rewind: bool,
     token: Token,
     pub previous_token: Token,
+    /// Location of the closing brace of every block, by its opening brace
+    block_ends: HashMap<(usize, usize), (usize, usize)>,
 }
 
 impl<'a, S: Read + Seek> Parser<S> {
@@ -28,6 +34,7 @@ impl<'a, S: Read + Seek> Parser<S> {
             rewind: false,
             token: Token::new(TokenKind::Eof, 0, (0, 0)),
             previous_token: Token::new(TokenKind::Eof, 0, (0, 0)),
+            block_ends: HashMap::new(),
         }
     }
 
@@ -38,6 +45,10 @@ impl<'a, S: Read + Seek> Parser<S> {
         }
     }
 
+    fn span(&self) -> Span {
+        Span::from(&self.token)
+    }
+
     fn next_token(&mut self) -> Result<&Token, Box<ScannerError>> {
         match self.rewind {
             true => {
@@ -93,15 +104,16 @@ impl<'a, S: Read + Seek> Parser<S> {
             _ => return Err(self.create_error(ParserErrorKind::ExpectedIdentifier, None)),
         }
         .clone();
+        let span = self.span();
 
         let def = match &self.next_token()?.kind {
             TokenKind::Delimiter(Delimiter::Semicolon) => {
-                Definition::VarDef(VarDef::new(vec![identifier], tc_type))
+                Definition::VarDef(VarDef::new(vec![identifier], tc_type, span))
             }
             TokenKind::Delimiter(Delimiter::LParen) => {
                 self.rewind = true;
                 let (vardefs, statement) = self.func_def()?;
-                Definition::FuncDef(FuncDef::new(identifier, tc_type, vardefs, statement))
+                Definition::FuncDef(FuncDef::new(identifier, tc_type, vardefs, statement, span))
             }
             _ => {
                 return Err(
@@ -154,7 +166,7 @@ impl<'a, S: Read + Seek> Parser<S> {
         }
         .clone();
 
-        param_list.push(VarDef::new(vec![identifier], tc_type));
+        param_list.push(VarDef::new(vec![identifier], tc_type, self.span()));
         param_list.append(&mut self.rep_formal_param()?.unwrap_or_default());
         self.debug_print("exiting formal_param_list");
         Ok(param_list)
@@ -181,7 +193,7 @@ impl<'a, S: Read + Seek> Parser<S> {
             _ => return Err(self.create_error(ParserErrorKind::ExpectedIdentifier, None)),
         }
         .clone();
-        params.push(VarDef::new(vec![identifier], tc_type));
+        params.push(VarDef::new(vec![identifier], tc_type, self.span()));
         if let Some(mut param) = self.rep_formal_param()? {
             params.append(&mut param)
         }
@@ -203,6 +215,7 @@ impl<'a, S: Read + Seek> Parser<S> {
             TokenKind::Delimiter(Delimiter::LCurly),
             ParserErrorKind::ExpectedDelimiter(Delimiter::LCurly),
         )?;
+        let span = self.span();
         let declarations = self.declarations()?;
         let statements = self.statements()?;
 
@@ -210,10 +223,11 @@ impl<'a, S: Read + Seek> Parser<S> {
             TokenKind::Delimiter(Delimiter::RCurly),
             ParserErrorKind::ExpectedDelimiter(Delimiter::RCurly),
         )?;
+        self.block_ends.insert(span.location, self.token.location);
         self.debug_print("exiting compound_statement");
-        Ok(Statement::BlockState(
-            declarations,
-            statements.unwrap_or_default(),
+        Ok(Statement::new(
+            StatementKind::BlockState(declarations, statements.unwrap_or_default()),
+            span,
         ))
     }
 
@@ -232,7 +246,7 @@ impl<'a, S: Read + Seek> Parser<S> {
 
         match &self.next_token()?.kind {
             TokenKind::Identifier(id) => {
-                declarations.push(VarDef::new(vec![id.clone()], toyc_type))
+                declarations.push(VarDef::new(vec![id.clone()], toyc_type, self.span()))
             }
             _ => return Err(self.create_error(ParserErrorKind::ExpectedIdentifier, None)),
         };
@@ -288,6 +302,7 @@ impl<'a, S: Read + Seek> Parser<S> {
 
     fn if_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering if_statement");
+        let span = self.span();
         self.accept(
             TokenKind::Delimiter(Delimiter::LParen),
             ParserErrorKind::ExpectedDelimiter(Delimiter::LParen),
@@ -300,10 +315,9 @@ impl<'a, S: Read + Seek> Parser<S> {
         let statement = self.statement()?;
         let toyc_else = self.else_stmt()?;
 
-        Ok(Statement::IfState(
-            expression,
-            Box::new(statement),
-            Box::new(toyc_else),
+        Ok(Statement::new(
+            StatementKind::IfState(expression, Box::new(statement), Box::new(toyc_else)),
+            span,
         ))
     }
 
@@ -337,11 +351,12 @@ impl<'a, S: Read + Seek> Parser<S> {
     fn null_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering null statement");
         self.debug_print("exiting null statement");
-        Ok(Statement::NullState)
+        Ok(Statement::new(StatementKind::NullState, self.span()))
     }
 
     fn return_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering return_statement");
+        let span = self.span();
         let expr = match &self.next_token()?.kind {
             TokenKind::Delimiter(Delimiter::Semicolon) => None,
             _ => {
@@ -356,10 +371,11 @@ impl<'a, S: Read + Seek> Parser<S> {
         };
 
         self.debug_print("exiting return_statement");
-        Ok(Statement::ReturnState(expr))
+        Ok(Statement::new(StatementKind::ReturnState(expr), span))
     }
     fn while_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering while_statement");
+        let span = self.span();
 
         self.accept(
             TokenKind::Delimiter(Delimiter::LParen),
@@ -376,10 +392,14 @@ impl<'a, S: Read + Seek> Parser<S> {
         let statement = self.statement()?;
 
         self.debug_print("exiting while_statement");
-        Ok(Statement::WhileState(expr, Box::new(statement)))
+        Ok(Statement::new(
+            StatementKind::WhileState(expr, Box::new(statement)),
+            span,
+        ))
     }
     fn read_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering read_statement");
+        let span = self.span();
         self.accept(
             TokenKind::Delimiter(Delimiter::LParen),
             ParserErrorKind::ExpectedDelimiter(Delimiter::LParen),
@@ -403,13 +423,14 @@ impl<'a, S: Read + Seek> Parser<S> {
         )?;
 
         self.debug_print("exiting read_statement");
-        Ok(Statement::ReadState(
-            identifier,
-            (!others.is_empty()).then_some(others),
+        Ok(Statement::new(
+            StatementKind::ReadState(identifier, (!others.is_empty()).then_some(others)),
+            span,
         ))
     }
     fn write_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering write_statement");
+        let span = self.span();
 
         self.accept(
             TokenKind::Delimiter(Delimiter::LParen),
@@ -429,19 +450,20 @@ impl<'a, S: Read + Seek> Parser<S> {
         )?;
         self.debug_print("exiting write_statement");
         let x = params.remove(0);
-        Ok(Statement::WriteState(
-            x,
-            (!params.is_empty()).then_some(params),
+        Ok(Statement::new(
+            StatementKind::WriteState(x, (!params.is_empty()).then_some(params)),
+            span,
         ))
     }
     fn new_line_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering new_line_statement");
+        let span = self.span();
         self.accept(
             TokenKind::Delimiter(Delimiter::Semicolon),
             ParserErrorKind::ExpectedDelimiter(Delimiter::Semicolon),
         )?;
         self.debug_print("exiting new_line_statement");
-        Ok(Statement::NewLineState)
+        Ok(Statement::new(StatementKind::NewLineState, span))
     }
     fn expression_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering expression_statement");
@@ -451,16 +473,18 @@ impl<'a, S: Read + Seek> Parser<S> {
             ParserErrorKind::ExpectedDelimiter(Delimiter::Semicolon),
         )?;
         self.debug_print("exiting expression_statement");
-        Ok(Statement::Expression(expression))
+        let span = expression.span;
+        Ok(Statement::new(StatementKind::Expression(expression), span))
     }
     fn break_statement(&mut self) -> Result<Statement, Box<ParserError>> {
         self.debug_print("entering break_statement");
+        let span = self.span();
         self.accept(
             TokenKind::Delimiter(Delimiter::Semicolon),
             ParserErrorKind::ExpectedDelimiter(Delimiter::Semicolon),
         )?;
         self.debug_print("exiting break_statement");
-        Ok(Statement::Break)
+        Ok(Statement::new(StatementKind::Break, span))
     }
 
     fn else_stmt(&mut self) -> Result<Option<Statement>, Box<ParserError>> {
@@ -502,9 +526,17 @@ impl<'a, S: Read + Seek> Parser<S> {
 
         let rep_expr = match &self.next_token()?.kind {
             TokenKind::AssignOP => {
+                let span = self.span();
                 let relop = self.relop_expression()?;
                 let rep = self.rep_expr(relop)?;
-                Expression::Expr(Operator::Assign, Box::new(relop_expression), Box::new(rep))
+                Expression::new(
+                    ExpressionKind::Expr(
+                        Operator::Assign,
+                        Box::new(relop_expression),
+                        Box::new(rep),
+                    ),
+                    span,
+                )
             }
             _ => {
                 self.rewind = true;
@@ -528,9 +560,13 @@ impl<'a, S: Read + Seek> Parser<S> {
         self.debug_print("entering rep_relop_expr");
         let relops = match self.next_token()?.kind.clone() {
             TokenKind::RelOP(op) => {
+                let span = self.span();
                 let simple_expression = self.simple_expression()?;
                 let rep = self.rep_relop_expr(simple_expression)?;
-                Expression::Expr(op.into(), Box::new(expression), Box::new(rep))
+                Expression::new(
+                    ExpressionKind::Expr(op.into(), Box::new(expression), Box::new(rep)),
+                    span,
+                )
             }
 
             _ => {
@@ -552,9 +588,13 @@ impl<'a, S: Read + Seek> Parser<S> {
         self.debug_print("entering rep_simple_expr");
         let expr = match self.next_token()?.kind.clone() {
             TokenKind::AddOP(op) => {
+                let span = self.span();
                 let term2 = self.term()?;
                 let rep = self.rep_simple_expr(term2)?;
-                Expression::Expr(op.into(), Box::new(term), Box::new(rep))
+                Expression::new(
+                    ExpressionKind::Expr(op.into(), Box::new(term), Box::new(rep)),
+                    span,
+                )
             }
 
             _ => {
@@ -579,9 +619,13 @@ impl<'a, S: Read + Seek> Parser<S> {
         self.debug_print("entering rep_term");
         let rep = match self.next_token()?.kind.clone() {
             TokenKind::MulOP(op) => {
+                let span = self.span();
                 let term2 = self.term()?;
                 let rep = self.rep_simple_expr(term2)?;
-                Expression::Expr(op.into(), Box::new(primary), Box::new(rep))
+                Expression::new(
+                    ExpressionKind::Expr(op.into(), Box::new(primary), Box::new(rep)),
+                    span,
+                )
             }
 
             _ => {
@@ -595,17 +639,19 @@ impl<'a, S: Read + Seek> Parser<S> {
     fn primary(&mut self) -> Result<Expression, Box<ParserError>> {
         self.debug_print("entering primary");
 
-        let primary = match self.next_token()?.kind.clone() {
+        let kind = self.next_token()?.kind.clone();
+        let span = self.span();
+        let primary = match kind {
             TokenKind::Identifier(id) => {
                 if let Some(fcall) = self.fcall_option()? {
-                    Expression::FuncCall(id, fcall)
+                    Expression::new(ExpressionKind::FuncCall(id, fcall), span)
                 } else {
-                    Expression::Identifier(id.clone())
+                    Expression::new(ExpressionKind::Identifier(id.clone()), span)
                 }
             }
-            TokenKind::Number { num, .. } => Expression::Number(num),
-            TokenKind::String(s) => Expression::StringLiteral(s.clone()),
-            TokenKind::CharLiteral(c) => Expression::CharLiteral(c),
+            TokenKind::Number { num, .. } => Expression::new(ExpressionKind::Number(num), span),
+            TokenKind::String(s) => Expression::new(ExpressionKind::StringLiteral(s.clone()), span),
+            TokenKind::CharLiteral(c) => Expression::new(ExpressionKind::CharLiteral(c), span),
             TokenKind::Delimiter(Delimiter::LParen) => {
                 let expr = self.expression()?;
                 self.accept(
@@ -614,9 +660,13 @@ impl<'a, S: Read + Seek> Parser<S> {
                 )?;
                 expr
             }
-            TokenKind::Delimiter(Delimiter::Not) => Expression::Not(Box::new(self.primary()?)),
+            TokenKind::Delimiter(Delimiter::Not) => {
+                Expression::new(ExpressionKind::Not(Box::new(self.primary()?)), span)
+            }
 
-            TokenKind::AddOP(AddOP::Minus) => Expression::Minus(Box::new(self.primary()?)),
+            TokenKind::AddOP(AddOP::Minus) => {
+                Expression::new(ExpressionKind::Minus(Box::new(self.primary()?)), span)
+            }
 
             _ => return Err(self.create_error(ParserErrorKind::Generic, None)),
         };
@@ -0,0 +1,159 @@
+use crate::parser::ast::*;
+use crate::scanner::token::Type;
+use itertools::Itertools;
+
+impl Program {
+    /// The program as S-expressions, one definition per line:
+    ///
+    /// ```text
+    /// (program
+    ///   (var int g)
+    ///   (defun int main () (block (var int a) (= a 1) (return a))))
+    /// ```
+    pub fn to_sexpr(&self) -> String {
+        let definitions = self
+            .definitions
+            .iter()
+            .map(|definition| match definition {
+                Definition::VarDef(var_def) => var_def_sexpr(var_def),
+                Definition::FuncDef(func_def) => func_def_sexpr(func_def),
+            })
+            .map(|definition| format!("\n  {definition}"))
+            .join("");
+        format!("(program{definitions})")
+    }
+}
+
+fn type_name(toyc_type: &Type) -> &'static str {
+    match toyc_type {
+        Type::Int => "int",
+        Type::Char => "char",
+    }
+}
+
+fn var_def_sexpr(var_def: &VarDef) -> String {
+    let names = var_def.identifiers.join(" ");
+    format!("(var {} {names})", type_name(&var_def.toyc_type))
+}
+
+fn func_def_sexpr(func_def: &FuncDef) -> String {
+    let params = func_def
+        .var_def
+        .iter()
+        .flat_map(|v| v.identifiers.iter().map(|id| (type_name(&v.toyc_type), id)))
+        .map(|(toyc_type, id)| format!("({toyc_type} {id})"))
+        .join(" ");
+    format!(
+        "(defun {} {} ({params}) {})",
+        type_name(&func_def.toyc_type),
+        func_def.identifier,
+        statement_sexpr(&func_def.statement)
+    )
+}
+
+fn statement_sexpr(statement: &Statement) -> String {
+    let list = |head: &str, items: Vec<String>| match items.is_empty() {
+        true => format!("({head})"),
+        false => format!("({head} {})", items.join(" ")),
+    };
+    match &statement.kind {
+        StatementKind::Expression(expr) => expression_sexpr(expr),
+        StatementKind::Break => "(break)".to_owned(),
+        StatementKind::BlockState(var_defs, statements) => {
+            let declarations = var_defs.iter().map(var_def_sexpr);
+            let statements = statements.iter().map(statement_sexpr);
+            list("block", declarations.chain(statements).collect())
+        }
+        StatementKind::IfState(expr, then_stmt, else_stmt) => {
+            let mut items = vec![expression_sexpr(expr), statement_sexpr(then_stmt)];
+            items.extend(else_stmt.as_ref().as_ref().map(statement_sexpr));
+            list("if", items)
+        }
+        StatementKind::NullState => "(empty)".to_owned(),
+        StatementKind::ReturnState(expr) => {
+            list("return", expr.iter().map(expression_sexpr).collect())
+        }
+        StatementKind::WhileState(expr, body) => {
+            list("while", vec![expression_sexpr(expr), statement_sexpr(body)])
+        }
+        StatementKind::ReadState(name, others) => {
+            let names = std::iter::once(name).chain(others.iter().flatten());
+            list("read", names.cloned().collect())
+        }
+        StatementKind::WriteState(expr, others) => {
+            let exprs = std::iter::once(expr).chain(others.iter().flatten());
+            list("write", exprs.map(expression_sexpr).collect())
+        }
+        StatementKind::NewLineState => "(newline)".to_owned(),
+    }
+}
+
+fn expression_sexpr(expression: &Expression) -> String {
+    match &expression.kind {
+        ExpressionKind::Number(num) => num.to_string(),
+        ExpressionKind::Identifier(id) => id.clone(),
+        ExpressionKind::CharLiteral(Some(c)) => format!("{c:?}"),
+        ExpressionKind::CharLiteral(None) => "''".to_owned(),
+        ExpressionKind::StringLiteral(s) => format!("{s:?}"),
+        ExpressionKind::FuncCall(name, arguments) => {
+            let arguments = arguments
+                .iter()
+                .map(|a| format!(" {}", expression_sexpr(a)));
+            format!("(call {name}{})", arguments.collect::<String>())
+        }
+        ExpressionKind::Expr(op, lhs, rhs) => {
+            format!("({op} {} {})", expression_sexpr(lhs), expression_sexpr(rhs))
+        }
+        ExpressionKind::Not(expr) => format!("(! {})", expression_sexpr(expr)),
+        ExpressionKind::Minus(expr) => format!("(- {})", expression_sexpr(expr)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::ast::Program;
+    use crate::Parser;
+    use std::io::Cursor;
+
+    fn parse(source: &str) -> Program {
+        Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse")
+    }
+
+    #[test]
+    fn test_sexpr() {
+        let program = parse(
+            "int g;
+char next(char c, int n){ if (n > 0) return c + 1; else return c; }
+int main(){
+    int a;
+    read(a);
+    while (!(a == 0)) { a = a - 1; if (a) break; ; }
+    write(\"a = \", next('x', -a), a * (g = 2));
+    newline;
+    return 0;
+}",
+        );
+        assert_eq!(
+            program.to_sexpr(),
+            "(program
+  (var int g)
+  (defun char next ((char c) (int n)) (block (if (> n 0) (return (+ c 1)) (return c))))
+  (defun int main () (block (var int a) (read a) \
+(while (! (== a 0)) (block (= a (- a 1)) (if a (break)) (empty))) \
+(write \"a = \" (call next 'x' (- a)) (* a (= g 2))) (newline) (return 0))))"
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let program = parse(include_str!("../../../../resources/demo.tc"));
+        let json = program.to_json();
+        let read = Program::from_json(&json).expect("failed to read JSON");
+        assert_eq!(read.to_string(), program.to_string());
+        assert_eq!(read.to_json(), json);
+        assert!(json.contains("\"location\""));
+        assert!(Program::from_json("{\"definitions\": [{\"Class\": {}}]}").is_err());
+    }
+}
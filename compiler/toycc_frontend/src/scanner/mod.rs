@@ -1,5 +1,5 @@
 use crate::scanner::error::{ScannerError, ScannerErrorKind};
-use crate::scanner::token::{Delimiter, Keyword, RelOP, Token, TokenKind, Type};
+use crate::scanner::token::{Comment, Delimiter, Keyword, RelOP, Token, TokenKind, Type};
 use crate::BufferedStream;
 use std::io::{Read, Seek};
 
@@ -37,6 +37,9 @@ pub struct Scanner<S: Read + Seek> {
     debug: Option<u32>,
     lines_read: usize,
     comments_nested: Vec<(usize, usize)>,
+    /// Text and location of the block comment being read
+    comment: Option<Comment>,
+    comments: Vec<Comment>,
     pub(crate) previous_location: (usize, usize),
 }
 
@@ -49,6 +52,8 @@ impl<S: Read + Seek> Scanner<S> {
             buffer: String::new(),
             lines_read: 0,
             comments_nested: vec![],
+            comment: None,
+            comments: vec![],
             position: 0,
             previous_location: (0, 0),
         }
@@ -78,6 +83,11 @@ impl<S: Read + Seek> Scanner<S> {
         self.position = 0;
     }
 
+    /// Comments read so far, in order
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
     fn change_state(&mut self, state: State, c: char) {
         self.previous_location = (self.lines_read, self.position + 1);
         self.push_char(c);
@@ -94,6 +104,9 @@ impl<S: Read + Seek> Scanner<S> {
             match self.state {
                 State::Initial => {
                     self.buffer.clear();
+                    if !" \t\n".contains(c) {
+                        self.previous_location = (self.lines_read, self.position + 1);
+                    }
                     match c {
                         ('a'..='z') | ('A'..='Z') => self.change_state(State::Identifier, c),
                         ('0'..='9') => self.change_state(State::Integer, c),
@@ -268,10 +281,16 @@ impl<S: Read + Seek> Scanner<S> {
 
                 State::CommentStart => match c {
                     '/' => {
+                        let line = self.stream.peek().unwrap_or_default();
+                        let text = line.chars().skip(self.position - 1).collect::<String>();
+                        let comment =
+                            Comment::new(text.trim_end().to_owned(), self.previous_location);
+                        self.comments.push(comment);
                         self.next_line();
                         self.state = State::Initial;
                     }
                     '*' => {
+                        self.comment = Some(Comment::new("/*".to_owned(), self.previous_location));
                         self.change_state(State::CommentEat, c);
                         self.comments_nested.push((self.lines_read, self.position));
                     }
@@ -291,6 +310,9 @@ impl<S: Read + Seek> Scanner<S> {
                 }
 
                 State::CommentEnd => {
+                    self.comment
+                        .iter_mut()
+                        .for_each(|comment| comment.text.push(c));
                     match c {
                         '/' => {
                             self.comments_nested.pop();
@@ -299,13 +321,19 @@ impl<S: Read + Seek> Scanner<S> {
                     }
 
                     match self.comments_nested.len() {
-                        0 => self.state = State::Initial,
+                        0 => {
+                            self.comments.extend(self.comment.take());
+                            self.state = State::Initial
+                        }
                         _ => self.state = State::CommentEat,
                     }
                     self.position += 1
                 }
 
                 State::CommentEat => {
+                    self.comment
+                        .iter_mut()
+                        .for_each(|comment| comment.text.push(c));
                     match c {
                         '*' => self.state = State::CommentEnd,
                         '/' => self.state = State::CommentNested,
@@ -456,7 +484,7 @@ impl<S: Read + Seek> Scanner<S> {
         let _ = self.stream.rewind();
         Some(
             self.stream
-                .nth(location.0 - 1)
+                .nth(location.0.saturating_sub(1))
                 .unwrap_or_default()
                 .trim_end()
                 .to_string(),
@@ -580,6 +608,28 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_comments() {
+        const SAMPLE_DATA: &str = "a /* one\n/* two */ */ b // three \n  c / d";
+        let mut scanner = Scanner::new(
+            BufferedStream::new(Cursor::new(SAMPLE_DATA), Some("sample.tc".to_string())),
+            None,
+        );
+        while scanner.next_token().unwrap().kind != TokenKind::Eof {}
+        let comments = scanner
+            .comments()
+            .iter()
+            .map(|c| (c.text.as_str(), c.location, c.end_line()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            [
+                ("/* one\n/* two */ */", (1, 3), 2),
+                ("// three", (2, 16), 2)
+            ]
+        );
+    }
+
     #[test]
     fn pass_string_literal() {
         const SAMPLE_DATA: &str = r#""Hello world! :D""#;
@@ -683,6 +733,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_assignments)]
     fn test_number_invalid_exp() {
         const SAMPLE_DATA: &str = r#"2E+1E1"#;
         let mut scanner = Scanner::new(
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -42,7 +43,7 @@ pub enum Keyword {
     Write,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Type {
     Int,
     Char,
@@ -95,6 +96,25 @@ impl Token {
     }
 }
 
+/// A comment, which the parser skips as trivia but the formatter keeps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// The whole comment, delimiters included
+    pub text: String,
+    pub location: (usize, usize),
+}
+
+impl Comment {
+    pub fn new(text: String, location: (usize, usize)) -> Self {
+        Self { text, location }
+    }
+
+    /// Line the comment ends on
+    pub fn end_line(&self) -> usize {
+        self.location.0 + self.text.matches('\n').count()
+    }
+}
+
 impl Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.kind)
@@ -0,0 +1,37 @@
+//! Formats every program in `resources` and checks that the result means
+//! the same and is left alone when formatted again.
+
+use std::fs;
+use std::io::Cursor;
+use toycc_frontend::Parser;
+
+#[test]
+fn test_format_round_trip() {
+    let resources = concat!(env!("CARGO_MANIFEST_DIR"), "/../../resources");
+    let mut formatted_any = false;
+    for entry in fs::read_dir(resources).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "tc") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        let name = path.to_string_lossy().to_string();
+        // Some resources exercise the scanner only and are not programs
+        let Ok(program) = Parser::new(Cursor::new(&source), &name, None).parse() else {
+            continue;
+        };
+        let formatted = Parser::new(Cursor::new(&source), &name, None)
+            .format()
+            .unwrap();
+        let reparsed = Parser::new(Cursor::new(&formatted), &name, None)
+            .parse()
+            .unwrap_or_else(|e| panic!("{e}\n{formatted}"));
+        assert_eq!(reparsed.to_sexpr(), program.to_sexpr(), "{name}");
+        let again = Parser::new(Cursor::new(&formatted), &name, None)
+            .format()
+            .unwrap();
+        assert_eq!(again, formatted, "{name}");
+        formatted_any = true;
+    }
+    assert!(formatted_any);
+}
@@ -0,0 +1,81 @@
+use toycc_frontend::ast::Span;
+use toycc_report::{Diagnostic, ErrorKind, Report, ReportLevel};
+
+#[derive(Debug, PartialEq)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    StackOverflow(usize),
+    UndeclaredIdentifier(String),
+    UndeclaredFunction(String),
+    ArgumentCount(String, usize, usize),
+    ExpectedIdentifier,
+    StringValue,
+    BreakOutsideLoop,
+    InvalidInput(String),
+    MissingMain,
+}
+
+#[derive(Debug, Report)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Option<Span>,
+    line: Option<String>,
+    stream_name: String,
+}
+
+impl RuntimeError {
+    pub fn new(
+        kind: RuntimeErrorKind,
+        span: Option<Span>,
+        line: Option<String>,
+        stream_name: String,
+    ) -> Self {
+        Self {
+            kind,
+            span,
+            line,
+            stream_name,
+        }
+    }
+}
+
+impl Diagnostic for RuntimeError {
+    fn info(&self) -> String {
+        match &self.kind {
+            RuntimeErrorKind::DivisionByZero => "division by 0".to_owned(),
+            RuntimeErrorKind::StackOverflow(depth) => {
+                format!("stack overflow: call depth exceeded {depth}")
+            }
+            RuntimeErrorKind::UndeclaredIdentifier(id) => format!("undeclared identifier '{id}'"),
+            RuntimeErrorKind::UndeclaredFunction(name) => format!("undeclared function {name}"),
+            RuntimeErrorKind::ArgumentCount(name, expected, actual) => {
+                format!("function {name} expects {expected} argument(s) but {actual} were given")
+            }
+            RuntimeErrorKind::ExpectedIdentifier => "expected identifier".to_owned(),
+            RuntimeErrorKind::StringValue => "string literal used as a value".to_owned(),
+            RuntimeErrorKind::BreakOutsideLoop => "break statement not within loop".to_owned(),
+            RuntimeErrorKind::InvalidInput(input) => format!("invalid input '{input}'"),
+            RuntimeErrorKind::MissingMain => "missing main function".to_owned(),
+        }
+    }
+
+    fn level(&self) -> ReportLevel {
+        match self.span {
+            Some(span) => ReportLevel::Error(ErrorKind::ParsingError {
+                file_name: self.stream_name.clone(),
+                pos: span.location,
+                len: span.len,
+                source: self.line.clone(),
+            }),
+            None => ReportLevel::Error(ErrorKind::NoHelpError),
+        }
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        None
+    }
+}
@@ -0,0 +1,505 @@
+use crate::error::{RuntimeError, RuntimeErrorKind};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::thread;
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Span, Statement,
+    StatementKind,
+};
+use toycc_frontend::Type;
+
+/// Deepest chain of ToyC calls before a stack overflow is reported
+pub const MAX_CALL_DEPTH: usize = 10_000;
+const STACK_SIZE: usize = 1 << 30;
+
+#[derive(Debug, Clone)]
+struct Variable {
+    value: i32,
+    toyc_type: Type,
+}
+
+enum Flow {
+    Normal,
+    Break(Span),
+    Return(i32),
+}
+
+/// Executes a parsed program directly, without generating any code
+pub struct Interpreter<'a> {
+    file_name: &'a str,
+    source: &'a str,
+    functions: HashMap<&'a str, &'a FuncDef>,
+    globals: HashMap<&'a str, Variable>,
+    frames: Vec<Vec<HashMap<&'a str, Variable>>>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(program: &'a Program, file_name: &'a str, source: &'a str) -> Self {
+        let mut functions = HashMap::new();
+        let mut globals = HashMap::new();
+        for definition in &program.definitions {
+            match definition {
+                Definition::FuncDef(func_def) => {
+                    functions.insert(func_def.identifier.as_str(), func_def);
+                }
+                Definition::VarDef(var_def) => {
+                    for id in &var_def.identifiers {
+                        globals.insert(
+                            id.as_str(),
+                            Variable {
+                                value: 0,
+                                toyc_type: var_def.toyc_type.clone(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        Self {
+            file_name,
+            source,
+            functions,
+            globals,
+            frames: vec![],
+        }
+    }
+
+    /// Runs `main` and returns its result. Execution happens on a thread with a
+    /// large stack so that deep ToyC recursion hits [`MAX_CALL_DEPTH`] first.
+    pub fn run<R, W>(&mut self, input: &mut R, output: &mut W) -> Result<i32, Box<RuntimeError>>
+    where
+        R: BufRead + Send,
+        W: Write + Send,
+    {
+        let main = match self.functions.get("main") {
+            Some(main) => *main,
+            None => return Err(self.create_error(RuntimeErrorKind::MissingMain, None)),
+        };
+        thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || {
+                    let result = self.call(main, vec![], main.span, input, output);
+                    let _ = output.flush();
+                    result
+                })
+                .expect("failed to spawn interpreter thread")
+                .join()
+                .expect("interpreter thread panicked")
+        })
+    }
+
+    fn call<R: BufRead, W: Write>(
+        &mut self,
+        func_def: &'a FuncDef,
+        arguments: Vec<i32>,
+        span: Span,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<i32, Box<RuntimeError>> {
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(
+                self.create_error(RuntimeErrorKind::StackOverflow(MAX_CALL_DEPTH), Some(span))
+            );
+        }
+        if func_def.var_def.len() != arguments.len() {
+            return Err(self.create_error(
+                RuntimeErrorKind::ArgumentCount(
+                    func_def.identifier.clone(),
+                    func_def.var_def.len(),
+                    arguments.len(),
+                ),
+                Some(span),
+            ));
+        }
+
+        let mut params = HashMap::new();
+        for (param, value) in func_def.var_def.iter().zip(arguments) {
+            for id in &param.identifiers {
+                params.insert(
+                    id.as_str(),
+                    Variable {
+                        value,
+                        toyc_type: param.toyc_type.clone(),
+                    },
+                );
+            }
+        }
+        self.frames.push(vec![params]);
+        let flow = self.execute(&func_def.statement, input, output);
+        self.frames.pop();
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal => Ok(0),
+            Flow::Break(span) => {
+                Err(self.create_error(RuntimeErrorKind::BreakOutsideLoop, Some(span)))
+            }
+        }
+    }
+
+    fn execute<R: BufRead, W: Write>(
+        &mut self,
+        statement: &'a Statement,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<Flow, Box<RuntimeError>> {
+        match &statement.kind {
+            StatementKind::Expression(expr) => {
+                self.evaluate(expr, input, output)?;
+            }
+            StatementKind::Break => return Ok(Flow::Break(statement.span)),
+            StatementKind::BlockState(var_defs, statements) => {
+                let mut scope = HashMap::new();
+                for var_def in var_defs {
+                    for id in &var_def.identifiers {
+                        scope.insert(
+                            id.as_str(),
+                            Variable {
+                                value: 0,
+                                toyc_type: var_def.toyc_type.clone(),
+                            },
+                        );
+                    }
+                }
+                self.scopes().push(scope);
+                let mut flow = Ok(Flow::Normal);
+                for statement in statements {
+                    flow = self.execute(statement, input, output);
+                    if !matches!(flow, Ok(Flow::Normal)) {
+                        break;
+                    }
+                }
+                self.scopes().pop();
+                return flow;
+            }
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
+                if self.evaluate(expr, input, output)? != 0 {
+                    return self.execute(if_stmt, input, output);
+                } else if let Some(else_stmt) = else_stmt.as_ref() {
+                    return self.execute(else_stmt, input, output);
+                }
+            }
+            StatementKind::NullState => {}
+            StatementKind::ReturnState(expr) => {
+                let value = match expr {
+                    Some(expr) => self.evaluate(expr, input, output)?,
+                    None => 0,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StatementKind::WhileState(expr, body) => {
+                while self.evaluate(expr, input, output)? != 0 {
+                    match self.execute(body, input, output)? {
+                        Flow::Normal => {}
+                        Flow::Break(_) => break,
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            StatementKind::ReadState(name, others) => {
+                let _ = output.flush();
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    let toyc_type = self.lookup(name, statement.span)?.toyc_type.clone();
+                    let value = self.read_value(&toyc_type, statement.span, input)?;
+                    self.lookup(name, statement.span)?.value = value;
+                }
+            }
+            StatementKind::WriteState(expr, others) => {
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    let text = match &expr.kind {
+                        ExpressionKind::StringLiteral(s) => unescape(s),
+                        _ => {
+                            let toyc_type = self.type_of(expr)?;
+                            let value = self.evaluate(expr, input, output)?;
+                            match toyc_type {
+                                Type::Int => value.to_string(),
+                                Type::Char => char::from_u32(value as u32)
+                                    .map(String::from)
+                                    .unwrap_or_default(),
+                            }
+                        }
+                    };
+                    let _ = output.write_all(text.as_bytes());
+                }
+            }
+            StatementKind::NewLineState => {
+                let _ = output.write_all(b"\n");
+                let _ = output.flush();
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn evaluate<R: BufRead, W: Write>(
+        &mut self,
+        expression: &'a Expression,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<i32, Box<RuntimeError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(num) => *num as i32,
+            ExpressionKind::Identifier(id) => self.lookup(id, expression.span)?.value,
+            ExpressionKind::CharLiteral(c) => c.map(|c| c as i32).unwrap_or_default(),
+            ExpressionKind::StringLiteral(_) => {
+                return Err(self.create_error(RuntimeErrorKind::StringValue, Some(expression.span)))
+            }
+            ExpressionKind::FuncCall(name, arguments) => {
+                let func_def = match self.functions.get(name.as_str()) {
+                    Some(func_def) => *func_def,
+                    None => {
+                        return Err(self.create_error(
+                            RuntimeErrorKind::UndeclaredFunction(name.clone()),
+                            Some(expression.span),
+                        ))
+                    }
+                };
+                let arguments = arguments
+                    .iter()
+                    .map(|a| self.evaluate(a, input, output))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(func_def, arguments, expression.span, input, output)?
+            }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                let id = match &lhs.kind {
+                    ExpressionKind::Identifier(id) => id,
+                    _ => {
+                        return Err(
+                            self.create_error(RuntimeErrorKind::ExpectedIdentifier, Some(lhs.span))
+                        )
+                    }
+                };
+                let value = self.evaluate(rhs, input, output)?;
+                self.lookup(id, lhs.span)?.value = value;
+                value
+            }
+            ExpressionKind::Expr(Operator::And, lhs, rhs) => {
+                (self.evaluate(lhs, input, output)? != 0 && self.evaluate(rhs, input, output)? != 0)
+                    as i32
+            }
+            ExpressionKind::Expr(Operator::Or, lhs, rhs) => {
+                (self.evaluate(lhs, input, output)? != 0 || self.evaluate(rhs, input, output)? != 0)
+                    as i32
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                let a = self.evaluate(lhs, input, output)?;
+                let b = self.evaluate(rhs, input, output)?;
+                match op {
+                    Operator::Plus => a.wrapping_add(b),
+                    Operator::Minus => a.wrapping_sub(b),
+                    Operator::Multiply => a.wrapping_mul(b),
+                    Operator::Divide | Operator::Modulo if b == 0 => {
+                        return Err(self
+                            .create_error(RuntimeErrorKind::DivisionByZero, Some(expression.span)))
+                    }
+                    Operator::Divide => a.wrapping_div(b),
+                    Operator::Modulo => a.wrapping_rem(b),
+                    Operator::LessEqual => (a <= b) as i32,
+                    Operator::LessThan => (a < b) as i32,
+                    Operator::GreaterEqual => (a >= b) as i32,
+                    Operator::GreaterThan => (a > b) as i32,
+                    Operator::Equal => (a == b) as i32,
+                    Operator::NotEqual => (a != b) as i32,
+                    Operator::Assign | Operator::And | Operator::Or => unreachable!(),
+                }
+            }
+            ExpressionKind::Not(expr) => (self.evaluate(expr, input, output)? == 0) as i32,
+            ExpressionKind::Minus(expr) => self.evaluate(expr, input, output)?.wrapping_neg(),
+        })
+    }
+
+    /// Static type of an expression, following the JVM backend: a compound
+    /// expression takes the type of its left-most operand
+    fn type_of(&mut self, expression: &'a Expression) -> Result<Type, Box<RuntimeError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(_) => Type::Int,
+            ExpressionKind::Identifier(id) => self.lookup(id, expression.span)?.toyc_type.clone(),
+            ExpressionKind::CharLiteral(_) => Type::Char,
+            ExpressionKind::StringLiteral(_) => {
+                return Err(self.create_error(RuntimeErrorKind::StringValue, Some(expression.span)))
+            }
+            ExpressionKind::FuncCall(name, _) => match self.functions.get(name.as_str()) {
+                Some(func_def) => func_def.toyc_type.clone(),
+                None => {
+                    return Err(self.create_error(
+                        RuntimeErrorKind::UndeclaredFunction(name.clone()),
+                        Some(expression.span),
+                    ))
+                }
+            },
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs)?,
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr)?,
+        })
+    }
+
+    fn read_value<R: BufRead>(
+        &mut self,
+        toyc_type: &Type,
+        span: Span,
+        input: &mut R,
+    ) -> Result<i32, Box<RuntimeError>> {
+        let mut word = String::new();
+        loop {
+            let (consumed, done) = match input.fill_buf() {
+                Ok([]) | Err(_) => (0, true),
+                Ok(buffer) => {
+                    let mut consumed = 0;
+                    let mut done = false;
+                    for &byte in buffer {
+                        if byte.is_ascii_whitespace() {
+                            if !word.is_empty() {
+                                done = true;
+                                break;
+                            }
+                        } else {
+                            word.push(byte as char);
+                            if *toyc_type == Type::Char {
+                                consumed += 1;
+                                done = true;
+                                break;
+                            }
+                        }
+                        consumed += 1;
+                    }
+                    (consumed, done)
+                }
+            };
+            input.consume(consumed);
+            if done {
+                break;
+            }
+        }
+
+        match toyc_type {
+            Type::Int => word.parse::<i32>().ok(),
+            Type::Char => word.chars().next().map(|c| c as i32),
+        }
+        .ok_or_else(|| self.create_error(RuntimeErrorKind::InvalidInput(word), Some(span)))
+    }
+
+    fn scopes(&mut self) -> &mut Vec<HashMap<&'a str, Variable>> {
+        self.frames.last_mut().expect("no active call frame")
+    }
+
+    fn lookup(&mut self, name: &str, span: Span) -> Result<&mut Variable, Box<RuntimeError>> {
+        let local = self
+            .frames
+            .last()
+            .and_then(|scopes| scopes.iter().rposition(|scope| scope.contains_key(name)));
+        if local.is_none() && !self.globals.contains_key(name) {
+            return Err(self.create_error(
+                RuntimeErrorKind::UndeclaredIdentifier(name.to_string()),
+                Some(span),
+            ));
+        }
+        Ok(match local {
+            Some(index) => self.scopes()[index].get_mut(name).unwrap(),
+            None => self.globals.get_mut(name).unwrap(),
+        })
+    }
+
+    fn create_error(&self, kind: RuntimeErrorKind, span: Option<Span>) -> Box<RuntimeError> {
+        let line = span.and_then(|span| {
+            self.source
+                .lines()
+                .nth(span.line().saturating_sub(1))
+                .map(|line| line.trim_end().to_string())
+        });
+        Box::new(RuntimeError::new(
+            kind,
+            span,
+            line,
+            self.file_name.to_string(),
+        ))
+    }
+}
+
+/// Expands the escape sequences Jasmin accepts in string constants, so that
+/// `write("\n")` behaves the same on both backends
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn run(source: &str, input: &str) -> (Result<i32, Box<RuntimeError>>, String) {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        let mut output = vec![];
+        let result =
+            Interpreter::new(&program, "test.tc", source).run(&mut Cursor::new(input), &mut output);
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_sample_program() {
+        let source = std::fs::read_to_string("../../resources/sample_run_part2.tc").unwrap();
+        let (result, output) = run(&source, "");
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(
+            output,
+            (1..=10)
+                .map(|i| format!("{i} is {}\n", if i % 2 == 0 { "even" } else { "odd" }))
+                .collect::<String>()
+        );
+    }
+
+    #[test]
+    fn test_read_write() {
+        let (result, output) = run(
+            "int main(){int a; char c; read(a, c); write(\"a = \", a + 1, c); newline; return a;}",
+            "  41\n x",
+        );
+        assert_eq!(result.unwrap(), 41);
+        assert_eq!(output, "a = 42x\n");
+    }
+
+    #[test]
+    fn test_recursion_and_scopes() {
+        let (result, output) = run(
+            "int fact(int n){ if (n <= 1) return 1; return n * fact(n - 1); }
+             int main(){ int a; a = 1; { int a; a = 5; write(fact(a)); } write(a); return 0; }",
+            "",
+        );
+        assert!(result.is_ok());
+        assert_eq!(output, "1201");
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let (result, _) = run("int main(){\n int a;\n a = 0;\n return 4 / a;\n}", "");
+        let error = result.unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+        assert_eq!(error.span.unwrap().line(), 4);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let (result, _) = run(
+            "int f(int n){ return f(n + 1); }\nint main(){\n return f(0);\n}",
+            "",
+        );
+        let error = result.unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::StackOverflow(MAX_CALL_DEPTH));
+        assert_eq!(error.span.unwrap().line(), 1);
+    }
+}
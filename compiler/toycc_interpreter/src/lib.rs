@@ -0,0 +1,5 @@
+mod error;
+pub mod interpreter;
+
+pub use error::{RuntimeError, RuntimeErrorKind};
+pub use interpreter::Interpreter;
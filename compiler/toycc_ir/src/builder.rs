@@ -0,0 +1,527 @@
+use crate::ir::{
+    BinaryOp, Block, BlockId, Function, Instruction, IoKind, Module, Operand, Terminator, UnaryOp,
+    VReg,
+};
+use toycc_backend_jvm::symbol_table::{self, Symbol, SymbolTable};
+use toycc_backend_jvm::{SemanticError, SemanticErrorKind};
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+
+/// Lowers a checked program to IR, with one register per local variable
+pub struct IrBuilder<'a> {
+    symbol_table: Vec<SymbolTable<'a>>,
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    current: usize,
+    loop_ends: Vec<BlockId>,
+    vreg_count: u32,
+}
+
+impl<'a> Default for IrBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> IrBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            symbol_table: vec![SymbolTable::default()],
+            blocks: vec![],
+            current: 0,
+            loop_ends: vec![],
+            vreg_count: 0,
+        }
+    }
+
+    pub fn build(&mut self, program: &'a Program) -> Result<Module, Box<SemanticError>> {
+        let mut module = Module::default();
+        for definition in &program.definitions {
+            match definition {
+                Definition::VarDef(var_def) => {
+                    for id in &var_def.identifiers {
+                        let variable = Symbol::Variable(id.clone(), var_def.toyc_type.clone(), 0);
+                        self.insert_symbol(id, variable)?;
+                        module.globals.push(id.clone());
+                    }
+                }
+                Definition::FuncDef(func_def) => {
+                    let arguments = func_def
+                        .var_def
+                        .iter()
+                        .flat_map(|v| v.identifiers.iter().map(|_| v.toyc_type.to_string()))
+                        .collect();
+                    let function = symbol_table::Function::new(
+                        func_def.identifier.clone(),
+                        arguments,
+                        vec![],
+                        func_def.toyc_type.clone(),
+                    );
+                    self.insert_symbol(&func_def.identifier, Symbol::Function(function))?;
+                }
+            }
+        }
+
+        for definition in &program.definitions {
+            if let Definition::FuncDef(func_def) = definition {
+                module.functions.push(self.build_function(func_def)?);
+            }
+        }
+        Ok(module)
+    }
+
+    fn build_function(&mut self, func_def: &'a FuncDef) -> Result<Function, Box<SemanticError>> {
+        self.blocks.clear();
+        self.vreg_count = 0;
+        self.current = self.new_block().0 as usize;
+        self.symbol_table.push(SymbolTable::default());
+
+        let mut params = vec![];
+        for var_def in &func_def.var_def {
+            for id in &var_def.identifiers {
+                params.push(self.declare_variable(id, var_def.toyc_type.clone())?);
+            }
+        }
+        let result = self.build_statement(&func_def.statement);
+        self.symbol_table.pop();
+        result?;
+        self.terminate(Terminator::Return(Operand::Const(0)));
+
+        let blocks = self
+            .blocks
+            .drain(..)
+            .map(|(instructions, terminator)| Block {
+                instructions,
+                terminator: terminator.expect("every block is terminated"),
+            })
+            .collect();
+        Ok(Function {
+            name: func_def.identifier.clone(),
+            params,
+            blocks,
+            vreg_count: self.vreg_count,
+            ssa: false,
+        })
+    }
+
+    fn build_statement(&mut self, statement: &'a Statement) -> Result<(), Box<SemanticError>> {
+        match &statement.kind {
+            StatementKind::Expression(expr) => {
+                self.build_expression(expr)?;
+            }
+            StatementKind::Break => match self.loop_ends.last() {
+                Some(&end) => {
+                    self.terminate(Terminator::Jump(end));
+                    let unreachable = self.new_block();
+                    self.switch_to(unreachable);
+                }
+                None => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::BreakOutsideLoop,
+                    )))
+                }
+            },
+            StatementKind::BlockState(var_defs, statements) => {
+                self.symbol_table.push(SymbolTable::default());
+                let result = self.build_block(var_defs, statements);
+                self.symbol_table.pop();
+                result?;
+            }
+            StatementKind::IfState(expr, if_stmt, else_stmt) => {
+                let condition = self.build_expression(expr)?;
+                let then_block = self.new_block();
+                let end_block = self.new_block();
+                match else_stmt.as_ref() {
+                    Some(else_stmt) => {
+                        let else_block = self.new_block();
+                        self.terminate(Terminator::Branch(condition, then_block, else_block));
+                        self.switch_to(then_block);
+                        self.build_statement(if_stmt)?;
+                        self.terminate(Terminator::Jump(end_block));
+                        self.switch_to(else_block);
+                        self.build_statement(else_stmt)?;
+                    }
+                    None => {
+                        self.terminate(Terminator::Branch(condition, then_block, end_block));
+                        self.switch_to(then_block);
+                        self.build_statement(if_stmt)?;
+                    }
+                }
+                self.terminate(Terminator::Jump(end_block));
+                self.switch_to(end_block);
+            }
+            StatementKind::NullState => {}
+            StatementKind::ReturnState(expr) => {
+                let value = match expr {
+                    Some(expr) => self.build_expression(expr)?,
+                    None => Operand::Const(0),
+                };
+                self.terminate(Terminator::Return(value));
+                let unreachable = self.new_block();
+                self.switch_to(unreachable);
+            }
+            StatementKind::WhileState(expr, body) => {
+                let condition_block = self.new_block();
+                let body_block = self.new_block();
+                let end_block = self.new_block();
+                self.terminate(Terminator::Jump(condition_block));
+                self.switch_to(condition_block);
+                let condition = self.build_expression(expr)?;
+                self.terminate(Terminator::Branch(condition, body_block, end_block));
+                self.switch_to(body_block);
+                self.loop_ends.push(end_block);
+                let result = self.build_statement(body);
+                self.loop_ends.pop();
+                result?;
+                self.terminate(Terminator::Jump(condition_block));
+                self.switch_to(end_block);
+            }
+            StatementKind::ReadState(name, others) => {
+                for name in std::iter::once(name).chain(others.iter().flatten()) {
+                    let (location, toyc_type) = self.variable(name)?;
+                    let kind = io_kind(&toyc_type);
+                    match location {
+                        Location::Local(dst) => self.emit(Instruction::Read { dst, kind }),
+                        Location::Global(global) => {
+                            let dst = self.new_vreg();
+                            self.emit(Instruction::Read { dst, kind });
+                            self.emit(Instruction::StoreGlobal {
+                                global,
+                                src: Operand::Reg(dst),
+                            });
+                        }
+                    }
+                }
+            }
+            StatementKind::WriteState(expr, others) => {
+                for expr in std::iter::once(expr).chain(others.iter().flatten()) {
+                    match &expr.kind {
+                        ExpressionKind::StringLiteral(s) => {
+                            self.emit(Instruction::WriteStr(unescape(s)))
+                        }
+                        _ => {
+                            let kind = io_kind(&self.type_of(expr)?);
+                            let src = self.build_expression(expr)?;
+                            self.emit(Instruction::Write { kind, src });
+                        }
+                    }
+                }
+            }
+            StatementKind::NewLineState => self.emit(Instruction::NewLine),
+        }
+        Ok(())
+    }
+
+    fn build_block(
+        &mut self,
+        var_defs: &'a [VarDef],
+        statements: &'a [Statement],
+    ) -> Result<(), Box<SemanticError>> {
+        for var_def in var_defs {
+            for id in &var_def.identifiers {
+                let dst = self.declare_variable(id, var_def.toyc_type.clone())?;
+                self.emit(Instruction::Copy {
+                    dst,
+                    src: Operand::Const(0),
+                });
+            }
+        }
+        statements
+            .iter()
+            .try_for_each(|statement| self.build_statement(statement))
+    }
+
+    fn build_expression(
+        &mut self,
+        expression: &'a Expression,
+    ) -> Result<Operand, Box<SemanticError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(num) => Operand::Const(*num as i32),
+            ExpressionKind::CharLiteral(c) => {
+                Operand::Const(c.map(|c| c as i32).unwrap_or_default())
+            }
+            ExpressionKind::StringLiteral(_) => {
+                return Err(Box::new(SemanticError::new(
+                    SemanticErrorKind::IncompatibleTypes,
+                )))
+            }
+            ExpressionKind::Identifier(id) => {
+                let dst = self.new_vreg();
+                match self.variable(id)?.0 {
+                    Location::Local(reg) => self.emit(Instruction::Copy {
+                        dst,
+                        src: Operand::Reg(reg),
+                    }),
+                    Location::Global(global) => self.emit(Instruction::LoadGlobal { dst, global }),
+                }
+                Operand::Reg(dst)
+            }
+            ExpressionKind::FuncCall(name, arguments) => {
+                self.function_type(name)?;
+                let args = arguments
+                    .iter()
+                    .map(|argument| self.build_expression(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let dst = self.new_vreg();
+                self.emit(Instruction::Call {
+                    dst,
+                    function: name.clone(),
+                    args,
+                });
+                Operand::Reg(dst)
+            }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                let location = match &lhs.kind {
+                    ExpressionKind::Identifier(id) => self.variable(id)?.0,
+                    _ => {
+                        return Err(Box::new(SemanticError::new(
+                            SemanticErrorKind::ExpectedIdentifier,
+                        )))
+                    }
+                };
+                let src = self.build_expression(rhs)?;
+                match location {
+                    Location::Local(dst) => self.emit(Instruction::Copy { dst, src }),
+                    Location::Global(global) => self.emit(Instruction::StoreGlobal { global, src }),
+                }
+                src
+            }
+            ExpressionKind::Expr(op, lhs, rhs) if op.is_logical() => {
+                let result = self.new_vreg();
+                let rhs_block = self.new_block();
+                let true_block = self.new_block();
+                let false_block = self.new_block();
+                let end_block = self.new_block();
+                let lhs = self.build_expression(lhs)?;
+                match op {
+                    Operator::And => {
+                        self.terminate(Terminator::Branch(lhs, rhs_block, false_block))
+                    }
+                    _ => self.terminate(Terminator::Branch(lhs, true_block, rhs_block)),
+                }
+                self.switch_to(rhs_block);
+                let rhs = self.build_expression(rhs)?;
+                self.terminate(Terminator::Branch(rhs, true_block, false_block));
+                for (block, value) in [(true_block, 1), (false_block, 0)] {
+                    self.switch_to(block);
+                    self.emit(Instruction::Copy {
+                        dst: result,
+                        src: Operand::Const(value),
+                    });
+                    self.terminate(Terminator::Jump(end_block));
+                }
+                self.switch_to(end_block);
+                Operand::Reg(result)
+            }
+            ExpressionKind::Expr(op, lhs, rhs) => {
+                let lhs = self.build_expression(lhs)?;
+                let rhs = self.build_expression(rhs)?;
+                let dst = self.new_vreg();
+                let op = match op {
+                    Operator::Plus => BinaryOp::Add,
+                    Operator::Minus => BinaryOp::Sub,
+                    Operator::Multiply => BinaryOp::Mul,
+                    Operator::Divide => BinaryOp::Div,
+                    Operator::Modulo => BinaryOp::Rem,
+                    Operator::LessEqual => BinaryOp::Le,
+                    Operator::LessThan => BinaryOp::Lt,
+                    Operator::GreaterEqual => BinaryOp::Ge,
+                    Operator::GreaterThan => BinaryOp::Gt,
+                    Operator::Equal => BinaryOp::Eq,
+                    _ => BinaryOp::Ne,
+                };
+                self.emit(Instruction::Binary { dst, op, lhs, rhs });
+                Operand::Reg(dst)
+            }
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => {
+                let op = match expression.kind {
+                    ExpressionKind::Not(_) => UnaryOp::Not,
+                    _ => UnaryOp::Neg,
+                };
+                let src = self.build_expression(expr)?;
+                let dst = self.new_vreg();
+                self.emit(Instruction::Unary { dst, op, src });
+                Operand::Reg(dst)
+            }
+        })
+    }
+
+    /// Static type of an expression: a compound expression takes the type of
+    /// its left-most operand, as in the JVM backend
+    fn type_of(&mut self, expression: &'a Expression) -> Result<Type, Box<SemanticError>> {
+        Ok(match &expression.kind {
+            ExpressionKind::Number(_) | ExpressionKind::StringLiteral(_) => Type::Int,
+            ExpressionKind::CharLiteral(_) => Type::Char,
+            ExpressionKind::Identifier(id) => self.variable(id)?.1,
+            ExpressionKind::FuncCall(name, _) => self.function_type(name)?,
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs)?,
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr)?,
+        })
+    }
+
+    fn variable(&mut self, name: &str) -> Result<(Location, Type), Box<SemanticError>> {
+        for (scope, table) in self.symbol_table.iter_mut().enumerate().rev() {
+            match table.find(name) {
+                Some(Symbol::Variable(_, toyc_type, _)) if scope == 0 => {
+                    return Ok((Location::Global(name.to_owned()), toyc_type.clone()))
+                }
+                Some(Symbol::Variable(_, toyc_type, reg)) => {
+                    return Ok((Location::Local(VReg(*reg as u32)), toyc_type.clone()))
+                }
+                Some(_) => {
+                    return Err(Box::new(SemanticError::new(
+                        SemanticErrorKind::ExpectedIdentifier,
+                    )))
+                }
+                None => {}
+            }
+        }
+        Err(Box::new(SemanticError::new(
+            SemanticErrorKind::UndeclaredIdentifier(name.to_owned()),
+        )))
+    }
+
+    fn function_type(&mut self, name: &str) -> Result<Type, Box<SemanticError>> {
+        match self.symbol_table[0].find(name) {
+            Some(Symbol::Function(function)) => Ok(function.return_type.clone()),
+            Some(_) => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::ExpectedFunction,
+            ))),
+            None => Err(Box::new(SemanticError::new(
+                SemanticErrorKind::UndeclaredFunction(name.to_owned()),
+            ))),
+        }
+    }
+
+    fn declare_variable(
+        &mut self,
+        name: &'a str,
+        toyc_type: Type,
+    ) -> Result<VReg, Box<SemanticError>> {
+        let reg = self.new_vreg();
+        let variable = Symbol::Variable(name.to_owned(), toyc_type, reg.0 as usize);
+        self.insert_symbol(name, variable)?;
+        Ok(reg)
+    }
+
+    fn insert_symbol(&mut self, name: &'a str, symbol: Symbol) -> Result<(), Box<SemanticError>> {
+        self.symbol_table
+            .iter_mut()
+            .next_back()
+            .unwrap()
+            .insert(name, symbol)
+            .map(|_| ())
+    }
+
+    fn new_vreg(&mut self) -> VReg {
+        self.vreg_count += 1;
+        VReg(self.vreg_count - 1)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((vec![], None));
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = block.0 as usize;
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current].1 = Some(terminator);
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.blocks[self.current].0.push(instruction);
+    }
+}
+
+enum Location {
+    Local(VReg),
+    Global(String),
+}
+
+fn io_kind(toyc_type: &Type) -> IoKind {
+    match toyc_type {
+        Type::Int => IoKind::Int,
+        Type::Char => IoKind::Char,
+    }
+}
+
+/// Expands the escape sequences accepted in ToyC string literals
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn build(source: &str) -> Module {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        IrBuilder::new().build(&program).expect("failed to build")
+    }
+
+    #[test]
+    fn test_dump() {
+        let module = build("int g; int addTwo(int n){ g = n + 2; return g; }");
+        assert_eq!(
+            module.to_string(),
+            "global @g
+
+function @addTwo(%0) {
+bb0:
+    %1 = copy %0
+    %2 = add %1, 2
+    store @g, %2
+    %3 = load @g
+    ret %3
+bb1:
+    ret 0
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let module = build(
+            "int main(){ int i; while (i < 3) { if (i == 1) break; i = i + 1; } write(i && 1); return i; }",
+        );
+        let main = &module.functions[0];
+        // bb0 enters the loop condition, which exits to the block after it
+        assert_eq!(main.blocks[0].terminator, Terminator::Jump(BlockId(1)));
+        let Terminator::Branch(_, body, end) = main.blocks[1].terminator else {
+            panic!("expected a conditional branch:\n{main}");
+        };
+        // the `break` inside the `if` jumps to the loop exit
+        let breaks = main
+            .blocks
+            .iter()
+            .filter(|b| b.terminator == Terminator::Jump(end))
+            .count();
+        assert_eq!(breaks, 1, "{main}");
+        assert_eq!(main.block(body).terminator.successors().len(), 2);
+        assert!(main.to_string().contains("write.int %"));
+    }
+}
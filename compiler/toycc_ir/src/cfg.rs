@@ -0,0 +1,340 @@
+use crate::ir::{BlockId, Function, Instruction};
+
+/// Predecessors of every block, without duplicates
+pub fn predecessors(function: &Function) -> Vec<Vec<BlockId>> {
+    let mut predecessors = vec![vec![]; function.blocks.len()];
+    for (index, block) in function.blocks.iter().enumerate() {
+        for successor in block.terminator.successors() {
+            let list: &mut Vec<BlockId> = &mut predecessors[successor.0 as usize];
+            if !list.contains(&BlockId(index as u32)) {
+                list.push(BlockId(index as u32));
+            }
+        }
+    }
+    predecessors
+}
+
+/// Blocks reachable from the entry, in reverse postorder
+pub fn reverse_postorder(function: &Function) -> Vec<BlockId> {
+    let mut visited = vec![false; function.blocks.len()];
+    let mut order = vec![];
+    // Each entry holds a block and the number of its successors visited so far
+    let mut stack = vec![(BlockId(0), 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.last_mut() {
+        let successors = function.block(*block).terminator.successors();
+        match successors.get(*next) {
+            Some(successor) => {
+                *next += 1;
+                if !visited[successor.0 as usize] {
+                    visited[successor.0 as usize] = true;
+                    stack.push((*successor, 0));
+                }
+            }
+            None => {
+                order.push(*block);
+                stack.pop();
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+/// Deletes the blocks that cannot be reached from the entry and renumbers the
+/// rest, keeping their order. Returns whether anything was removed.
+pub fn remove_unreachable_blocks(function: &mut Function) -> bool {
+    let mut reachable = vec![false; function.blocks.len()];
+    for block in reverse_postorder(function) {
+        reachable[block.0 as usize] = true;
+    }
+    if reachable.iter().all(|&r| r) {
+        return false;
+    }
+
+    let mut numbers = vec![None; function.blocks.len()];
+    let mut count = 0;
+    for (index, &reachable) in reachable.iter().enumerate() {
+        if reachable {
+            numbers[index] = Some(BlockId(count));
+            count += 1;
+        }
+    }
+    let blocks = std::mem::take(&mut function.blocks);
+    for (index, mut block) in blocks.into_iter().enumerate() {
+        if !reachable[index] {
+            continue;
+        }
+        for target in block.terminator.successors_mut() {
+            *target = numbers[target.0 as usize].unwrap();
+        }
+        for instruction in &mut block.instructions {
+            if let Instruction::Phi { incoming, .. } = instruction {
+                incoming.retain(|(block, _)| reachable[block.0 as usize]);
+                for (block, _) in incoming {
+                    *block = numbers[block.0 as usize].unwrap();
+                }
+            }
+        }
+        function.blocks.push(block);
+    }
+    true
+}
+
+/// Immediate dominators of the reachable blocks, computed with the iterative
+/// algorithm of Cooper, Harvey and Kennedy
+pub struct DominatorTree {
+    idom: Vec<Option<BlockId>>,
+    children: Vec<Vec<BlockId>>,
+    /// Position of every reachable block in reverse postorder
+    order: Vec<Option<usize>>,
+}
+
+impl DominatorTree {
+    pub fn new(function: &Function) -> Self {
+        let rpo = reverse_postorder(function);
+        let predecessors = predecessors(function);
+        let mut order = vec![None; function.blocks.len()];
+        for (position, block) in rpo.iter().enumerate() {
+            order[block.0 as usize] = Some(position);
+        }
+
+        let mut idom = vec![None; function.blocks.len()];
+        idom[0] = Some(BlockId(0));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for &predecessor in &predecessors[block.0 as usize] {
+                    if idom[predecessor.0 as usize].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(other) => intersect(&idom, &order, predecessor, other),
+                    });
+                }
+                if new_idom != idom[block.0 as usize] {
+                    idom[block.0 as usize] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![vec![]; function.blocks.len()];
+        for &block in rpo.iter().skip(1) {
+            children[idom[block.0 as usize].unwrap().0 as usize].push(block);
+        }
+        idom[0] = None;
+        Self {
+            idom,
+            children,
+            order,
+        }
+    }
+
+    /// Immediate dominator of a block; `None` for the entry and for
+    /// unreachable blocks
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.idom[block.0 as usize]
+    }
+
+    /// Blocks immediately dominated by `block`
+    pub fn children(&self, block: BlockId) -> &[BlockId] {
+        &self.children[block.0 as usize]
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.order[block.0 as usize].is_some()
+    }
+
+    /// Whether every path from the entry to `b` goes through `a`
+    pub fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        if !self.is_reachable(b) {
+            return false;
+        }
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom(b) {
+                Some(parent) => b = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Dominance frontier of every block: the blocks where its dominance
+    /// ends, which is where definitions made in it may need a phi
+    pub fn frontiers(&self, function: &Function) -> Vec<Vec<BlockId>> {
+        let mut frontiers = vec![vec![]; function.blocks.len()];
+        for (index, predecessors) in predecessors(function).iter().enumerate() {
+            let block = BlockId(index as u32);
+            if predecessors.len() < 2 || !self.is_reachable(block) {
+                continue;
+            }
+            for &predecessor in predecessors {
+                let mut runner = Some(predecessor);
+                while let Some(current) = runner {
+                    if Some(current) == self.idom(block) || !self.is_reachable(current) {
+                        break;
+                    }
+                    let frontier: &mut Vec<BlockId> = &mut frontiers[current.0 as usize];
+                    if !frontier.contains(&block) {
+                        frontier.push(block);
+                    }
+                    runner = self.idom(current);
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+/// A natural loop: the header and the blocks that reach one of its back
+/// edges without going through it. Loops sharing a header are merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: BlockId,
+    /// Blocks of the loop, header included, in increasing order
+    pub blocks: Vec<BlockId>,
+    /// Sources of the back edges
+    pub latches: Vec<BlockId>,
+}
+
+impl Loop {
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.blocks.binary_search(&block).is_ok()
+    }
+}
+
+/// Natural loops of the reachable blocks, innermost first: a loop nested in
+/// another one always has fewer blocks
+pub fn natural_loops(function: &Function, tree: &DominatorTree) -> Vec<Loop> {
+    let mut loops: Vec<Loop> = vec![];
+    for block in reverse_postorder(function) {
+        for header in function.block(block).terminator.successors() {
+            if !tree.dominates(header, block) {
+                continue;
+            }
+            match loops.iter_mut().find(|l| l.header == header) {
+                Some(l) if l.latches.contains(&block) => {}
+                Some(l) => l.latches.push(block),
+                None => loops.push(Loop {
+                    header,
+                    blocks: vec![],
+                    latches: vec![block],
+                }),
+            }
+        }
+    }
+
+    let predecessors = predecessors(function);
+    for l in &mut loops {
+        let mut blocks = vec![l.header];
+        let mut worklist = l.latches.clone();
+        while let Some(block) = worklist.pop() {
+            if !blocks.contains(&block) && tree.is_reachable(block) {
+                blocks.push(block);
+                worklist.extend(&predecessors[block.0 as usize]);
+            }
+        }
+        blocks.sort();
+        l.blocks = blocks;
+    }
+    loops.sort_by_key(|l| l.blocks.len());
+    loops
+}
+
+fn intersect(
+    idom: &[Option<BlockId>],
+    order: &[Option<usize>],
+    mut a: BlockId,
+    mut b: BlockId,
+) -> BlockId {
+    let position = |block: BlockId| order[block.0 as usize].unwrap();
+    while a != b {
+        while position(a) > position(b) {
+            a = idom[a.0 as usize].unwrap();
+        }
+        while position(b) > position(a) {
+            b = idom[b.0 as usize].unwrap();
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::{Block, Operand, Terminator};
+
+    /// Builds a function with empty blocks and the given terminators
+    fn function(terminators: Vec<Terminator>) -> Function {
+        Function {
+            name: "f".to_owned(),
+            params: vec![],
+            blocks: terminators
+                .into_iter()
+                .map(|terminator| Block {
+                    instructions: vec![],
+                    terminator,
+                })
+                .collect(),
+            vreg_count: 0,
+            ssa: false,
+        }
+    }
+
+    #[test]
+    fn test_loop_dominators() {
+        let condition = Operand::Const(1);
+        // bb0 -> bb1 (loop header) -> bb2 (body) -> bb3 | bb4 -> bb1; bb1 -> bb5
+        let function = function(vec![
+            Terminator::Jump(BlockId(1)),
+            Terminator::Branch(condition, BlockId(2), BlockId(5)),
+            Terminator::Branch(condition, BlockId(3), BlockId(4)),
+            Terminator::Jump(BlockId(4)),
+            Terminator::Jump(BlockId(1)),
+            Terminator::Return(condition),
+            Terminator::Jump(BlockId(5)),
+        ]);
+        let tree = DominatorTree::new(&function);
+        assert_eq!(tree.idom(BlockId(4)), Some(BlockId(2)));
+        assert_eq!(tree.idom(BlockId(5)), Some(BlockId(1)));
+        assert!(tree.dominates(BlockId(1), BlockId(3)));
+        assert!(!tree.dominates(BlockId(3), BlockId(4)));
+        assert!(!tree.is_reachable(BlockId(6)));
+
+        let frontiers = tree.frontiers(&function);
+        assert_eq!(frontiers[3], vec![BlockId(4)]);
+        assert_eq!(frontiers[4], vec![BlockId(1)]);
+        assert_eq!(frontiers[2], vec![BlockId(1)]);
+        assert!(frontiers[0].is_empty());
+
+        let loops = natural_loops(&function, &tree);
+        assert_eq!(
+            loops,
+            vec![Loop {
+                header: BlockId(1),
+                blocks: vec![BlockId(1), BlockId(2), BlockId(3), BlockId(4)],
+                latches: vec![BlockId(4)],
+            }]
+        );
+        assert!(!loops[0].contains(BlockId(5)));
+    }
+
+    #[test]
+    fn test_remove_unreachable_blocks() {
+        let mut function = function(vec![
+            Terminator::Jump(BlockId(2)),
+            Terminator::Jump(BlockId(2)),
+            Terminator::Return(Operand::Const(0)),
+        ]);
+        assert!(remove_unreachable_blocks(&mut function));
+        assert_eq!(function.blocks.len(), 2);
+        assert_eq!(function.blocks[0].terminator, Terminator::Jump(BlockId(1)));
+        assert!(!remove_unreachable_blocks(&mut function));
+    }
+}
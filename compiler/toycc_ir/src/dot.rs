@@ -0,0 +1,63 @@
+//! Control flow graphs in the Graphviz DOT language
+use crate::ir::{Function, Terminator};
+use std::fmt::Write;
+
+/// The control flow graph of a function: a box per basic block listing its
+/// instructions and terminator, and an edge per successor. The edges of a
+/// branch are labeled `true` and `false`.
+pub fn function_to_dot(function: &Function) -> String {
+    let mut dot = format!("digraph \"{}\" {{\n", escape(&function.name));
+    dot.push_str("    node [shape=box, fontname=monospace];\n");
+    for (index, block) in function.blocks.iter().enumerate() {
+        let mut label = format!("bb{index}:\\l");
+        for instruction in &block.instructions {
+            label.push_str(&format!("  {}\\l", escape(&instruction.to_string())));
+        }
+        label.push_str(&format!("  {}\\l", escape(&block.terminator.to_string())));
+        writeln!(dot, "    bb{index} [label=\"{label}\"];").unwrap();
+    }
+    for (index, block) in function.blocks.iter().enumerate() {
+        match &block.terminator {
+            Terminator::Jump(target) => writeln!(dot, "    bb{index} -> {target};").unwrap(),
+            Terminator::Branch(_, then_block, else_block) => {
+                writeln!(dot, "    bb{index} -> {then_block} [label=\"true\"];").unwrap();
+                writeln!(dot, "    bb{index} -> {else_block} [label=\"false\"];").unwrap();
+            }
+            Terminator::Return(_) => {}
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Escapes a string for a double-quoted DOT identifier
+pub fn escape(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::IrBuilder;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    #[test]
+    fn test_function_to_dot() {
+        let program = Parser::new(
+            Cursor::new("int main(){ int a; read(a); if (a) write(\"yes\"); return a; }"),
+            "test.tc",
+            None,
+        )
+        .parse()
+        .expect("failed to parse");
+        let module = IrBuilder::new().build(&program).expect("failed to build");
+        let dot = function_to_dot(&module.functions[0]);
+        assert!(dot.starts_with("digraph \"main\" {\n"), "{dot}");
+        assert!(dot.contains("bb0 [label=\"bb0:\\l"), "{dot}");
+        assert!(dot.contains("write.str \\\"yes\\\"\\l"), "{dot}");
+        assert!(dot.contains("[label=\"true\"];"), "{dot}");
+        assert!(dot.contains("[label=\"false\"];"), "{dot}");
+        assert!(dot.ends_with("}\n"), "{dot}");
+    }
+}
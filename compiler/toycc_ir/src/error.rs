@@ -0,0 +1,48 @@
+use toycc_report::{Diagnostic, ErrorKind, Report, ReportLevel};
+
+#[derive(Report, Debug, PartialEq)]
+pub enum IrError {
+    UndefinedFunction(String),
+    ArgumentCount(String, usize, usize),
+    DivisionByZero,
+    StackOverflow(usize),
+    InvalidInput(String),
+    Verify(String),
+}
+
+impl IrError {
+    /// Names the pass after which verification failed
+    pub(crate) fn after(self, pass: &str) -> Self {
+        match self {
+            Self::Verify(message) => Self::Verify(format!("after {pass}: {message}")),
+            error => error,
+        }
+    }
+}
+
+impl Diagnostic for IrError {
+    fn info(&self) -> String {
+        match self {
+            Self::UndefinedFunction(name) => format!("undefined function @{name}"),
+            Self::ArgumentCount(name, expected, actual) => {
+                format!("function @{name} expects {expected} argument(s) but {actual} were given")
+            }
+            Self::DivisionByZero => "division by 0".to_string(),
+            Self::StackOverflow(depth) => format!("stack overflow: call depth exceeded {depth}"),
+            Self::InvalidInput(input) => format!("invalid input '{input}'"),
+            Self::Verify(message) => format!("invalid IR {message}"),
+        }
+    }
+
+    fn level(&self) -> ReportLevel {
+        ReportLevel::Error(ErrorKind::NoHelpError)
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        None
+    }
+}
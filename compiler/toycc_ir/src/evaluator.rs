@@ -0,0 +1,250 @@
+use crate::error::IrError;
+use crate::ir::{BlockId, Function, Instruction, IoKind, Module, Operand, Terminator, VReg};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// Deepest chain of calls before a stack overflow is reported, matching the
+/// limit of the tree-walking interpreter
+pub const MAX_FRAMES: usize = 10_000;
+
+struct Frame<'a> {
+    function: &'a Function,
+    block: usize,
+    index: usize,
+    registers: Vec<i32>,
+    /// Register of the caller that receives the result
+    result: Option<VReg>,
+}
+
+/// Executes IR directly, counting the instructions it executes
+pub struct Evaluator<'a> {
+    functions: HashMap<&'a str, &'a Function>,
+    globals: HashMap<&'a str, i32>,
+    steps: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(module: &'a Module) -> Self {
+        Self {
+            functions: module
+                .functions
+                .iter()
+                .map(|f| (f.name.as_str(), f))
+                .collect(),
+            globals: module.globals.iter().map(|g| (g.as_str(), 0)).collect(),
+            steps: 0,
+        }
+    }
+
+    /// Number of instructions and terminators executed so far
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Runs `main` and returns its result
+    pub fn run<R, W>(&mut self, input: &mut R, output: &mut W) -> Result<i32, IrError>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let result = self.execute(input, output);
+        let _ = output.flush();
+        result
+    }
+
+    fn execute<R, W>(&mut self, input: &mut R, output: &mut W) -> Result<i32, IrError>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let mut frames = vec![self.frame("main", vec![], None)?];
+        loop {
+            let depth = frames.len();
+            let frame = frames.last_mut().unwrap();
+            let block = &frame.function.blocks[frame.block];
+            self.steps += 1;
+            let Some(instruction) = block.instructions.get(frame.index) else {
+                match &block.terminator {
+                    Terminator::Jump(target) => enter(frame, *target),
+                    Terminator::Branch(condition, then_block, else_block) => {
+                        let target = match value(&frame.registers, condition) {
+                            0 => else_block,
+                            _ => then_block,
+                        };
+                        enter(frame, *target);
+                    }
+                    Terminator::Return(operand) => {
+                        let result = value(&frame.registers, operand);
+                        let frame = frames.pop().unwrap();
+                        match frames.last_mut() {
+                            Some(caller) => {
+                                caller.registers[frame.result.unwrap().0 as usize] = result
+                            }
+                            None => return Ok(result),
+                        }
+                    }
+                }
+                continue;
+            };
+            frame.index += 1;
+
+            let registers = &mut frame.registers;
+            match instruction {
+                Instruction::Copy { dst, src } => registers[dst.0 as usize] = value(registers, src),
+                Instruction::Unary { dst, op, src } => {
+                    registers[dst.0 as usize] = op.evaluate(value(registers, src))
+                }
+                Instruction::Binary { dst, op, lhs, rhs } => {
+                    registers[dst.0 as usize] = op
+                        .evaluate(value(registers, lhs), value(registers, rhs))
+                        .ok_or(IrError::DivisionByZero)?
+                }
+                Instruction::LoadGlobal { dst, global } => {
+                    registers[dst.0 as usize] = self.globals[global.as_str()]
+                }
+                Instruction::StoreGlobal { global, src } => {
+                    let src = value(registers, src);
+                    *self.globals.get_mut(global.as_str()).unwrap() = src;
+                }
+                Instruction::Call {
+                    dst,
+                    function,
+                    args,
+                } => {
+                    if depth >= MAX_FRAMES {
+                        return Err(IrError::StackOverflow(MAX_FRAMES));
+                    }
+                    let args = args.iter().map(|a| value(registers, a)).collect();
+                    let callee = self.frame(function, args, Some(*dst))?;
+                    frames.push(callee);
+                }
+                Instruction::Read { dst, kind } => {
+                    let _ = output.flush();
+                    registers[dst.0 as usize] = read_value(input, *kind)?;
+                }
+                Instruction::Write { kind, src } => {
+                    let src = value(registers, src);
+                    let _ = match kind {
+                        IoKind::Int => write!(output, "{src}"),
+                        IoKind::Char => match char::from_u32(src as u32) {
+                            Some(c) => write!(output, "{c}"),
+                            None => Ok(()),
+                        },
+                    };
+                }
+                Instruction::WriteStr(string) => {
+                    let _ = output.write_all(string.as_bytes());
+                }
+                Instruction::NewLine => {
+                    let _ = output.write_all(b"\n");
+                    let _ = output.flush();
+                }
+                Instruction::Phi { .. } => unreachable!("phis are evaluated on block entry"),
+            }
+        }
+    }
+
+    fn frame(
+        &self,
+        name: &str,
+        args: Vec<i32>,
+        result: Option<VReg>,
+    ) -> Result<Frame<'a>, IrError> {
+        let function = *self
+            .functions
+            .get(name)
+            .ok_or_else(|| IrError::UndefinedFunction(name.to_owned()))?;
+        if function.params.len() != args.len() {
+            return Err(IrError::ArgumentCount(
+                name.to_owned(),
+                function.params.len(),
+                args.len(),
+            ));
+        }
+        let mut registers = vec![0; function.vreg_count as usize];
+        for (param, arg) in function.params.iter().zip(args) {
+            registers[param.0 as usize] = arg;
+        }
+        Ok(Frame {
+            function,
+            block: 0,
+            index: 0,
+            registers,
+            result,
+        })
+    }
+}
+
+/// Transfers control to `target`, evaluating its phis in parallel
+fn enter(frame: &mut Frame, target: BlockId) {
+    let previous = BlockId(frame.block as u32);
+    let block = &frame.function.blocks[target.0 as usize];
+    let phis = block.instructions.iter().take_while(|i| i.is_phi());
+    let values = phis
+        .clone()
+        .map(|phi| match phi {
+            Instruction::Phi { dst, incoming } => {
+                let (_, operand) = incoming
+                    .iter()
+                    .find(|(block, _)| *block == previous)
+                    .expect("phi has an operand for every predecessor");
+                (*dst, value(&frame.registers, operand))
+            }
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    frame.index = values.len();
+    for (dst, value) in values {
+        frame.registers[dst.0 as usize] = value;
+    }
+    frame.block = target.0 as usize;
+}
+
+fn value(registers: &[i32], operand: &Operand) -> i32 {
+    match operand {
+        Operand::Const(value) => *value,
+        Operand::Reg(reg) => registers[reg.0 as usize],
+    }
+}
+
+/// Reads a whitespace-separated integer, or the next non-whitespace
+/// character, as the interpreter does
+fn read_value<R: BufRead>(input: &mut R, kind: IoKind) -> Result<i32, IrError> {
+    let mut word = String::new();
+    loop {
+        let (consumed, done) = match input.fill_buf() {
+            Ok([]) | Err(_) => (0, true),
+            Ok(buffer) => {
+                let mut consumed = 0;
+                let mut done = false;
+                for &byte in buffer {
+                    if byte.is_ascii_whitespace() {
+                        if !word.is_empty() {
+                            done = true;
+                            break;
+                        }
+                    } else {
+                        word.push(byte as char);
+                        if kind == IoKind::Char {
+                            consumed += 1;
+                            done = true;
+                            break;
+                        }
+                    }
+                    consumed += 1;
+                }
+                (consumed, done)
+            }
+        };
+        input.consume(consumed);
+        if done {
+            break;
+        }
+    }
+
+    match kind {
+        IoKind::Int => word.parse::<i32>().ok(),
+        IoKind::Char => word.chars().next().map(|c| c as i32),
+    }
+    .ok_or(IrError::InvalidInput(word))
+}
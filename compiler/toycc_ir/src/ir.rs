@@ -0,0 +1,405 @@
+use std::fmt::{Display, Formatter};
+
+/// A virtual register. Before SSA construction a register may be assigned
+/// more than once: each ToyC variable lowers to a single register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VReg(pub u32);
+
+/// Index of a block in [`Function::blocks`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Const(i32),
+    Reg(VReg),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    /// Logical negation: 1 for 0 and 0 otherwise
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Whether an I/O intrinsic reads or writes a number or a character
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IoKind {
+    Int,
+    Char,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Copy {
+        dst: VReg,
+        src: Operand,
+    },
+    Unary {
+        dst: VReg,
+        op: UnaryOp,
+        src: Operand,
+    },
+    Binary {
+        dst: VReg,
+        op: BinaryOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    LoadGlobal {
+        dst: VReg,
+        global: String,
+    },
+    StoreGlobal {
+        global: String,
+        src: Operand,
+    },
+    Call {
+        dst: VReg,
+        function: String,
+        args: Vec<Operand>,
+    },
+    Read {
+        dst: VReg,
+        kind: IoKind,
+    },
+    Write {
+        kind: IoKind,
+        src: Operand,
+    },
+    WriteStr(String),
+    NewLine,
+    /// Selects the operand of the predecessor control came from. Phis only
+    /// appear at the start of a block, and all of them read their operands
+    /// before any of them is written.
+    Phi {
+        dst: VReg,
+        incoming: Vec<(BlockId, Operand)>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Goes to the first block when the condition is not 0
+    Branch(Operand, BlockId, BlockId),
+    Return(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<VReg>,
+    /// The entry block is always the first one
+    pub blocks: Vec<Block>,
+    /// Number of virtual registers in use; registers are numbered from 0
+    pub vreg_count: u32,
+    /// Whether every register is defined exactly once
+    pub ssa: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Module {
+    pub globals: Vec<String>,
+    pub functions: Vec<Function>,
+}
+
+impl BinaryOp {
+    /// Applies the operator with 32-bit wrapping semantics; `None` for a
+    /// division or remainder by zero
+    pub fn evaluate(self, a: i32, b: i32) -> Option<i32> {
+        Some(match self {
+            Self::Add => a.wrapping_add(b),
+            Self::Sub => a.wrapping_sub(b),
+            Self::Mul => a.wrapping_mul(b),
+            Self::Div | Self::Rem if b == 0 => return None,
+            Self::Div => a.wrapping_div(b),
+            Self::Rem => a.wrapping_rem(b),
+            Self::Eq => (a == b) as i32,
+            Self::Ne => (a != b) as i32,
+            Self::Lt => (a < b) as i32,
+            Self::Le => (a <= b) as i32,
+            Self::Gt => (a > b) as i32,
+            Self::Ge => (a >= b) as i32,
+        })
+    }
+}
+
+impl UnaryOp {
+    pub fn evaluate(self, a: i32) -> i32 {
+        match self {
+            Self::Neg => a.wrapping_neg(),
+            Self::Not => (a == 0) as i32,
+        }
+    }
+}
+
+impl Instruction {
+    /// Register written by the instruction, if any
+    pub fn dst(&self) -> Option<VReg> {
+        match self {
+            Self::Copy { dst, .. }
+            | Self::Unary { dst, .. }
+            | Self::Binary { dst, .. }
+            | Self::LoadGlobal { dst, .. }
+            | Self::Call { dst, .. }
+            | Self::Read { dst, .. }
+            | Self::Phi { dst, .. } => Some(*dst),
+            Self::StoreGlobal { .. } | Self::Write { .. } | Self::WriteStr(_) | Self::NewLine => {
+                None
+            }
+        }
+    }
+
+    pub fn dst_mut(&mut self) -> Option<&mut VReg> {
+        match self {
+            Self::Copy { dst, .. }
+            | Self::Unary { dst, .. }
+            | Self::Binary { dst, .. }
+            | Self::LoadGlobal { dst, .. }
+            | Self::Call { dst, .. }
+            | Self::Read { dst, .. }
+            | Self::Phi { dst, .. } => Some(dst),
+            Self::StoreGlobal { .. } | Self::Write { .. } | Self::WriteStr(_) | Self::NewLine => {
+                None
+            }
+        }
+    }
+
+    /// Operands read by the instruction, in evaluation order
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Self::Copy { src, .. }
+            | Self::Unary { src, .. }
+            | Self::StoreGlobal { src, .. }
+            | Self::Write { src, .. } => vec![*src],
+            Self::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Self::Call { args, .. } => args.clone(),
+            Self::Phi { incoming, .. } => incoming.iter().map(|(_, operand)| *operand).collect(),
+            Self::LoadGlobal { .. } | Self::Read { .. } | Self::WriteStr(_) | Self::NewLine => {
+                vec![]
+            }
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Self::Copy { src, .. }
+            | Self::Unary { src, .. }
+            | Self::StoreGlobal { src, .. }
+            | Self::Write { src, .. } => vec![src],
+            Self::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Self::Call { args, .. } => args.iter_mut().collect(),
+            Self::Phi { incoming, .. } => incoming.iter_mut().map(|(_, operand)| operand).collect(),
+            Self::LoadGlobal { .. } | Self::Read { .. } | Self::WriteStr(_) | Self::NewLine => {
+                vec![]
+            }
+        }
+    }
+
+    pub fn is_phi(&self) -> bool {
+        matches!(self, Self::Phi { .. })
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Self::Jump(target) => vec![*target],
+            Self::Branch(_, then_block, else_block) => vec![*then_block, *else_block],
+            Self::Return(_) => vec![],
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Self::Jump(target) => vec![target],
+            Self::Branch(_, then_block, else_block) => vec![then_block, else_block],
+            Self::Return(_) => vec![],
+        }
+    }
+
+    pub fn operand_mut(&mut self) -> Option<&mut Operand> {
+        match self {
+            Self::Jump(_) => None,
+            Self::Branch(operand, ..) | Self::Return(operand) => Some(operand),
+        }
+    }
+}
+
+impl Function {
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0 as usize]
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> &mut Block {
+        &mut self.blocks[id.0 as usize]
+    }
+
+    pub fn new_vreg(&mut self) -> VReg {
+        self.vreg_count += 1;
+        VReg(self.vreg_count - 1)
+    }
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+}
+
+impl Display for VReg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Const(value) => write!(f, "{value}"),
+            Self::Reg(reg) => write!(f, "{reg}"),
+        }
+    }
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Neg => write!(f, "neg"),
+            Self::Not => write!(f, "not"),
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Rem => "rem",
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Lt => "lt",
+            Self::Le => "le",
+            Self::Gt => "gt",
+            Self::Ge => "ge",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl Display for IoKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int => write!(f, "int"),
+            Self::Char => write!(f, "char"),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Copy { dst, src } => write!(f, "{dst} = copy {src}"),
+            Self::Unary { dst, op, src } => write!(f, "{dst} = {op} {src}"),
+            Self::Binary { dst, op, lhs, rhs } => write!(f, "{dst} = {op} {lhs}, {rhs}"),
+            Self::LoadGlobal { dst, global } => write!(f, "{dst} = load @{global}"),
+            Self::StoreGlobal { global, src } => write!(f, "store @{global}, {src}"),
+            Self::Call {
+                dst,
+                function,
+                args,
+            } => {
+                let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                write!(f, "{dst} = call @{function}({})", args.join(", "))
+            }
+            Self::Read { dst, kind } => write!(f, "{dst} = read.{kind}"),
+            Self::Write { kind, src } => write!(f, "write.{kind} {src}"),
+            Self::WriteStr(string) => write!(f, "write.str {string:?}"),
+            Self::NewLine => write!(f, "newline"),
+            Self::Phi { dst, incoming } => {
+                let incoming = incoming
+                    .iter()
+                    .map(|(block, operand)| format!("[{block}: {operand}]"))
+                    .collect::<Vec<_>>();
+                write!(f, "{dst} = phi {}", incoming.join(", "))
+            }
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Jump(target) => write!(f, "jmp {target}"),
+            Self::Branch(condition, then_block, else_block) => {
+                write!(f, "br {condition}, {then_block}, {else_block}")
+            }
+            Self::Return(value) => write!(f, "ret {value}"),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>();
+        writeln!(f, "function @{}({}) {{", self.name, params.join(", "))?;
+        for (index, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(index as u32))?;
+            for instruction in &block.instructions {
+                writeln!(f, "    {instruction}")?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "global @{global}")?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{function}")?;
+        }
+        Ok(())
+    }
+}
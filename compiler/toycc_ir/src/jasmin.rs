@@ -0,0 +1,444 @@
+//! Jasmin code generation from the IR, so that the JVM target can compile
+//! the code the IR passes optimized
+use crate::ir::{BinaryOp, BlockId, Function, Instruction, IoKind, Module, Operand, Terminator};
+use crate::ir::{UnaryOp, VReg};
+use toycc_backend_jvm::peephole;
+use toycc_backend_jvm::semantic_analyzer::CLASS_INIT_HEADER;
+
+/// Generates a Jasmin class with the same layout as the one the semantic
+/// analyzer emits: a static method per function, `main` renamed to
+/// `toyc_main` behind a `main([Ljava/lang/String;)V` wrapper, and a static
+/// field per global. Registers live in local variable slots, parameters
+/// first, and block `bbN` starts at label `BN`.
+pub struct JasminGenerator<'a> {
+    class_name: &'a str,
+    source_name: &'a str,
+    peephole: bool,
+    peephole_counts: (usize, usize),
+}
+
+/// Code of the function being generated
+struct MethodBody<'a> {
+    class_name: &'a str,
+    function: &'a Function,
+    slots: Vec<usize>,
+    /// Number of reads of every register
+    uses: Vec<usize>,
+    lines: Vec<String>,
+    label_count: usize,
+}
+
+impl<'a> JasminGenerator<'a> {
+    pub fn new(class_name: &'a str, source_name: &'a str) -> Self {
+        Self {
+            class_name,
+            source_name,
+            peephole: false,
+            peephole_counts: (0, 0),
+        }
+    }
+
+    pub fn peephole(&mut self, peephole: bool) -> &mut Self {
+        self.peephole = peephole;
+        self
+    }
+
+    /// Instructions in the method bodies before and after peephole
+    /// optimization
+    pub fn peephole_counts(&self) -> (usize, usize) {
+        self.peephole_counts
+    }
+
+    /// Panics if the module is in SSA form
+    pub fn generate(&mut self, module: &Module) -> String {
+        assert!(
+            module.functions.iter().all(|function| !function.ssa),
+            "the module must be out of SSA form"
+        );
+        let mut jasmin = format!(
+            ".source {}\n.class public {}\n.super java/lang/Object\n",
+            self.source_name, self.class_name
+        );
+        for global in &module.globals {
+            jasmin += &format!(".field public static {global} I\n");
+        }
+        jasmin += CLASS_INIT_HEADER;
+        for function in &module.functions {
+            jasmin += &self.generate_function(function);
+        }
+        jasmin += "\n.method public static main([Ljava/lang/String;)V\n";
+        jasmin += &format!(
+            "\tinvokestatic {}/toyc_main()I\n\tpop\n\treturn\n.end method\n",
+            self.class_name
+        );
+        jasmin
+    }
+
+    fn generate_function(&mut self, function: &Function) -> String {
+        let mut body = MethodBody::new(self.class_name, function);
+        let locals = body.generate();
+        let mut lines = body.lines;
+        if self.peephole {
+            self.peephole_counts.0 += peephole::instruction_count(&lines);
+            peephole::optimize(&mut lines);
+            self.peephole_counts.1 += peephole::instruction_count(&lines);
+        }
+        let body = lines
+            .iter()
+            .map(|line| match line.ends_with(':') {
+                true => format!("{line}\n"),
+                false => format!("\t{line}\n"),
+            })
+            .collect::<String>();
+        let stack = function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .map(|instruction| match instruction {
+                Instruction::Call { args, .. } => args.len(),
+                _ => 0,
+            })
+            .fold(3, usize::max);
+        format!(
+            "\n.method public static {}({})I\n\t.limit stack {stack}\n\t.limit locals {locals}\n{body}.end method\n",
+            method_name(&function.name),
+            "I".repeat(function.params.len()),
+        )
+    }
+}
+
+impl<'a> MethodBody<'a> {
+    fn new(class_name: &'a str, function: &'a Function) -> Self {
+        let mut slots = vec![None; function.vreg_count as usize];
+        for (slot, param) in function.params.iter().enumerate() {
+            slots[param.0 as usize] = Some(slot);
+        }
+        let mut next = function.params.len();
+        let slots = slots
+            .into_iter()
+            .map(|slot| {
+                slot.unwrap_or_else(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect();
+
+        let mut uses = vec![0; function.vreg_count as usize];
+        for block in &function.blocks {
+            let terminator = match block.terminator {
+                Terminator::Jump(_) => None,
+                Terminator::Branch(operand, ..) | Terminator::Return(operand) => Some(operand),
+            };
+            let operands = block
+                .instructions
+                .iter()
+                .flat_map(|instruction| instruction.operands())
+                .chain(terminator);
+            for operand in operands {
+                if let Operand::Reg(reg) = operand {
+                    uses[reg.0 as usize] += 1;
+                }
+            }
+        }
+
+        Self {
+            class_name,
+            function,
+            slots,
+            uses,
+            lines: vec![],
+            label_count: 0,
+        }
+    }
+
+    /// Generates the body and returns the number of local variable slots it
+    /// uses. A function that reads input keeps its `Scanner` in the slot
+    /// after the registers.
+    fn generate(&mut self) -> usize {
+        let function = self.function;
+        let mut locals = self.slots.len();
+        let reads = function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .any(|instruction| matches!(instruction, Instruction::Read { .. }));
+        if reads {
+            self.emit("new java/util/Scanner");
+            self.emit("dup");
+            self.emit("getstatic java/lang/System/in Ljava/io/InputStream;");
+            self.emit("invokespecial java/util/Scanner/<init>(Ljava/io/InputStream;)V");
+            self.emit(format!("astore {locals}"));
+            locals += 1;
+        }
+
+        for (index, block) in function.blocks.iter().enumerate() {
+            self.lines.push(format!("B{index}:"));
+            let next_block = BlockId(index as u32 + 1);
+            // A comparison only read by the branch that ends the block jumps
+            // to its target directly instead of being materialized
+            let fused = match (block.instructions.split_last(), &block.terminator) {
+                (Some((instruction, rest)), Terminator::Branch(Operand::Reg(condition), ..))
+                    if instruction.dst() == Some(*condition)
+                        && self.uses[condition.0 as usize] == 1
+                        && is_condition(instruction) =>
+                {
+                    for instruction in rest {
+                        self.instruction(instruction);
+                    }
+                    Some(self.condition(instruction))
+                }
+                _ => {
+                    for instruction in &block.instructions {
+                        self.instruction(instruction);
+                    }
+                    None
+                }
+            };
+
+            match &block.terminator {
+                Terminator::Jump(target) if *target == next_block => {}
+                Terminator::Jump(target) => self.emit(format!("goto B{}", target.0)),
+                Terminator::Branch(condition, then_block, else_block) => {
+                    let (jump, inverse) = fused.unwrap_or_else(|| {
+                        self.push(*condition);
+                        ("ifne".to_string(), "ifeq".to_string())
+                    });
+                    match *then_block == next_block {
+                        true => self.emit(format!("{inverse} B{}", else_block.0)),
+                        false => {
+                            self.emit(format!("{jump} B{}", then_block.0));
+                            if *else_block != next_block {
+                                self.emit(format!("goto B{}", else_block.0));
+                            }
+                        }
+                    }
+                }
+                Terminator::Return(value) => {
+                    self.push(*value);
+                    self.emit("ireturn");
+                }
+            }
+        }
+        locals
+    }
+
+    /// Pushes the operands of a comparison or a logical negation and returns
+    /// the jumps taken when it is true and when it is false
+    fn condition(&mut self, instruction: &Instruction) -> (String, String) {
+        match instruction {
+            Instruction::Binary { op, lhs, rhs, .. } => {
+                let (jump, inverse) = comparison(*op).unwrap();
+                self.push(*lhs);
+                self.push(*rhs);
+                (format!("if_icmp{jump}"), format!("if_icmp{inverse}"))
+            }
+            Instruction::Unary { src, .. } => {
+                self.push(*src);
+                ("ifeq".to_string(), "ifne".to_string())
+            }
+            _ => unreachable!("not a condition"),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Copy { src, .. } => self.push(*src),
+            Instruction::Unary { op, src, .. } => {
+                self.push(*src);
+                match op {
+                    UnaryOp::Neg => self.emit("ineg"),
+                    UnaryOp::Not => self.materialize("ifeq".to_string()),
+                }
+            }
+            Instruction::Binary { op, lhs, rhs, .. } => {
+                self.push(*lhs);
+                self.push(*rhs);
+                match op {
+                    BinaryOp::Add => self.emit("iadd"),
+                    BinaryOp::Sub => self.emit("isub"),
+                    BinaryOp::Mul => self.emit("imul"),
+                    BinaryOp::Div => self.emit("idiv"),
+                    BinaryOp::Rem => self.emit("irem"),
+                    op => {
+                        let (jump, _) = comparison(*op).unwrap();
+                        self.materialize(format!("if_icmp{jump}"));
+                    }
+                }
+            }
+            Instruction::LoadGlobal { global, .. } => {
+                self.emit(format!("getstatic {}/{global} I", self.class_name));
+            }
+            Instruction::StoreGlobal { global, src } => {
+                self.push(*src);
+                self.emit(format!("putstatic {}/{global} I", self.class_name));
+            }
+            Instruction::Call { function, args, .. } => {
+                for arg in args {
+                    self.push(*arg);
+                }
+                self.emit(format!(
+                    "invokestatic {}/{}({})I",
+                    self.class_name,
+                    method_name(function),
+                    "I".repeat(args.len())
+                ));
+            }
+            Instruction::Read { kind, .. } => {
+                self.emit(format!("aload {}", self.slots.len()));
+                self.emit(match kind {
+                    IoKind::Int => "invokevirtual java/util/Scanner/nextInt()I",
+                    IoKind::Char => "invokevirtual java/util/Scanner/nextChar()C",
+                });
+            }
+            Instruction::Write { kind, src } => {
+                self.emit("getstatic java/lang/System/out Ljava/io/PrintStream;");
+                self.push(*src);
+                self.emit(match kind {
+                    IoKind::Int => "invokevirtual java/io/PrintStream/print(I)V",
+                    IoKind::Char => "invokevirtual java/io/PrintStream/print(C)V",
+                });
+            }
+            Instruction::WriteStr(string) => {
+                self.emit("getstatic java/lang/System/out Ljava/io/PrintStream;");
+                self.emit(format!("ldc \"{}\"", escape(string)));
+                self.emit("invokevirtual java/io/PrintStream/print(Ljava/lang/String;)V");
+            }
+            Instruction::NewLine => {
+                self.emit("getstatic java/lang/System/out Ljava/io/PrintStream;");
+                self.emit("invokevirtual java/io/PrintStream/println()V");
+            }
+            Instruction::Phi { .. } => unreachable!("phi outside of SSA form"),
+        }
+        if let Some(dst) = instruction.dst() {
+            self.emit(format!("istore {}", self.slot(dst)));
+        }
+    }
+
+    /// Turns the jump into the 1 or 0 it would take or not
+    fn materialize(&mut self, jump: String) {
+        let count = self.label_count;
+        self.label_count += 1;
+        self.emit(format!("{jump} T{count}"));
+        self.emit("iconst_0");
+        self.emit(format!("goto E{count}"));
+        self.lines.push(format!("T{count}:"));
+        self.emit("iconst_1");
+        self.lines.push(format!("E{count}:"));
+    }
+
+    fn push(&mut self, operand: Operand) {
+        match operand {
+            Operand::Const(value) => self.emit(push_constant(value)),
+            Operand::Reg(reg) => self.emit(format!("iload {}", self.slot(reg))),
+        }
+    }
+
+    fn slot(&self, reg: VReg) -> usize {
+        self.slots[reg.0 as usize]
+    }
+
+    fn emit(&mut self, line: impl Into<String>) {
+        self.lines.push(line.into());
+    }
+}
+
+fn is_condition(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Binary { op, .. } => comparison(*op).is_some(),
+        Instruction::Unary { op, .. } => *op == UnaryOp::Not,
+        _ => false,
+    }
+}
+
+fn method_name(function: &str) -> &str {
+    match function {
+        "main" => "toyc_main",
+        name => name,
+    }
+}
+
+/// Condition suffixes of a comparison and of its negation
+fn comparison(op: BinaryOp) -> Option<(&'static str, &'static str)> {
+    Some(match op {
+        BinaryOp::Eq => ("eq", "ne"),
+        BinaryOp::Ne => ("ne", "eq"),
+        BinaryOp::Lt => ("lt", "ge"),
+        BinaryOp::Le => ("le", "gt"),
+        BinaryOp::Gt => ("gt", "le"),
+        BinaryOp::Ge => ("ge", "lt"),
+        _ => return None,
+    })
+}
+
+fn push_constant(value: i32) -> String {
+    match value {
+        -1 => "iconst_m1".to_owned(),
+        0..=5 => format!("iconst_{value}"),
+        -128..=127 => format!("bipush {value}"),
+        -32768..=32767 => format!("sipush {value}"),
+        _ => format!("ldc {value}"),
+    }
+}
+
+/// Escapes a string for a double-quoted `ldc` operand
+fn escape(string: &str) -> String {
+    string
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::IrBuilder;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn generate(source: &str) -> String {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        let module = IrBuilder::new().build(&program).expect("failed to build");
+        JasminGenerator::new("Test", "test.tc").generate(&module)
+    }
+
+    #[test]
+    fn test_generate() {
+        let jasmin = generate(
+            "int g; int add(int a, int b){ return a + b; }
+int main(){ g = add(1, 2); if (g < 4) write(\"a\\tb\\n\"); return g; }",
+        );
+        assert!(jasmin.starts_with(".source test.tc\n.class public Test\n"));
+        assert!(jasmin.contains(".field public static g I\n"), "{jasmin}");
+        assert!(
+            jasmin.contains(".method public static add(II)I\n"),
+            "{jasmin}"
+        );
+        assert!(
+            jasmin.contains(".method public static toyc_main()I\n"),
+            "{jasmin}"
+        );
+        assert!(
+            jasmin.contains("\tinvokestatic Test/add(II)I\n"),
+            "{jasmin}"
+        );
+        assert!(jasmin.contains("\tputstatic Test/g I\n"), "{jasmin}");
+        assert!(jasmin.contains("\tldc \"a\\tb\\n\"\n"), "{jasmin}");
+        assert!(
+            jasmin.contains("\tinvokestatic Test/toyc_main()I\n"),
+            "{jasmin}"
+        );
+    }
+
+    #[test]
+    fn test_fused_comparison() {
+        let jasmin = generate("int main(){ int a; read(a); while (a > 0) a = a - 1; return a; }");
+        assert!(jasmin.contains("\tif_icmp"), "{jasmin}");
+        assert!(!jasmin.contains("iconst_0\n\tgoto E"), "{jasmin}");
+        assert!(jasmin.contains("\tastore "), "{jasmin}");
+    }
+}
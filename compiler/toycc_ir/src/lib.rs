@@ -0,0 +1,26 @@
+//! A target-independent three-address-code IR. Every value is a 32-bit
+//! integer held in a virtual register; functions are lists of basic blocks
+//! ending in an explicit jump, branch or return, and I/O is expressed with
+//! intrinsic instructions instead of runtime calls.
+mod builder;
+pub mod cfg;
+pub mod dot;
+mod error;
+mod evaluator;
+mod ir;
+pub mod jasmin;
+pub mod loops;
+mod pass;
+pub mod ssa;
+pub mod tail_call;
+mod verify;
+
+pub use builder::IrBuilder;
+pub use error::IrError;
+pub use evaluator::{Evaluator, MAX_FRAMES};
+pub use ir::{
+    BinaryOp, Block, BlockId, Function, Instruction, IoKind, Module, Operand, Terminator, UnaryOp,
+    VReg,
+};
+pub use pass::{Pass, PassManager};
+pub use verify::verify;
@@ -0,0 +1,477 @@
+//! Loop optimizations on functions in SSA form. Both passes leave functions
+//! outside SSA form alone, and give every loop a preheader first: a block
+//! outside the loop that is the only predecessor of the header from outside
+//! and has no other successor, where hoisted code can go.
+use crate::cfg::{natural_loops, predecessors, reverse_postorder, DominatorTree, Loop};
+use crate::ir::{
+    BinaryOp, Block, BlockId, Function, Instruction, Module, Operand, Terminator, VReg,
+};
+use crate::pass::Pass;
+use std::collections::{HashMap, HashSet};
+
+/// Moves the instructions of a loop whose operands do not change while it
+/// runs to its preheader, innermost loops first, so that an expression can
+/// leave several loops. Only instructions without side effects move, and a
+/// division only when it cannot trap, as the loop may run zero times.
+pub fn hoist_invariants(function: &mut Function) {
+    if !function.ssa {
+        return;
+    }
+    insert_preheaders(function);
+    let predecessors = predecessors(function);
+    let order = reverse_postorder(function);
+    let tree = DominatorTree::new(function);
+    for l in natural_loops(function, &tree) {
+        let Some(preheader) = preheader(&predecessors, &l) else {
+            continue;
+        };
+        let blocks = order.iter().filter(|&&b| l.contains(b)).copied();
+        let blocks = blocks.collect::<Vec<_>>();
+        let mut defined = blocks
+            .iter()
+            .flat_map(|&b| &function.block(b).instructions)
+            .filter_map(Instruction::dst)
+            .collect::<HashSet<_>>();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &blocks {
+                let mut index = 0;
+                while index < function.block(block).instructions.len() {
+                    let instruction = &function.block(block).instructions[index];
+                    if !is_invariant(instruction, &defined) {
+                        index += 1;
+                        continue;
+                    }
+                    let instruction = function.block_mut(block).instructions.remove(index);
+                    defined.remove(&instruction.dst().unwrap());
+                    function.block_mut(preheader).instructions.push(instruction);
+                    changed = true;
+                }
+            }
+        }
+    }
+}
+
+fn is_invariant(instruction: &Instruction, defined: &HashSet<VReg>) -> bool {
+    let pure = match instruction {
+        Instruction::Copy { .. } | Instruction::Unary { .. } => true,
+        Instruction::Binary {
+            op: BinaryOp::Div | BinaryOp::Rem,
+            rhs,
+            ..
+        } => matches!(rhs, Operand::Const(value) if *value != 0),
+        Instruction::Binary { .. } => true,
+        _ => false,
+    };
+    pure && instruction.operands().iter().all(|operand| match operand {
+        Operand::Reg(reg) => !defined.contains(reg),
+        Operand::Const(_) => true,
+    })
+}
+
+/// Replaces every multiplication of a basic induction variable by a
+/// constant inside a loop with a register of its own that is increased
+/// along with the variable. A basic induction variable is a phi of the
+/// header that every back edge increases by the same constant.
+pub fn reduce_strength(function: &mut Function) {
+    if !function.ssa {
+        return;
+    }
+    insert_preheaders(function);
+    let predecessors = predecessors(function);
+    let tree = DominatorTree::new(function);
+    for l in natural_loops(function, &tree) {
+        let Some(preheader) = preheader(&predecessors, &l) else {
+            continue;
+        };
+        let copies = copies(function);
+        let variables = induction_variables(function, &l, preheader, &copies);
+        let mut multiplications = vec![];
+        for &block in &l.blocks {
+            for instruction in &function.block(block).instructions {
+                let &Instruction::Binary {
+                    dst,
+                    op: BinaryOp::Mul,
+                    lhs,
+                    rhs,
+                } = instruction
+                else {
+                    continue;
+                };
+                let (variable, factor) = match (resolve(&copies, lhs), resolve(&copies, rhs)) {
+                    (Operand::Reg(reg), Operand::Const(factor))
+                    | (Operand::Const(factor), Operand::Reg(reg)) => (reg, factor),
+                    _ => continue,
+                };
+                if let Some(index) = variables.iter().position(|v| v.phi == variable) {
+                    multiplications.push((block, dst, index, factor));
+                }
+            }
+        }
+
+        // Accumulators holding `variable * factor`, by variable and factor
+        let mut accumulators: HashMap<(usize, i32), VReg> = HashMap::new();
+        for (block, dst, variable, factor) in multiplications {
+            let accumulator = *accumulators.entry((variable, factor)).or_insert_with(|| {
+                add_accumulator(function, &l, preheader, &variables[variable], factor)
+            });
+            let instructions = &mut function.block_mut(block).instructions;
+            let index = instructions.iter().position(|i| i.dst() == Some(dst));
+            instructions[index.unwrap()] = Instruction::Copy {
+                dst,
+                src: Operand::Reg(accumulator),
+            };
+        }
+    }
+}
+
+/// A phi of a loop header that starts at `initial` and is increased by
+/// `step` in the instruction defining `next`, which every back edge passes
+struct InductionVariable {
+    phi: VReg,
+    initial: Operand,
+    step: i32,
+    next: VReg,
+}
+
+fn induction_variables(
+    function: &Function,
+    l: &Loop,
+    preheader: BlockId,
+    copies: &HashMap<VReg, Operand>,
+) -> Vec<InductionVariable> {
+    let definitions = l
+        .blocks
+        .iter()
+        .flat_map(|&b| &function.block(b).instructions)
+        .filter_map(|i| Some((i.dst()?, i)))
+        .collect::<HashMap<_, _>>();
+    let header = &function.block(l.header).instructions;
+    let phis = header.iter().take_while(|i| i.is_phi());
+    phis.filter_map(|phi| {
+        let Instruction::Phi { dst, incoming } = phi else {
+            unreachable!()
+        };
+        let (_, initial) = incoming.iter().find(|(b, _)| *b == preheader)?;
+        let mut latches = incoming.iter().filter(|(b, _)| *b != preheader);
+        let Operand::Reg(next) = resolve(copies, latches.next()?.1) else {
+            return None;
+        };
+        if !latches.all(|(_, operand)| resolve(copies, *operand) == Operand::Reg(next)) {
+            return None;
+        }
+        let step = match definitions.get(&next)? {
+            Instruction::Binary { op, lhs, rhs, .. } => {
+                match (op, resolve(copies, *lhs), resolve(copies, *rhs)) {
+                    (BinaryOp::Add, Operand::Reg(reg), Operand::Const(step))
+                    | (BinaryOp::Add, Operand::Const(step), Operand::Reg(reg))
+                        if reg == *dst =>
+                    {
+                        step
+                    }
+                    (BinaryOp::Sub, Operand::Reg(reg), Operand::Const(step)) if reg == *dst => {
+                        step.wrapping_neg()
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some(InductionVariable {
+            phi: *dst,
+            initial: resolve(copies, *initial),
+            step,
+            next,
+        })
+    })
+    .collect()
+}
+
+/// Adds a phi to the header holding `variable * factor`: it starts at the
+/// initial value times the factor, computed in the preheader, and grows by
+/// the step times the factor right after the variable is increased
+fn add_accumulator(
+    function: &mut Function,
+    l: &Loop,
+    preheader: BlockId,
+    variable: &InductionVariable,
+    factor: i32,
+) -> VReg {
+    let initial = match variable.initial {
+        Operand::Const(value) => Operand::Const(value.wrapping_mul(factor)),
+        Operand::Reg(reg) => {
+            let dst = function.new_vreg();
+            function
+                .block_mut(preheader)
+                .instructions
+                .push(Instruction::Binary {
+                    dst,
+                    op: BinaryOp::Mul,
+                    lhs: Operand::Reg(reg),
+                    rhs: Operand::Const(factor),
+                });
+            Operand::Reg(dst)
+        }
+    };
+    let accumulator = function.new_vreg();
+    let next = function.new_vreg();
+    let incoming = std::iter::once((preheader, initial))
+        .chain(l.latches.iter().map(|&latch| (latch, Operand::Reg(next))))
+        .collect();
+    function.block_mut(l.header).instructions.insert(
+        0,
+        Instruction::Phi {
+            dst: accumulator,
+            incoming,
+        },
+    );
+    let increment = Instruction::Binary {
+        dst: next,
+        op: BinaryOp::Add,
+        lhs: Operand::Reg(accumulator),
+        rhs: Operand::Const(variable.step.wrapping_mul(factor)),
+    };
+    for &block in &l.blocks {
+        let instructions = &mut function.block_mut(block).instructions;
+        if let Some(index) = instructions
+            .iter()
+            .position(|i| i.dst() == Some(variable.next))
+        {
+            instructions.insert(index + 1, increment);
+            break;
+        }
+    }
+    accumulator
+}
+
+/// Source of every copy of a function
+fn copies(function: &Function) -> HashMap<VReg, Operand> {
+    let instructions = function.blocks.iter().flat_map(|b| &b.instructions);
+    instructions
+        .filter_map(|instruction| match instruction {
+            Instruction::Copy { dst, src } => Some((*dst, *src)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The value a register copies, following chains of copies. In SSA form a
+/// copy always holds the value its source has where the copy is used.
+fn resolve(copies: &HashMap<VReg, Operand>, mut operand: Operand) -> Operand {
+    while let Operand::Reg(reg) = operand {
+        match copies.get(&reg) {
+            Some(&src) => operand = src,
+            None => break,
+        }
+    }
+    operand
+}
+
+/// Preheader of a loop, if it has one
+fn preheader(predecessors: &[Vec<BlockId>], l: &Loop) -> Option<BlockId> {
+    match outside_predecessors(predecessors, l).as_slice() {
+        &[block] => Some(block),
+        _ => None,
+    }
+}
+
+fn outside_predecessors(predecessors: &[Vec<BlockId>], l: &Loop) -> Vec<BlockId> {
+    let predecessors = predecessors[l.header.0 as usize].iter();
+    predecessors.filter(|&&b| !l.contains(b)).copied().collect()
+}
+
+/// Adds a preheader to every loop that lacks one, except a loop headed by
+/// the entry block. When the header is entered from several blocks, its
+/// phis take the value from the preheader, which merges them with phis of
+/// its own.
+fn insert_preheaders(function: &mut Function) {
+    loop {
+        let predecessors = predecessors(function);
+        let tree = DominatorTree::new(function);
+        let missing = natural_loops(function, &tree).into_iter().find_map(|l| {
+            let outside = outside_predecessors(&predecessors, &l);
+            match outside.as_slice() {
+                [] => None,
+                &[block] if function.block(block).terminator.successors() == [l.header] => None,
+                _ => Some((l.header, outside)),
+            }
+        });
+        let Some((header, outside)) = missing else {
+            return;
+        };
+
+        let preheader = BlockId(function.blocks.len() as u32);
+        let mut phis = vec![];
+        let phi_count = function
+            .block(header)
+            .instructions
+            .iter()
+            .take_while(|i| i.is_phi())
+            .count();
+        for index in 0..phi_count {
+            let dst = function.new_vreg();
+            let Instruction::Phi { incoming, .. } =
+                &mut function.block_mut(header).instructions[index]
+            else {
+                unreachable!()
+            };
+            let (entering, staying) = incoming.drain(..).partition(|(b, _)| outside.contains(b));
+            *incoming = staying;
+            match entering.as_slice() {
+                &[(_, operand)] => incoming.push((preheader, operand)),
+                _ => {
+                    incoming.push((preheader, Operand::Reg(dst)));
+                    phis.push(Instruction::Phi {
+                        dst,
+                        incoming: entering,
+                    });
+                }
+            }
+        }
+        function.blocks.push(Block {
+            instructions: phis,
+            terminator: Terminator::Jump(header),
+        });
+        for block in outside {
+            for target in function.block_mut(block).terminator.successors_mut() {
+                if *target == header {
+                    *target = preheader;
+                }
+            }
+        }
+    }
+}
+
+/// Number of instructions inside loops, counting an instruction once per
+/// loop it belongs to
+pub fn instructions_in_loops(module: &Module) -> usize {
+    count_in_loops(module, |_| true)
+}
+
+/// Number of multiplications inside loops, counted like
+/// [`instructions_in_loops`]
+pub fn multiplications_in_loops(module: &Module) -> usize {
+    count_in_loops(module, |instruction| {
+        matches!(
+            instruction,
+            Instruction::Binary {
+                op: BinaryOp::Mul,
+                ..
+            }
+        )
+    })
+}
+
+fn count_in_loops(module: &Module, counted: impl Fn(&Instruction) -> bool) -> usize {
+    let mut count = 0;
+    for function in &module.functions {
+        let tree = DominatorTree::new(function);
+        for l in natural_loops(function, &tree) {
+            let blocks = l.blocks.iter().map(|&b| function.block(b));
+            let instructions = blocks.flat_map(|b| &b.instructions);
+            count += instructions.filter(|i| counted(i)).count();
+        }
+    }
+    count
+}
+
+/// Loop-invariant code motion
+pub struct LoopInvariantCodeMotion;
+
+impl Pass for LoopInvariantCodeMotion {
+    fn name(&self) -> &'static str {
+        "licm"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        module.functions.iter_mut().for_each(hoist_invariants);
+    }
+}
+
+/// Strength reduction of multiplied induction variables
+pub struct StrengthReduction;
+
+impl Pass for StrengthReduction {
+    fn name(&self) -> &'static str {
+        "strength"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        module.functions.iter_mut().for_each(reduce_strength);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ssa::construct_ssa;
+    use crate::IrBuilder;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn build(source: &str) -> Module {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        let mut module = IrBuilder::new().build(&program).expect("failed to build");
+        module.functions.iter_mut().for_each(construct_ssa);
+        module
+    }
+
+    #[test]
+    fn test_hoisting() {
+        let mut module = build(
+            "int f(int a, int b){ int i; int s;
+             while (i < 10) { s = s + (a * b) + (a / 2) + (a / b); i = i + 1; }
+             return s; }",
+        );
+        let before = instructions_in_loops(&module);
+        LoopInvariantCodeMotion.run(&mut module);
+        crate::verify(&module).unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+        let f = &module.functions[0];
+        let tree = DominatorTree::new(f);
+        let l = &natural_loops(f, &tree)[0];
+        let binary = |op| {
+            let blocks = l.blocks.iter().map(|&b| f.block(b));
+            let mut instructions = blocks.flat_map(|b| &b.instructions);
+            instructions.any(|i| matches!(i, Instruction::Binary { op: o, .. } if *o == op))
+        };
+        assert!(!binary(BinaryOp::Mul), "{f}");
+        assert!(binary(BinaryOp::Add));
+        // `a / 2` leaves the loop, while `a / b` may divide by zero
+        let divisions = l
+            .blocks
+            .iter()
+            .flat_map(|&b| &f.block(b).instructions)
+            .filter(|i| {
+                matches!(
+                    i,
+                    Instruction::Binary {
+                        op: BinaryOp::Div,
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(divisions, 1, "{f}");
+        assert!(instructions_in_loops(&module) < before);
+    }
+
+    #[test]
+    fn test_strength_reduction() {
+        let mut module = build(
+            "int f(int n){ int i; int s; i = n;
+             while (i > 0) { s = s + (i * 4); i = i - 1; }
+             return s; }",
+        );
+        assert_eq!(multiplications_in_loops(&module), 1);
+        StrengthReduction.run(&mut module);
+        crate::verify(&module).unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+        assert_eq!(multiplications_in_loops(&module), 0, "{module}");
+        // The accumulator starts at `n * 4` and goes down by 4
+        let f = module.functions[0].to_string();
+        assert!(f.contains(", 4\n") && f.contains(", -4\n"), "{f}");
+    }
+}
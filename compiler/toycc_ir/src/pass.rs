@@ -0,0 +1,57 @@
+use crate::error::IrError;
+use crate::ir::Module;
+use crate::verify::verify;
+
+/// A transformation of a whole module
+pub trait Pass {
+    /// Short name used in diagnostics
+    fn name(&self) -> &'static str;
+    fn run(&mut self, module: &mut Module);
+}
+
+/// Runs passes in the order they were added. In debug builds the module is
+/// verified before the first pass and after every pass, so that a broken
+/// transformation is reported by name instead of miscompiling silently.
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    verify: bool,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self {
+            passes: vec![],
+            verify: cfg!(debug_assertions),
+        }
+    }
+
+    pub fn add<P: Pass + 'static>(&mut self, pass: P) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Turns verification between passes on or off
+    pub fn verify(&mut self, verify: bool) -> &mut Self {
+        self.verify = verify;
+        self
+    }
+
+    pub fn run(&mut self, module: &mut Module) -> Result<(), IrError> {
+        if self.verify {
+            verify(module).map_err(|e| e.after("lowering"))?;
+        }
+        for pass in &mut self.passes {
+            pass.run(module);
+            if self.verify {
+                verify(module).map_err(|e| e.after(pass.name()))?;
+            }
+        }
+        Ok(())
+    }
+}
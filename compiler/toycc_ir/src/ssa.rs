@@ -0,0 +1,384 @@
+use crate::cfg::{predecessors, remove_unreachable_blocks, DominatorTree};
+use crate::ir::{Block, BlockId, Function, Instruction, Module, Operand, Terminator, VReg};
+use crate::pass::Pass;
+use std::collections::HashMap;
+
+/// Rewrites a function into SSA form following Cytron et al. Phis are only
+/// placed for registers that are live across blocks, and a register read on
+/// a path where it was never written reads the constant 0, the value every
+/// register starts with. Unreachable blocks are removed first.
+pub fn construct_ssa(function: &mut Function) {
+    if function.ssa {
+        return;
+    }
+    remove_unreachable_blocks(function);
+    let tree = DominatorTree::new(function);
+    let frontiers = tree.frontiers(function);
+    let registers = function.vreg_count as usize;
+
+    let mut definitions = vec![vec![]; registers];
+    let mut crosses_blocks = vec![false; registers];
+    for param in &function.params {
+        definitions[param.0 as usize].push(BlockId(0));
+    }
+    for (index, block) in function.blocks.iter().enumerate() {
+        let mut defined = vec![false; registers];
+        let uses = block
+            .instructions
+            .iter()
+            .map(|i| (i.operands(), i.dst()))
+            .chain(std::iter::once((
+                terminator_operand(&block.terminator),
+                None,
+            )));
+        for (operands, dst) in uses {
+            for operand in operands {
+                if let Operand::Reg(reg) = operand {
+                    crosses_blocks[reg.0 as usize] |= !defined[reg.0 as usize];
+                }
+            }
+            if let Some(dst) = dst {
+                defined[dst.0 as usize] = true;
+                let blocks: &mut Vec<BlockId> = &mut definitions[dst.0 as usize];
+                if !blocks.contains(&BlockId(index as u32)) {
+                    blocks.push(BlockId(index as u32));
+                }
+            }
+        }
+    }
+
+    // Variables that receive a phi at the start of each block
+    let mut phis = vec![vec![]; function.blocks.len()];
+    for (reg, blocks) in definitions.iter().enumerate() {
+        if !crosses_blocks[reg] {
+            continue;
+        }
+        let mut has_phi = vec![false; function.blocks.len()];
+        let mut worklist = blocks.clone();
+        while let Some(block) = worklist.pop() {
+            for &frontier in &frontiers[block.0 as usize] {
+                if !has_phi[frontier.0 as usize] {
+                    has_phi[frontier.0 as usize] = true;
+                    phis[frontier.0 as usize].push(VReg(reg as u32));
+                    if !blocks.contains(&frontier) {
+                        worklist.push(frontier);
+                    }
+                }
+            }
+        }
+    }
+    for (block, variables) in function.blocks.iter_mut().zip(&phis) {
+        let new_phis = variables.iter().map(|&dst| Instruction::Phi {
+            dst,
+            incoming: vec![],
+        });
+        block.instructions.splice(0..0, new_phis);
+    }
+
+    rename(function, &tree, &phis);
+    function.ssa = true;
+    compact_registers(function);
+}
+
+enum Visit {
+    Enter(BlockId),
+    /// Pops the names pushed while visiting a block
+    Exit(Vec<VReg>),
+}
+
+fn rename(function: &mut Function, tree: &DominatorTree, phis: &[Vec<VReg>]) {
+    let mut stacks: Vec<Vec<Operand>> = vec![vec![]; function.vreg_count as usize];
+    for param in &function.params {
+        stacks[param.0 as usize].push(Operand::Reg(*param));
+    }
+    let current = |stacks: &[Vec<Operand>], reg: VReg| {
+        stacks[reg.0 as usize]
+            .last()
+            .copied()
+            .unwrap_or(Operand::Const(0))
+    };
+
+    let mut visits = vec![Visit::Enter(BlockId(0))];
+    while let Some(visit) = visits.pop() {
+        let id = match visit {
+            Visit::Enter(id) => id,
+            Visit::Exit(pushed) => {
+                for reg in pushed {
+                    stacks[reg.0 as usize].pop();
+                }
+                continue;
+            }
+        };
+
+        let mut pushed = vec![];
+        let mut vreg_count = function.vreg_count;
+        let block = function.block_mut(id);
+        for instruction in &mut block.instructions {
+            if !instruction.is_phi() {
+                for operand in instruction.operands_mut() {
+                    if let Operand::Reg(reg) = *operand {
+                        *operand = current(&stacks, reg);
+                    }
+                }
+            }
+            if let Some(dst) = instruction.dst_mut() {
+                let name = VReg(vreg_count);
+                vreg_count += 1;
+                stacks[dst.0 as usize].push(Operand::Reg(name));
+                pushed.push(*dst);
+                *dst = name;
+            }
+        }
+        if let Some(Operand::Reg(reg)) = block.terminator.operand_mut() {
+            let renamed = current(&stacks, *reg);
+            *block.terminator.operand_mut().unwrap() = renamed;
+        }
+        function.vreg_count = vreg_count;
+
+        let mut successors = function.block(id).terminator.successors();
+        successors.dedup();
+        for successor in successors {
+            let block = function.block_mut(successor);
+            for (phi, variable) in block
+                .instructions
+                .iter_mut()
+                .zip(&phis[successor.0 as usize])
+            {
+                if let Instruction::Phi { incoming, .. } = phi {
+                    incoming.push((id, current(&stacks, *variable)));
+                }
+            }
+        }
+
+        visits.push(Visit::Exit(pushed));
+        for &child in tree.children(id).iter().rev() {
+            visits.push(Visit::Enter(child));
+        }
+    }
+}
+
+/// Replaces the phis of a function in SSA form with copies at the end of
+/// its predecessors. Critical edges are split first so that the copies only
+/// run on the edge they belong to, and the copies for one edge go through
+/// temporaries when a phi reads the result of another phi of the same block.
+pub fn destruct_ssa(function: &mut Function) {
+    if !function.ssa {
+        return;
+    }
+    let predecessors = predecessors(function);
+    for (index, predecessors) in predecessors.iter().enumerate() {
+        let id = BlockId(index as u32);
+        let phi_count = function.blocks[index]
+            .instructions
+            .iter()
+            .take_while(|i| i.is_phi())
+            .count();
+        if phi_count == 0 {
+            continue;
+        }
+        let phis = function.blocks[index]
+            .instructions
+            .drain(..phi_count)
+            .map(|phi| match phi {
+                Instruction::Phi { dst, incoming } => (dst, incoming),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        for &predecessor in predecessors {
+            let copies = phis
+                .iter()
+                .map(|(dst, incoming)| {
+                    let (_, src) = incoming
+                        .iter()
+                        .find(|(block, _)| *block == predecessor)
+                        .expect("phi has an operand for every predecessor");
+                    (*dst, *src)
+                })
+                .collect::<Vec<_>>();
+            let instructions = sequentialize(function, copies);
+
+            let mut successors = function.block(predecessor).terminator.successors();
+            successors.dedup();
+            if successors.len() == 1 {
+                let block = function.block_mut(predecessor);
+                block.instructions.extend(instructions);
+            } else {
+                let edge = BlockId(function.blocks.len() as u32);
+                function.blocks.push(Block {
+                    instructions,
+                    terminator: Terminator::Jump(id),
+                });
+                let terminator = &mut function.block_mut(predecessor).terminator;
+                for target in terminator.successors_mut() {
+                    if *target == id {
+                        *target = edge;
+                    }
+                }
+            }
+        }
+    }
+    function.ssa = false;
+}
+
+/// Orders the parallel copies of one edge so that none of them overwrites a
+/// register another one still has to read
+fn sequentialize(function: &mut Function, copies: Vec<(VReg, Operand)>) -> Vec<Instruction> {
+    let reads_destination = copies
+        .iter()
+        .any(|(_, src)| copies.iter().any(|(dst, _)| *src == Operand::Reg(*dst)));
+    if !reads_destination {
+        return copies
+            .into_iter()
+            .map(|(dst, src)| Instruction::Copy { dst, src })
+            .collect();
+    }
+    let temporaries = copies
+        .iter()
+        .map(|_| function.new_vreg())
+        .collect::<Vec<_>>();
+    let to_temporaries = copies
+        .iter()
+        .zip(&temporaries)
+        .map(|((_, src), &dst)| Instruction::Copy { dst, src: *src });
+    let from_temporaries = copies
+        .iter()
+        .zip(&temporaries)
+        .map(|((dst, _), &temporary)| Instruction::Copy {
+            dst: *dst,
+            src: Operand::Reg(temporary),
+        });
+    to_temporaries.chain(from_temporaries).collect()
+}
+
+/// Renumbers registers densely in order of definition, parameters first
+pub fn compact_registers(function: &mut Function) {
+    let mut numbers = HashMap::new();
+    let mut number = |reg: &mut VReg| {
+        let next = VReg(numbers.len() as u32);
+        *reg = *numbers.entry(*reg).or_insert(next);
+    };
+    for param in &mut function.params {
+        number(param);
+    }
+    for block in &mut function.blocks {
+        block
+            .instructions
+            .iter_mut()
+            .filter_map(Instruction::dst_mut)
+            .for_each(&mut number);
+    }
+    for block in &mut function.blocks {
+        for instruction in &mut block.instructions {
+            for operand in instruction.operands_mut() {
+                if let Operand::Reg(reg) = operand {
+                    number(reg);
+                }
+            }
+        }
+        if let Some(Operand::Reg(reg)) = block.terminator.operand_mut() {
+            number(reg);
+        }
+    }
+    function.vreg_count = numbers.len() as u32;
+}
+
+fn terminator_operand(terminator: &Terminator) -> Vec<Operand> {
+    match terminator {
+        Terminator::Jump(_) => vec![],
+        Terminator::Branch(operand, ..) | Terminator::Return(operand) => vec![*operand],
+    }
+}
+
+/// Puts every function into SSA form
+pub struct SsaConstruction;
+
+impl Pass for SsaConstruction {
+    fn name(&self) -> &'static str {
+        "ssa"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        module.functions.iter_mut().for_each(construct_ssa);
+    }
+}
+
+/// Takes every function out of SSA form
+pub struct SsaDestruction;
+
+impl Pass for SsaDestruction {
+    fn name(&self) -> &'static str {
+        "out-of-ssa"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        module.functions.iter_mut().for_each(destruct_ssa);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::verify::verify;
+    use crate::IrBuilder;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn build(source: &str) -> Module {
+        let program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        IrBuilder::new().build(&program).expect("failed to build")
+    }
+
+    #[test]
+    fn test_loop_phi() {
+        let mut module = build("int main(){ int i; while (i < 10) i = i + 1; return i; }");
+        let main = &mut module.functions[0];
+        construct_ssa(main);
+        verify(&module).unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+        assert_eq!(
+            module.to_string(),
+            "function @main() {
+bb0:
+    %0 = copy 0
+    jmp bb1
+bb1:
+    %1 = phi [bb0: %0], [bb2: %6]
+    %2 = copy %1
+    %3 = lt %2, 10
+    br %3, bb2, bb3
+bb2:
+    %4 = copy %1
+    %5 = add %4, 1
+    %6 = copy %5
+    jmp bb1
+bb3:
+    %7 = copy %1
+    ret %7
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // The loop swaps `a` and `b` through `t`, giving the header three phis
+        let mut module = build(
+            "int main(){ int a; int b; int t; a = 1; b = 2;
+             while (a < 5) { t = a; a = b; b = t + 3; } return (a * 10) + b; }",
+        );
+        let run = |module: &Module| {
+            crate::Evaluator::new(module)
+                .run(&mut &b""[..], &mut vec![])
+                .unwrap()
+        };
+        let expected = run(&module);
+        construct_ssa(&mut module.functions[0]);
+        assert_eq!(run(&module), expected);
+        destruct_ssa(&mut module.functions[0]);
+        verify(&module).unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+        assert!(!module.to_string().contains("phi"));
+        assert_eq!(run(&module), expected);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::ir::{Block, BlockId, Function, Instruction, Module, Operand, Terminator};
+use crate::pass::Pass;
+
+/// Turns every `call` of a function to itself whose result is returned
+/// right away into copies of the arguments to the parameters and a jump back
+/// to the start of the function, so that deep recursion runs in constant
+/// stack space. The arguments go through fresh registers first, as they may
+/// read parameters that are assigned before them.
+pub fn eliminate_tail_calls(function: &mut Function) {
+    let tail_calls = (0..function.blocks.len())
+        .filter(|&index| is_self_tail_call(function, &function.blocks[index]))
+        .collect::<Vec<_>>();
+    if tail_calls.is_empty() {
+        return;
+    }
+
+    // The entry block has no predecessors, so the body moves to a new block
+    // the tail calls can jump to
+    let start = BlockId(function.blocks.len() as u32);
+    let entry = std::mem::replace(
+        &mut function.blocks[0],
+        Block {
+            instructions: vec![],
+            terminator: Terminator::Jump(start),
+        },
+    );
+    function.blocks.push(entry);
+
+    for index in tail_calls {
+        let index = match index {
+            0 => start.0 as usize,
+            index => index,
+        };
+        let Some(Instruction::Call { args, .. }) = function.blocks[index].instructions.pop() else {
+            unreachable!("checked by is_self_tail_call")
+        };
+        let temporaries = args
+            .into_iter()
+            .map(|src| {
+                let dst = function.new_vreg();
+                function.blocks[index]
+                    .instructions
+                    .push(Instruction::Copy { dst, src });
+                dst
+            })
+            .collect::<Vec<_>>();
+        let block = &mut function.blocks[index];
+        for (&dst, temporary) in function.params.iter().zip(temporaries) {
+            let src = Operand::Reg(temporary);
+            block.instructions.push(Instruction::Copy { dst, src });
+        }
+        block.terminator = Terminator::Jump(start);
+    }
+}
+
+/// Number of self tail calls in a module
+pub fn count_self_tail_calls(module: &Module) -> usize {
+    module
+        .functions
+        .iter()
+        .map(|function| {
+            let blocks = function.blocks.iter();
+            blocks.filter(|b| is_self_tail_call(function, b)).count()
+        })
+        .sum()
+}
+
+fn is_self_tail_call(function: &Function, block: &Block) -> bool {
+    match (block.instructions.last(), &block.terminator) {
+        (
+            Some(Instruction::Call {
+                dst,
+                function: callee,
+                args,
+            }),
+            Terminator::Return(Operand::Reg(result)),
+        ) => dst == result && *callee == function.name && args.len() == function.params.len(),
+        _ => false,
+    }
+}
+
+/// Eliminates self tail calls. Runs before SSA construction: a function
+/// already in SSA form is left alone, as its parameters cannot be assigned.
+pub struct TailCallElimination;
+
+impl Pass for TailCallElimination {
+    fn name(&self) -> &'static str {
+        "tailcall"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        module
+            .functions
+            .iter_mut()
+            .filter(|function| !function.ssa)
+            .for_each(eliminate_tail_calls);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::IrBuilder;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    #[test]
+    fn test_tail_calls() {
+        let program = Parser::new(
+            Cursor::new(
+                "int gcd(int a, int b){ if (b == 0) return a; return gcd(b, a % b); }
+                 int sum(int n){ if (n == 0) return 0; return n + sum(n - 1); }",
+            ),
+            "test.tc",
+            None,
+        )
+        .parse()
+        .expect("failed to parse");
+        let mut module = IrBuilder::new().build(&program).expect("failed to build");
+        TailCallElimination.run(&mut module);
+        crate::verify(&module).unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+
+        let calls = |function: &Function| {
+            function
+                .blocks
+                .iter()
+                .flat_map(|block| &block.instructions)
+                .filter(|instruction| matches!(instruction, Instruction::Call { .. }))
+                .count()
+        };
+        let gcd = &module.functions[0];
+        assert_eq!(calls(gcd), 0, "{gcd}");
+        // The entry and the former tail call both jump to the moved body
+        let start = gcd.blocks[0].terminator.clone();
+        let jumps = gcd.blocks.iter().filter(|b| b.terminator == start).count();
+        assert_eq!(jumps, 2, "{gcd}");
+        // The result of `sum(n - 1)` is used before returning
+        assert_eq!(calls(&module.functions[1]), 1);
+    }
+}
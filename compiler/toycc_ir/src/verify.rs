@@ -0,0 +1,380 @@
+use crate::cfg::{predecessors, reverse_postorder, DominatorTree};
+use crate::error::IrError;
+use crate::ir::{BlockId, Function, Instruction, Module, Operand, Terminator, VReg};
+
+/// Checks the structural invariants every pass must preserve:
+///
+/// - branch targets exist and phis only appear, at the start of blocks, in
+///   functions in SSA form, with one operand per predecessor
+/// - registers are in range, globals are declared, and calls name a
+///   function of the module with the right number of arguments; as every
+///   value is a 32-bit integer, this is all there is to type consistency
+/// - every register is defined before it is used: in SSA form it has one
+///   definition that dominates its uses, otherwise it is written on every
+///   path from the entry
+pub fn verify(module: &Module) -> Result<(), IrError> {
+    module
+        .functions
+        .iter()
+        .try_for_each(|function| Verifier { module, function }.verify())
+}
+
+struct Verifier<'a> {
+    module: &'a Module,
+    function: &'a Function,
+}
+
+impl<'a> Verifier<'a> {
+    fn verify(&self) -> Result<(), IrError> {
+        let function = self.function;
+        if function.blocks.is_empty() {
+            return Err(self.error(None, "function has no blocks".to_owned()));
+        }
+        for param in &function.params {
+            self.check_register(None, *param)?;
+        }
+        for (index, block) in function.blocks.iter().enumerate() {
+            for target in block.terminator.successors() {
+                if target.0 as usize >= function.blocks.len() {
+                    let message = format!("jump to missing block {target}");
+                    return Err(self.error(Some(BlockId(index as u32)), message));
+                }
+            }
+        }
+        let predecessors = predecessors(function);
+        for (index, block) in function.blocks.iter().enumerate() {
+            let id = BlockId(index as u32);
+            let mut phis_allowed = function.ssa;
+            for instruction in &block.instructions {
+                phis_allowed &= instruction.is_phi();
+                self.check_instruction(id, instruction, phis_allowed, &predecessors[index])?;
+            }
+            for operand in terminator_operands(&block.terminator) {
+                self.check_operand(Some(id), operand)?;
+            }
+        }
+        match function.ssa {
+            true => self.check_dominance(),
+            false => self.check_definite_definitions(&predecessors),
+        }
+    }
+
+    fn check_instruction(
+        &self,
+        block: BlockId,
+        instruction: &Instruction,
+        phis_allowed: bool,
+        predecessors: &[BlockId],
+    ) -> Result<(), IrError> {
+        if let Some(dst) = instruction.dst() {
+            self.check_register(Some(block), dst)?;
+        }
+        for operand in instruction.operands() {
+            self.check_operand(Some(block), operand)?;
+        }
+        match instruction {
+            Instruction::Phi { .. } if !phis_allowed => {
+                Err(self.error(Some(block), format!("misplaced phi '{instruction}'")))
+            }
+            Instruction::Phi { incoming, .. } => {
+                let mut blocks = incoming.iter().map(|(b, _)| *b).collect::<Vec<_>>();
+                let mut expected = predecessors.to_vec();
+                blocks.sort();
+                expected.sort();
+                match blocks == expected {
+                    true => Ok(()),
+                    false => Err(self.error(
+                        Some(block),
+                        format!("'{instruction}' does not match the predecessors"),
+                    )),
+                }
+            }
+            Instruction::LoadGlobal { global, .. } | Instruction::StoreGlobal { global, .. }
+                if !self.module.globals.contains(global) =>
+            {
+                Err(self.error(Some(block), format!("undeclared global @{global}")))
+            }
+            Instruction::Call { function, args, .. } => {
+                match self.module.function(function) {
+                    None => Err(IrError::UndefinedFunction(function.clone())),
+                    Some(callee) if callee.params.len() != args.len() => Err(
+                        IrError::ArgumentCount(function.clone(), callee.params.len(), args.len()),
+                    ),
+                    Some(_) => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// In SSA form every register has one definition, which dominates its
+    /// uses; a phi uses its operand at the end of the matching predecessor
+    fn check_dominance(&self) -> Result<(), IrError> {
+        let function = self.function;
+        let tree = DominatorTree::new(function);
+        let mut definitions = vec![None; function.vreg_count as usize];
+        for param in &function.params {
+            definitions[param.0 as usize] = Some((BlockId(0), 0));
+        }
+        for (index, block) in function.blocks.iter().enumerate() {
+            for (position, instruction) in block.instructions.iter().enumerate() {
+                if let Some(dst) = instruction.dst() {
+                    let definition = &mut definitions[dst.0 as usize];
+                    if definition.is_some() {
+                        return Err(self.error(
+                            Some(BlockId(index as u32)),
+                            format!("{dst} is defined more than once"),
+                        ));
+                    }
+                    *definition = Some((BlockId(index as u32), position + 1));
+                }
+            }
+        }
+
+        let check = |reg: VReg, block: BlockId, position: usize| {
+            let dominated = match definitions[reg.0 as usize] {
+                Some((definition, defined_at)) if definition == block => defined_at <= position,
+                Some((definition, _)) => tree.dominates(definition, block),
+                None => false,
+            };
+            match dominated {
+                true => Ok(()),
+                false => {
+                    Err(self.error(Some(block), format!("{reg} is used before it is defined")))
+                }
+            }
+        };
+        for (index, block) in function.blocks.iter().enumerate() {
+            let id = BlockId(index as u32);
+            if !tree.is_reachable(id) {
+                continue;
+            }
+            for (position, instruction) in block.instructions.iter().enumerate() {
+                match instruction {
+                    Instruction::Phi { incoming, .. } => {
+                        for (predecessor, operand) in incoming {
+                            if let Operand::Reg(reg) = operand {
+                                let end = function.block(*predecessor).instructions.len();
+                                check(*reg, *predecessor, end)?;
+                            }
+                        }
+                    }
+                    _ => {
+                        for reg in registers(instruction.operands()) {
+                            check(reg, id, position)?;
+                        }
+                    }
+                }
+            }
+            for reg in registers(terminator_operands(&block.terminator)) {
+                check(reg, id, block.instructions.len())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Outside SSA form a register may be written several times, so uses
+    /// are checked with a forward analysis of the registers written on every
+    /// path. A block no path reaches assumes everything is written.
+    fn check_definite_definitions(&self, predecessors: &[Vec<BlockId>]) -> Result<(), IrError> {
+        let function = self.function;
+        let registers_count = function.vreg_count as usize;
+        let mut entry_state = vec![false; registers_count];
+        for param in &function.params {
+            entry_state[param.0 as usize] = true;
+        }
+
+        let order = reverse_postorder(function);
+        let mut outputs = vec![vec![true; registers_count]; function.blocks.len()];
+        let input = |outputs: &[Vec<bool>], block: BlockId| match block.0 {
+            0 => entry_state.clone(),
+            _ => {
+                let mut state = vec![true; registers_count];
+                for predecessor in &predecessors[block.0 as usize] {
+                    for (defined, output) in state.iter_mut().zip(&outputs[predecessor.0 as usize])
+                    {
+                        *defined &= output;
+                    }
+                }
+                state
+            }
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order {
+                let mut state = input(&outputs, block);
+                for instruction in &function.block(block).instructions {
+                    if let Some(dst) = instruction.dst() {
+                        state[dst.0 as usize] = true;
+                    }
+                }
+                if state != outputs[block.0 as usize] {
+                    outputs[block.0 as usize] = state;
+                    changed = true;
+                }
+            }
+        }
+
+        for &block in &order {
+            let mut state = input(&outputs, block);
+            let instructions = &function.block(block).instructions;
+            let uses = instructions
+                .iter()
+                .map(|i| (i.operands(), i.dst()))
+                .chain(std::iter::once((
+                    terminator_operands(&function.block(block).terminator),
+                    None,
+                )));
+            for (operands, dst) in uses {
+                for reg in registers(operands) {
+                    if !state[reg.0 as usize] {
+                        return Err(self.error(
+                            Some(block),
+                            format!("{reg} may be used before it is defined"),
+                        ));
+                    }
+                }
+                if let Some(dst) = dst {
+                    state[dst.0 as usize] = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_operand(&self, block: Option<BlockId>, operand: Operand) -> Result<(), IrError> {
+        match operand {
+            Operand::Reg(reg) => self.check_register(block, reg),
+            Operand::Const(_) => Ok(()),
+        }
+    }
+
+    fn check_register(&self, block: Option<BlockId>, reg: VReg) -> Result<(), IrError> {
+        match reg.0 < self.function.vreg_count {
+            true => Ok(()),
+            false => Err(self.error(block, format!("{reg} is out of range"))),
+        }
+    }
+
+    fn error(&self, block: Option<BlockId>, message: String) -> IrError {
+        let location = match block {
+            Some(block) => format!("@{} {block}", self.function.name),
+            None => format!("@{}", self.function.name),
+        };
+        IrError::Verify(format!("in {location}: {message}"))
+    }
+}
+
+fn registers(operands: Vec<Operand>) -> impl Iterator<Item = VReg> {
+    operands.into_iter().filter_map(|operand| match operand {
+        Operand::Reg(reg) => Some(reg),
+        Operand::Const(_) => None,
+    })
+}
+
+fn terminator_operands(terminator: &Terminator) -> Vec<Operand> {
+    match terminator {
+        Terminator::Jump(_) => vec![],
+        Terminator::Branch(operand, ..) | Terminator::Return(operand) => vec![*operand],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::Block;
+
+    fn module(ssa: bool, blocks: Vec<Block>) -> Module {
+        Module {
+            globals: vec![],
+            functions: vec![Function {
+                name: "main".to_owned(),
+                params: vec![],
+                blocks,
+                vreg_count: 2,
+                ssa,
+            }],
+        }
+    }
+
+    fn block(instructions: Vec<Instruction>, terminator: Terminator) -> Block {
+        Block {
+            instructions,
+            terminator,
+        }
+    }
+
+    fn copy(dst: u32, src: Operand) -> Instruction {
+        Instruction::Copy {
+            dst: VReg(dst),
+            src,
+        }
+    }
+
+    #[test]
+    fn test_use_before_definition() {
+        let condition = Operand::Const(1);
+        // %0 is only written on one side of the branch
+        let blocks = vec![
+            block(
+                vec![],
+                Terminator::Branch(condition, BlockId(1), BlockId(2)),
+            ),
+            block(
+                vec![copy(0, Operand::Const(4))],
+                Terminator::Jump(BlockId(2)),
+            ),
+            block(vec![], Terminator::Return(Operand::Reg(VReg(0)))),
+        ];
+        let error = verify(&module(false, blocks.clone())).unwrap_err();
+        assert_eq!(
+            error,
+            IrError::Verify("in @main bb2: %0 may be used before it is defined".to_owned())
+        );
+        assert!(verify(&module(true, blocks)).is_err());
+    }
+
+    #[test]
+    fn test_ssa_rules() {
+        let phi = Instruction::Phi {
+            dst: VReg(1),
+            incoming: vec![(BlockId(0), Operand::Reg(VReg(0)))],
+        };
+        let valid = vec![
+            block(
+                vec![copy(0, Operand::Const(1))],
+                Terminator::Jump(BlockId(1)),
+            ),
+            block(vec![phi.clone()], Terminator::Return(Operand::Reg(VReg(1)))),
+        ];
+        assert_eq!(verify(&module(true, valid.clone())), Ok(()));
+        // phis are not allowed outside SSA form
+        assert!(verify(&module(false, valid.clone())).is_err());
+
+        let mut redefined = valid.clone();
+        redefined[1].instructions.push(copy(0, Operand::Const(2)));
+        assert!(verify(&module(true, redefined)).is_err());
+
+        let mut misplaced = valid;
+        misplaced[1].instructions.insert(0, Instruction::NewLine);
+        assert!(verify(&module(true, misplaced)).is_err());
+    }
+
+    #[test]
+    fn test_calls_and_targets() {
+        let call = Instruction::Call {
+            dst: VReg(0),
+            function: "main".to_owned(),
+            args: vec![Operand::Const(1)],
+        };
+        let blocks = vec![block(vec![call], Terminator::Return(Operand::Const(0)))];
+        assert_eq!(
+            verify(&module(false, blocks)),
+            Err(IrError::ArgumentCount("main".to_owned(), 0, 1))
+        );
+
+        let blocks = vec![block(vec![], Terminator::Jump(BlockId(3)))];
+        assert!(verify(&module(false, blocks)).is_err());
+    }
+}
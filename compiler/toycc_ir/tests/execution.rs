@@ -0,0 +1,145 @@
+//! Lowers programs to IR and compares the evaluator's output with the
+//! tree-walking interpreter.
+
+use std::io::Cursor;
+use toycc_ir::ssa::{SsaConstruction, SsaDestruction};
+use toycc_ir::tail_call::TailCallElimination;
+use toycc_ir::{Evaluator, IrBuilder, IrError, Module, PassManager, MAX_FRAMES};
+use toycc_test_support::parse;
+
+fn evaluate(module: &Module, input: &str) -> (String, i32) {
+    let mut output = vec![];
+    let code = Evaluator::new(module)
+        .run(&mut Cursor::new(input.to_owned()), &mut output)
+        .unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+    (String::from_utf8(output).unwrap(), code)
+}
+
+fn run_both(source: &str, input: &str) -> (String, i32) {
+    let mut module = None;
+    let expected = toycc_test_support::run_both(source, input, |program| {
+        let built = IrBuilder::new().build(program).expect("failed to build");
+        let (output, code) = evaluate(&built, input);
+        module = Some(built.clone());
+        (built, output, Some(code))
+    });
+
+    // Going through SSA form and back must not change the behaviour
+    let mut ssa = module.unwrap();
+    PassManager::new()
+        .verify(true)
+        .add(SsaConstruction)
+        .run(&mut ssa)
+        .unwrap_or_else(|e| panic!("{e:?}\n{ssa}"));
+    assert_eq!(evaluate(&ssa, input), expected, "{ssa}");
+    PassManager::new()
+        .verify(true)
+        .add(SsaDestruction)
+        .run(&mut ssa)
+        .unwrap_or_else(|e| panic!("{e:?}\n{ssa}"));
+    assert_eq!(evaluate(&ssa, input), expected, "{ssa}");
+    expected
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    assert_eq!(
+        run_both(source, "7 1").0,
+        "input a: input a: a = 3\na = 21\n"
+    );
+}
+
+#[test]
+fn test_recursion_and_globals() {
+    let source = "int calls;
+int fib(int n){ calls = calls + 1; if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+int main(){ read(calls); write(fib(15), \" \", calls); newline; return fib(10); }";
+    assert_eq!(run_both(source, "100"), ("610 2073\n".to_owned(), 55));
+}
+
+#[test]
+fn test_operators_and_evaluation_order() {
+    let source = "int main(){
+    int a;
+    char c;
+    read(c, a);
+    write(c, \" \", -a, \" \", a / 5, \" \", a % 5, \" \", (a > 3) && (a < 50), (a < 3) || !a, \"\\n\");
+    write(a >= 41, a <= 40, a == 41, a != 41, 100000 * a, \"\\n\");
+    write(a + (a = 3), \" \", (a = 4) + a);
+    newline;
+    return a;
+}";
+    assert_eq!(
+        run_both(source, "  x 41"),
+        ("x -41 8 1 10\n10104100000\n44 8\n".to_owned(), 4)
+    );
+}
+
+#[test]
+fn test_nested_loops_and_shadowing() {
+    let source = "int main(){
+    int i;
+    int j;
+    while (i < 3) {
+        int i2;
+        j = 0;
+        while (1) {
+            if (j > i) break;
+            { int j; j = 7; write(j); }
+            j = j + 1;
+        }
+        write(i2);
+        newline;
+        i = i + 1;
+    }
+    return 0;
+}";
+    assert_eq!(run_both(source, "").0, "70\n770\n7770\n");
+}
+
+#[test]
+fn test_runtime_errors() {
+    let run = |source: &str| {
+        let program = parse(source);
+        let module = IrBuilder::new().build(&program).unwrap();
+        Evaluator::new(&module).run(&mut &b""[..], &mut vec![])
+    };
+    assert_eq!(
+        run("int main(){ int z; write(1 / z); return 0; }"),
+        Err(IrError::DivisionByZero)
+    );
+    assert_eq!(
+        run("int down(int n){ return down(n + 1); } int main(){ return down(0); }"),
+        Err(IrError::StackOverflow(MAX_FRAMES))
+    );
+    assert_eq!(
+        run("int main(){ int a; read(a); return a; }"),
+        Err(IrError::InvalidInput(String::new()))
+    );
+}
+
+#[test]
+fn test_tail_calls() {
+    let source =
+        "int count(int n, int acc){ if (n == 0) return acc; return count(n - 1, acc + 1); }
+int gcd(int a, int b){ if (b == 0) return a; return gcd(b, a % b); }
+int main(){ write(count(1000000, 0), \" \", gcd(1071, 462)); newline; return 0; }";
+    let program = parse(source);
+    let mut module = IrBuilder::new().build(&program).unwrap();
+    let run = |module: &Module| {
+        let mut output = vec![];
+        Evaluator::new(module)
+            .run(&mut &b""[..], &mut output)
+            .map(|_| String::from_utf8(output).unwrap())
+    };
+    assert_eq!(run(&module), Err(IrError::StackOverflow(MAX_FRAMES)));
+
+    PassManager::new()
+        .verify(true)
+        .add(TailCallElimination)
+        .add(SsaConstruction)
+        .run(&mut module)
+        .unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+    assert_eq!(run(&module).as_deref(), Ok("1000000 21\n"));
+}
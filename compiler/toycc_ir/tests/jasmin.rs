@@ -0,0 +1,80 @@
+//! Generates Jasmin from the IR, runs it in the bytecode interpreter and
+//! compares its output with the tree-walking interpreter, with and without
+//! tail-call elimination and the peephole optimizer.
+
+use toycc_ir::jasmin::JasminGenerator;
+use toycc_ir::tail_call::TailCallElimination;
+use toycc_ir::{IrBuilder, Module, PassManager};
+use toycc_jvm_interpreter::{Class, Vm};
+use toycc_test_support::CORPUS;
+
+fn run_jasmin(module: &Module, input: &str, peephole: bool) -> (String, String) {
+    let jasmin = JasminGenerator::new("test", "test.tc")
+        .peephole(peephole)
+        .generate(module);
+    let class = Class::parse(&jasmin).unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+    let mut vm = Vm::new(&class, input);
+    vm.run_main().unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+    let output = vm.output().to_owned();
+    (jasmin, output)
+}
+
+fn run_both(source: &str, input: &str) -> String {
+    let mut expected = String::new();
+    for optimize in [false, true] {
+        (expected, _) = toycc_test_support::run_both(source, input, |program| {
+            let mut module = IrBuilder::new().build(program).expect("failed to build");
+            if optimize {
+                PassManager::new()
+                    .verify(true)
+                    .add(TailCallElimination)
+                    .run(&mut module)
+                    .unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+            }
+            let (jasmin, output) = run_jasmin(&module, input, optimize);
+            (jasmin, output, None)
+        });
+    }
+    expected
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    assert_eq!(run_both(source, "7 1"), "input a: input a: a = 3\na = 21\n");
+}
+
+#[test]
+fn test_corpus() {
+    for &(source, input) in CORPUS {
+        run_both(source, input);
+    }
+}
+
+#[test]
+fn test_globals_and_recursion() {
+    let source = "int calls;
+int gcd(int a, int b){ calls = calls + 1; if (b == 0) return a; return gcd(b, a % b); }
+int fact(int n){ if (n <= 1) return 1; return n * fact(n - 1); }
+int main(){
+    write(gcd(1071, 462), \" \", fact(10), \" \", calls, \" \", -100000, '!');
+    newline;
+    return 0;
+}";
+    assert_eq!(run_both(source, ""), "21 3628800 4 -100000!\n");
+}
+
+#[test]
+fn test_conditions() {
+    let source = "int main(){
+    int a;
+    int b;
+    read(a, b);
+    if (!(a < b) || (a == 3)) write(\"x\");
+    if ((a != b) && !(b > 9)) write(\"y\");
+    write(a <= b, !a, \"\\n\");
+    return 0;
+}";
+    assert_eq!(run_both(source, "3 5"), "xy10\n");
+    assert_eq!(run_both(source, "0 12"), "11\n");
+}
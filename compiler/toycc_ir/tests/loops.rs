@@ -0,0 +1,82 @@
+//! Runs programs with loops before and after loop-invariant code motion and
+//! strength reduction and checks that only the work done changes, both in
+//! the evaluator and in the Jasmin generated from the optimized IR.
+
+use std::io::Cursor;
+use toycc_ir::jasmin::JasminGenerator;
+use toycc_ir::loops::{
+    instructions_in_loops, multiplications_in_loops, LoopInvariantCodeMotion, StrengthReduction,
+};
+use toycc_ir::ssa::{SsaConstruction, SsaDestruction};
+use toycc_ir::{Evaluator, IrBuilder, Module, PassManager};
+use toycc_jvm_interpreter::{Class, Vm};
+use toycc_test_support::{parse, CORPUS};
+
+fn run(module: &Module, input: &str) -> (String, i32, usize) {
+    let mut output = vec![];
+    let mut evaluator = Evaluator::new(module);
+    let code = evaluator
+        .run(&mut Cursor::new(input.to_owned()), &mut output)
+        .unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+    (String::from_utf8(output).unwrap(), code, evaluator.steps())
+}
+
+fn run_jasmin(module: &Module, input: &str) -> String {
+    let jasmin = JasminGenerator::new("test", "test.tc")
+        .peephole(true)
+        .generate(module);
+    let class = Class::parse(&jasmin).unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+    let mut vm = Vm::new(&class, input);
+    vm.run_main().unwrap_or_else(|e| panic!("{e:?}\n{jasmin}"));
+    vm.output().to_owned()
+}
+
+#[test]
+fn test_loop_optimizations() {
+    for &(source, input) in CORPUS {
+        let program = parse(source);
+        let unoptimized = IrBuilder::new().build(&program).expect("failed to build");
+        let (expected, expected_code, _) = run(&unoptimized, input);
+
+        let mut module = unoptimized.clone();
+        PassManager::new()
+            .verify(true)
+            .add(SsaConstruction)
+            .run(&mut module)
+            .unwrap();
+        let (_, _, steps) = run(&module, input);
+        let (instructions, multiplications) = (
+            instructions_in_loops(&module),
+            multiplications_in_loops(&module),
+        );
+
+        PassManager::new()
+            .verify(true)
+            .add(LoopInvariantCodeMotion)
+            .add(StrengthReduction)
+            .run(&mut module)
+            .unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+        let (output, code, optimized_steps) = run(&module, input);
+        assert_eq!(
+            (output, code),
+            (expected.clone(), expected_code),
+            "{module}"
+        );
+        assert!(optimized_steps <= steps, "{module}");
+        assert!(instructions_in_loops(&module) <= instructions);
+        if multiplications > 0 {
+            assert!(
+                multiplications_in_loops(&module) < multiplications,
+                "{module}"
+            );
+        }
+
+        PassManager::new()
+            .verify(true)
+            .add(SsaDestruction)
+            .run(&mut module)
+            .unwrap_or_else(|e| panic!("{e:?}\n{module}"));
+        assert_eq!(run(&module, input).0, expected, "{module}");
+        assert_eq!(run_jasmin(&module, input), expected, "{module}");
+    }
+}
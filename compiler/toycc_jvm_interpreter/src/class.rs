@@ -0,0 +1,405 @@
+use crate::error::VmError;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A single bytecode instruction. Branch targets are indices into the
+/// containing method's code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Push(i32),
+    Ldc(String),
+    Iload(usize),
+    Istore(usize),
+    Aload(usize),
+    Astore(usize),
+    Iinc(usize, i32),
+    Iadd,
+    Isub,
+    Imul,
+    Idiv,
+    Irem,
+    Ineg,
+    Iand,
+    Ior,
+    Ixor,
+    IfIcmp(Condition, usize),
+    If(Condition, usize),
+    Goto(usize),
+    InvokeStatic(String),
+    InvokeVirtual(String),
+    InvokeSpecial(String),
+    GetStatic(String),
+    PutStatic(String),
+    New(String),
+    Dup,
+    Pop,
+    Swap,
+    Nop,
+    Return,
+    Ireturn,
+}
+
+#[derive(Debug, Default)]
+pub struct Method {
+    pub name: String,
+    pub descriptor: String,
+    pub code: Vec<Instruction>,
+    pub max_locals: usize,
+    /// `(pc, line)` pairs taken from `.line` directives
+    pub line_numbers: Vec<(usize, usize)>,
+}
+
+/// Jump instructions waiting for their target label, by code index
+type PendingJumps = Vec<(usize, String)>;
+type Labels = HashMap<String, usize>;
+
+#[derive(Debug, Default)]
+pub struct Class {
+    pub name: String,
+    pub source: Option<String>,
+    pub fields: Vec<String>,
+    methods: HashMap<String, Method>,
+}
+
+impl Class {
+    /// Assembles a class from the Jasmin source toycc generates
+    pub fn parse(jasmin: &str) -> Result<Self, VmError> {
+        let mut class = Class::default();
+        let mut method: Option<(Method, PendingJumps, Labels)> = None;
+
+        for (index, line) in jasmin.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let (opcode, operand) = match line.split_once(char::is_whitespace) {
+                Some((opcode, operand)) => (opcode, operand.trim()),
+                None => (line, ""),
+            };
+            let syntax = |message: &str| VmError::Syntax(line_number, message.to_string());
+
+            if opcode == ".end" {
+                let (mut current, jumps, labels) =
+                    method.take().ok_or_else(|| syntax("unexpected .end"))?;
+                for (pc, label) in jumps {
+                    let index = *labels
+                        .get(&label)
+                        .ok_or_else(|| VmError::UndefinedLabel(label.clone()))?;
+                    match &mut current.code[pc] {
+                        Instruction::IfIcmp(_, target)
+                        | Instruction::If(_, target)
+                        | Instruction::Goto(target) => *target = index,
+                        _ => unreachable!(),
+                    }
+                }
+                class
+                    .methods
+                    .insert(format!("{}{}", current.name, current.descriptor), current);
+                continue;
+            }
+
+            if let Some((current, jumps, labels)) = method.as_mut() {
+                if let Some(label) = line.strip_suffix(':') {
+                    labels.insert(label.to_string(), current.code.len());
+                    continue;
+                }
+                let pc = current.code.len();
+                let mut target = || {
+                    jumps.push((pc, operand.to_string()));
+                    0
+                };
+                let instruction = match opcode {
+                    ".limit" => {
+                        if let Some(("locals", count)) = operand.split_once(char::is_whitespace) {
+                            current.max_locals =
+                                count.trim().parse().map_err(|_| syntax("invalid limit"))?;
+                        }
+                        continue;
+                    }
+                    ".line" => {
+                        let line = operand.parse().map_err(|_| syntax("invalid line"))?;
+                        current.line_numbers.push((pc, line));
+                        continue;
+                    }
+                    ".var" | ".throws" | ".catch" => continue,
+                    "iconst_m1" => Instruction::Push(-1),
+                    "iconst_0" => Instruction::Push(0),
+                    "iconst_1" => Instruction::Push(1),
+                    "iconst_2" => Instruction::Push(2),
+                    "iconst_3" => Instruction::Push(3),
+                    "iconst_4" => Instruction::Push(4),
+                    "iconst_5" => Instruction::Push(5),
+                    "bipush" | "sipush" => {
+                        Instruction::Push(operand.parse().map_err(|_| syntax("invalid constant"))?)
+                    }
+                    "ldc" | "ldc_w" => match operand.strip_prefix('"') {
+                        Some(string) => Instruction::Ldc(
+                            unquote(string)
+                                .ok_or_else(|| syntax("unterminated string constant"))?,
+                        ),
+                        None => Instruction::Push(
+                            operand.parse().map_err(|_| syntax("invalid constant"))?,
+                        ),
+                    },
+                    "iload" | "istore" | "aload" | "astore" => {
+                        let slot = operand.parse().map_err(|_| syntax("invalid local"))?;
+                        match opcode {
+                            "iload" => Instruction::Iload(slot),
+                            "istore" => Instruction::Istore(slot),
+                            "aload" => Instruction::Aload(slot),
+                            _ => Instruction::Astore(slot),
+                        }
+                    }
+                    "iload_0" | "iload_1" | "iload_2" | "iload_3" => {
+                        Instruction::Iload(opcode[6..].parse().unwrap())
+                    }
+                    "istore_0" | "istore_1" | "istore_2" | "istore_3" => {
+                        Instruction::Istore(opcode[7..].parse().unwrap())
+                    }
+                    "aload_0" | "aload_1" | "aload_2" | "aload_3" => {
+                        Instruction::Aload(opcode[6..].parse().unwrap())
+                    }
+                    "astore_0" | "astore_1" | "astore_2" | "astore_3" => {
+                        Instruction::Astore(opcode[7..].parse().unwrap())
+                    }
+                    "iinc" => {
+                        let (slot, constant) = operand
+                            .split_once(char::is_whitespace)
+                            .ok_or_else(|| syntax("expected local and constant"))?;
+                        Instruction::Iinc(
+                            slot.parse().map_err(|_| syntax("invalid local"))?,
+                            constant
+                                .trim()
+                                .parse()
+                                .map_err(|_| syntax("invalid constant"))?,
+                        )
+                    }
+                    "iadd" => Instruction::Iadd,
+                    "isub" => Instruction::Isub,
+                    "imul" => Instruction::Imul,
+                    "idiv" => Instruction::Idiv,
+                    "irem" => Instruction::Irem,
+                    "ineg" => Instruction::Ineg,
+                    "iand" => Instruction::Iand,
+                    "ior" => Instruction::Ior,
+                    "ixor" => Instruction::Ixor,
+                    "if_icmpeq" => Instruction::IfIcmp(Condition::Eq, target()),
+                    "if_icmpne" => Instruction::IfIcmp(Condition::Ne, target()),
+                    "if_icmplt" => Instruction::IfIcmp(Condition::Lt, target()),
+                    "if_icmple" => Instruction::IfIcmp(Condition::Le, target()),
+                    "if_icmpgt" => Instruction::IfIcmp(Condition::Gt, target()),
+                    "if_icmpge" => Instruction::IfIcmp(Condition::Ge, target()),
+                    "ifeq" => Instruction::If(Condition::Eq, target()),
+                    "ifne" => Instruction::If(Condition::Ne, target()),
+                    "iflt" => Instruction::If(Condition::Lt, target()),
+                    "ifle" => Instruction::If(Condition::Le, target()),
+                    "ifgt" => Instruction::If(Condition::Gt, target()),
+                    "ifge" => Instruction::If(Condition::Ge, target()),
+                    "goto" | "goto_w" => Instruction::Goto(target()),
+                    "invokestatic" => Instruction::InvokeStatic(operand.to_string()),
+                    "invokevirtual" => Instruction::InvokeVirtual(operand.to_string()),
+                    "invokespecial" => Instruction::InvokeSpecial(operand.to_string()),
+                    "getstatic" | "putstatic" => {
+                        let field = operand
+                            .split_whitespace()
+                            .next()
+                            .ok_or_else(|| syntax("expected field"))?
+                            .to_string();
+                        match opcode {
+                            "getstatic" => Instruction::GetStatic(field),
+                            _ => Instruction::PutStatic(field),
+                        }
+                    }
+                    "new" => Instruction::New(operand.to_string()),
+                    "dup" => Instruction::Dup,
+                    "pop" => Instruction::Pop,
+                    "swap" => Instruction::Swap,
+                    "nop" => Instruction::Nop,
+                    "return" => Instruction::Return,
+                    "ireturn" => Instruction::Ireturn,
+                    _ => return Err(VmError::UnsupportedInstruction(line.to_string())),
+                };
+                current.code.push(instruction);
+                continue;
+            }
+
+            match opcode {
+                ".source" => class.source = Some(operand.to_string()),
+                ".class" => {
+                    class.name = operand
+                        .split_whitespace()
+                        .last()
+                        .ok_or_else(|| syntax("expected class name"))?
+                        .to_string()
+                }
+                ".super" | ".implements" => {}
+                ".field" => {
+                    let mut words = operand.split_whitespace().rev();
+                    words.next();
+                    let name = words.next().ok_or_else(|| syntax("expected field name"))?;
+                    class.fields.push(name.to_string());
+                }
+                ".method" => {
+                    let signature = operand
+                        .split_whitespace()
+                        .last()
+                        .ok_or_else(|| syntax("expected method signature"))?;
+                    let (name, descriptor) = signature
+                        .split_once('(')
+                        .ok_or_else(|| syntax("expected method descriptor"))?;
+                    method = Some((
+                        Method {
+                            name: name.to_string(),
+                            descriptor: format!("({descriptor}"),
+                            max_locals: 256,
+                            ..Default::default()
+                        },
+                        vec![],
+                        HashMap::new(),
+                    ));
+                }
+                _ => return Err(syntax(&format!("unexpected '{line}'"))),
+            }
+        }
+
+        match method {
+            Some(_) => Err(VmError::Syntax(
+                jasmin.lines().count(),
+                "missing .end method".to_string(),
+            )),
+            None => Ok(class),
+        }
+    }
+
+    /// Looks a method up by name and descriptor, e.g. `main([Ljava/lang/String;)V`
+    pub fn method(&self, signature: &str) -> Option<&Method> {
+        self.methods.get(signature)
+    }
+
+    pub fn methods(&self) -> impl Iterator<Item = &Method> {
+        self.methods.values()
+    }
+}
+
+impl Method {
+    /// Number of parameters and whether a value is returned, from the descriptor
+    pub fn arity(&self) -> (usize, bool) {
+        descriptor_arity(&self.descriptor)
+    }
+
+    /// Source line for the instruction at `pc`, if the method has line numbers
+    pub fn line_at(&self, pc: usize) -> Option<usize> {
+        self.line_numbers
+            .iter()
+            .take_while(|(start, _)| *start <= pc)
+            .last()
+            .map(|(_, line)| *line)
+    }
+}
+
+pub(crate) fn descriptor_arity(descriptor: &str) -> (usize, bool) {
+    let (params, result) = descriptor
+        .trim_start_matches('(')
+        .split_once(')')
+        .unwrap_or_default();
+    let mut count = 0;
+    let mut chars = params.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => continue,
+            'L' => {
+                chars.by_ref().find(|c| *c == ';');
+            }
+            _ => {}
+        }
+        count += 1;
+    }
+    (count, result != "V")
+}
+
+fn unquote(string: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut chars = string.chars();
+    loop {
+        match chars.next()? {
+            '"' => return Some(result),
+            '\\' => match chars.next()? {
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                'r' => result.push('\r'),
+                c => result.push(c),
+            },
+            c => result.push(c),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Condition::Eq => "eq",
+                Condition::Ne => "ne",
+                Condition::Lt => "lt",
+                Condition::Le => "le",
+                Condition::Gt => "gt",
+                Condition::Ge => "ge",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_method() {
+        let class = Class::parse(
+            ".source t.tc\n.class public t\n.super java/lang/Object\n\
+             .method public static f(IC)I\n\t.limit locals 3\nL1:\n\t.line 2\n\
+             \tiload 0\n\tifeq L1\n\tldc \"a \\\"b\\\"\\n\"\n\tpop\n\tsipush 300\n\tireturn\n.end method\n",
+        )
+        .unwrap();
+        assert_eq!(class.name, "t");
+        assert_eq!(class.source.as_deref(), Some("t.tc"));
+        let method = class.method("f(IC)I").unwrap();
+        assert_eq!(method.arity(), (2, true));
+        assert_eq!(method.max_locals, 3);
+        assert_eq!(
+            method.code,
+            vec![
+                Instruction::Iload(0),
+                Instruction::If(Condition::Eq, 0),
+                Instruction::Ldc("a \"b\"\n".to_string()),
+                Instruction::Pop,
+                Instruction::Push(300),
+                Instruction::Ireturn,
+            ]
+        );
+        assert_eq!(method.line_at(4), Some(2));
+    }
+
+    #[test]
+    fn test_undefined_label() {
+        let result =
+            Class::parse(".class public t\n.method public static f()V\n\tgoto L\n.end method\n");
+        assert_eq!(
+            result.unwrap_err(),
+            VmError::UndefinedLabel("L".to_string())
+        );
+    }
+}
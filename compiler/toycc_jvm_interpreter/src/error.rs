@@ -0,0 +1,48 @@
+use toycc_report::{Diagnostic, ErrorKind, Report, ReportLevel};
+
+#[derive(Report, Debug, PartialEq)]
+pub enum VmError {
+    Syntax(usize, String),
+    UndefinedLabel(String),
+    UnsupportedInstruction(String),
+    NoSuchMethod(String),
+    NoSuchField(String),
+    Verify(String),
+    ArithmeticException,
+    StackOverflowError,
+    InputMismatchException(String),
+    NoSuchElementException,
+}
+
+impl Diagnostic for VmError {
+    fn info(&self) -> String {
+        match self {
+            Self::Syntax(line, message) => format!("line {line}: {message}"),
+            Self::UndefinedLabel(label) => format!("undefined label {label}"),
+            Self::UnsupportedInstruction(instruction) => {
+                format!("unsupported instruction '{instruction}'")
+            }
+            Self::NoSuchMethod(method) => format!("java.lang.NoSuchMethodError: {method}"),
+            Self::NoSuchField(field) => format!("java.lang.NoSuchFieldError: {field}"),
+            Self::Verify(message) => format!("java.lang.VerifyError: {message}"),
+            Self::ArithmeticException => "java.lang.ArithmeticException: / by zero".to_string(),
+            Self::StackOverflowError => "java.lang.StackOverflowError".to_string(),
+            Self::InputMismatchException(input) => {
+                format!("java.util.InputMismatchException: '{input}'")
+            }
+            Self::NoSuchElementException => "java.util.NoSuchElementException".to_string(),
+        }
+    }
+
+    fn level(&self) -> ReportLevel {
+        ReportLevel::Error(ErrorKind::NoHelpError)
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        None
+    }
+}
@@ -0,0 +1,10 @@
+//! A small interpreter for the subset of JVM bytecode that toycc emits. It reads
+//! the generated Jasmin assembly directly, so generated programs can be run and
+//! checked on machines without a Java installation.
+mod class;
+mod error;
+mod vm;
+
+pub use class::{Class, Condition, Instruction, Method};
+pub use error::VmError;
+pub use vm::{Vm, MAX_FRAMES};
@@ -0,0 +1,471 @@
+use crate::class::{descriptor_arity, Class, Condition, Instruction, Method};
+use crate::error::VmError;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Deepest call stack before a `StackOverflowError` is thrown
+pub const MAX_FRAMES: usize = 10_000;
+
+const MAIN: &str = "main([Ljava/lang/String;)V";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Object {
+    Null,
+    PrintStream,
+    InputStream,
+    Scanner,
+    String(Rc<str>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Top,
+    Int(i32),
+    Ref(Object),
+}
+
+struct Frame<'a> {
+    method: &'a Method,
+    pc: usize,
+    locals: Vec<Value>,
+    stack: Vec<Value>,
+}
+
+/// Executes the methods of a single class. Standard library calls are limited
+/// to what toycc generates: `System.out` printing and `java.util.Scanner` reads.
+pub struct Vm<'a> {
+    class: &'a Class,
+    input: Vec<char>,
+    input_position: usize,
+    output: String,
+    statics: HashMap<String, i32>,
+    /// Number of instructions executed so far
+    pub executed: u64,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(class: &'a Class, input: &str) -> Self {
+        Self {
+            class,
+            input: input.chars().collect(),
+            input_position: 0,
+            output: String::new(),
+            statics: HashMap::new(),
+            executed: 0,
+        }
+    }
+
+    /// Everything printed to `System.out` so far
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn run_main(&mut self) -> Result<(), VmError> {
+        let method = self.find_method(MAIN)?;
+        self.execute(method, vec![Value::Ref(Object::Null)])
+            .map(|_| ())
+    }
+
+    /// Calls a static `int`/`char` method of the class, e.g. `addTwo(I)I`
+    pub fn invoke(&mut self, signature: &str, arguments: &[i32]) -> Result<Option<i32>, VmError> {
+        let method = self.find_method(signature)?;
+        let arguments = arguments.iter().map(|a| Value::Int(*a)).collect();
+        Ok(match self.execute(method, arguments)? {
+            Some(Value::Int(value)) => Some(value),
+            _ => None,
+        })
+    }
+
+    fn find_method(&self, signature: &str) -> Result<&'a Method, VmError> {
+        self.class
+            .method(signature)
+            .ok_or_else(|| VmError::NoSuchMethod(format!("{}.{signature}", self.class.name)))
+    }
+
+    fn new_frame(method: &'a Method, arguments: Vec<Value>) -> Frame<'a> {
+        let mut locals = arguments;
+        locals.resize(method.max_locals.max(locals.len()), Value::Top);
+        Frame {
+            method,
+            pc: 0,
+            locals,
+            stack: vec![],
+        }
+    }
+
+    fn execute(
+        &mut self,
+        method: &'a Method,
+        arguments: Vec<Value>,
+    ) -> Result<Option<Value>, VmError> {
+        let mut frames = vec![Self::new_frame(method, arguments)];
+
+        while let Some(frame) = frames.last_mut() {
+            let instruction = frame.method.code.get(frame.pc).ok_or_else(|| {
+                VmError::Verify(format!("falling off the end of {}", frame.method.name))
+            })?;
+            frame.pc += 1;
+            self.executed += 1;
+
+            match instruction {
+                Instruction::Push(value) => frame.stack.push(Value::Int(*value)),
+                Instruction::Ldc(string) => frame
+                    .stack
+                    .push(Value::Ref(Object::String(Rc::from(string.as_str())))),
+                Instruction::Iload(slot) => {
+                    let value = Self::local(frame, *slot)?;
+                    if !matches!(value, Value::Int(_)) {
+                        return Err(VmError::Verify(format!(
+                            "iload of uninitialized or non-int local {slot} in {}",
+                            frame.method.name
+                        )));
+                    }
+                    frame.stack.push(value);
+                }
+                Instruction::Aload(slot) => {
+                    let value = Self::local(frame, *slot)?;
+                    if !matches!(value, Value::Ref(_)) {
+                        return Err(VmError::Verify(format!(
+                            "aload of uninitialized or non-reference local {slot} in {}",
+                            frame.method.name
+                        )));
+                    }
+                    frame.stack.push(value);
+                }
+                Instruction::Istore(slot) => {
+                    let value = Value::Int(Self::pop_int(frame)?);
+                    Self::store(frame, *slot, value)?;
+                }
+                Instruction::Astore(slot) => {
+                    let value = Self::pop(frame)?;
+                    if !matches!(value, Value::Ref(_)) {
+                        return Err(VmError::Verify("astore of a non-reference".to_string()));
+                    }
+                    Self::store(frame, *slot, value)?;
+                }
+                Instruction::Iinc(slot, constant) => match Self::local(frame, *slot)? {
+                    Value::Int(value) => {
+                        Self::store(frame, *slot, Value::Int(value.wrapping_add(*constant)))?
+                    }
+                    _ => return Err(VmError::Verify(format!("iinc of non-int local {slot}"))),
+                },
+                Instruction::Iadd
+                | Instruction::Isub
+                | Instruction::Imul
+                | Instruction::Idiv
+                | Instruction::Irem
+                | Instruction::Iand
+                | Instruction::Ior
+                | Instruction::Ixor => {
+                    let b = Self::pop_int(frame)?;
+                    let a = Self::pop_int(frame)?;
+                    let result = match instruction {
+                        Instruction::Iadd => a.wrapping_add(b),
+                        Instruction::Isub => a.wrapping_sub(b),
+                        Instruction::Imul => a.wrapping_mul(b),
+                        Instruction::Idiv | Instruction::Irem if b == 0 => {
+                            return Err(VmError::ArithmeticException)
+                        }
+                        Instruction::Idiv => a.wrapping_div(b),
+                        Instruction::Irem => a.wrapping_rem(b),
+                        Instruction::Iand => a & b,
+                        Instruction::Ior => a | b,
+                        _ => a ^ b,
+                    };
+                    frame.stack.push(Value::Int(result));
+                }
+                Instruction::Ineg => {
+                    let a = Self::pop_int(frame)?;
+                    frame.stack.push(Value::Int(a.wrapping_neg()));
+                }
+                Instruction::IfIcmp(condition, target) => {
+                    let b = Self::pop_int(frame)?;
+                    let a = Self::pop_int(frame)?;
+                    if compare(*condition, a, b) {
+                        frame.pc = *target;
+                    }
+                }
+                Instruction::If(condition, target) => {
+                    let a = Self::pop_int(frame)?;
+                    if compare(*condition, a, 0) {
+                        frame.pc = *target;
+                    }
+                }
+                Instruction::Goto(target) => frame.pc = *target,
+                Instruction::InvokeStatic(signature) => {
+                    let (class, signature) = split_member(signature);
+                    if class != self.class.name {
+                        return Err(VmError::NoSuchMethod(format!("{class}.{signature}")));
+                    }
+                    let method = self.find_method(signature)?;
+                    let (count, _) = method.arity();
+                    if frame.stack.len() < count {
+                        return Err(VmError::Verify("stack underflow".to_string()));
+                    }
+                    let arguments = frame.stack.split_off(frame.stack.len() - count);
+                    if frames.len() >= MAX_FRAMES {
+                        return Err(VmError::StackOverflowError);
+                    }
+                    frames.push(Self::new_frame(method, arguments));
+                }
+                Instruction::InvokeVirtual(signature) => self.invoke_virtual(frame, signature)?,
+                Instruction::InvokeSpecial(signature) => {
+                    let (_, signature) = split_member(signature);
+                    let (count, _) = descriptor_arity(signature.trim_start_matches(|c| c != '('));
+                    for _ in 0..=count {
+                        Self::pop(frame)?;
+                    }
+                }
+                Instruction::GetStatic(field) => {
+                    let value = match field.as_str() {
+                        "java/lang/System/out" => Value::Ref(Object::PrintStream),
+                        "java/lang/System/in" => Value::Ref(Object::InputStream),
+                        field => Value::Int(self.static_field(field)?.copied().unwrap_or(0)),
+                    };
+                    frame.stack.push(value);
+                }
+                Instruction::PutStatic(field) => {
+                    let value = Self::pop_int(frame)?;
+                    self.static_field(field)?;
+                    self.statics.insert(field.clone(), value);
+                }
+                Instruction::New(class) => match class.as_str() {
+                    "java/util/Scanner" => frame.stack.push(Value::Ref(Object::Scanner)),
+                    class => return Err(VmError::UnsupportedInstruction(format!("new {class}"))),
+                },
+                Instruction::Dup => {
+                    let value = frame
+                        .stack
+                        .last()
+                        .cloned()
+                        .ok_or_else(|| VmError::Verify("stack underflow".to_string()))?;
+                    frame.stack.push(value);
+                }
+                Instruction::Pop => {
+                    Self::pop(frame)?;
+                }
+                Instruction::Swap => {
+                    let b = Self::pop(frame)?;
+                    let a = Self::pop(frame)?;
+                    frame.stack.push(b);
+                    frame.stack.push(a);
+                }
+                Instruction::Nop => {}
+                Instruction::Return | Instruction::Ireturn => {
+                    let result = match instruction {
+                        Instruction::Ireturn => Some(Value::Int(Self::pop_int(frame)?)),
+                        _ => None,
+                    };
+                    frames.pop();
+                    match frames.last_mut() {
+                        Some(caller) => caller.stack.extend(result),
+                        None => return Ok(result),
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn invoke_virtual(&mut self, frame: &mut Frame, signature: &str) -> Result<(), VmError> {
+        match signature {
+            "java/io/PrintStream/print(I)V" | "java/io/PrintStream/println(I)V" => {
+                let value = Self::pop_int(frame)?;
+                self.output += &value.to_string();
+            }
+            "java/io/PrintStream/print(C)V" | "java/io/PrintStream/println(C)V" => {
+                let value = Self::pop_int(frame)?;
+                self.output.extend(char::from_u32(value as u16 as u32));
+            }
+            "java/io/PrintStream/print(Ljava/lang/String;)V"
+            | "java/io/PrintStream/println(Ljava/lang/String;)V" => match Self::pop(frame)? {
+                Value::Ref(Object::String(string)) => self.output += &string,
+                Value::Ref(Object::Null) => self.output += "null",
+                _ => return Err(VmError::Verify("expected a java/lang/String".to_string())),
+            },
+            "java/io/PrintStream/println()V" => {}
+            "java/util/Scanner/nextInt()I" | "java/util/Scanner/nextChar()C" => {
+                match Self::pop(frame)? {
+                    Value::Ref(Object::Scanner) => {}
+                    _ => return Err(VmError::Verify("expected a java/util/Scanner".to_string())),
+                }
+                let token = self.next_token(signature.ends_with('C'))?;
+                let value = match signature.ends_with('C') {
+                    true => token.chars().next().map(|c| c as i32),
+                    false => token.parse::<i32>().ok(),
+                }
+                .ok_or(VmError::InputMismatchException(token))?;
+                frame.stack.push(Value::Int(value));
+                return Ok(());
+            }
+            signature => return Err(VmError::NoSuchMethod(signature.to_string())),
+        }
+        if signature.starts_with("java/io/PrintStream/println") {
+            self.output.push('\n');
+        }
+        match Self::pop(frame)? {
+            Value::Ref(Object::PrintStream) => Ok(()),
+            _ => Err(VmError::Verify(
+                "expected a java/io/PrintStream".to_string(),
+            )),
+        }
+    }
+
+    /// Reads the next whitespace separated token, or a single character for
+    /// the `nextChar` stub
+    fn next_token(&mut self, single: bool) -> Result<String, VmError> {
+        while self
+            .input
+            .get(self.input_position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.input_position += 1;
+        }
+        let mut token = String::new();
+        while let Some(c) = self.input.get(self.input_position) {
+            if c.is_whitespace() {
+                break;
+            }
+            token.push(*c);
+            self.input_position += 1;
+            if single {
+                break;
+            }
+        }
+        match token.is_empty() {
+            true => Err(VmError::NoSuchElementException),
+            false => Ok(token),
+        }
+    }
+
+    fn static_field(&self, field: &str) -> Result<Option<&i32>, VmError> {
+        let (class, name) = split_member(field);
+        match class == self.class.name && self.class.fields.iter().any(|f| f == name) {
+            true => Ok(self.statics.get(field)),
+            false => Err(VmError::NoSuchField(field.to_string())),
+        }
+    }
+
+    fn local(frame: &Frame, slot: usize) -> Result<Value, VmError> {
+        frame
+            .locals
+            .get(slot)
+            .cloned()
+            .ok_or_else(|| VmError::Verify(format!("local {slot} exceeds .limit locals")))
+    }
+
+    fn store(frame: &mut Frame, slot: usize, value: Value) -> Result<(), VmError> {
+        match frame.locals.get_mut(slot) {
+            Some(local) => {
+                *local = value;
+                Ok(())
+            }
+            None => Err(VmError::Verify(format!(
+                "local {slot} exceeds .limit locals"
+            ))),
+        }
+    }
+
+    fn pop(frame: &mut Frame) -> Result<Value, VmError> {
+        frame
+            .stack
+            .pop()
+            .ok_or_else(|| VmError::Verify("stack underflow".to_string()))
+    }
+
+    fn pop_int(frame: &mut Frame) -> Result<i32, VmError> {
+        match Self::pop(frame)? {
+            Value::Int(value) => Ok(value),
+            _ => Err(VmError::Verify("expected an int on the stack".to_string())),
+        }
+    }
+}
+
+fn compare(condition: Condition, a: i32, b: i32) -> bool {
+    match condition {
+        Condition::Eq => a == b,
+        Condition::Ne => a != b,
+        Condition::Lt => a < b,
+        Condition::Le => a <= b,
+        Condition::Gt => a > b,
+        Condition::Ge => a >= b,
+    }
+}
+
+/// Splits `pkg/Class/member(desc)` into the class and `member(desc)`
+fn split_member(signature: &str) -> (&str, &str) {
+    let end = signature.find('(').unwrap_or(signature.len());
+    match signature[..end].rfind('/') {
+        Some(index) => (&signature[..index], &signature[index + 1..]),
+        None => ("", signature),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn class(body: &str) -> Class {
+        Class::parse(&format!(
+            ".class public t\n.super java/lang/Object\n{body}\n\
+             .method public static main([Ljava/lang/String;)V\n\
+             \tinvokestatic t/toyc_main()I\n\tpop\n\treturn\n.end method\n"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_print_and_read() {
+        let class = class(
+            ".method public static toyc_main()I\n\t.limit locals 2\n\
+             \tnew java/util/Scanner\n\tdup\n\tgetstatic java/lang/System/in Ljava/io/InputStream;\n\
+             \tinvokespecial java/util/Scanner/<init>(Ljava/io/InputStream;)V\n\tastore 1\n\
+             \taload 1\n\tinvokevirtual java/util/Scanner/nextInt()I\n\tistore 0\n\
+             \tgetstatic java/lang/System/out Ljava/io/PrintStream;\n\tldc \"got \"\n\
+             \tinvokevirtual java/io/PrintStream/print(Ljava/lang/String;)V\n\
+             \tgetstatic java/lang/System/out Ljava/io/PrintStream;\n\tiload 0\n\tsipush 1000\n\timul\n\
+             \tinvokevirtual java/io/PrintStream/print(I)V\n\
+             \tgetstatic java/lang/System/out Ljava/io/PrintStream;\n\
+             \tinvokevirtual java/io/PrintStream/println()V\n\ticonst_0\n\tireturn\n.end method",
+        );
+        let mut vm = Vm::new(&class, " -42 ");
+        vm.run_main().unwrap();
+        assert_eq!(vm.output(), "got -42000\n");
+    }
+
+    #[test]
+    fn test_uninitialized_local() {
+        let class = class(
+            ".method public static toyc_main()I\n\t.limit locals 1\n\tiload 0\n\tireturn\n.end method",
+        );
+        assert!(matches!(
+            Vm::new(&class, "").run_main(),
+            Err(VmError::Verify(_))
+        ));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let class = class(
+            ".method public static toyc_main()I\n\tinvokestatic t/toyc_main()I\n\tireturn\n.end method",
+        );
+        assert_eq!(
+            Vm::new(&class, "").run_main(),
+            Err(VmError::StackOverflowError)
+        );
+    }
+
+    #[test]
+    fn test_invoke() {
+        let class = class(
+            ".method public static toyc_main()I\n\ticonst_0\n\tireturn\n.end method\n\
+             .method public static div(II)I\n\tiload 0\n\tiload 1\n\tidiv\n\tireturn\n.end method",
+        );
+        let mut vm = Vm::new(&class, "");
+        assert_eq!(vm.invoke("div(II)I", &[7, 2]), Ok(Some(3)));
+        assert_eq!(
+            vm.invoke("div(II)I", &[7, 0]),
+            Err(VmError::ArithmeticException)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::pass::Pass;
+use crate::warning::UnreachableCode;
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, Program, Span, Statement, StatementKind,
+};
+
+/// Removes the statements control can never reach: those following a
+/// `return` or `break`, the branch of an `if` its constant condition rules
+/// out, and loops whose condition is 0. Run after folding, so that every
+/// constant condition is a literal.
+pub fn eliminate_dead_code(program: &mut Program) {
+    for definition in &mut program.definitions {
+        if let Definition::FuncDef(func_def) = definition {
+            eliminate_in_statement(&mut func_def.statement);
+        }
+    }
+}
+
+fn eliminate_in_statement(statement: &mut Statement) {
+    match &mut statement.kind {
+        StatementKind::BlockState(_, statements) => {
+            if let Some(end) = statements.iter().position(|s| !s.completes()) {
+                statements.truncate(end + 1);
+            }
+            statements.iter_mut().for_each(eliminate_in_statement);
+        }
+        StatementKind::IfState(expr, then_stmt, else_stmt) => match expr.constant_value() {
+            Some(0) => {
+                let taken = else_stmt.take();
+                replace(statement, taken);
+            }
+            Some(_) => {
+                let taken = std::mem::replace(&mut **then_stmt, null_statement(statement.span));
+                replace(statement, Some(taken));
+            }
+            None => {
+                eliminate_in_statement(then_stmt);
+                if let Some(else_stmt) = else_stmt.as_mut() {
+                    eliminate_in_statement(else_stmt);
+                }
+            }
+        },
+        StatementKind::WhileState(expr, _) if expr.constant_value() == Some(0) => {
+            replace(statement, None)
+        }
+        StatementKind::WhileState(_, body) => eliminate_in_statement(body),
+        StatementKind::Expression(_)
+        | StatementKind::Break
+        | StatementKind::NullState
+        | StatementKind::ReturnState(_)
+        | StatementKind::ReadState(..)
+        | StatementKind::WriteState(..)
+        | StatementKind::NewLineState => {}
+    }
+}
+
+/// Replaces a statement with the branch that is always taken, or with an
+/// empty statement
+fn replace(statement: &mut Statement, taken: Option<Statement>) {
+    *statement = taken.unwrap_or_else(|| null_statement(statement.span));
+    eliminate_in_statement(statement);
+}
+
+fn null_statement(span: Span) -> Statement {
+    Statement::new(StatementKind::NullState, span)
+}
+
+/// Warns once for every stretch of unreachable code, at its first
+/// statement. Only the code reachable so far is searched, so a dead
+/// statement inside dead code is not reported again.
+pub fn find_unreachable_code(
+    program: &Program,
+    file_name: &str,
+    source: &str,
+) -> Vec<UnreachableCode> {
+    let mut spans = vec![];
+    for definition in &program.definitions {
+        if let Definition::FuncDef(func_def) = definition {
+            unreachable_in_statement(&func_def.statement, &mut spans);
+        }
+    }
+    spans
+        .into_iter()
+        .map(|span: Span| {
+            let line = source.lines().nth(span.line() - 1).map(str::to_owned);
+            UnreachableCode::new(span, line, file_name.to_owned())
+        })
+        .collect()
+}
+
+fn unreachable_in_statement(statement: &Statement, spans: &mut Vec<Span>) {
+    match &statement.kind {
+        StatementKind::BlockState(_, statements) => {
+            for (index, statement) in statements.iter().enumerate() {
+                unreachable_in_statement(statement, spans);
+                if !statement.completes() {
+                    if let Some(dead) = statements.get(index + 1) {
+                        spans.push(start(dead));
+                    }
+                    break;
+                }
+            }
+        }
+        StatementKind::IfState(expr, then_stmt, else_stmt) => {
+            let else_stmt = else_stmt.as_ref().as_ref();
+            match expr.constant_value() {
+                Some(0) => spans.push(start(then_stmt)),
+                _ => unreachable_in_statement(then_stmt, spans),
+            }
+            if let Some(else_stmt) = else_stmt {
+                match expr.constant_value() {
+                    Some(0) | None => unreachable_in_statement(else_stmt, spans),
+                    Some(_) => spans.push(start(else_stmt)),
+                }
+            }
+        }
+        StatementKind::WhileState(expr, body) => match expr.constant_value() {
+            Some(0) => spans.push(start(body)),
+            _ => unreachable_in_statement(body, spans),
+        },
+        StatementKind::Expression(_)
+        | StatementKind::Break
+        | StatementKind::NullState
+        | StatementKind::ReturnState(_)
+        | StatementKind::ReadState(..)
+        | StatementKind::WriteState(..)
+        | StatementKind::NewLineState => {}
+    }
+}
+
+/// Where a statement starts. An expression statement is located at its
+/// operator, so the warning points at its left-most operand instead.
+fn start(statement: &Statement) -> Span {
+    fn left_most(expression: &Expression) -> &Expression {
+        match &expression.kind {
+            ExpressionKind::Expr(_, lhs, _) => left_most(lhs),
+            _ => expression,
+        }
+    }
+    match &statement.kind {
+        StatementKind::Expression(expr) => left_most(expr).span,
+        _ => statement.span,
+    }
+}
+
+/// Removes unreachable code
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        eliminate_dead_code(program)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    fn parse(source: &str) -> Program {
+        Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse")
+    }
+
+    fn body(program: &Program) -> &[Statement] {
+        let Definition::FuncDef(main) = &program.definitions[0] else {
+            unreachable!()
+        };
+        let StatementKind::BlockState(_, statements) = &main.statement.kind else {
+            unreachable!()
+        };
+        statements
+    }
+
+    #[test]
+    fn test_elimination() {
+        let mut program = parse(
+            "int main(){ int a;
+             if (0) a = 1; else a = 2;
+             while (0) a = 3;
+             while (a) { return a; a = 4; }
+             if (1) return 5;
+             write(a);
+             return 6; }",
+        );
+        eliminate_dead_code(&mut program);
+        let statements = body(&program);
+        assert_eq!(statements.len(), 4);
+        assert!(matches!(statements[0].kind, StatementKind::Expression(_)));
+        assert!(matches!(statements[1].kind, StatementKind::NullState));
+        let StatementKind::WhileState(_, loop_body) = &statements[2].kind else {
+            panic!("expected a loop");
+        };
+        assert!(matches!(&loop_body.kind, StatementKind::BlockState(_, s) if s.len() == 1));
+        assert!(matches!(statements[3].kind, StatementKind::ReturnState(_)));
+    }
+
+    #[test]
+    fn test_warnings() {
+        let source = "int main(){ int a;
+  while (1) { break; a = 1; }
+  if (0) { return 1; a = 2; }
+  return 0;
+  a = 3; write(a);
+}";
+        let program = parse(source);
+        let lines = find_unreachable_code(&program, "test.tc", source)
+            .iter()
+            .map(|warning| warning.span.location)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![(2, 22), (3, 10), (5, 3)]);
+    }
+}
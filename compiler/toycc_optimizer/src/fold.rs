@@ -0,0 +1,145 @@
+use crate::pass::Pass;
+use crate::{is_char, literal};
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, Program, Statement, StatementKind,
+};
+
+/// Replaces every expression made only of literals with its value
+pub fn fold_constants(program: &mut Program) {
+    for definition in &mut program.definitions {
+        if let Definition::FuncDef(func_def) = definition {
+            fold_statement(&mut func_def.statement);
+        }
+    }
+}
+
+fn fold_statement(statement: &mut Statement) {
+    match &mut statement.kind {
+        StatementKind::Expression(expr) => fold_expression(expr),
+        StatementKind::BlockState(_, statements) => statements.iter_mut().for_each(fold_statement),
+        StatementKind::IfState(expr, then_stmt, else_stmt) => {
+            fold_expression(expr);
+            fold_statement(then_stmt);
+            if let Some(else_stmt) = else_stmt.as_mut() {
+                fold_statement(else_stmt);
+            }
+        }
+        StatementKind::ReturnState(Some(expr)) => fold_expression(expr),
+        StatementKind::WhileState(expr, body) => {
+            fold_expression(expr);
+            fold_statement(body);
+        }
+        StatementKind::WriteState(expr, others) => {
+            fold_expression(expr);
+            others.iter_mut().flatten().for_each(fold_expression);
+        }
+        StatementKind::Break
+        | StatementKind::NullState
+        | StatementKind::ReturnState(None)
+        | StatementKind::ReadState(..)
+        | StatementKind::NewLineState => {}
+    }
+}
+
+pub(crate) fn fold_expression(expression: &mut Expression) {
+    match &mut expression.kind {
+        ExpressionKind::Expr(_, lhs, rhs) => {
+            fold_expression(lhs);
+            fold_expression(rhs);
+        }
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => fold_expression(expr),
+        ExpressionKind::FuncCall(_, arguments) => arguments.iter_mut().for_each(fold_expression),
+        ExpressionKind::Number(_)
+        | ExpressionKind::CharLiteral(_)
+        | ExpressionKind::Identifier(_)
+        | ExpressionKind::StringLiteral(_) => return,
+    }
+    if let Some(kind) = expression
+        .constant_value()
+        .and_then(|value| literal(value, is_char(expression)))
+    {
+        expression.kind = kind;
+    }
+}
+
+/// Folds constant expressions
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "fold"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        fold_constants(program)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    /// Folds `expression` written as the return value of `main`
+    fn fold(expression: &str) -> ExpressionKind {
+        let source = format!("int main(){{ int x; return {expression}; }}");
+        let mut program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        fold_constants(&mut program);
+        let Definition::FuncDef(main) = program.definitions.remove(0) else {
+            unreachable!()
+        };
+        let StatementKind::BlockState(_, mut statements) = main.statement.kind else {
+            unreachable!()
+        };
+        match statements.remove(0).kind {
+            StatementKind::ReturnState(Some(expr)) => expr.kind,
+            _ => unreachable!(),
+        }
+    }
+
+    fn number(kind: ExpressionKind) -> Option<i32> {
+        match kind {
+            ExpressionKind::Number(num) => Some(num as i32),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(number(fold("(3 * 4) + 1")), Some(13));
+        assert_eq!(number(fold("(-(7 % 4)) - (9 / 2)")), Some(-7));
+        assert_eq!(number(fold("(2 < 3) == !0")), Some(1));
+        // ToyC integers wrap around like the JVM's
+        assert_eq!(number(fold("2147483647 + 1")), Some(i32::MIN));
+        assert_eq!(
+            number(fold("((0 - 2147483647) - 1) / (0 - 1)")),
+            Some(i32::MIN)
+        );
+    }
+
+    #[test]
+    fn test_partial_folding() {
+        let ExpressionKind::Expr(_, lhs, rhs) = fold("x + (2 * 3)") else {
+            panic!("expected a binary expression");
+        };
+        assert!(matches!(lhs.kind, ExpressionKind::Identifier(_)));
+        assert!(matches!(rhs.kind, ExpressionKind::Number(num) if num == 6.0));
+
+        // A division by zero is left for the semantic check to report
+        assert!(matches!(fold("1 / (2 - 2)"), ExpressionKind::Expr(..)));
+        assert_eq!(number(fold("0 && (x = 1)")), Some(0));
+        assert!(matches!(fold("1 && (x = 1)"), ExpressionKind::Expr(..)));
+    }
+
+    #[test]
+    fn test_character_type_is_kept() {
+        assert!(matches!(
+            fold("'a' + 1"),
+            ExpressionKind::CharLiteral(Some('b'))
+        ));
+        assert!(matches!(fold("'a' - 'b'"), ExpressionKind::Expr(..)));
+    }
+}
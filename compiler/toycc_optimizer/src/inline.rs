@@ -0,0 +1,391 @@
+use crate::pass::{expression_size, Pass};
+use std::collections::HashMap;
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+
+/// Largest body, in expression nodes, inlined when `-inline-threshold` is
+/// not given
+pub const DEFAULT_INLINE_THRESHOLD: usize = 16;
+
+/// Replaces calls to functions whose body returns a small call-free expression
+/// with that expression
+pub struct Inliner {
+    threshold: usize,
+    report: Vec<String>,
+}
+
+impl Inliner {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            report: vec![],
+        }
+    }
+}
+
+impl Pass for Inliner {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        let mut context = Context::default();
+        for definition in &mut program.definitions {
+            match definition {
+                Definition::VarDef(var_def) => context.declare_globals(var_def),
+                Definition::FuncDef(func_def) => {
+                    context.function(func_def, &mut self.report);
+                    context.add_callee(func_def, self.threshold);
+                }
+            }
+        }
+    }
+
+    fn report(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.report)
+    }
+}
+
+struct Callee {
+    params: Vec<(String, Type)>,
+    return_type: Type,
+    /// The returned expression when the function can be inlined
+    body: Option<Expression>,
+}
+
+#[derive(Default)]
+struct Context {
+    globals: HashMap<String, Type>,
+    functions: HashMap<String, Callee>,
+    /// Variables of the function being processed, by scope
+    scopes: Vec<HashMap<String, Type>>,
+    caller: String,
+}
+
+impl Context {
+    fn declare_globals(&mut self, var_def: &VarDef) {
+        for id in &var_def.identifiers {
+            self.globals.insert(id.clone(), var_def.toyc_type.clone());
+        }
+    }
+
+    fn declare(&mut self, var_def: &VarDef) {
+        let scope = self.scopes.last_mut().unwrap();
+        for id in &var_def.identifiers {
+            scope.insert(id.clone(), var_def.toyc_type.clone());
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<&Type> {
+        self.scopes.iter().rev().find_map(|s| s.get(name))
+    }
+
+    fn function(&mut self, func_def: &mut FuncDef, report: &mut Vec<String>) {
+        self.caller = func_def.identifier.clone();
+        self.scopes = vec![HashMap::new()];
+        func_def.var_def.iter().for_each(|v| self.declare(v));
+        self.statement(&mut func_def.statement, report);
+    }
+
+    fn add_callee(&mut self, func_def: &FuncDef, threshold: usize) {
+        let params = func_def
+            .var_def
+            .iter()
+            .flat_map(|v| {
+                v.identifiers
+                    .iter()
+                    .map(|id| (id.clone(), v.toyc_type.clone()))
+            })
+            .collect();
+        let returned = match &func_def.statement.kind {
+            StatementKind::BlockState(var_defs, statements) if var_defs.is_empty() => {
+                match statements.as_slice() {
+                    [statement] => returned_expression(statement),
+                    _ => None,
+                }
+            }
+            _ => returned_expression(&func_def.statement),
+        };
+        let body = returned
+            .filter(|e| is_leaf(e) && expression_size(e) <= threshold)
+            .cloned();
+        let callee = Callee {
+            params,
+            return_type: func_def.toyc_type.clone(),
+            body,
+        };
+        self.functions.insert(func_def.identifier.clone(), callee);
+    }
+
+    fn statement(&mut self, statement: &mut Statement, report: &mut Vec<String>) {
+        match &mut statement.kind {
+            StatementKind::Expression(expr) | StatementKind::ReturnState(Some(expr)) => {
+                self.expression(expr, report)
+            }
+            StatementKind::BlockState(var_defs, statements) => {
+                self.scopes.push(HashMap::new());
+                var_defs.iter().for_each(|v| self.declare(v));
+                statements
+                    .iter_mut()
+                    .for_each(|s| self.statement(s, report));
+                self.scopes.pop();
+            }
+            StatementKind::IfState(expr, then_stmt, else_stmt) => {
+                self.expression(expr, report);
+                self.statement(then_stmt, report);
+                if let Some(else_stmt) = else_stmt.as_mut() {
+                    self.statement(else_stmt, report);
+                }
+            }
+            StatementKind::WhileState(expr, body) => {
+                self.expression(expr, report);
+                self.statement(body, report);
+            }
+            StatementKind::WriteState(expr, others) => {
+                self.expression(expr, report);
+                others
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|e| self.expression(e, report));
+            }
+            StatementKind::Break
+            | StatementKind::NullState
+            | StatementKind::ReturnState(None)
+            | StatementKind::ReadState(..)
+            | StatementKind::NewLineState => {}
+        }
+    }
+
+    fn expression(&mut self, expression: &mut Expression, report: &mut Vec<String>) {
+        match &mut expression.kind {
+            ExpressionKind::Expr(_, lhs, rhs) => {
+                self.expression(lhs, report);
+                self.expression(rhs, report);
+            }
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => {
+                self.expression(expr, report)
+            }
+            ExpressionKind::FuncCall(name, arguments) => {
+                arguments
+                    .iter_mut()
+                    .for_each(|a| self.expression(a, report));
+                if let Some(inlined) = self.inline(name, arguments) {
+                    report.push(format!(
+                        "inlined {name} into {} at line {}",
+                        self.caller,
+                        expression.span.line()
+                    ));
+                    expression.kind = inlined.kind;
+                }
+            }
+            ExpressionKind::Number(_)
+            | ExpressionKind::CharLiteral(_)
+            | ExpressionKind::Identifier(_)
+            | ExpressionKind::StringLiteral(_) => {}
+        }
+    }
+
+    /// The body of `name` with the parameters replaced by `arguments`, when
+    /// the call can be inlined
+    fn inline(&self, name: &str, arguments: &[Expression]) -> Option<Expression> {
+        let callee = self.functions.get(name)?;
+        let body = callee.body.as_ref()?;
+        if callee.params.len() != arguments.len() {
+            return None;
+        }
+        // Each argument must still be evaluated once and in any order
+        for ((param, param_type), argument) in callee.params.iter().zip(arguments) {
+            let uses = count_uses(body, param);
+            let simple = matches!(
+                argument.kind,
+                ExpressionKind::Number(_)
+                    | ExpressionKind::CharLiteral(_)
+                    | ExpressionKind::Identifier(_)
+            );
+            if !(simple || uses == 1 && is_leaf(argument))
+                || self.type_of(argument).as_ref() != Some(param_type)
+            {
+                return None;
+            }
+        }
+        let mut free = vec![];
+        free_variables(body, &callee.params, &mut free);
+        if free.iter().any(|name| self.local(name).is_some()) {
+            return None;
+        }
+
+        let params = callee
+            .params
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .zip(arguments)
+            .collect::<HashMap<_, _>>();
+        let inlined = substitute(body, &params);
+        (self.type_of(&inlined).as_ref() == Some(&callee.return_type) && !divides_by_zero(&inlined))
+            .then_some(inlined)
+    }
+
+    /// Type of an expression in the caller, the type of its left-most operand
+    fn type_of(&self, expression: &Expression) -> Option<Type> {
+        match &expression.kind {
+            ExpressionKind::Number(_) => Some(Type::Int),
+            ExpressionKind::CharLiteral(_) => Some(Type::Char),
+            ExpressionKind::Identifier(id) => self.local(id).or(self.globals.get(id)).cloned(),
+            ExpressionKind::FuncCall(name, _) => {
+                self.functions.get(name).map(|f| f.return_type.clone())
+            }
+            ExpressionKind::Expr(_, lhs, _) => self.type_of(lhs),
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.type_of(expr),
+            ExpressionKind::StringLiteral(_) => None,
+        }
+    }
+}
+
+fn returned_expression(statement: &Statement) -> Option<&Expression> {
+    match &statement.kind {
+        StatementKind::ReturnState(expr) => expr.as_ref(),
+        _ => None,
+    }
+}
+
+/// Whether an expression has no calls, assignments or strings
+fn is_leaf(expression: &Expression) -> bool {
+    match &expression.kind {
+        ExpressionKind::Expr(Operator::Assign, ..)
+        | ExpressionKind::FuncCall(..)
+        | ExpressionKind::StringLiteral(_) => false,
+        ExpressionKind::Expr(_, lhs, rhs) => is_leaf(lhs) && is_leaf(rhs),
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => is_leaf(expr),
+        ExpressionKind::Number(_)
+        | ExpressionKind::CharLiteral(_)
+        | ExpressionKind::Identifier(_) => true,
+    }
+}
+
+/// Whether an expression divides by a constant zero, which the code
+/// generators reject while the call it would replace only fails when run
+fn divides_by_zero(expression: &Expression) -> bool {
+    match &expression.kind {
+        ExpressionKind::Expr(Operator::Divide | Operator::Modulo, _, rhs)
+            if rhs.constant_value() == Some(0) =>
+        {
+            true
+        }
+        ExpressionKind::Expr(_, lhs, rhs) => divides_by_zero(lhs) || divides_by_zero(rhs),
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => divides_by_zero(expr),
+        _ => false,
+    }
+}
+
+fn count_uses(expression: &Expression, name: &str) -> usize {
+    match &expression.kind {
+        ExpressionKind::Identifier(id) => (id == name) as usize,
+        ExpressionKind::Expr(_, lhs, rhs) => count_uses(lhs, name) + count_uses(rhs, name),
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => count_uses(expr, name),
+        _ => 0,
+    }
+}
+
+/// Variables a leaf body reads besides its parameters, which are globals
+fn free_variables(expression: &Expression, params: &[(String, Type)], names: &mut Vec<String>) {
+    match &expression.kind {
+        ExpressionKind::Identifier(id) if params.iter().all(|(p, _)| p != id) => {
+            names.push(id.clone())
+        }
+        ExpressionKind::Expr(_, lhs, rhs) => {
+            free_variables(lhs, params, names);
+            free_variables(rhs, params, names);
+        }
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => {
+            free_variables(expr, params, names)
+        }
+        _ => {}
+    }
+}
+
+fn substitute(expression: &Expression, params: &HashMap<&str, &Expression>) -> Expression {
+    let kind = match &expression.kind {
+        ExpressionKind::Identifier(id) => match params.get(id.as_str()) {
+            Some(argument) => return (*argument).clone(),
+            None => ExpressionKind::Identifier(id.clone()),
+        },
+        ExpressionKind::Expr(op, lhs, rhs) => ExpressionKind::Expr(
+            op.clone(),
+            Box::new(substitute(lhs, params)),
+            Box::new(substitute(rhs, params)),
+        ),
+        ExpressionKind::Not(expr) => ExpressionKind::Not(Box::new(substitute(expr, params))),
+        ExpressionKind::Minus(expr) => ExpressionKind::Minus(Box::new(substitute(expr, params))),
+        kind => kind.clone(),
+    };
+    Expression::new(kind, expression.span)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    /// Inlines calls in a program and returns the report and the program
+    fn inline(source: &str) -> (Vec<String>, String) {
+        let mut program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        let mut inliner = Inliner::new(DEFAULT_INLINE_THRESHOLD);
+        inliner.run(&mut program);
+        (inliner.report(), program.to_string())
+    }
+
+    #[test]
+    fn test_leaf_functions() {
+        let (report, program) = inline(
+            "int addTwo(int a){ return (a + 2); }
+int twice(int x){ return addTwo(x) * 2; }
+int main(){ int a; int b; a = 1; b = twice(a + 1) + addTwo(b = 3); return b; }",
+        );
+        assert_eq!(
+            report,
+            [
+                "inlined addTwo into twice at line 2",
+                "inlined twice into main at line 3",
+            ]
+        );
+        // The assignment in the second argument keeps its call
+        assert!(program.contains("addTwo"));
+        assert_eq!(program.matches("twice").count(), 1);
+    }
+
+    #[test]
+    fn test_shadowing_and_types() {
+        // The parameter `g` of `main` would capture the global read by `f`
+        let (report, _) = inline(
+            "int g;
+int f(int a){ return a + g; }
+int h(int g){ return f(g); }
+int main(){ int a; a = 2; return f(a); }",
+        );
+        assert_eq!(report, ["inlined f into main at line 4"]);
+
+        // `c + 1` would be a char, while `next` returns an int
+        let (report, _) = inline(
+            "int next(int n){ return n + 1; }
+int square(int n){ return n * n; }
+int main(){ char c; int i; c = 'a'; i = 2; write(next(c), square(i + 1)); return square(i); }",
+        );
+        assert_eq!(report, ["inlined square into main at line 3"]);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        // `1 / 0` would be rejected, while the call only fails when run
+        let (report, _) = inline(
+            "int div(int a, int b){ return a / (b - 1); }
+int main(){ write(div(6, 3)); write(div(1, 1)); return 0; }",
+        );
+        assert_eq!(report, ["inlined div into main at line 2"]);
+    }
+}
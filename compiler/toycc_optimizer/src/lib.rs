@@ -0,0 +1,38 @@
+//! Optimizations over the checked AST. Every backend generates code from the
+//! AST, so rewriting it here benefits all of them. Passes keep the static
+//! type of each expression: ToyC gives a compound expression the type of its
+//! left-most operand, so a folded character expression stays a character.
+mod dead_code;
+mod fold;
+mod inline;
+mod pass;
+mod propagate;
+mod warning;
+
+pub use dead_code::{eliminate_dead_code, find_unreachable_code, DeadCodeElimination};
+pub use fold::{fold_constants, ConstantFolding};
+pub use inline::{Inliner, DEFAULT_INLINE_THRESHOLD};
+pub use pass::{Pass, PassManager};
+pub use propagate::{propagate_constants, ConstantPropagation};
+pub use warning::UnreachableCode;
+
+use toycc_frontend::ast::{Expression, ExpressionKind};
+
+/// Literal replacing an expression whose value is known, or `None` when a
+/// character expression has a value that is not a character
+pub(crate) fn literal(value: i32, is_char: bool) -> Option<ExpressionKind> {
+    match is_char {
+        true => char::from_u32(value as u32).map(|c| ExpressionKind::CharLiteral(Some(c))),
+        false => Some(ExpressionKind::Number(value as f64)),
+    }
+}
+
+/// Whether the left-most operand of a literal expression is a character
+pub(crate) fn is_char(expression: &Expression) -> bool {
+    match &expression.kind {
+        ExpressionKind::CharLiteral(_) => true,
+        ExpressionKind::Expr(_, lhs, _) => is_char(lhs),
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => is_char(expr),
+        _ => false,
+    }
+}
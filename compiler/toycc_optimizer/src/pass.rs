@@ -0,0 +1,193 @@
+use crate::dead_code::DeadCodeElimination;
+use crate::fold::ConstantFolding;
+use crate::inline::Inliner;
+use crate::propagate::ConstantPropagation;
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, Program, Statement, StatementKind,
+};
+
+/// A transformation of the whole program
+pub trait Pass {
+    fn name(&self) -> &'static str;
+    fn run(&mut self, program: &mut Program);
+
+    /// Lines describing what the last run did, shown at debug level 3
+    fn report(&mut self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Runs the passes of an optimization level; backends run their own passes
+/// and record them here
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    backend_passes: Vec<&'static str>,
+    debug: Option<u32>,
+    ran: Vec<&'static str>,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self {
+            passes: vec![],
+            backend_passes: vec![],
+            debug: None,
+            ran: vec![],
+        }
+    }
+
+    /// Passes of `-O<level>`
+    pub fn for_level(level: u32, inline_threshold: usize) -> Self {
+        let mut manager = Self::new();
+        if level >= 2 {
+            manager
+                .add(Inliner::new(inline_threshold))
+                .add_backend("licm")
+                .add_backend("strength");
+        }
+        if level >= 1 {
+            manager
+                .add(ConstantPropagation)
+                .add(ConstantFolding)
+                .add(DeadCodeElimination)
+                .add_backend("tailcall")
+                .add_backend("peephole");
+        }
+        manager
+    }
+
+    pub fn add<P: Pass + 'static>(&mut self, pass: P) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn add_backend(&mut self, name: &'static str) -> &mut Self {
+        self.backend_passes.push(name);
+        self
+    }
+
+    /// Prints what every pass did at debug levels 0 and 3
+    pub fn debug(&mut self, debug: Option<u32>) -> &mut Self {
+        self.debug = debug;
+        self
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.backend_passes.contains(&name) || self.passes.iter().any(|pass| pass.name() == name)
+    }
+
+    pub fn run(&mut self, program: &mut Program) {
+        for pass in &mut self.passes {
+            let before = program_size(program);
+            pass.run(program);
+            let after = program_size(program);
+            self.ran.push(pass.name());
+            debug_print(self.debug, pass.name(), before, after, "nodes");
+            for line in pass.report() {
+                if let Some(0 | 3) = self.debug {
+                    println!("[OPTIMIZER] {}: {line}", pass.name());
+                }
+            }
+        }
+    }
+
+    /// Records a run of a backend pass that shrank the code from `before` to
+    /// `after` units, such as instructions
+    pub fn record(&mut self, name: &'static str, before: usize, after: usize, unit: &str) {
+        self.ran.push(name);
+        debug_print(self.debug, name, before, after, unit);
+    }
+
+    /// Names of the passes that ran, in order
+    pub fn ran(&self) -> &[&'static str] {
+        &self.ran
+    }
+}
+
+fn debug_print(debug: Option<u32>, name: &str, before: usize, after: usize, unit: &str) {
+    if let Some(0 | 3) = debug {
+        println!("[OPTIMIZER] {name}: {before} -> {after} {unit}");
+    }
+}
+
+/// Number of statements and expressions in a program
+pub(crate) fn program_size(program: &Program) -> usize {
+    program
+        .definitions
+        .iter()
+        .map(|definition| match definition {
+            Definition::FuncDef(func_def) => statement_size(&func_def.statement),
+            Definition::VarDef(_) => 0,
+        })
+        .sum()
+}
+
+pub(crate) fn statement_size(statement: &Statement) -> usize {
+    1 + match &statement.kind {
+        StatementKind::Expression(expr) | StatementKind::ReturnState(Some(expr)) => {
+            expression_size(expr)
+        }
+        StatementKind::BlockState(_, statements) => statements.iter().map(statement_size).sum(),
+        StatementKind::IfState(expr, then_stmt, else_stmt) => {
+            expression_size(expr)
+                + statement_size(then_stmt)
+                + else_stmt.as_ref().as_ref().map_or(0, statement_size)
+        }
+        StatementKind::WhileState(expr, body) => expression_size(expr) + statement_size(body),
+        StatementKind::WriteState(expr, others) => std::iter::once(expr)
+            .chain(others.iter().flatten())
+            .map(expression_size)
+            .sum(),
+        StatementKind::Break
+        | StatementKind::NullState
+        | StatementKind::ReturnState(None)
+        | StatementKind::ReadState(..)
+        | StatementKind::NewLineState => 0,
+    }
+}
+
+pub(crate) fn expression_size(expression: &Expression) -> usize {
+    1 + match &expression.kind {
+        ExpressionKind::Expr(_, lhs, rhs) => expression_size(lhs) + expression_size(rhs),
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => expression_size(expr),
+        ExpressionKind::FuncCall(_, arguments) => arguments.iter().map(expression_size).sum(),
+        ExpressionKind::Number(_)
+        | ExpressionKind::CharLiteral(_)
+        | ExpressionKind::Identifier(_)
+        | ExpressionKind::StringLiteral(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    #[test]
+    fn test_levels() {
+        let source = "int main(){ int a; a = 2 * 3; if (0) write(a); return a; }";
+        let mut program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        let size = program_size(&program);
+
+        let mut manager = PassManager::for_level(0, 0);
+        manager.run(&mut program);
+        assert!(manager.ran().is_empty());
+        assert!(!manager.is_enabled("peephole"));
+        assert_eq!(program_size(&program), size);
+
+        let mut manager = PassManager::for_level(1, 0);
+        manager.run(&mut program);
+        manager.record("peephole", 10, 8, "instructions");
+        assert_eq!(manager.ran(), ["propagate", "fold", "dce", "peephole"]);
+        assert!(program_size(&program) < size);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::literal;
+use crate::pass::Pass;
+use std::collections::HashMap;
+use toycc_frontend::ast::{
+    Definition, Expression, ExpressionKind, FuncDef, Operator, Program, Statement, StatementKind,
+    VarDef,
+};
+use toycc_frontend::Type;
+
+/// Replaces reads of local variables whose value is known with the value
+pub fn propagate_constants(program: &mut Program) {
+    for definition in &mut program.definitions {
+        if let Definition::FuncDef(func_def) = definition {
+            Propagator::default().function(func_def);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Propagator {
+    /// Variables visible in each open scope, by name
+    scopes: Vec<HashMap<String, usize>>,
+    /// Whether each variable is a `char`
+    chars: Vec<bool>,
+    /// Known value of the variables
+    values: HashMap<usize, i32>,
+}
+
+impl Propagator {
+    fn function(&mut self, func_def: &mut FuncDef) {
+        self.scopes.push(HashMap::new());
+        func_def.var_def.iter().for_each(|v| self.declare(v));
+        self.statement(&mut func_def.statement);
+    }
+
+    fn declare(&mut self, var_def: &VarDef) {
+        for id in &var_def.identifiers {
+            self.chars.push(var_def.toyc_type == Type::Char);
+            let variable = self.chars.len() - 1;
+            self.scopes.last_mut().unwrap().insert(id.clone(), variable);
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rev().find_map(|s| s.get(name).copied())
+    }
+
+    fn value(&self, name: &str) -> Option<i32> {
+        self.values.get(&self.resolve(name)?).copied()
+    }
+
+    fn forget(&mut self, names: &[String]) {
+        for name in names {
+            if let Some(variable) = self.resolve(name) {
+                self.values.remove(&variable);
+            }
+        }
+    }
+
+    /// Keeps the values `other` agrees with, where two paths join
+    fn join(&mut self, other: &HashMap<usize, i32>) {
+        self.values.retain(|k, v| other.get(k) == Some(v));
+    }
+
+    fn statement(&mut self, statement: &mut Statement) {
+        match &mut statement.kind {
+            StatementKind::Expression(expr) => self.expression(expr),
+            StatementKind::BlockState(var_defs, statements) => {
+                self.scopes.push(HashMap::new());
+                var_defs.iter().for_each(|v| self.declare(v));
+                statements.iter_mut().for_each(|s| self.statement(s));
+                self.scopes.pop();
+            }
+            StatementKind::IfState(expr, then_stmt, else_stmt) => {
+                self.expression(expr);
+                let before = self.values.clone();
+                self.statement(then_stmt);
+                let after_then = std::mem::replace(&mut self.values, before);
+                if let Some(else_stmt) = else_stmt.as_mut() {
+                    self.statement(else_stmt);
+                }
+                self.join(&after_then);
+            }
+            StatementKind::WhileState(expr, body) => {
+                let mut in_body = vec![];
+                assigned_in_statement(body, &mut in_body);
+                let mut in_loop = in_body.clone();
+                assigned_in_expression(expr, &mut in_loop);
+                self.forget(&in_loop);
+                self.expression(expr);
+                let at_exit = self.values.clone();
+                self.statement(body);
+                // A `break` may leave with the values of the body
+                self.values = at_exit;
+                self.forget(&in_body);
+            }
+            StatementKind::ReturnState(Some(expr)) => self.expression(expr),
+            StatementKind::ReadState(name, others) => {
+                let names = std::iter::once(&*name).chain(others.iter().flatten());
+                self.forget(&names.cloned().collect::<Vec<_>>());
+            }
+            StatementKind::WriteState(expr, others) => {
+                self.expression(expr);
+                others.iter_mut().flatten().for_each(|e| self.expression(e));
+            }
+            StatementKind::Break
+            | StatementKind::NullState
+            | StatementKind::ReturnState(None)
+            | StatementKind::NewLineState => {}
+        }
+    }
+
+    /// Visits an expression in evaluation order, so that an assignment is
+    /// only seen by the operands evaluated after it
+    fn expression(&mut self, expression: &mut Expression) {
+        match &mut expression.kind {
+            ExpressionKind::Identifier(name) => {
+                let Some(variable) = self.resolve(name) else {
+                    return;
+                };
+                let value = self.values.get(&variable).copied();
+                if let Some(kind) = value.and_then(|v| literal(v, self.chars[variable])) {
+                    expression.kind = kind;
+                }
+            }
+            ExpressionKind::Expr(Operator::Assign, lhs, rhs) => {
+                self.expression(rhs);
+                if let ExpressionKind::Identifier(name) = &lhs.kind {
+                    if let Some(variable) = self.resolve(name) {
+                        match rhs.constant_value() {
+                            Some(value) => self.values.insert(variable, value),
+                            None => self.values.remove(&variable),
+                        };
+                    }
+                }
+            }
+            ExpressionKind::Expr(Operator::And | Operator::Or, lhs, rhs) => {
+                self.expression(lhs);
+                // The right operand is not always evaluated
+                let before = self.values.clone();
+                self.expression(rhs);
+                self.join(&before);
+            }
+            ExpressionKind::Expr(Operator::Divide | Operator::Modulo, lhs, rhs) => {
+                self.expression(lhs);
+                if rhs.value_with(&|name| self.value(name)) != Some(0) {
+                    self.expression(rhs);
+                }
+            }
+            ExpressionKind::Expr(_, lhs, rhs) => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+            ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => self.expression(expr),
+            ExpressionKind::FuncCall(_, arguments) => {
+                arguments.iter_mut().for_each(|a| self.expression(a))
+            }
+            ExpressionKind::Number(_)
+            | ExpressionKind::CharLiteral(_)
+            | ExpressionKind::StringLiteral(_) => {}
+        }
+    }
+}
+
+/// Names of the variables a statement may assign, including with `read`
+fn assigned_in_statement(statement: &Statement, names: &mut Vec<String>) {
+    match &statement.kind {
+        StatementKind::Expression(expr) | StatementKind::ReturnState(Some(expr)) => {
+            assigned_in_expression(expr, names)
+        }
+        StatementKind::BlockState(_, statements) => statements
+            .iter()
+            .for_each(|s| assigned_in_statement(s, names)),
+        StatementKind::IfState(expr, then_stmt, else_stmt) => {
+            assigned_in_expression(expr, names);
+            assigned_in_statement(then_stmt, names);
+            if let Some(else_stmt) = else_stmt.as_ref() {
+                assigned_in_statement(else_stmt, names);
+            }
+        }
+        StatementKind::WhileState(expr, body) => {
+            assigned_in_expression(expr, names);
+            assigned_in_statement(body, names);
+        }
+        StatementKind::ReadState(name, others) => names.extend(
+            std::iter::once(name)
+                .chain(others.iter().flatten())
+                .cloned(),
+        ),
+        StatementKind::WriteState(expr, others) => std::iter::once(expr)
+            .chain(others.iter().flatten())
+            .for_each(|e| assigned_in_expression(e, names)),
+        StatementKind::Break
+        | StatementKind::NullState
+        | StatementKind::ReturnState(None)
+        | StatementKind::NewLineState => {}
+    }
+}
+
+fn assigned_in_expression(expression: &Expression, names: &mut Vec<String>) {
+    match &expression.kind {
+        ExpressionKind::Expr(op, lhs, rhs) => {
+            if let (Operator::Assign, ExpressionKind::Identifier(name)) = (op, &lhs.kind) {
+                names.push(name.clone());
+            }
+            assigned_in_expression(lhs, names);
+            assigned_in_expression(rhs, names);
+        }
+        ExpressionKind::Not(expr) | ExpressionKind::Minus(expr) => {
+            assigned_in_expression(expr, names)
+        }
+        ExpressionKind::FuncCall(_, arguments) => arguments
+            .iter()
+            .for_each(|a| assigned_in_expression(a, names)),
+        ExpressionKind::Number(_)
+        | ExpressionKind::CharLiteral(_)
+        | ExpressionKind::Identifier(_)
+        | ExpressionKind::StringLiteral(_) => {}
+    }
+}
+
+/// Propagates the known values of local variables
+pub struct ConstantPropagation;
+
+impl Pass for ConstantPropagation {
+    fn name(&self) -> &'static str {
+        "propagate"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        propagate_constants(program)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use toycc_frontend::Parser;
+
+    /// Propagates constants in `main` and returns the statements of its body
+    fn propagate(body: &str) -> Vec<Statement> {
+        let source = format!("int main(){{ int a; int b; {body} }}");
+        let mut program = Parser::new(Cursor::new(source), "test.tc", None)
+            .parse()
+            .expect("failed to parse");
+        propagate_constants(&mut program);
+        crate::fold_constants(&mut program);
+        let Definition::FuncDef(main) = program.definitions.remove(0) else {
+            unreachable!()
+        };
+        let StatementKind::BlockState(_, statements) = main.statement.kind else {
+            unreachable!()
+        };
+        statements
+    }
+
+    /// Right-hand side of the assignment in an expression statement
+    fn assigned_value(statement: &Statement) -> &ExpressionKind {
+        match &statement.kind {
+            StatementKind::Expression(Expression {
+                kind: ExpressionKind::Expr(Operator::Assign, _, rhs),
+                ..
+            }) => &rhs.kind,
+            _ => panic!("expected an assignment"),
+        }
+    }
+
+    #[test]
+    fn test_straight_line() {
+        let statements = propagate("a = 3; b = a + 2; read(a); b = a + 2; return b;");
+        assert!(matches!(assigned_value(&statements[1]), ExpressionKind::Number(n) if *n == 5.0));
+        assert!(matches!(
+            assigned_value(&statements[3]),
+            ExpressionKind::Expr(..)
+        ));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let statements = propagate(
+            "a = 1; b = 2;
+             if (a) b = 3; else a = 4;
+             write(a, b);
+             while (b < 10) b = b + a;
+             return (a + b);",
+        );
+        // `a` is 1 after the `if` only when the `else` branch is not taken
+        let StatementKind::WriteState(a, Some(b)) = &statements[3].kind else {
+            panic!("expected a write");
+        };
+        assert!(matches!(a.kind, ExpressionKind::Identifier(_)));
+        assert!(matches!(b[0].kind, ExpressionKind::Identifier(_)));
+
+        let statements = propagate("a = 1; b = 2; while (b < 10) b = b + a; return (a + b);");
+        let StatementKind::WhileState(condition, body) = &statements[2].kind else {
+            panic!("expected a loop");
+        };
+        // `b` changes in the loop but `a` does not
+        assert!(matches!(condition.kind, ExpressionKind::Expr(..)));
+        let ExpressionKind::Expr(_, _, rhs) = assigned_value(body) else {
+            unreachable!()
+        };
+        assert!(matches!(rhs.kind, ExpressionKind::Number(n) if n == 1.0));
+    }
+
+    #[test]
+    fn test_no_new_division_by_zero() {
+        let statements = propagate("a = 0; b = 7 / a; return b;");
+        let ExpressionKind::Expr(_, _, divisor) = assigned_value(&statements[1]) else {
+            panic!("expected a division");
+        };
+        assert!(matches!(divisor.kind, ExpressionKind::Identifier(_)));
+    }
+}
@@ -0,0 +1,43 @@
+use toycc_frontend::ast::Span;
+use toycc_report::{Diagnostic, Report, ReportLevel, WarningKind};
+
+/// A statement control can never reach
+#[derive(Debug, Report)]
+pub struct UnreachableCode {
+    pub span: Span,
+    line: Option<String>,
+    stream_name: String,
+}
+
+impl UnreachableCode {
+    pub fn new(span: Span, line: Option<String>, stream_name: String) -> Self {
+        Self {
+            span,
+            line,
+            stream_name,
+        }
+    }
+}
+
+impl Diagnostic for UnreachableCode {
+    fn info(&self) -> String {
+        "unreachable code".to_owned()
+    }
+
+    fn level(&self) -> ReportLevel {
+        ReportLevel::Warning(WarningKind::ParsingWarning {
+            file_name: self.stream_name.clone(),
+            pos: self.span.location,
+            len: self.span.len,
+            source: self.line.clone(),
+        })
+    }
+
+    fn help(&self) -> Option<String> {
+        None
+    }
+
+    fn others(&self) -> Option<&dyn Report> {
+        None
+    }
+}
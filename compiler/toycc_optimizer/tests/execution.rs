@@ -0,0 +1,126 @@
+//! Runs programs with the interpreter before and after optimization and
+//! compares what they print and return.
+
+use toycc_backend_jvm::semantic_analyzer::SemanticAnalyzer;
+use toycc_optimizer::{PassManager, DEFAULT_INLINE_THRESHOLD};
+use toycc_test_support::{interpret, parse, run_both};
+
+fn check(source: &str, input: &str) -> (String, i32) {
+    run_both(source, input, |_| {
+        let mut program = parse(source);
+        PassManager::for_level(2, DEFAULT_INLINE_THRESHOLD).run(&mut program);
+        let (output, code) = interpret(&program, source, input);
+        (program, output, Some(code))
+    })
+}
+
+#[test]
+fn test_demo() {
+    let source = include_str!("../../../resources/demo.tc");
+    check(source, "7 1");
+}
+
+#[test]
+fn test_loops_and_branches() {
+    let source = "int main(){
+    int i;
+    int n;
+    int total;
+    n = 4;
+    total = (n * 10) + 1;
+    i = 0;
+    while ((i = i + 1) < n) {
+        if (i == 2) { total = total + n; break; }
+        n = n + 0;
+    }
+    write(i, \" \", n, \" \", total, \" \", (n > 3) && (total = 7), \" \", total);
+    newline;
+    read(n);
+    write(n * 2, 'a' + 1, -2147483647 - 2);
+    newline;
+    return total;
+}";
+    assert_eq!(
+        check(source, "21"),
+        ("2 4 45 1 7\n42b2147483647\n".to_owned(), 7)
+    );
+}
+
+#[test]
+fn test_nested_scopes_and_calls() {
+    let source = "int g;
+int bump(int x){ g = g + x; return g; }
+int main(){
+    int a;
+    a = 5;
+    { int b; b = a + bump(a); write(b, \" \"); }
+    g = 1;
+    write(g + a, \" \", bump(a), \" \", g);
+    newline;
+    return 0;
+}";
+    assert_eq!(check(source, "").0, "10 6 6 6\n");
+}
+
+#[test]
+fn test_dead_code() {
+    let source = "int sign(int x){
+    if (x < 0) return -1; else if (x == 0) return 0; else return 1;
+    write(\"never\");
+}
+int main(){
+    int i;
+    int debug;
+    debug = 0;
+    i = 0;
+    while (1) {
+        if (debug) write(\"debug \");
+        while (debug) { write(i); }
+        i = i + 1;
+        if (i > 3) break;
+    }
+    write(sign(i - 5), sign(0), sign(i));
+    newline;
+    return i;
+    newline;
+}";
+    assert_eq!(check(source, ""), ("-101\n".to_owned(), 4));
+}
+
+#[test]
+fn test_inlining() {
+    let source = "int g;
+int addTwo(int a){ return (a + 2); }
+int scale(int a, int b){ return (a * b) + g; }
+int main(){
+    int a;
+    int b;
+    char c;
+    g = 10;
+    a = 1;
+    b = 0;
+    c = 'x';
+    while (b < 3) {
+        a = addTwo(a) + scale(b, a - 1);
+        b = b + 1;
+    }
+    write(a, \" \", scale(addTwo(b), 2), \" \", addTwo(c), \" \", c + 1);
+    newline;
+    return scale(a, b);
+}";
+    assert_eq!(check(source, "").0, "121 20 122 y\n");
+}
+
+#[test]
+fn test_levels_accept_the_same_programs() {
+    // The division by zero only fails when run, at every level
+    let source = "int div(int a, int b){ return a / b; }
+int main(){ write(div(1, 0)); return 0; }";
+    for level in 0..=2 {
+        let mut program = parse(source);
+        PassManager::for_level(level, DEFAULT_INLINE_THRESHOLD).run(&mut program);
+        SemanticAnalyzer::new("test", "test.tc", false)
+            .analyze_program(&program)
+            .unwrap_or_else(|e| panic!("-O{level}: {e}\n{program}"));
+    }
+}
@@ -18,9 +18,21 @@ pub enum WarningKind {
     },
 }
 
+/// Extra context attached to an error or warning through
+/// [`Diagnostic::others`]
+pub enum NoteKind {
+    ParsingNote {
+        file_name: String,
+        pos: (usize, usize),
+        len: usize,
+        source: Option<String>,
+    },
+}
+
 pub enum ReportLevel {
     Warning(WarningKind),
     Error(ErrorKind),
+    Note(NoteKind),
     Info,
 }
 
@@ -13,14 +13,12 @@ pub fn derive_report(input: TokenStream) -> TokenStream {
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     let expanded = quote! {
-        use std::fmt;
         impl #impl_generics Report for #name #ty_generics #where_clause{
             fn message(&self) -> String{
                 use colored::{Colorize, Color};
                 use toycc_report::ReportLevel;
-                use toycc_report::{WarningKind, ErrorKind};
+                use toycc_report::{WarningKind, ErrorKind, NoteKind};
                 let mut buffer = String::new();
-                let mut d = self.others();
 
                 buffer += &*match self.level(){
                     ReportLevel::Info => format!("{}: {}",self.info().white().bold(), self.help().unwrap_or_default()),
@@ -55,6 +53,31 @@ pub fn derive_report(input: TokenStream) -> TokenStream {
                                 },
                             }
                         }
+                    ReportLevel::Note(n) => {
+                        let level = "note".cyan().bold();
+                        match n{
+                            NoteKind::ParsingNote {file_name, pos, len, source} => {
+                                let spaces = " ".repeat(pos.1-1);
+                                let tail = match len {
+                                    0 => "".to_owned(),
+                                    1 => format!("\n{}^",spaces),
+                                    len => format!("\n{}^\n{}{}",spaces,spaces,"~".repeat(len-1 as usize)).bright_green().to_string(),
+                                };
+                                let source = match source{
+                                    Some(source) => format!("\n{source}"),
+                                    None => "".to_owned(),
+                                };
+                                format!("{}:{}:{}: {}: {}{}{}",
+                                    file_name.white().bold(),
+                                    pos.0.to_string().white().bold(),
+                                    pos.1.to_string().white().bold(),
+                                    level,
+                                    self.info().white().bold(),
+                                    source,
+                                    tail.bright_green())
+                            },
+                        }
+                    }
                     ReportLevel::Error(e) => {
                         let level = "error".red().bold();
                         match e{
@@ -89,21 +112,16 @@ pub fn derive_report(input: TokenStream) -> TokenStream {
                         }
                     },
                 };
-                while let Some(c) = d{
+                // The message of a report already ends with those it links to
+                if let Some(c) = self.others(){
                     buffer.push('\n');
                     buffer+=c.message().as_str();
-                    d = c.others();
-                    if let Some(e) = d{
-                        if e.others().is_none(){
-                            break;
-                        }
-                    }
                 }
                 buffer
             }
         }
-        impl #impl_generics fmt::Display for #name #ty_generics #where_clause{
-            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        impl #impl_generics ::std::fmt::Display for #name #ty_generics #where_clause{
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
                 write!(f,"{}",self.message())
             }
         }
@@ -0,0 +1,165 @@
+//! Helpers shared by the tests that run compiled programs: a corpus of test
+//! programs, parsing, running native programs and comparing what the
+//! compiled code does with the tree-walking interpreter.
+use std::fmt::Display;
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+use toycc_frontend::ast::Program;
+use toycc_frontend::Parser;
+use toycc_interpreter::Interpreter;
+
+/// Programs exercising branches, loops, calls and globals, with their input
+pub const CORPUS: &[(&str, &str)] = &[
+    (include_str!("../../../resources/demo.tc"), "7 1"),
+    (
+        "int main(){
+    int a;
+    int b;
+    read(a);
+    b = a;
+    if ((a < 10) == 1) write(\"small \");
+    if ((a > 3) != 1) write(\"tiny \");
+    while (b > 0) {
+        b = b - 3;
+        if (b == 2) break;
+    }
+    write(b, \" \", (a >= 0) && (b <= 0), \" \", !(a == 4));
+    newline;
+    return 0;
+}",
+        "4",
+    ),
+    (
+        "int max(int x, int y){ if (x > y) return x; else return y; }
+int main(){
+    int i;
+    int total;
+    i = 0;
+    total = 0;
+    while (i < 5) {
+        if (i != 0) {
+            total = total + max(i, 3);
+        } else {
+            total = total - 1;
+        }
+        i = i + 1;
+    }
+    write(total);
+    newline;
+    return total;
+}",
+        "",
+    ),
+    (
+        "int main(){
+    int i; int j; int a; int b; int s;
+    read(a, b);
+    i = 0;
+    while (i < 10) {
+        j = 0;
+        while (j < i) {
+            s = s + (i * 4) + (j * 3) + (a * b) + (a / 3) + (b % 7);
+            j = j + 1;
+        }
+        write(s, \" \", i * 8, \" \", (i * 4) - (a * b));
+        newline;
+        i = i + 2;
+    }
+    return s;
+}",
+        "12 5",
+    ),
+    (
+        "int g;
+int step(int n){ g = g + n; return g; }
+int main(){
+    int i; int k; int t;
+    read(k);
+    i = k;
+    while (i > 0) {
+        t = (k * 2) + (i * -3);
+        if (t > 20) break;
+        write(t, \" \", step(i * 5), \" \", k / i);
+        newline;
+        i = i - 1;
+    }
+    while (i > 100) { write(i * 7, 1 / k); }
+    return g;
+}",
+        "6",
+    ),
+    (
+        "int main(){
+    int i; int z;
+    char c;
+    read(c, z);
+    while (i < 5) {
+        write(c + 1, \" \", (i * 3) + (z * z));
+        if (z) write(\" \", 100 / z);
+        newline;
+        i = i + 1;
+    }
+    return 0;
+}",
+        "a 0",
+    ),
+];
+
+pub fn parse(source: &str) -> Program {
+    Parser::new(Cursor::new(source.to_owned()), "test.tc", None)
+        .parse()
+        .expect("failed to parse")
+}
+
+/// What the interpreter prints and returns for `program`
+pub fn interpret(program: &Program, source: &str, input: &str) -> (String, i32) {
+    let mut output = vec![];
+    let code = Interpreter::new(program, "test.tc", source)
+        .run(&mut Cursor::new(input.to_owned()), &mut output)
+        .expect("interpreter failed");
+    (String::from_utf8(output).unwrap(), code)
+}
+
+/// Compiles and runs `source` with `run`, which returns the generated code,
+/// what it printed and its exit code when the target has one, and checks
+/// them against the interpreter. Returns what the interpreter printed and
+/// returned.
+pub fn run_both<T: Display>(
+    source: &str,
+    input: &str,
+    run: impl FnOnce(&Program) -> (T, String, Option<i32>),
+) -> (String, i32) {
+    let program = parse(source);
+    let expected = interpret(&program, source, input);
+    let (code, output, exit_code) = run(&program);
+    assert_eq!(output, expected.0, "{code}");
+    if let Some(exit_code) = exit_code {
+        assert_eq!(exit_code, expected.1, "{code}");
+    }
+    expected
+}
+
+/// Runs a native program on `input` and returns what it printed and its exit
+/// status. What it writes to stderr is passed on to the test's output.
+pub fn run_program(command: &mut Command, input: &str) -> (String, i32) {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprint!("{stderr}");
+    let status = output
+        .status
+        .code()
+        .unwrap_or_else(|| panic!("killed by a signal: {stderr}"));
+    (String::from_utf8_lossy(&output.stdout).into_owned(), status)
+}
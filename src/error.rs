@@ -4,6 +4,7 @@ use toycc_report::{Diagnostic, ErrorKind, Report, ReportLevel};
 pub enum Error {
     MissingInput,
     FileNotFound(String),
+    InvalidAst(String),
 }
 
 impl Diagnostic for Error {
@@ -11,6 +12,7 @@ impl Diagnostic for Error {
         match self {
             Error::MissingInput => "no input files".to_string(),
             Error::FileNotFound(_) => "no such file or directory".to_string(),
+            Error::InvalidAst(_) => "invalid JSON abstract syntax tree".to_string(),
         }
     }
 
@@ -20,6 +22,7 @@ impl Diagnostic for Error {
             Self::FileNotFound(name) => {
                 ReportLevel::Error(ErrorKind::SimpleError(format!("'{name}'")))
             }
+            Self::InvalidAst(reason) => ReportLevel::Error(ErrorKind::SimpleError(reason.clone())),
         }
     }
 
@@ -3,14 +3,32 @@ mod error;
 use std::ffi::OsStr;
 use std::fmt::Display;
 use std::fs::File;
-use std::io::{stdout, Write};
+use std::io::{stdin, stdout, BufReader, Cursor, Write};
 use std::path::Path;
 use std::process::exit;
 
 use crate::error::Error;
-use toycc_argparser::Arguments;
+use toycc_argparser::{Arguments, AstFormat, Emit, FormatArguments, SymbolFormat, Target};
+use toycc_backend_c::CGenerator;
+use toycc_backend_jvm::definite_assignment::find_uninitialized_reads;
 use toycc_backend_jvm::semantic_analyzer::SemanticAnalyzer;
+use toycc_backend_llvm::LlvmGenerator;
+use toycc_backend_riscv::RiscvGenerator;
+use toycc_backend_wasm::WasmGenerator;
+use toycc_backend_x86::X86Generator;
+use toycc_bytecode::{BytecodeCompiler, Module, Vm};
+use toycc_frontend::ast::Program;
 use toycc_frontend::Parser;
+use toycc_interpreter::Interpreter;
+use toycc_ir::dot::function_to_dot;
+use toycc_ir::jasmin::JasminGenerator;
+use toycc_ir::loops::{
+    instructions_in_loops, multiplications_in_loops, LoopInvariantCodeMotion, StrengthReduction,
+};
+use toycc_ir::ssa::{SsaConstruction, SsaDestruction};
+use toycc_ir::tail_call::{count_self_tail_calls, TailCallElimination};
+use toycc_ir::{IrBuilder, Module as IrModule, Pass as IrPass, PassManager as IrPassManager};
+use toycc_optimizer::{find_unreachable_code, PassManager, DEFAULT_INLINE_THRESHOLD};
 use toycc_report::{Diagnostic, Report};
 fn main() {
     let args = match Arguments::parse() {
@@ -25,6 +43,9 @@ fn main() {
         Arguments::print_authors();
         exit(0)
     }
+    if let Some(format) = &args.format {
+        format_files(format);
+    }
     if args.file_name.is_none() {
         handle_error(Error::MissingInput);
     }
@@ -40,33 +61,316 @@ fn main() {
     };
     let path = Path::new(OsStr::new(args.file_name.as_ref().unwrap()));
 
-    let mut parser = Parser::new(&file, args.file_name.as_ref().unwrap().as_str(), debug);
+    if path.extension() == Some(OsStr::new("tcb")) {
+        let bytes = std::fs::read(path).unwrap_or_default();
+        let module = Module::decode(&bytes).unwrap_or_else(|e| handle_error(e));
+        match args.run {
+            true => run_bytecode(&module),
+            false => print!("{module}"),
+        }
+        exit(0);
+    }
 
-    let parsed_program = parser.parse().unwrap_or_else(|e| handle_error(*e));
+    // An AST written with -abstract=json, possibly transformed by another
+    // tool, takes the place of the source, which diagnostics cannot quote
+    let from_json = path.extension() == Some(OsStr::new("json"));
+    let source = match from_json {
+        true => String::new(),
+        false => std::fs::read_to_string(path).unwrap_or_default(),
+    };
+    let mut parsed_program = match from_json {
+        true => {
+            let json = std::fs::read_to_string(path).unwrap_or_default();
+            Program::from_json(&json)
+                .unwrap_or_else(|e| handle_error(Error::InvalidAst(e.to_string())))
+        }
+        false => Parser::new(&file, args.file_name.as_ref().unwrap().as_str(), debug)
+            .parse()
+            .unwrap_or_else(|e| handle_error(*e)),
+    };
     if args.dump_ast || args.verbose {
-        println!("{parsed_program}");
+        match args.ast_format {
+            AstFormat::Tree => println!("{parsed_program}"),
+            AstFormat::Json => println!("{}", parsed_program.to_json()),
+            AstFormat::Sexpr => println!("{}", parsed_program.to_sexpr()),
+        }
     }
 
     let file_name = args
         .output
         .unwrap_or(path.file_stem().unwrap().to_string_lossy().to_string());
     let class_name = args.class.unwrap_or(file_name.clone());
-    let jasmin_program = SemanticAnalyzer::new(class_name.as_str(), args.dump_sym)
+    let source_name = path.file_name().unwrap().to_string_lossy().to_string();
+    let dump_table = args.dump_sym && args.symbol_format == SymbolFormat::Table;
+    let mut checker = SemanticAnalyzer::new(class_name.as_str(), source_name.as_str(), dump_table);
+    checker
         .analyze_program(&parsed_program)
         .unwrap_or_else(|e| handle_error(*e));
+    if args.dump_sym && args.symbol_format == SymbolFormat::Json {
+        println!("{}", checker.symbol_scopes().to_json());
+    }
+    let stream_name = args.file_name.as_ref().unwrap();
+    for warning in find_unreachable_code(&parsed_program, stream_name, &source) {
+        eprintln!("{warning}");
+    }
+    if !args.no_unused_warnings {
+        for warning in checker.unused_symbols(stream_name, &source) {
+            eprintln!("{warning}");
+        }
+    }
+    if args.shadow_warnings {
+        for warning in checker.shadowed_symbols(stream_name, &source) {
+            eprintln!("{warning}");
+        }
+    }
+    let uninitialized = find_uninitialized_reads(&parsed_program, stream_name, &source);
+    let initialize_locals = !uninitialized.is_empty();
+    match args.no_uninitialized_errors {
+        true => uninitialized
+            .into_iter()
+            .for_each(|warning| eprintln!("{}", warning.as_warning())),
+        false if initialize_locals => {
+            uninitialized.iter().for_each(|error| eprintln!("{error}"));
+            exit(1)
+        }
+        false => {}
+    }
 
+    if args.run && args.target != Target::Bytecode {
+        Interpreter::new(&parsed_program, args.file_name.as_ref().unwrap(), &source)
+            .run(&mut BufReader::new(stdin()), &mut stdout())
+            .unwrap_or_else(|e| handle_error(*e));
+        exit(0);
+    }
+
+    if args.emit == Some(Emit::DotAst) {
+        let mut dot_file = File::create(format!("{file_name}.ast.dot")).unwrap();
+        dot_file
+            .write_all(parsed_program.to_dot().as_bytes())
+            .expect("failed to write to file");
+        exit(0);
+    }
+
+    // Only a checked program is optimized, so that folding cannot hide an
+    // error such as a call to an undeclared function behind `0 && ...`
+    let inline_threshold = args
+        .inline_threshold
+        .map_or(DEFAULT_INLINE_THRESHOLD, |n| n as usize);
+    let mut passes = PassManager::for_level(args.optimize, inline_threshold);
+    passes.debug(debug).run(&mut parsed_program);
+
+    if let Some(emit) = args.emit {
+        let mut module = IrBuilder::new()
+            .build(&parsed_program)
+            .unwrap_or_else(|e| handle_error(*e));
+        optimize_ir(&mut module, &mut passes, emit == Emit::Ssa);
+        print_passes(&passes, args.print_passes);
+        match emit {
+            Emit::DotCfg => {
+                for function in &module.functions {
+                    let mut dot_file =
+                        File::create(format!("{file_name}.{}.dot", function.name)).unwrap();
+                    dot_file
+                        .write_all(function_to_dot(function).as_bytes())
+                        .expect("failed to write to file");
+                }
+            }
+            _ => print!("{module}"),
+        }
+        exit(0);
+    }
+
+    let mut binary = None;
+    let (program, extension) = match args.target {
+        // Like the bytecode target, the optimizing levels go through the IR
+        Target::Jvm if passes.is_enabled("tailcall") => {
+            let mut module = IrBuilder::new()
+                .build(&parsed_program)
+                .unwrap_or_else(|e| handle_error(*e));
+            optimize_ir(&mut module, &mut passes, false);
+            let peephole = passes.is_enabled("peephole");
+            let mut generator = JasminGenerator::new(class_name.as_str(), source_name.as_str());
+            let program = generator.peephole(peephole).generate(&module);
+            if peephole {
+                let (before, after) = generator.peephole_counts();
+                passes.record("peephole", before, after, "instructions");
+            }
+            (program, "j")
+        }
+        Target::Jvm => {
+            let peephole = passes.is_enabled("peephole");
+            let tail_calls = passes.is_enabled("tailcall");
+            let mut analyzer =
+                SemanticAnalyzer::new(class_name.as_str(), source_name.as_str(), false);
+            let program = analyzer
+                .peephole(peephole)
+                .tail_calls(tail_calls)
+                .dead_code(passes.is_enabled("dce"))
+                .initialize_locals(initialize_locals)
+                .analyze_program(&parsed_program)
+                .unwrap_or_else(|e| handle_error(*e));
+            if tail_calls {
+                let count = analyzer.tail_call_count();
+                passes.record("tailcall", count, 0, "self tail calls");
+            }
+            if peephole {
+                let (before, after) = analyzer.peephole_counts();
+                passes.record("peephole", before, after, "instructions");
+            }
+            (program, "j")
+        }
+        Target::Llvm => (
+            LlvmGenerator::new(source_name.as_str())
+                .generate(&parsed_program)
+                .unwrap_or_else(|e| handle_error(*e)),
+            "ll",
+        ),
+        Target::C => (
+            CGenerator::new(source_name.as_str())
+                .generate(&parsed_program)
+                .unwrap_or_else(|e| handle_error(*e)),
+            "c",
+        ),
+        Target::X86_64 => (
+            X86Generator::new(source_name.as_str())
+                .generate(&parsed_program)
+                .unwrap_or_else(|e| handle_error(*e)),
+            "s",
+        ),
+        Target::Wasm => {
+            let module = WasmGenerator::new()
+                .generate(&parsed_program)
+                .unwrap_or_else(|e| handle_error(*e));
+            if args.binary {
+                let mut binary_file = File::create(format!("{file_name}.wasm")).unwrap();
+                binary_file
+                    .write_all(&module.encode())
+                    .expect("failed to write to file");
+            }
+            (module.to_string(), "wat")
+        }
+        Target::Riscv32 => (
+            RiscvGenerator::new(source_name.as_str())
+                .generate(&parsed_program)
+                .unwrap_or_else(|e| handle_error(*e)),
+            "s",
+        ),
+        Target::Bytecode => {
+            // The optimizing levels compile through the IR, so that its
+            // tail call and loop passes shape the generated code
+            let module = match passes.is_enabled("tailcall") {
+                true => {
+                    let mut module = IrBuilder::new()
+                        .build(&parsed_program)
+                        .unwrap_or_else(|e| handle_error(*e));
+                    optimize_ir(&mut module, &mut passes, false);
+                    BytecodeCompiler::new().compile_ir(&module)
+                }
+                false => BytecodeCompiler::new()
+                    .compile(&parsed_program)
+                    .unwrap_or_else(|e| handle_error(*e)),
+            };
+            if args.run {
+                print_passes(&passes, args.print_passes);
+                run_bytecode(&module);
+                exit(0);
+            }
+            binary = Some(module.encode());
+            (module.to_string(), "tcb")
+        }
+    };
+
+    print_passes(&passes, args.print_passes);
     if args.dump_cgn || args.verbose {
-        println!("{jasmin_program}");
+        println!("{program}");
     }
 
-    let mut output_file = File::create(format!("{file_name}.j")).unwrap();
+    let mut output_file = File::create(format!("{file_name}.{extension}")).unwrap();
     output_file
-        .write_all(jasmin_program.as_bytes())
+        .write_all(binary.as_deref().unwrap_or(program.as_bytes()))
         .expect("failed to write to file");
 }
 
+/// Rewrites the files in the canonical style, or with `--check` lists those
+/// that are not in it and fails
+fn format_files(args: &FormatArguments) -> ! {
+    if args.files.is_empty() {
+        handle_error(Error::MissingInput);
+    }
+    let mut unformatted = false;
+    for file_name in &args.files {
+        let source = std::fs::read_to_string(file_name)
+            .unwrap_or_else(|_| handle_error(Error::FileNotFound(file_name.clone())));
+        let formatted = Parser::new(Cursor::new(&source), file_name, None)
+            .format()
+            .unwrap_or_else(|e| handle_error(*e));
+        if formatted == source {
+            continue;
+        }
+        match args.check {
+            true => {
+                println!("{file_name}");
+                unformatted = true;
+            }
+            false => std::fs::write(file_name, formatted).expect("failed to write to file"),
+        }
+    }
+    exit(unformatted as i32)
+}
+
+fn run_bytecode(module: &Module) {
+    Vm::new(module)
+        .and_then(|mut vm| vm.run(&mut BufReader::new(stdin()), &mut stdout()))
+        .unwrap_or_else(|e| handle_error(e));
+}
+
+/// Runs the IR passes the optimization level enables, recording each of
+/// them, and leaves the module in SSA form only when `ssa` is set
+fn optimize_ir(module: &mut IrModule, passes: &mut PassManager, ssa: bool) {
+    fn run<P: IrPass + 'static>(module: &mut IrModule, pass: P) {
+        IrPassManager::new()
+            .add(pass)
+            .run(module)
+            .unwrap_or_else(|e| handle_error(e));
+    }
+
+    if passes.is_enabled("tailcall") {
+        let before = count_self_tail_calls(module);
+        run(module, TailCallElimination);
+        let after = count_self_tail_calls(module);
+        passes.record("tailcall", before, after, "self tail calls");
+    }
+    let loops = passes.is_enabled("licm");
+    if ssa || loops {
+        run(module, SsaConstruction);
+    }
+    if loops {
+        let before = instructions_in_loops(module);
+        run(module, LoopInvariantCodeMotion);
+        let after = instructions_in_loops(module);
+        passes.record("licm", before, after, "instructions in loops");
+
+        let before = multiplications_in_loops(module);
+        run(module, StrengthReduction);
+        let after = multiplications_in_loops(module);
+        passes.record("strength", before, after, "multiplications in loops");
+    }
+    if !ssa && loops {
+        run(module, SsaDestruction);
+    }
+}
+
+fn print_passes(passes: &PassManager, enabled: bool) {
+    if enabled {
+        match passes.ran() {
+            [] => println!("passes: none"),
+            ran => println!("passes: {}", ran.join(", ")),
+        }
+    }
+}
+
 fn handle_error<T: Report + Diagnostic + Display>(error: T) -> ! {
     let _ = stdout().flush();
-    println!("{}", error);
+    eprintln!("{}", error);
     exit(1)
 }